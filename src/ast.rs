@@ -1,56 +1,85 @@
+//! The syntax tree, as it's parsed
+//!
+//! Every node keeps its span in the source. With the `serde` feature, trees serialize and
+//! deserialize as they are, spans included.
+
+use std::path::PathBuf;
+
 use lexer::TextSpan;
 use lexer::Token;
 
+/// A whole parsed source
 #[derive(Debug)]
 #[derive(Eq, PartialEq)]
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Ast {
+    /// The nodes at the top level, which is usually one expression
     pub repr: Vec<Node>,
+    /// Files spliced in by `include::expand`, which spans from them point into
+    pub files: Vec<PathBuf>,
 }
 
+/// Anything in a source
 #[derive(Debug)]
 #[derive(Eq, PartialEq)]
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Node {
+    /// `[{ ... }]`
     Expression(TextSpan, Expression),
+    /// `||`
     Divider(TextSpan),
+    /// A run of blank lines
     EmptyLines(TextSpan),
+    /// `$$name`
     Variable(TextSpan, String),
+    /// A word, or a `''quoted''` run of words
     Text(TextSpan, String),
+    /// `::key value`
     Property(TextSpan, String, Box<Node>),
 }
 
 impl Node {
+    /// An expression of the nodes inside it, for the parser
     pub fn expr(span: TextSpan, repr: Vec<Node>) -> Node {
         Node::Expression(span, Expression {
             repr: repr
         })
     }
 
+    /// A divider, for the parser
     pub fn div(span: TextSpan) -> Node {
         Node::Divider(span)
     }
 
+    /// Blank lines, for the parser
     pub fn empty(span: TextSpan) -> Node {
         Node::EmptyLines(span)
     }
 
+    /// A variable from its token, for the parser
     pub fn var(span: TextSpan, txt: Token) -> Node {
         Node::Variable(span, txt.contents().unwrap().to_owned())
     }
 
+    /// Text from its token, for the parser
     pub fn txt(span: TextSpan, txt: Token) -> Node {
         Node::Text(span, txt.contents().unwrap().to_owned())
     }
 
+    /// A property from its key's token and its value, for the parser
     pub fn prop(span: TextSpan, key: Token, value: Node) -> Node {
         Node::Property(span, key.contents().unwrap().to_owned(), Box::new(value))
     }
 }
 
+/// What's between an expression's `[{` and `}]`
 #[derive(Debug)]
 #[derive(Eq, PartialEq)]
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Expression {
+    /// Its nodes, in order
     pub repr: Vec<Node>
 }
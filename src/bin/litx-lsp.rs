@@ -0,0 +1,15 @@
+extern crate litx;
+
+use std::io;
+use std::io::Write;
+use std::process;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    if let Err(e) = litx::lsp::serve(stdin.lock(), stdout.lock()) {
+        // stdout belongs to the client, so complain on stderr
+        let _ = writeln!(io::stderr(), "litx-lsp: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,252 @@
+extern crate litx;
+
+use std::env;
+use std::io;
+use std::fs::File;
+use std::io::{ Read, Write };
+use std::path::Path;
+use std::process;
+
+const USAGE: &'static str = "\
+Usage:
+    litx check <document> [--deny <lint>]... [--font-dir <dir>]...
+    litx render <document> [--to html|text|pdf] [--columns <n>] [--font-dir <dir>]...
+    litx watch <document> [--port <port>]
+    litx dump-ast <file> [--expression]
+
+Commands:
+    check       Build a document and report any warnings. Each --deny turns
+                a lint's warnings into errors, and --deny warnings turns them
+                all into errors. Fonts are looked for in each --font-dir,
+                then $LITX_FONT_PATH and the system font directories
+    render      Print a document as HTML, as plain text --columns wide, or
+                as a PDF, with fonts found as for check
+    watch       Serve a live HTML preview, rebuilding whenever the document,
+                its strategy, or anything they include changes
+    dump-ast    Print the syntax tree of a file, or with --expression the
+                expression tree it lowers to. Output is JSON when litx is
+                built with the serde feature";
+
+const DEFAULT_PORT: u16 = 8000;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(|s| &s[..]) {
+        Some("check") => check(&args[1 ..]),
+        Some("render") => render(&args[1 ..]),
+        Some("watch") => watch(&args[1 ..]),
+        Some("dump-ast") => dump_ast(&args[1 ..]),
+        _ => usage(),
+    };
+    if let Err(e) = result {
+        let _ = writeln!(io::stderr(), "litx: {}", e);
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    let _ = writeln!(io::stderr(), "{}", USAGE);
+    process::exit(2);
+}
+
+fn check(args: &[String]) -> litx::Result<()> {
+    let mut document = None;
+    let mut deny = Vec::new();
+    let mut font_dirs = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--deny" => match args.next() {
+                Some(lint) => deny.push(lint),
+                None => usage(),
+            },
+            "--font-dir" => match args.next() {
+                Some(dir) => font_dirs.push(dir),
+                None => usage(),
+            },
+            _ if document.is_none() => document = Some(arg),
+            _ => usage(),
+        }
+    }
+    let document = match document {
+        Some(document) => document,
+        None => usage(),
+    };
+    let doc = try!(litx::Document::open(Path::new(document)));
+
+    let library = library(&font_dirs);
+    let mut faces: Vec<&str> = doc.paragraphs().iter().map(|p| &p.style().face[..]).collect();
+    faces.sort();
+    faces.dedup();
+    let mut fonts = litx::Diagnostics::new();
+    for face in faces {
+        library.resolve(&litx::FontStack::parse(face), &mut fonts);
+    }
+
+    // Each set of diagnostics, with what to call where they're from and the source their spans
+    // are offsets into. Only the document's can point into files it includes.
+    let (strategy, strategy_source) = match doc.strategy().path() {
+        Some(path) => (path.display().to_string(), read(path).ok()),
+        None => ("strategy".to_owned(), None),
+    };
+    let mut sources = vec![
+        (doc.strategy().diagnostics().clone(), strategy, strategy_source, false),
+        (doc.diagnostics().clone(), document.clone(), Some(try!(read(document))), true),
+        (fonts, "fonts".to_owned(), None, false),
+    ];
+    for &mut (ref mut diagnostics, ref name, ref source, includes) in &mut sources {
+        for lint in &deny {
+            diagnostics.deny(lint);
+        }
+        for d in diagnostics.iter() {
+            let severity = match d.severity {
+                litx::Severity::Error => "error",
+                litx::Severity::Warning => "warning",
+                litx::Severity::Note => "note",
+            };
+            let place = match (source, d.span) {
+                (&Some(ref source), Some(span)) if span.is_root() => {
+                    let (line, col) = line_col(source, span.low);
+                    format!("{}:{}:{}", name, line, col)
+                }
+                // Spans in included files are offsets into those files
+                (&Some(_), Some(span)) if includes => match doc.file_of(span) {
+                    Some(file) => match read(file) {
+                        Ok(source) => {
+                            let (line, col) = line_col(&source, span.low);
+                            format!("{}:{}:{}", file.display(), line, col)
+                        }
+                        Err(_) => file.display().to_string(),
+                    },
+                    None => name.clone(),
+                },
+                _ => name.clone(),
+            };
+            let _ = writeln!(io::stderr(), "{}: {}[{}]: {}", place, severity, d.lint, d.message);
+        }
+    }
+    for &(ref diagnostics, _, _, _) in &sources {
+        try!(diagnostics.check());
+    }
+    Ok(())
+}
+
+/// Fonts in each --font-dir, then the usual places
+fn library(font_dirs: &[&String]) -> litx::FontLibrary {
+    let mut library = litx::FontLibrary::new(font_dirs);
+    for dir in litx::FontLibrary::system_dirs() {
+        library.add_dir(&dir);
+    }
+    library
+}
+
+/// One-based line and column of a byte offset, or of the end if it's past it
+fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &source[.. offset];
+    let line = before.matches('\n').count() + 1;
+    let col = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    (line, col)
+}
+
+fn read<P: AsRef<Path>>(file: P) -> litx::Result<String> {
+    let mut source = String::new();
+    try!(try!(File::open(file)).read_to_string(&mut source));
+    Ok(source)
+}
+
+fn render(args: &[String]) -> litx::Result<()> {
+    let mut document = None;
+    let mut format = "html";
+    let mut columns = litx::render::text::DEFAULT_COLUMNS;
+    let mut font_dirs = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--to" => format = match args.next().map(|f| &f[..]) {
+                Some("html") => "html",
+                Some("text") => "text",
+                Some("pdf") => "pdf",
+                _ => usage(),
+            },
+            "--font-dir" => match args.next() {
+                Some(dir) => font_dirs.push(dir),
+                None => usage(),
+            },
+            "--columns" => {
+                columns = match args.next().and_then(|c| c.parse().ok()) {
+                    Some(columns) => columns,
+                    None => usage(),
+                }
+            }
+            _ if document.is_none() => document = Some(arg),
+            _ => usage(),
+        }
+    }
+    let document = match document {
+        Some(document) => document,
+        None => usage(),
+    };
+    let doc = try!(litx::Document::open(Path::new(document)));
+    let out = match format {
+        "text" => try!(litx::render::text::render(&doc, columns)).into_bytes(),
+        "pdf" => try!(litx::render::pdf::render(&doc, &library(&font_dirs))),
+        _ => try!(litx::render::html::render(&doc)).into_bytes(),
+    };
+    try!(io::stdout().write_all(&out));
+    Ok(())
+}
+
+fn watch(args: &[String]) -> litx::Result<()> {
+    let mut document = None;
+    let mut port = DEFAULT_PORT;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--port" => {
+                port = match args.next().and_then(|p| p.parse().ok()) {
+                    Some(port) => port,
+                    None => usage(),
+                }
+            }
+            _ if document.is_none() => document = Some(arg),
+            _ => usage(),
+        }
+    }
+    match document {
+        Some(document) => {
+            let server = try!(litx::watch::Server::bind(Path::new(document), port));
+            println!("Serving {} on http://{}/", document, try!(server.addr()));
+            let document = document.clone();
+            server.serve(move |version| println!("Rebuilt {} (version {})", document, version))
+        }
+        None => usage(),
+    }
+}
+
+fn dump_ast(args: &[String]) -> litx::Result<()> {
+    let mut file = None;
+    let mut expression = false;
+    for arg in args {
+        match &arg[..] {
+            "--expression" => expression = true,
+            _ if file.is_none() => file = Some(arg),
+            _ => usage(),
+        }
+    }
+    let file = match file {
+        Some(file) => file,
+        None => usage(),
+    };
+    let source = try!(read(file));
+    let dump = if expression {
+        try!(litx::dump::expression(&source))
+    } else {
+        try!(litx::dump::ast(&source))
+    };
+    println!("{}", dump);
+    Ok(())
+}
@@ -0,0 +1,58 @@
+//! Captions
+//!
+//! Numbered tables and figures are each counted through the document, and their captions start
+//! with their name and number, like `Table 2.` or `Figure 1.`. A strategy's `::captions` block says
+//! what they're called:
+//!
+//! ```text
+//! ::captions [{ ::table ''Tabelle'' ::figure ''Abbildung'' }]
+//! ```
+
+use expression::{ Expression, Node };
+
+/// What tables are called, when nothing says otherwise
+const DEFAULT_TABLE: &'static str = "Table";
+/// What figures are called, when nothing says otherwise
+const DEFAULT_FIGURE: &'static str = "Figure";
+
+/// A strategy's `::captions`
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Captions {
+    /// What tables are called
+    pub table: String,
+    /// What figures are called
+    pub figure: String,
+}
+
+impl Default for Captions {
+    fn default() -> Captions {
+        Captions { table: DEFAULT_TABLE.to_owned(), figure: DEFAULT_FIGURE.to_owned() }
+    }
+}
+
+impl Captions {
+    /// Read `[{ ::table ''Table'' ::figure ''Figure'' }]`
+    pub fn from_expression(expr: &Expression) -> Captions {
+        let mut captions = Captions::default();
+        if let Some(table) = expr.prop("table").and_then(Node::as_text) {
+            captions.table = table.to_owned();
+        }
+        if let Some(figure) = expr.prop("figure").and_then(Node::as_text) {
+            captions.figure = figure.to_owned();
+        }
+        captions
+    }
+
+    /// What a numbered table's caption starts with
+    pub fn table_prefix(&self, number: &str) -> String {
+        format!("{} {}.", self.table, number)
+    }
+
+    /// What a numbered figure's caption starts with
+    pub fn figure_prefix(&self, number: &str) -> String {
+        format!("{} {}.", self.figure, number)
+    }
+}
@@ -0,0 +1,168 @@
+//! Dates
+//!
+//! Dates are written `yyyy-mm-dd`, which is what `$$special/today` gives. `[{ date }]` prints one
+//! with a format like .NET's custom date formats: runs of `d`, `M` and `y` are the day, month and
+//! year, text in single quotes is kept as is, and anything else is printed as written.
+//!
+//! ```text
+//! [{ date ::format ''d MMMM yyyy'' ::source $$meta/date }]    // 18 October 2026
+//! ```
+
+use std::fmt;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+const MONTHS: &'static [&'static str] = &[
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+const WEEKDAYS: &'static [&'static str] = &[
+    "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday",
+];
+
+/// A day in the proleptic Gregorian calendar
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Date {
+    /// The year
+    pub year: i64,
+    /// The month, from 1
+    pub month: u32,
+    /// The day of the month, from 1
+    pub day: u32,
+}
+
+impl Date {
+    /// Today (UTC)
+    pub fn today() -> Date {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        Date::from_days((secs / 86400) as i64)
+    }
+
+    /// The date some days after 1970-01-01
+    fn from_days(days: i64) -> Date {
+        // Civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719468;
+        let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        Date { year: year, month: month as u32, day: day as u32 }
+    }
+
+    /// Days since 1970-01-01, the inverse of `from_days`
+    fn days(&self) -> i64 {
+        let year = if self.month <= 2 { self.year - 1 } else { self.year };
+        let era = (if year >= 0 { year } else { year - 399 }) / 400;
+        let yoe = year - era * 400;
+        let mp = (if self.month > 2 { self.month - 3 } else { self.month + 9 }) as i64;
+        let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    /// Read `yyyy-mm-dd`, which must be a real day
+    pub fn parse(text: &str) -> Option<Date> {
+        let parts: Vec<&str> = text.trim().split('-').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+        let date = match (parts[0].parse(), parts[1].parse(), parts[2].parse()) {
+            (Ok(year), Ok(month), Ok(day)) => Date { year: year, month: month, day: day },
+            _ => return None,
+        };
+        if date.month < 1 || date.month > 12 || date.day < 1 || Date::from_days(date.days()) != date {
+            return None;
+        }
+        Some(date)
+    }
+
+    /// The day of the week, from Monday as 0
+    fn weekday(&self) -> usize {
+        // 1970-01-01 was a Thursday
+        ((self.days() + 3).rem_euclid(7)) as usize
+    }
+
+    /// Print the date with a format like `d MMMM yyyy`
+    pub fn format(&self, format: &str) -> String {
+        let chars: Vec<char> = format.chars().collect();
+        let mut out = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let run = chars[i..].iter().take_while(|&&d| d == c).count();
+            match c {
+                'd' => match run {
+                    1 => out.push_str(&self.day.to_string()),
+                    2 => out.push_str(&format!("{:02}", self.day)),
+                    3 => out.push_str(&WEEKDAYS[self.weekday()][..3]),
+                    _ => out.push_str(WEEKDAYS[self.weekday()]),
+                },
+                'M' => {
+                    let name = MONTHS[self.month as usize - 1];
+                    match run {
+                        1 => out.push_str(&self.month.to_string()),
+                        2 => out.push_str(&format!("{:02}", self.month)),
+                        3 => out.push_str(&name[..3]),
+                        _ => out.push_str(name),
+                    }
+                }
+                'y' => match run {
+                    1 => out.push_str(&(self.year % 100).to_string()),
+                    2 => out.push_str(&format!("{:02}", self.year % 100)),
+                    n => out.push_str(&format!("{:01$}", self.year, n)),
+                },
+                '\'' => {
+                    let literal: String = chars[i + 1..].iter().take_while(|&&d| d != '\'').collect();
+                    out.push_str(&literal);
+                    i += literal.chars().count() + 2;
+                    continue;
+                }
+                _ => {
+                    out.push(c);
+                    i += 1;
+                    continue;
+                }
+            }
+            i += run;
+        }
+        out
+    }
+}
+
+impl fmt::Display for Date {
+    /// As `yyyy-mm-dd`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_and_writes_days() {
+        let date = Date::parse("2026-10-18").unwrap();
+        assert_eq!(Date { year: 2026, month: 10, day: 18 }, date);
+        assert_eq!("2026-10-18", date.to_string());
+        assert_eq!(date, Date::from_days(date.days()));
+        assert_eq!(Date { year: 1970, month: 1, day: 1 }, Date::from_days(0));
+        assert!(Date::parse("2026-02-29").is_none());
+        assert!(Date::parse("2024-02-29").is_some());
+        assert!(Date::parse("January, 1000").is_none());
+    }
+
+    #[test]
+    fn formats() {
+        let date = Date::parse("2026-03-05").unwrap();
+        assert_eq!("5 March 2026", date.format("d MMMM yyyy"));
+        assert_eq!("Thu, 05 Mar 26", date.format("ddd, dd MMM yy"));
+        assert_eq!("Thursday the 5th of 3", date.format("dddd 'the' d'th of' M"));
+    }
+}
@@ -0,0 +1,225 @@
+//! Problems which shouldn't stop a build
+//!
+//! Documents and strategies collect these as they're built. Each has a lint name, which can be
+//! denied to turn its warnings into errors, like `--deny unused-meta` or `--deny warnings`.
+
+use std::slice;
+
+use error::{ Error, EvaluationError, EvaluationKind, Result };
+use lexer::TextSpan;
+
+/// A `::meta` value the strategy doesn't ask for
+pub const UNUSED_META: &'static str = "unused-meta";
+/// A top level `::key` nothing understands
+pub const UNKNOWN_KEY: &'static str = "unknown-key";
+/// A `$$variable` with no value
+pub const UNDEFINED_VARIABLE: &'static str = "undefined-variable";
+/// An expression whose title nothing understands, and which sets nothing
+pub const UNKNOWN_EXPRESSION: &'static str = "unknown-expression";
+
+/// A font which had to fall back on another in its stack
+pub const FONT_FALLBACK: &'static str = "font-fallback";
+/// A `::language` there are no hyphenation patterns for
+pub const UNKNOWN_LANGUAGE: &'static str = "unknown-language";
+
+/// Denying this denies every lint
+pub const ALL_WARNINGS: &'static str = "warnings";
+
+/// How bad a diagnostic is
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Severity {
+    /// Worth knowing, but nothing's wrong
+    Note,
+    /// Probably a mistake
+    Warning,
+    /// Fails the build
+    Error,
+}
+
+/// A single problem
+#[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Diagnostic {
+    /// The lint which found it, like `unused-meta`
+    pub lint: String,
+    /// How bad it is
+    pub severity: Severity,
+    /// Where it is, if it's tied to a place
+    pub span: Option<TextSpan>,
+    /// What's wrong
+    pub message: String,
+    /// The error it fails with when denied, if it's more than its lint being denied
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub kind: Option<EvaluationKind>,
+}
+
+/// Diagnostics collected while building something
+#[derive(Debug)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Diagnostics {
+    list: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// No diagnostics
+    pub fn new() -> Diagnostics {
+        Diagnostics { list: Vec::new() }
+    }
+
+    /// Add a diagnostic
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.list.push(diagnostic);
+    }
+
+    /// Add a warning
+    pub fn warn<S: Into<String>>(&mut self, lint: &str, span: Option<TextSpan>, message: S) {
+        self.push(Diagnostic {
+            lint: lint.to_owned(),
+            severity: Severity::Warning,
+            span: span,
+            message: message.into(),
+            kind: None,
+        });
+    }
+
+    /// Add a warning which fails with its own kind of error when denied
+    pub fn warn_of<S: Into<String>>(&mut self, lint: &str, kind: EvaluationKind, span: Option<TextSpan>, message: S) {
+        self.warn(lint, span, message);
+        if let Some(last) = self.list.last_mut() {
+            last.kind = Some(kind);
+        }
+    }
+
+    /// Turn warnings from a lint into errors. `warnings` means every lint.
+    pub fn deny(&mut self, lint: &str) {
+        for d in &mut self.list {
+            if d.severity == Severity::Warning && (lint == ALL_WARNINGS || d.lint == lint) {
+                d.severity = Severity::Error;
+            }
+        }
+    }
+
+    /// Fail with the first error, if there are any
+    pub fn check(&self) -> Result<()> {
+        match self.list.iter().find(|d| d.severity == Severity::Error) {
+            Some(d) => {
+                let mut failure = match d.kind {
+                    Some(ref kind) => EvaluationError::new(kind.clone()).note(format!("{} is denied", d.lint)),
+                    None => EvaluationError::new(EvaluationKind::Denied(d.lint.clone())).note(d.message.clone()),
+                };
+                if let Some(span) = d.span {
+                    failure = failure.at(span);
+                }
+                Err(Error::EvaluationFailure(failure))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Every diagnostic, in the order they were found
+    pub fn iter<'a>(&'a self) -> slice::Iter<'a, Diagnostic> {
+        self.list.iter()
+    }
+
+    /// How many there are
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Whether there are none at all
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> slice::Iter<'a, Diagnostic> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+    use error::{ Error, EvaluationKind };
+    use lexer::TextSpan;
+
+    #[test]
+    fn deny_promotes_matching_lints() {
+        let mut d = Diagnostics::new();
+        d.warn(UNUSED_META, None, "a");
+        d.warn(UNKNOWN_KEY, None, "b");
+        assert!(d.check().is_ok());
+
+        d.deny(UNKNOWN_KEY);
+        let severities: Vec<_> = d.iter().map(|d| d.severity).collect();
+        assert_eq!(vec![Severity::Warning, Severity::Error], severities);
+        match d.check() {
+            Err(Error::EvaluationFailure(ref f)) =>
+                assert_eq!(&EvaluationKind::Denied(UNKNOWN_KEY.to_owned()), f.kind()),
+            other => panic!("Expected a denied warning, got {:?}", other),
+        }
+
+        d.deny(ALL_WARNINGS);
+        assert!(d.iter().all(|d| d.severity == Severity::Error));
+    }
+
+    #[test]
+    fn documents_collect_warnings() {
+        let strategy = Strategy::new("[{strategy ::name s ::metafields [{ [{field ::name title}] }] ::colour red }]")
+            .unwrap();
+        let lints: Vec<_> = strategy.diagnostics().iter().map(|d| (&d.lint[..], d.span)).collect();
+        assert_eq!(vec![(UNKNOWN_KEY, Some(TextSpan::new(62, 74)))], lints);
+
+        let src = "[{document ::meta [{ ::title T ::subtitle S }] ::colour red || $$meta/author $$special/page-no }]";
+        let doc = Document::new(src, strategy).unwrap();
+        let lints: Vec<_> = doc.diagnostics().iter().map(|d| (&d.lint[..], d.span)).collect();
+        assert_eq!(vec![
+            (UNUSED_META, Some(TextSpan::new(31, 43))),
+            (UNKNOWN_KEY, Some(TextSpan::new(47, 59))),
+            (UNDEFINED_VARIABLE, Some(TextSpan::new(63, 76))),
+        ], lints);
+    }
+
+    #[test]
+    fn warns_about_unknown_languages() {
+        let src = "[{document ::strategy none ::language tlh || Qapla' [{ language en || Success }] }]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        let lints: Vec<_> = doc.diagnostics().iter().map(|d| &d.lint[..]).collect();
+        assert_eq!(vec![UNKNOWN_LANGUAGE], lints);
+    }
+
+    #[test]
+    fn denied_lints_fail_with_their_own_kinds() {
+        let src = "[{document ::strategy none || [{ shout || Hi }] [{ quote ::size 12pt || $$who }] }]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        let lints: Vec<_> = doc.diagnostics().iter().map(|d| (&d.lint[..], d.span)).collect();
+        assert_eq!(vec![
+            (UNKNOWN_EXPRESSION, Some(TextSpan::new(30, 47))),
+            (UNDEFINED_VARIABLE, Some(TextSpan::new(72, 77))),
+        ], lints);
+
+        let mut diagnostics = doc.diagnostics().clone();
+        diagnostics.deny(UNDEFINED_VARIABLE);
+        match diagnostics.check() {
+            Err(Error::EvaluationFailure(ref f)) =>
+                assert_eq!(&EvaluationKind::UndefinedVariable("who".to_owned()), f.kind()),
+            other => panic!("Expected an undefined variable, got {:?}", other),
+        }
+        diagnostics.deny(UNKNOWN_EXPRESSION);
+        match diagnostics.check() {
+            Err(Error::EvaluationFailure(ref f)) =>
+                assert_eq!(&EvaluationKind::UnknownExpression("shout".to_owned()), f.kind()),
+            other => panic!("Expected an unknown expression, got {:?}", other),
+        }
+    }
+}
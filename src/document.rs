@@ -1,14 +1,103 @@
 //! Document strategy
 
-use error::{ Result };
-use expression::Expression;
-use lexer::Lexer;
+use std::collections::BTreeMap;
+use std::mem;
+use std::path::{ Path, PathBuf };
+
+use ast;
+use ast::Ast;
+use caption::Captions;
+use date::Date;
+use diagnostic;
+use diagnostic::Diagnostics;
+use error::{ Error, EvaluationError, EvaluationKind, Result };
+use expression::{ Expression, Node };
+use figure;
+use figure::Figure;
+use image::Image;
+use include;
+use include::Resolver;
+use inline;
+use inline::{ Format, Inline, Run };
+use layout::hyphenate;
+use layout::hyphenate::Hyphenation;
+use layout::page::PageSetup;
+use lexer::{ Lexer, TextSpan };
+use list::{ List, ListItem };
+use note::{ Note, NotePlacement, Notes };
+use outline;
+use outline::{ Numbering, OutlineEntry, MAX_LEVEL };
 use parser;
+use reference;
+use reference::{ Reference, Target };
+use running::Running;
+use style::{ ComputedStyle, TextSettings, STYLE_KEYS };
+use table;
+use table::{ Cell, Grid, Row, Table };
+use value::{ Align, Length, Unit, Value };
+use visit::ast::{ walk_expression, Visitor };
+
+/// Keys every document understands at its top level, whatever its strategy
+pub const DOCUMENT_KEYS: &'static [&'static str] = &["strategy", "meta", "page"];
+
+/// How far each level of a table of contents is indented past the one above, in inches
+const CONTENTS_INDENT_IN: f64 = 0.5;
+
+/// Punctuation which follows text without a space, even after an inline expression
+const CLOSING_PUNCTUATION: &'static [char] = &['.', ',', ';', ':', '!', '?', '\u{2019}', '\u{201D}'];
+
+/// Keys a strategy file understands at its top level
+pub const STRATEGY_KEYS: &'static [&'static str] = &[
+    "name", "metafields", "fields", "text-settings", "hyphenation", "headings", "header", "footer",
+    "notes", "captions", "frontmatter", "backmatter", "bibliography", "body",
+];
 
 /// A document is a cool beans kinda character
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Document {
     strategy: Strategy,
+    /// Values given in the document's `::meta` block
+    meta: BTreeMap<String, String>,
+    /// Paragraphs of frontmatter, body text and backmatter
+    paragraphs: Vec<Paragraph>,
+    /// Where the body starts in `paragraphs`
+    body_start: usize,
+    /// Where the backmatter starts in `paragraphs`
+    body_end: usize,
+    /// Tables of contents waiting for every heading to be known
+    contents: Vec<Contents>,
+    /// How many headings there have been at each level, since the last heading above it
+    counts: Vec<usize>,
+    /// How deeply `[{ section }]`s are nested
+    depth: usize,
+    /// Terms marked with `[{ term }]`, by their lowercase text, to how they're written and their
+    /// definitions
+    glossary: BTreeMap<String, (String, String)>,
+    /// Index entries, by their lowercase text, to how they're written and the paragraphs marked
+    index: BTreeMap<String, (String, Vec<usize>)>,
+    /// Notes, until they're set as endnotes
+    notes: Vec<Note>,
+    /// What each `[{ label }]` names
+    labels: BTreeMap<String, Target>,
+    /// What a `[{ label }]` would name here, innermost last
+    targets: Vec<Target>,
+    /// How deeply `[{ list }]`s are nested
+    list_depth: usize,
+    /// Where in the main text the paragraphs being gathered apart from it are, while gathering
+    pinned: Option<usize>,
+    /// How many numbered tables there have been
+    tables: usize,
+    /// How many numbered figures there have been
+    figures: usize,
+    /// Finds the files the document refers to, like images
+    resolver: Resolver,
+    /// Files included into the document, as in `Ast::files`
+    files: Vec<PathBuf>,
+    /// Paper size and margins
+    page: PageSetup,
+    /// Problems found while building the document
+    diagnostics: Diagnostics,
 }
 
 impl Document {
@@ -16,18 +105,1269 @@ impl Document {
     pub fn new(source: &str, strat: Strategy) -> Result<Document> {
         let lexer = Lexer::new(source);
         let ast = try!(parser::parse(lexer));
-        let expr = Expression::from_ast(ast).unwrap();
-        println!("{:#?}", expr);
-        let doc = Document {
+        Document::from_ast(ast, strat)
+    }
+
+    /// Read a document from a file, loading the strategy it asks for from beside it
+    pub fn open(path: &Path) -> Result<Document> {
+        let source = try!(include::read(path));
+        let resolver = Resolver::new(path.parent().unwrap_or(Path::new(".")));
+        let strategy = match include::strategy_name(&source) {
+            Some(ref name) if name != "none" => try!(Strategy::open(&try!(resolver.strategy(name)))),
+            _ => Strategy::none(),
+        };
+        let ast = try!(parser::parse(Lexer::new(&source)));
+        let base = path.parent().unwrap_or(Path::new("."));
+        Document::from_ast_in(try!(include::expand(ast, &resolver)), strategy, base)
+    }
+
+    /// Create a document from an already parsed source, finding the files it refers to, like
+    /// images, in the current directory
+    pub fn from_ast(ast: Ast, strat: Strategy) -> Result<Document> {
+        Document::from_ast_in(ast, strat, Path::new("."))
+    }
+
+    /// Create a document from an already parsed source, finding the files it refers to, like
+    /// images, in `base`
+    pub fn from_ast_in(ast: Ast, strat: Strategy, base: &Path) -> Result<Document> {
+        let files = ast.files.clone();
+        Document::build(ast, strat, base).map_err(|e| include::locate(e, &files))
+    }
+
+    fn build(ast: Ast, strat: Strategy, base: &Path) -> Result<Document> {
+        let files = ast.files.clone();
+        let spans = Spans::new(&ast);
+        let expr = try!(Expression::from_ast(ast));
+        let mut meta = BTreeMap::new();
+        if let Some(block) = expr.prop("meta").and_then(Node::as_expr) {
+            for (key, value) in block.props() {
+                if let Some(text) = value.as_text() {
+                    meta.insert(key.to_owned(), text.to_owned());
+                }
+            }
+        }
+        for field in strat.meta_fields() {
+            if !field.is_optional() && !meta.contains_key(field.name()) {
+                let kind = EvaluationKind::MissingField(format!("meta/{}", field.name()));
+                let note = format!("the {} strategy requires it in ::meta", strat.name());
+                return Err(EvaluationError::new(kind).note(note).into());
+            }
+        }
+        let page = try!(page_setup(&expr, &strat));
+        let mut doc = Document {
             strategy: strat,
+            meta: meta,
+            paragraphs: Vec::new(),
+            body_start: 0,
+            body_end: 0,
+            contents: Vec::new(),
+            counts: Vec::new(),
+            depth: 0,
+            glossary: BTreeMap::new(),
+            index: BTreeMap::new(),
+            notes: Vec::new(),
+            labels: BTreeMap::new(),
+            targets: Vec::new(),
+            list_depth: 0,
+            pinned: None,
+            tables: 0,
+            figures: 0,
+            resolver: Resolver::new(base),
+            files: files,
+            page: page,
+            diagnostics: Diagnostics::new(),
         };
+        doc.check_keys(&expr, &spans);
+        let style = try!(doc.strategy.text_settings().base().enter(&expr, doc.strategy.text_settings()));
+
+        let mut paragraph = Pending::default();
+        let frontmatter = doc.strategy.frontmatter().cloned();
+        if let Some(ref frontmatter) = frontmatter {
+            try!(doc.add_matter(&frontmatter.content, &mut paragraph, &style));
+        }
+        doc.body_start = doc.paragraphs.len();
+        let frontmatter_contents = doc.contents.len();
+        if frontmatter.map(|f| f.page) == Some(FrontmatterPage::BeforeFirst) {
+            paragraph = Pending { break_before: true, starts_section: true, ..Pending::default() };
+        }
+
+        for section in expr.sections() {
+            for node in &section.content {
+                try!(doc.add_body_node(node, &mut paragraph, &style, &spans));
+            }
+        }
+        doc.end_paragraph(&mut paragraph, &style);
+        doc.add_endnotes(&style);
+        doc.body_end = doc.paragraphs.len();
+        let body_contents = doc.contents.len();
+
+        let backmatter = doc.strategy.backmatter().cloned();
+        if let Some(ref backmatter) = backmatter {
+            if backmatter.page == BackmatterPage::AfterLast {
+                paragraph.break_before = true;
+            }
+            try!(doc.add_matter(&backmatter.content, &mut paragraph, &style));
+        }
+        doc.add_contents(frontmatter_contents, body_contents);
+        try!(doc.resolve_references(&spans));
+        doc.check_languages(&spans);
         Ok(doc)
     }
+
+    /// Set a strategy's frontmatter or backmatter
+    fn add_matter(&mut self, content: &Expression, paragraph: &mut Pending, style: &ComputedStyle) -> Result<()> {
+        let inner = try!(style.enter(content, self.strategy.text_settings()));
+        for section in content.sections() {
+            for node in &section.content {
+                // It comes from the strategy, so the document's spans don't apply
+                try!(self.add_body_node(node, paragraph, &inner, &Spans::default()));
+            }
+        }
+        self.end_paragraph(paragraph, &inner);
+        Ok(())
+    }
+
+    /// Set the notes after the body, under a heading, if the strategy wants endnotes
+    fn add_endnotes(&mut self, style: &ComputedStyle) {
+        if self.strategy.notes().placement != NotePlacement::Endnotes || self.notes.is_empty() {
+            return;
+        }
+        let mut heading = Pending { heading: Some(1), ..Pending::default() };
+        heading.push_text(&self.strategy.notes().title);
+        let heading_style = self.heading_style(1, style);
+        self.end_paragraph(&mut heading, &heading_style);
+        for note in mem::replace(&mut self.notes, Vec::new()) {
+            self.paragraphs.extend(note.paragraphs);
+        }
+    }
+
+    /// Fill in every `[{ toc }]` with an entry for each heading. Entries in the first
+    /// `frontmatter` tables of contents are part of the frontmatter, and those after the first
+    /// `body` are part of the backmatter.
+    fn add_contents(&mut self, frontmatter: usize, body: usize) {
+        let contents = mem::replace(&mut self.contents, Vec::new());
+        let headings: Vec<(usize, usize, Paragraph)> = self.paragraphs.iter().enumerate()
+            .filter_map(|(i, p)| p.heading.map(|level| (i, level, p.clone())))
+            .collect();
+        // Where a paragraph ends up once the entries before it are in
+        let moved = |i: usize| i + headings.len() * contents.iter().filter(|c| c.at <= i).count();
+        for target in self.labels.values_mut() {
+            if let Target::Paragraph(i) = *target {
+                *target = Target::Paragraph(moved(i));
+            }
+        }
+
+        let mut breaks = Pending::default();
+        let mut old = mem::replace(&mut self.paragraphs, Vec::new()).into_iter().enumerate().peekable();
+        for toc in &contents {
+            while old.peek().map_or(false, |&(i, _)| i < toc.at) {
+                let (_, paragraph) = old.next().unwrap();
+                self.paragraphs.push(breaks.take_breaks(paragraph.moved(&moved)));
+            }
+            // An empty table of contents leaves its breaks to whatever follows
+            breaks.break_before |= toc.break_before;
+            breaks.starts_section |= toc.starts_section;
+            for &(target, level, ref heading) in &headings {
+                let mut style = toc.style.clone();
+                style.indent = Length::new(CONTENTS_INDENT_IN * (level - 1) as f64, Unit::In);
+                let mut entry = Paragraph::new(heading.text.clone(), style);
+                entry.inlines = heading.inlines.clone();
+                entry.contents_entry = Some(moved(target));
+                self.paragraphs.push(breaks.take_breaks(entry));
+            }
+        }
+        for (_, paragraph) in old {
+            self.paragraphs.push(breaks.take_breaks(paragraph.moved(&moved)));
+        }
+        self.body_start += headings.len() * frontmatter;
+        self.body_end += headings.len() * body;
+    }
+
+    /// Fill in every `[{ ref }]`, now every label is known
+    fn resolve_references(&mut self, spans: &Spans) -> Result<()> {
+        let described: BTreeMap<Target, (Option<String>, String)> = self.labels.values()
+            .map(|&target| (target, self.describe(target)))
+            .collect();
+        let describe = |target: Target| described[&target].clone();
+        let notes = self.notes.iter_mut().flat_map(|note| note.paragraphs.iter_mut());
+        for paragraph in self.paragraphs.iter_mut().chain(notes) {
+            if let Some(label) = paragraph.resolve(&self.labels, &describe) {
+                return Err(located(EvaluationKind::UnresolvedLabel(label.clone()), spans.reference(&label)));
+            }
+        }
+        Ok(())
+    }
+
+    /// The number, if it has one, and title of what a label is on
+    fn describe(&self, target: Target) -> (Option<String>, String) {
+        let (paragraph, number) = match target {
+            Target::Paragraph(i) => {
+                let paragraph = self.paragraphs.get(i);
+                (paragraph, paragraph.and_then(Paragraph::number).map(|n| n.to_owned()))
+            }
+            Target::Note(n) => {
+                let footnote = self.notes.iter().find(|note| note.number == n).and_then(|note| note.paragraphs.first());
+                let endnote = self.paragraphs.iter().find(|p| p.note == Some(n));
+                (footnote.or(endnote), Some(n.to_string()))
+            }
+        };
+        // Headings and notes start with their numbers, and tables' and figures' captions with
+        // their names too
+        let text = paragraph.map_or("", Paragraph::text);
+        let captions = self.strategy.captions();
+        let prefix = match (&number, paragraph) {
+            (&Some(ref n), Some(p)) if p.table.is_some() => captions.table_prefix(n),
+            (&Some(ref n), Some(p)) if p.figure.is_some() => captions.figure_prefix(n),
+            (&Some(ref n), _) => n.clone(),
+            (&None, _) => String::new(),
+        };
+        let title = if text.starts_with(&prefix[..]) { text[prefix.len() ..].trim_start().to_owned() } else { text.to_owned() };
+        (number, title)
+    }
+
+    /// Warn, once each, about languages which can't be hyphenated
+    fn check_languages(&mut self, spans: &Spans) {
+        let mut unknown: Vec<String> = self.paragraphs.iter()
+            .map(|p| p.style.language.clone())
+            .filter(|l| !hyphenate::is_supported(l))
+            .collect();
+        unknown.sort();
+        unknown.dedup();
+        for language in unknown {
+            let msg = format!("there are no hyphenation patterns for ::language {}, so it won't be hyphenated", language);
+            self.diagnostics.warn(diagnostic::UNKNOWN_LANGUAGE, spans.key("language"), msg);
+        }
+    }
+
+    /// Warn about keys and meta values the strategy doesn't know.
+    /// The empty strategy knows nothing, so it lets everything through.
+    fn check_keys(&mut self, expr: &Expression, spans: &Spans) {
+        if self.strategy.name() == "none" {
+            return;
+        }
+        for key in self.meta.keys() {
+            if !self.strategy.meta_fields().iter().any(|f| f.name() == key) {
+                let msg = format!("::{} isn't a meta field of the {} strategy", key, self.strategy.name());
+                self.diagnostics.warn(diagnostic::UNUSED_META, spans.meta(key), msg);
+            }
+        }
+        for (key, _) in expr.props() {
+            let known = DOCUMENT_KEYS.contains(&key)
+                || STYLE_KEYS.contains(&key)
+                || self.strategy.fields().iter().any(|f| f.name() == key);
+            if !known {
+                let msg = format!("::{} isn't understood by the {} strategy", key, self.strategy.name());
+                self.diagnostics.warn(diagnostic::UNKNOWN_KEY, spans.key(key), msg);
+            }
+        }
+    }
+
+    /// Inline formatting is kept in the paragraph, and other expressions only keep their text.
+    /// An expression which changes the style is set apart in paragraphs of its own.
+    fn add_body_node(&mut self, node: &Node, paragraph: &mut Pending, style: &ComputedStyle, spans: &Spans)
+        -> Result<()>
+    {
+        let text = match *node {
+            Node::Atom(ref s) => s.clone(),
+            Node::Var(ref v) => match self.variable(v) {
+                Some(value) => value,
+                None => {
+                    // Special variables are filled in later, by the renderer
+                    if !v.starts_with("special/") {
+                        let msg = format!("$${} has no value", v);
+                        let kind = EvaluationKind::UndefinedVariable(v.clone());
+                        self.diagnostics.warn_of(diagnostic::UNDEFINED_VARIABLE, kind, spans.variable(v), msg);
+                    }
+                    format!("$${}", v)
+                }
+            },
+            Node::Expr(ref e) if e.title() == Some("page-break") || e.title() == Some("section-break") => {
+                self.end_paragraph(paragraph, style);
+                paragraph.break_before = true;
+                paragraph.starts_section |= e.title() == Some("section-break");
+                return Ok(());
+            }
+            Node::Expr(ref e) if e.title() == Some("toc") => {
+                self.end_paragraph(paragraph, style);
+                let settings = self.strategy.text_settings();
+                let base = settings.style("toc").map_or_else(|| style.clone(), |toc| toc.apply(style));
+                self.contents.push(Contents {
+                    at: self.here(),
+                    style: try!(base.enter(e, settings)),
+                    break_before: paragraph.break_before,
+                    starts_section: paragraph.starts_section,
+                });
+                *paragraph = Pending::default();
+                return Ok(());
+            }
+            Node::Expr(ref e) if e.title() == Some("term") => {
+                let term = text_of(e);
+                let definition = e.prop("def").and_then(Node::as_text).unwrap_or("").to_owned();
+                self.glossary.entry(term.to_lowercase()).or_insert((term, definition));
+                if let Some(last) = e.sections().last() {
+                    for inner in &last.content {
+                        try!(self.add_body_node(inner, paragraph, style, spans));
+                    }
+                }
+                return Ok(());
+            }
+            Node::Expr(ref e) if e.title() == Some("index") && !text_of(e).is_empty() => {
+                let entry = text_of(e);
+                // Where the paragraph being gathered will go
+                let at = self.here();
+                self.index.entry(entry.to_lowercase()).or_insert_with(|| (entry, Vec::new())).1.push(at);
+                return Ok(());
+            }
+            Node::Expr(ref e) if e.title() == Some("index") || e.title() == Some("glossary") => {
+                self.end_paragraph(paragraph, style);
+                let name = e.title().unwrap_or("");
+                let settings = self.strategy.text_settings();
+                let base = settings.style(name).map_or_else(|| style.clone(), |named| named.apply(style));
+                let inner = try!(base.enter(e, settings));
+                let entries: Vec<Paragraph> = if name == "index" {
+                    self.index.values().map(|&(ref entry, ref at)| {
+                        let mut listed = Paragraph::new(entry.clone(), inner.clone());
+                        listed.page_refs = at.clone();
+                        listed
+                    }).collect()
+                } else {
+                    self.glossary.values().map(|&(ref term, ref definition)| {
+                        let text = if definition.is_empty() { term.clone() } else { format!("{}: {}", term, definition) };
+                        Paragraph::new(text, inner.clone())
+                    }).collect()
+                };
+                for entry in entries {
+                    let entry = paragraph.take_breaks(entry);
+                    self.paragraphs.push(entry);
+                }
+                return Ok(());
+            }
+            Node::Expr(ref e) if e.title() == Some("label") => {
+                let label = text_of(e);
+                let target = match self.targets.last() {
+                    Some(&target) => target,
+                    None => return Err(located(EvaluationKind::MisplacedLabel(label.clone()), spans.label(&label))),
+                };
+                if self.labels.insert(label.clone(), target).is_some() {
+                    return Err(located(EvaluationKind::DuplicateLabel(label.clone()), spans.label(&label)));
+                }
+                return Ok(());
+            }
+            Node::Expr(ref e) if e.title() == Some("ref") => {
+                paragraph.push_ref(try!(Reference::from_expression(e, text_of(e))));
+                return Ok(());
+            }
+            Node::Expr(ref e) if e.title() == Some("list") => {
+                self.end_paragraph(paragraph, style);
+                let list = try!(List::from_expression(e));
+                let item_style = {
+                    let settings = self.strategy.text_settings();
+                    let base = settings.style("list").map_or_else(|| style.clone(), |named| named.apply(style));
+                    // A list's `::style` is how it's marked, not a named style
+                    let mut item_style = try!(base.enter(&without_prop(e, "style"), settings));
+                    let margin = item_style.margin_pt() + item_style.list_indent_pt();
+                    item_style.margin = Length::new(margin, Unit::Pt);
+                    item_style.indent = Length::new(0.0, Unit::Pt);
+                    item_style
+                };
+                self.list_depth += 1;
+                let items = e.sections().iter().filter(|section| section.content.iter().any(|node| match *node {
+                    Node::Prop(..) | Node::Blank => false,
+                    _ => true,
+                }));
+                for (n, item) in items.enumerate() {
+                    self.end_paragraph(paragraph, &item_style);
+                    paragraph.item = Some(ListItem {
+                        depth: self.list_depth,
+                        marker: Some(list.marker(n)),
+                        list: if n == 0 { Some(list.clone()) } else { None },
+                    });
+                    for inner in &item.content {
+                        try!(self.add_body_node(inner, paragraph, &item_style, spans));
+                    }
+                }
+                self.end_paragraph(paragraph, &item_style);
+                self.list_depth -= 1;
+                return Ok(());
+            }
+            Node::Expr(ref e) if e.title() == Some("table") => {
+                return self.add_table(e, paragraph, style, spans);
+            }
+            Node::Expr(ref e) if e.title() == Some("figure") => {
+                return self.add_figure(e, paragraph, style, spans);
+            }
+            Node::Expr(ref e) if e.title() == Some("if-exists") => {
+                // Every variable before the `||` must have a value
+                let exists = e.sections().first().map_or(true, |first| {
+                    first.content.iter().all(|n| match *n {
+                        Node::Var(ref v) => self.variable(v).is_some(),
+                        _ => true,
+                    })
+                });
+                if exists && e.sections().len() > 1 {
+                    if let Some(last) = e.sections().last() {
+                        for inner in &last.content {
+                            try!(self.add_body_node(inner, paragraph, style, spans));
+                        }
+                    }
+                }
+                return Ok(());
+            }
+            Node::Expr(ref e) if e.title() == Some("date") => {
+                let source = match e.prop("source") {
+                    Some(&Node::Var(ref v)) => self.variable(v),
+                    Some(&Node::Atom(ref s)) => Some(s.clone()),
+                    _ => Some(Date::today().to_string()),
+                };
+                let source = source.unwrap_or_default();
+                let format = e.prop("format").and_then(Node::as_text);
+                match (Date::parse(&source), format) {
+                    (Some(date), Some(format)) => date.format(format),
+                    // Anything that isn't a date is printed as it is
+                    _ => source,
+                }
+            }
+            Node::Expr(ref e) if Format::from_title(e.title()).is_some() => {
+                let format = Format::from_title(e.title()).unwrap_or(Format::Italic);
+                // Superscript is a mark on the word before it, like a note's number
+                paragraph.joined = format == Format::Superscript;
+                paragraph.open(format);
+                if let Some(last) = e.sections().last() {
+                    for inner in &last.content {
+                        try!(self.add_body_node(inner, paragraph, style, spans));
+                    }
+                }
+                paragraph.close();
+                return Ok(());
+            }
+            Node::Expr(ref e) if e.title() == Some("note") => {
+                let number = self.notes.len() + 1;
+                self.notes.push(Note { number: number, paragraphs: Vec::new() });
+                paragraph.push_note(number);
+                let note_style = {
+                    let settings = self.strategy.text_settings();
+                    let mut base = settings.style("note").map_or_else(|| style.clone(), |note| note.apply(style));
+                    base.keep_with_next = false;
+                    try!(base.enter(e, settings))
+                };
+                // The note's paragraphs are gathered apart from the text around it
+                let ((), mut paragraphs) = try!(self.apart(|doc| {
+                    let mut text = Pending::default();
+                    text.open(Format::Superscript);
+                    text.push_text(&number.to_string());
+                    text.close();
+                    doc.targets.push(Target::Note(number));
+                    if let Some(last) = e.sections().last() {
+                        for inner in &last.content {
+                            try!(doc.add_body_node(inner, &mut text, &note_style, spans));
+                        }
+                    }
+                    doc.targets.pop();
+                    doc.end_paragraph(&mut text, &note_style);
+                    Ok(())
+                }));
+                if let Some(first) = paragraphs.first_mut() {
+                    first.note = Some(number);
+                }
+                self.notes[number - 1].paragraphs = paragraphs;
+                return Ok(());
+            }
+            Node::Expr(ref e) if heading_level(e.title()).is_some() => {
+                let title: Vec<&Node> = e.sections().last().map_or_else(Vec::new, |last| last.content.iter().collect());
+                return self.add_heading(e, heading_level(e.title()).unwrap_or(1), &title, paragraph, style, spans);
+            }
+            Node::Expr(ref e) if e.title() == Some("section") => {
+                self.depth += 1;
+                let title: Vec<&Node> = e.prop("title").into_iter().collect();
+                self.end_paragraph(paragraph, style);
+                // Labels in the section name its heading
+                let heading = Target::Paragraph(self.here());
+                try!(self.add_heading(e, self.depth.min(MAX_LEVEL), &title, paragraph, style, spans));
+                let inner_style = try!(style.enter(e, self.strategy.text_settings()));
+                self.targets.push(heading);
+                if e.sections().len() > 1 {
+                    if let Some(last) = e.sections().last() {
+                        for inner in &last.content {
+                            try!(self.add_body_node(inner, paragraph, &inner_style, spans));
+                        }
+                    }
+                }
+                self.targets.pop();
+                self.end_paragraph(paragraph, &inner_style);
+                self.depth -= 1;
+                return Ok(());
+            }
+            Node::Expr(ref e) => {
+                let settings = self.strategy.text_settings();
+                if let Some(title) = e.title() {
+                    let understood = STYLE_KEYS.contains(&title) || settings.style(title).is_some();
+                    if !understood && e.props().is_empty() {
+                        let msg = format!("nothing understands [{{ {} }}], so only its text is kept", title);
+                        let kind = EvaluationKind::UnknownExpression(title.to_owned());
+                        self.diagnostics.warn_of(diagnostic::UNKNOWN_EXPRESSION, kind, spans.title(title), msg);
+                    }
+                }
+                let inner_style = try!(style.enter(e, settings));
+                let restyled = inner_style != *style;
+                if restyled {
+                    self.end_paragraph(paragraph, style);
+                }
+                if let Some(last) = e.sections().last() {
+                    for inner in &last.content {
+                        try!(self.add_body_node(inner, paragraph, &inner_style, spans));
+                    }
+                }
+                if restyled {
+                    self.end_paragraph(paragraph, &inner_style);
+                }
+                return Ok(());
+            }
+            Node::Blank => {
+                self.end_paragraph(paragraph, style);
+                return Ok(());
+            }
+            Node::Prop(..) => return Ok(()),
+        };
+        paragraph.push_text(&text);
+        Ok(())
+    }
+
+    /// Set a heading, numbered unless it's `::numbered false`, in the strategy's style for its
+    /// level, kept with what follows
+    fn add_heading(&mut self, expr: &Expression, level: usize, title: &[&Node], paragraph: &mut Pending,
+                   style: &ComputedStyle, spans: &Spans) -> Result<()>
+    {
+        self.end_paragraph(paragraph, style);
+        let heading_style = try!(self.heading_style(level, style).enter(expr, self.strategy.text_settings()));
+        if try!(numbered(expr)) {
+            self.counts.resize(level, 0);
+            self.counts[level - 1] += 1;
+            paragraph.number = self.strategy.numbering().number(&self.counts);
+            if let Some(number) = paragraph.number.clone() {
+                paragraph.push_text(&number);
+            }
+        }
+        paragraph.heading = Some(level);
+        self.targets.push(Target::Paragraph(self.here()));
+        for node in title {
+            try!(self.add_body_node(node, paragraph, &heading_style, spans));
+        }
+        self.targets.pop();
+        self.end_paragraph(paragraph, &heading_style);
+        paragraph.heading = None;
+        Ok(())
+    }
+
+    /// Set a table as a paragraph of its caption, numbered unless it's `::numbered false`, with its
+    /// rows of cells
+    fn add_table(&mut self, expr: &Expression, paragraph: &mut Pending, style: &ComputedStyle, spans: &Spans)
+        -> Result<()>
+    {
+        let align = try!(table::alignments(expr));
+        let widths = try!(table::widths(expr));
+        self.add_captioned(Captioned::Table, expr, paragraph, style, |doc, table_style, caption, caption_style| {
+            let mut rows = Vec::new();
+            let mut grid = Grid::default();
+            if expr.sections().len() > 1 {
+                if let Some(last) = expr.sections().last() {
+                    for node in &last.content {
+                        match *node {
+                            Node::Expr(ref row) if row.title() == Some("row") => {
+                                rows.push(try!(doc.add_row(row, table_style, &align, &mut grid, spans)));
+                            }
+                            // Rows are often set apart by blank lines, which don't end the caption
+                            Node::Blank => {}
+                            _ => try!(doc.add_body_node(node, caption, caption_style, spans)),
+                        }
+                    }
+                }
+            }
+            Ok(Table::new(rows, &widths))
+        }, |paragraph, table| paragraph.table = Some(table))
+    }
+
+    /// Set a figure as a paragraph of its caption, numbered unless it's `::numbered false`, with its
+    /// image
+    fn add_figure(&mut self, expr: &Expression, paragraph: &mut Pending, style: &ComputedStyle, spans: &Spans)
+        -> Result<()>
+    {
+        let src = match expr.prop("src").and_then(Node::as_text) {
+            Some(src) => src.to_owned(),
+            None => {
+                let failure = EvaluationError::new(EvaluationKind::MissingField("src".to_owned()))
+                    .note("figures need an image, like ::src ''plot.png''");
+                return Err(failure.into());
+            }
+        };
+        let path = try!(self.resolver.resource(&src));
+        let image = try!(Image::open(&path));
+        self.add_captioned(Captioned::Figure, expr, paragraph, style, |doc, figure_style, caption, caption_style| {
+            let (width, height) = try!(figure::size(expr, &image, figure_style.size));
+            if expr.sections().len() > 1 {
+                if let Some(last) = expr.sections().last() {
+                    for node in &last.content {
+                        try!(doc.add_body_node(node, caption, caption_style, spans));
+                    }
+                }
+            }
+            Ok(Figure { src: src, path: path, image: image, width: width, height: height })
+        }, |paragraph, figure| paragraph.figure = Some(figure))
+    }
+
+    /// Set a table or figure as a paragraph of its caption, numbered unless it's `::numbered false`.
+    ///
+    /// `gather` reads the expression in the block's style, adding its caption to the pending
+    /// paragraph it's given in the caption's style, and `set` puts what it made in the paragraph.
+    fn add_captioned<T, G, S>(&mut self, kind: Captioned, expr: &Expression, paragraph: &mut Pending,
+                              style: &ComputedStyle, gather: G, set: S) -> Result<()>
+        where G: FnOnce(&mut Document, &ComputedStyle, &mut Pending, &ComputedStyle) -> Result<T>,
+              S: FnOnce(&mut Paragraph, T)
+    {
+        self.end_paragraph(paragraph, style);
+        let (block_style, caption_style) = {
+            let settings = self.strategy.text_settings();
+            let base = settings.style(kind.name()).map_or_else(|| style.clone(), |named| named.apply(style));
+            let block_style = try!(base.enter(expr, settings));
+            let caption_style = settings.style("caption").map_or_else(|| block_style.clone(), |named| named.apply(&block_style));
+            (block_style, caption_style)
+        };
+        let mut caption = Pending::default();
+        if try!(numbered(expr)) {
+            let count = match kind {
+                Captioned::Table => &mut self.tables,
+                Captioned::Figure => &mut self.figures,
+            };
+            *count += 1;
+            let number = count.to_string();
+            caption.push_text(&match kind {
+                Captioned::Table => self.strategy.captions().table_prefix(&number),
+                Captioned::Figure => self.strategy.captions().figure_prefix(&number),
+            });
+            caption.number = Some(number);
+        }
+        let list_item = self.list_item(paragraph);
+
+        // Labels in it name it, and what's in it is gathered apart from the text around it,
+        // outside any lists
+        let target = Target::Paragraph(self.here());
+        self.targets.push(target);
+        let list_depth = mem::replace(&mut self.list_depth, 0);
+        let gathered = self.apart(|doc| gather(doc, &block_style, &mut caption, &caption_style));
+        self.list_depth = list_depth;
+        self.targets.pop();
+        let (gathered, caption_before) = try!(gathered);
+
+        // Any of the caption set apart by a change of style goes before the block
+        for before in caption_before {
+            let before = paragraph.take_breaks(before);
+            self.paragraphs.push(before);
+        }
+        let at = Target::Paragraph(self.here());
+        for labelled in self.labels.values_mut().filter(|labelled| **labelled == target) {
+            *labelled = at;
+        }
+        let mut block = Paragraph::new(String::new(), caption_style);
+        block.inlines = caption.take_inlines();
+        block.text = caption.text;
+        block.number = caption.number;
+        block.list_item = list_item;
+        set(&mut block, gathered);
+        let block = paragraph.take_breaks(block);
+        self.paragraphs.push(block);
+        Ok(())
+    }
+
+    /// A table's `[{ row }]`, with a cell for each section after its first
+    fn add_row(&mut self, expr: &Expression, style: &ComputedStyle, align: &[Align], grid: &mut Grid, spans: &Spans)
+        -> Result<Row>
+    {
+        let header = match expr.prop("header").and_then(Node::as_value) {
+            Some(value) => try!(value.as_bool()),
+            None => false,
+        };
+        let row_style = try!(style.enter(expr, self.strategy.text_settings()));
+        let mut cells = Vec::new();
+        for section in expr.sections().iter().skip(1) {
+            // A `[{ cell }]` alone in its section is the cell, with its spans and style
+            let mut nodes = section.content.iter().filter(|node| match **node {
+                Node::Blank => false,
+                _ => true,
+            });
+            let cell = match (nodes.next(), nodes.next()) {
+                (Some(&Node::Expr(ref cell)), None) if cell.title() == Some("cell") => Some(cell),
+                _ => None,
+            };
+            let (colspan, rowspan) = match cell {
+                Some(cell) => try!(table::spans(cell)),
+                None => (1, 1),
+            };
+            let column = grid.place(colspan, rowspan);
+            let cell_style = {
+                // Cells are set in from their edges by nothing, and aren't indented
+                let mut base = row_style.clone();
+                base.margin = Length::new(0.0, Unit::Pt);
+                base.indent = Length::new(0.0, Unit::Pt);
+                if let Some(&align) = align.get(column) {
+                    base.align = align;
+                }
+                match cell {
+                    Some(cell) => try!(base.enter(cell, self.strategy.text_settings())),
+                    None => base,
+                }
+            };
+            let content = match cell {
+                Some(cell) if cell.sections().len() > 1 => &cell.sections()[cell.sections().len() - 1].content[..],
+                Some(_) => &[][..],
+                None => &section.content[..],
+            };
+
+            let ((), paragraphs) = try!(self.apart(|doc| {
+                let mut text = Pending::default();
+                if header {
+                    text.open(Format::Bold);
+                }
+                for node in content {
+                    try!(doc.add_body_node(node, &mut text, &cell_style, spans));
+                }
+                doc.end_paragraph(&mut text, &cell_style);
+                Ok(())
+            }));
+            cells.push(Cell { column: column, colspan: colspan, rowspan: rowspan, paragraphs: paragraphs });
+        }
+        grid.end_row();
+        Ok(Row { header: header, cells: cells })
+    }
+
+    /// Where the next paragraph goes in the main text. Paragraphs gathered apart from it, like
+    /// notes' and table cells', are where what they're part of is.
+    fn here(&self) -> usize {
+        self.pinned.unwrap_or(self.paragraphs.len())
+    }
+
+    /// Gather paragraphs apart from the text around them, returning what `gather` does and
+    /// the paragraphs it added
+    fn apart<T, F>(&mut self, gather: F) -> Result<(T, Vec<Paragraph>)>
+        where F: FnOnce(&mut Document) -> Result<T>
+    {
+        let here = self.here();
+        let pinned = mem::replace(&mut self.pinned, Some(here));
+        let around = mem::replace(&mut self.paragraphs, Vec::new());
+        let gathered = gather(self);
+        self.pinned = pinned;
+        let paragraphs = mem::replace(&mut self.paragraphs, around);
+        gathered.map(|gathered| (gathered, paragraphs))
+    }
+
+    /// A heading's style: the strategy's for its level, kept with the next paragraph
+    fn heading_style(&self, level: usize, style: &ComputedStyle) -> ComputedStyle {
+        let mut base = style.clone();
+        base.keep_with_next = true;
+        match self.strategy.text_settings().style(&format!("h{}", level)) {
+            Some(named) => named.apply(&base),
+            None => base,
+        }
+    }
+
+    fn end_paragraph(&mut self, paragraph: &mut Pending, style: &ComputedStyle) {
+        if !paragraph.text.is_empty() {
+            self.paragraphs.push(Paragraph {
+                text: paragraph.text.clone(),
+                inlines: paragraph.take_inlines(),
+                style: style.clone(),
+                break_before: paragraph.break_before,
+                starts_section: paragraph.starts_section,
+                heading: paragraph.heading,
+                number: paragraph.number.take(),
+                note: None,
+                list_item: self.list_item(paragraph),
+                contents_entry: None,
+                page_refs: Vec::new(),
+                table: None,
+                figure: None,
+            });
+            // A heading split by a change of style carries on as the same heading, and formats
+            // carry on into the next paragraph
+            *paragraph = Pending {
+                heading: paragraph.heading,
+                open: mem::replace(&mut paragraph.open, Vec::new()),
+                ..Pending::default()
+            };
+        }
+    }
+
+    /// Where the paragraph being gathered is in the lists around it
+    fn list_item(&self, paragraph: &mut Pending) -> Option<ListItem> {
+        // Paragraphs after an item's first carry on in its list
+        paragraph.item.take().or_else(|| if self.list_depth > 0 {
+            Some(ListItem { depth: self.list_depth, marker: None, list: None })
+        } else {
+            None
+        })
+    }
+
+    /// The strategy this document was built with
+    pub fn strategy(&self) -> &Strategy {
+        &self.strategy
+    }
+
+    /// The document's title, from `::meta [{ ::title ... }]`
+    pub fn title(&self) -> Option<String> {
+        self.variable("meta/title")
+    }
+
+    /// Every paragraph, in order: the frontmatter's, the body's, then the backmatter's
+    pub fn paragraphs(&self) -> &[Paragraph] {
+        &self.paragraphs
+    }
+
+    /// Notes set as footnotes, which aren't among the paragraphs. Renderers with pages set them
+    /// at the foot of the page they're referred to on, and others after everything else.
+    pub fn footnotes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    /// Every heading, as a tree
+    pub fn outline(&self) -> Vec<OutlineEntry> {
+        outline::outline(&self.paragraphs)
+    }
+
+    /// Paragraphs of frontmatter, as the strategy's `::frontmatter` sets them for this document
+    pub fn frontmatter(&self) -> &[Paragraph] {
+        &self.paragraphs[.. self.body_start]
+    }
+
+    /// Paragraphs of body text
+    pub fn body(&self) -> &[Paragraph] {
+        &self.paragraphs[self.body_start .. self.body_end]
+    }
+
+    /// Paragraphs of backmatter, as the strategy's `::backmatter` sets them for this document
+    pub fn backmatter(&self) -> &[Paragraph] {
+        &self.paragraphs[self.body_end ..]
+    }
+
+    /// The paper size and margins, from the strategy's `page` field
+    pub fn page(&self) -> &PageSetup {
+        &self.page
+    }
+
+    /// The included file a span is in, or `None` if it's in the document's own file
+    pub fn file_of(&self, span: TextSpan) -> Option<&Path> {
+        span.file.checked_sub(1).and_then(|i| self.files.get(i)).map(|f| f.as_path())
+    }
+
+    /// Warnings found while building the document
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// Resolve a variable, given without its `$$`, like `meta/lastname`.
+    ///
+    /// Meta variables missing from the document fall back on the strategy's default.
+    /// Variables which only make sense while rendering (like `special/page-no`) resolve to None.
+    pub fn variable(&self, name: &str) -> Option<String> {
+        let mut parts = name.splitn(2, '/');
+        match (parts.next(), parts.next()) {
+            (Some("meta"), Some(key)) => {
+                if let Some(value) = self.meta.get(key) {
+                    return Some(value.clone());
+                }
+                let default = self.strategy.meta_fields().iter()
+                    .find(|f| f.name() == key)
+                    .and_then(Field::default);
+                match default {
+                    Some(d) if d.starts_with("$$") => self.variable(&d[2 ..]),
+                    Some(d) => Some(d.to_owned()),
+                    None => None,
+                }
+            }
+            (Some("special"), Some("today")) => Some(Date::today().to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// A paragraph of body text, and the style it's set in
+#[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Paragraph {
+    text: String,
+    inlines: Vec<Inline>,
+    style: ComputedStyle,
+    break_before: bool,
+    starts_section: bool,
+    heading: Option<usize>,
+    number: Option<String>,
+    note: Option<usize>,
+    list_item: Option<ListItem>,
+    contents_entry: Option<usize>,
+    page_refs: Vec<usize>,
+    table: Option<Table>,
+    figure: Option<Figure>,
+}
+
+impl Paragraph {
+    /// A paragraph of text in a style, with no breaks before it
+    pub fn new(text: String, style: ComputedStyle) -> Paragraph {
+        Paragraph {
+            inlines: if text.is_empty() { Vec::new() } else { vec![Inline::Text(text.clone())] },
+            text: text,
+            style: style,
+            break_before: false,
+            starts_section: false,
+            heading: None,
+            number: None,
+            note: None,
+            list_item: None,
+            contents_entry: None,
+            page_refs: Vec::new(),
+            table: None,
+            figure: None,
+        }
+    }
+
+    /// The same paragraph, with other unformatted text
+    pub fn with_text(&self, text: String) -> Paragraph {
+        Paragraph { inlines: vec![Inline::Text(text.clone())], text: text, ..self.clone() }
+    }
+
+    /// Point it at paragraphs which have moved
+    fn moved<F: Fn(usize) -> usize>(mut self, moved: &F) -> Paragraph {
+        self.contents_entry = self.contents_entry.map(|i| moved(i));
+        self.page_refs = self.page_refs.iter().map(|&i| moved(i)).collect();
+        self
+    }
+
+    /// The paragraph's text, without its formatting
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The paragraph's text, with its formatting
+    pub fn inlines(&self) -> &[Inline] {
+        &self.inlines
+    }
+
+    /// The paragraph's text, in runs of the same formatting
+    pub fn runs(&self) -> Vec<Run> {
+        inline::runs(&self.inlines)
+    }
+
+    /// The numbers of the notes it refers to, in order, and then those its cells refer to
+    pub fn notes(&self) -> Vec<usize> {
+        let mut notes = inline::notes(&self.inlines);
+        if let Some(ref table) = self.table {
+            notes.extend(table.paragraphs().into_iter().flat_map(Paragraph::notes));
+        }
+        notes
+    }
+
+    /// If it starts a note's text, the note's number
+    pub fn note(&self) -> Option<usize> {
+        self.note
+    }
+
+    /// Where it is in a list, if it's in one
+    pub fn list_item(&self) -> Option<&ListItem> {
+        self.list_item.as_ref()
+    }
+
+    /// If it's a table's caption, the table
+    pub fn table(&self) -> Option<&Table> {
+        self.table.as_ref()
+    }
+
+    /// If it's a figure's caption, the figure
+    pub fn figure(&self) -> Option<&Figure> {
+        self.figure.as_ref()
+    }
+
+    /// The style it's set in
+    pub fn style(&self) -> &ComputedStyle {
+        &self.style
+    }
+
+    /// Whether it follows a `[{page-break}]` or `[{section-break}]`
+    pub fn break_before(&self) -> bool {
+        self.break_before
+    }
+
+    /// Whether it follows a `[{section-break}]`, so starts a section with its own page numbers
+    pub fn starts_section(&self) -> bool {
+        self.starts_section
+    }
+
+    /// Its level, from 1, if it's a heading like `[{ h1 || ... }]`
+    pub fn heading(&self) -> Option<usize> {
+        self.heading
+    }
+
+    /// A heading's number, which its text starts with, if it's numbered
+    pub fn number(&self) -> Option<&str> {
+        self.number.as_ref().map(|n| &n[..])
+    }
+
+    /// If it's an entry in a table of contents, the index in `Document::paragraphs` of the heading
+    /// it lists
+    pub fn contents_entry(&self) -> Option<usize> {
+        self.contents_entry
+    }
+
+    /// For index entries, the indexes in `Document::paragraphs` of the paragraphs whose pages it
+    /// lists
+    pub fn page_refs(&self) -> &[usize] {
+        &self.page_refs
+    }
+
+    /// Whether it or its cells have `[{ ref ::show page }]`s, which outputs with pages set again
+    /// once they're known
+    pub fn refers_to_pages(&self) -> bool {
+        reference::refers_to_pages(&self.inlines)
+            || self.table.as_ref().map_or(false, |table| table.paragraphs().iter().any(|p| p.refers_to_pages()))
+    }
+
+    /// The same paragraph, with its and its cells' page references set to the pages their
+    /// targets start on
+    pub fn with_pages<F: Fn(Target) -> Option<usize>>(&self, page: &F) -> Paragraph {
+        let mut paragraph = self.clone();
+        paragraph.set_pages(page);
+        paragraph
+    }
+
+    fn set_pages<F: Fn(Target) -> Option<usize>>(&mut self, page: &F) {
+        reference::set_pages(&mut self.inlines, page);
+        self.text = inline::plain(&self.inlines);
+        if let Some(ref mut table) = self.table {
+            for cell in table.paragraphs_mut() {
+                cell.set_pages(page);
+            }
+        }
+    }
+
+    /// Fill in its and its cells' references. Returns the first label nothing is named, if
+    /// there is one.
+    fn resolve<F>(&mut self, labels: &BTreeMap<String, Target>, describe: &F) -> Option<String>
+        where F: Fn(Target) -> (Option<String>, String)
+    {
+        let mut unresolved = None;
+        if reference::has_references(&self.inlines) {
+            unresolved = reference::resolve(&mut self.inlines, labels, describe);
+            self.text = inline::plain(&self.inlines);
+        }
+        if let Some(ref mut table) = self.table {
+            for cell in table.paragraphs_mut() {
+                let missing = cell.resolve(labels, describe);
+                unresolved = unresolved.or(missing);
+            }
+        }
+        unresolved
+    }
+}
+
+/// A paragraph being gathered
+#[derive(Debug)]
+#[derive(Default)]
+struct Pending {
+    text: String,
+    /// The text, with the formats closed so far
+    inlines: Vec<Inline>,
+    /// Formats still open, innermost last, with the text in each
+    open: Vec<(Format, Vec<Inline>)>,
+    /// Whether the next text follows on without a space
+    joined: bool,
+    break_before: bool,
+    starts_section: bool,
+    heading: Option<usize>,
+    number: Option<String>,
+    /// The list item the next paragraph starts
+    item: Option<ListItem>,
+}
+
+impl Pending {
+    /// Add a word or words, after a space unless it starts the paragraph, follows on, or starts
+    /// with closing punctuation
+    fn push_text(&mut self, text: &str) {
+        let spaced = !self.text.is_empty() && !self.joined && !text.starts_with(CLOSING_PUNCTUATION);
+        self.joined = false;
+        if spaced {
+            self.text.push(' ');
+        }
+        self.text.push_str(text);
+        self.add(Inline::Text(if spaced { format!(" {}", text) } else { text.to_owned() }));
+    }
+
+    /// Refer to a label, like a word. Its text is only known once every label is, when the
+    /// paragraph's text is filled in again.
+    fn push_ref(&mut self, reference: Reference) {
+        if !self.text.is_empty() && !self.joined {
+            self.text.push(' ');
+            self.add(Inline::Text(" ".to_owned()));
+        }
+        self.joined = false;
+        self.text.push_str(&reference.label);
+        self.add(Inline::Ref(reference));
+    }
+
+    /// Refer to a note, straight after the text before
+    fn push_note(&mut self, number: usize) {
+        self.text.push_str(&number.to_string());
+        self.add(Inline::Note(number));
+    }
+
+    /// Add to the innermost open format, joining text to the text before it
+    fn add(&mut self, inline: Inline) {
+        let inlines = match self.open.last_mut() {
+            Some(&mut (_, ref mut inner)) => inner,
+            None => &mut self.inlines,
+        };
+        if let Inline::Text(ref text) = inline {
+            if let Some(&mut Inline::Text(ref mut last)) = inlines.last_mut() {
+                last.push_str(text);
+                return;
+            }
+        }
+        inlines.push(inline);
+    }
+
+    /// Start text in a format
+    fn open(&mut self, format: Format) {
+        self.open.push((format, Vec::new()));
+    }
+
+    /// End the innermost format. The space before its first word goes before it instead.
+    fn close(&mut self) {
+        let (format, mut inner) = match self.open.pop() {
+            Some(open) => open,
+            None => return,
+        };
+        let mut spaced = false;
+        if let Some(&mut Inline::Text(ref mut first)) = inner.first_mut() {
+            if first.starts_with(' ') {
+                first.remove(0);
+                spaced = true;
+            }
+        }
+        if inner.first() == Some(&Inline::Text(String::new())) {
+            inner.remove(0);
+        }
+        if spaced {
+            self.add(Inline::Text(" ".to_owned()));
+        }
+        if !inner.is_empty() {
+            self.add(Inline::Styled(format, inner));
+        }
+    }
+
+    /// Take the text so far, closing every format, and opening them again for what follows
+    fn take_inlines(&mut self) -> Vec<Inline> {
+        let formats: Vec<Format> = self.open.iter().map(|&(format, _)| format).collect();
+        for _ in &formats {
+            self.close();
+        }
+        self.open = formats.into_iter().map(|format| (format, Vec::new())).collect();
+        mem::replace(&mut self.inlines, Vec::new())
+    }
+
+    /// Give a paragraph the breaks waiting for it, if there are any
+    fn take_breaks(&mut self, mut paragraph: Paragraph) -> Paragraph {
+        paragraph.break_before |= mem::replace(&mut self.break_before, false);
+        paragraph.starts_section |= mem::replace(&mut self.starts_section, false);
+        paragraph
+    }
+}
+
+/// Where a `[{ toc }]` was, and how its entries are set
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct Contents {
+    /// The paragraph its entries go before
+    at: usize,
+    style: ComputedStyle,
+    break_before: bool,
+    starts_section: bool,
+}
+
+/// Blocks set as a paragraph of their caption
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+enum Captioned {
+    Table,
+    Figure,
+}
+
+impl Captioned {
+    /// The strategy's style for it
+    fn name(self) -> &'static str {
+        match self {
+            Captioned::Table => "table",
+            Captioned::Figure => "figure",
+        }
+    }
+}
+
+/// Whether an expression is numbered, which it is unless it's `::numbered false`
+fn numbered(expr: &Expression) -> Result<bool> {
+    match expr.prop("numbered").and_then(Node::as_value) {
+        Some(value) => value.as_bool(),
+        None => Ok(true),
+    }
+}
+
+/// The words in an expression, like `entry` in `[{ index ''entry'' }]` or `word` in
+/// `[{ term || word }]`
+fn text_of(expr: &Expression) -> String {
+    let words: Vec<&str> = expr.sections().iter()
+        .flat_map(|section| section.content.iter().filter_map(Node::as_text))
+        .collect();
+    words.join(" ")
+}
+
+/// An expression without one of its properties
+fn without_prop(expr: &Expression, key: &str) -> Expression {
+    let mut expr = expr.clone();
+    for section in &mut expr.sections {
+        section.content.retain(|node| match *node {
+            Node::Prop(ref k, _) => k != key,
+            _ => true,
+        });
+    }
+    expr
+}
+
+/// An evaluation error, where it happened if that's known
+fn located(kind: EvaluationKind, span: Option<TextSpan>) -> Error {
+    let failure = EvaluationError::new(kind);
+    match span {
+        Some(span) => failure.at(span).into(),
+        None => failure.into(),
+    }
+}
+
+/// The level of a heading expression, `h1` to `h6`
+fn heading_level(title: Option<&str>) -> Option<usize> {
+    match title {
+        Some(t) if t.starts_with('h') => t[1 ..].parse().ok().filter(|&level| level >= 1 && level <= MAX_LEVEL),
+        _ => None,
+    }
+}
+
+/// The page a document asks for with its strategy's page field (the one of `::kind page`), or that
+/// field's default. Strategies without one still let documents give `::page`.
+fn page_setup(expr: &Expression, strategy: &Strategy) -> Result<PageSetup> {
+    let field = strategy.fields().iter().find(|f| f.kind() == "page");
+    let name = field.map_or("page", Field::name);
+    let given = expr.prop(name).and_then(Node::as_text).or_else(|| field.and_then(Field::default));
+    match given {
+        Some(text) => PageSetup::parse(&Value::parse(text)),
+        None => Ok(PageSetup::default()),
+    }
 }
 
 /// A document strategy is a template used for handling certain features of a document.
 /// The strategy contains default formatting information, as well as meta-fields.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Strategy {
     /// The name of the strategy.
     name: String,
@@ -39,10 +1379,18 @@ pub struct Strategy {
     fields: Vec<Field>,
     /// Default text formatting stuff.
     text_settings: TextSettings,
+    /// Hyphenation limits and exceptions
+    hyphenation: Hyphenation,
+    /// How headings are numbered
+    numbering: Numbering,
+    /// Where notes are set
+    notes: Notes,
+    /// What numbered tables and figures are called
+    captions: Captions,
     /// Header format, if any
-    header: Option<Header>,
+    header: Option<Running>,
     /// Footer format, if any
-    footer: Option<Footer>,
+    footer: Option<Running>,
     /// Frontmatter, if any
     frontmatter: Option<Frontmatter>,
     /// Backmatter, if any
@@ -50,7 +1398,11 @@ pub struct Strategy {
     /// Bibliography, if any
     bibliography: Option<Bibliography>,
     /// Body formatter
-    body: Body
+    body: Body,
+    /// Problems found while reading the strategy
+    diagnostics: Diagnostics,
+    /// The file it was read from, if it was
+    path: Option<PathBuf>,
 }
 
 impl Strategy {
@@ -58,60 +1410,544 @@ impl Strategy {
     pub fn new(source: &str) -> Result<Strategy> {
         let lexer = Lexer::new(source);
         let ast = try!(parser::parse(lexer));
-        let expr = Expression::from_ast(ast).unwrap();
-        println!("{:#?}", expr);
-        let s = Strategy {
-            name: "".to_owned(),
+        Strategy::from_ast(ast)
+    }
+
+    /// Read a strategy from a file, with its includes found relative to it
+    pub fn open(path: &Path) -> Result<Strategy> {
+        let source = try!(include::read(path));
+        let ast = try!(parser::parse(Lexer::new(&source)));
+        let resolver = Resolver::new(path.parent().unwrap_or(Path::new(".")));
+        let mut strategy = try!(Strategy::from_ast(try!(include::expand(ast, &resolver))));
+        strategy.path = Some(path.to_owned());
+        Ok(strategy)
+    }
+
+    /// Create a strategy from an already parsed source.
+    /// Includes should already be expanded, see `include::expand`.
+    pub fn from_ast(ast: Ast) -> Result<Strategy> {
+        let spans = Spans::new(&ast);
+        let expr = try!(Expression::from_ast(ast));
+        let mut s = Strategy::none();
+        for (key, _) in expr.props() {
+            if !STRATEGY_KEYS.contains(&key) {
+                let msg = format!("::{} isn't something strategies have", key);
+                s.diagnostics.warn(diagnostic::UNKNOWN_KEY, spans.key(key), msg);
+            }
+        }
+        if let Some(name) = expr.prop("name").and_then(Node::as_text) {
+            s.name = name.to_owned();
+        }
+        s.meta = try!(Field::list(expr.prop("metafields")));
+        s.fields = try!(Field::list(expr.prop("fields")));
+        if let Some(settings) = expr.prop("text-settings").and_then(Node::as_expr) {
+            s.text_settings = try!(TextSettings::from_expression(settings));
+        }
+        if let Some(hyphenation) = expr.prop("hyphenation").and_then(Node::as_expr) {
+            s.hyphenation = try!(Hyphenation::from_expression(hyphenation));
+        }
+        if let Some(headings) = expr.prop("headings").and_then(Node::as_expr) {
+            s.numbering = try!(Numbering::from_expression(headings));
+        }
+        if let Some(notes) = expr.prop("notes").and_then(Node::as_expr) {
+            s.notes = try!(Notes::from_expression(notes));
+        }
+        if let Some(captions) = expr.prop("captions").and_then(Node::as_expr) {
+            s.captions = Captions::from_expression(captions);
+        }
+        if let Some(header) = expr.prop("header") {
+            s.header = try!(Running::from_node(header, &s.text_settings, "border-top"));
+        }
+        if let Some(footer) = expr.prop("footer") {
+            s.footer = try!(Running::from_node(footer, &s.text_settings, "border-bottom"));
+        }
+        if let Some(frontmatter) = expr.prop("frontmatter") {
+            s.frontmatter = try!(Frontmatter::from_node(frontmatter));
+        }
+        if let Some(backmatter) = expr.prop("backmatter") {
+            s.backmatter = try!(Backmatter::from_node(backmatter));
+        }
+        Ok(s)
+    }
+
+    /// The empty strategy, used by documents with `::strategy none`
+    pub fn none() -> Strategy {
+        Strategy {
+            name: "none".to_owned(),
             meta: Vec::new(),
             fields: Vec::new(),
-            text_settings: TextSettings,
+            text_settings: TextSettings::new(),
+            hyphenation: Hyphenation::default(),
+            numbering: Numbering::default(),
+            notes: Notes::default(),
+            captions: Captions::default(),
             header: None,
             footer: None,
             frontmatter: None,
             backmatter: None,
             bibliography: None,
             body: Body,
-        };
-        Ok(s)
+            diagnostics: Diagnostics::new(),
+            path: None,
+        }
+    }
+
+    /// The name of the strategy
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Fields which documents fill in under `::meta`
+    pub fn meta_fields(&self) -> &[Field] {
+        &self.meta
+    }
+
+    /// Top level fields documents may give
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Named styles
+    pub fn text_settings(&self) -> &TextSettings {
+        &self.text_settings
+    }
+
+    /// How words are hyphenated
+    pub fn hyphenation(&self) -> &Hyphenation {
+        &self.hyphenation
+    }
+
+    /// How headings are numbered
+    pub fn numbering(&self) -> &Numbering {
+        &self.numbering
+    }
+
+    /// Where notes are set
+    pub fn notes(&self) -> &Notes {
+        &self.notes
+    }
+
+    /// What numbered tables and figures are called
+    pub fn captions(&self) -> &Captions {
+        &self.captions
+    }
+
+    /// The running header, if there is one
+    pub fn header(&self) -> Option<&Running> {
+        self.header.as_ref()
+    }
+
+    /// The running footer, if there is one
+    pub fn footer(&self) -> Option<&Running> {
+        self.footer.as_ref()
+    }
+
+    /// The frontmatter, if there is any
+    pub fn frontmatter(&self) -> Option<&Frontmatter> {
+        self.frontmatter.as_ref()
+    }
+
+    /// The backmatter, if there is any
+    pub fn backmatter(&self) -> Option<&Backmatter> {
+        self.backmatter.as_ref()
+    }
+
+    /// Warnings found while reading the strategy
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// The file it was read from, if it was
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(|path| path.as_path())
     }
 }
 
+/// Where things are in a source, since expression trees don't keep spans
+#[derive(Default)]
+struct Spans {
+    /// Top level `::keys`
+    keys: Vec<(String, TextSpan)>,
+    /// Keys in the `::meta` block
+    meta: Vec<(String, TextSpan)>,
+    /// Every `$$variable`
+    variables: Vec<(String, TextSpan)>,
+    /// Every `[{ label }]`, by its label
+    labels: Vec<(String, TextSpan)>,
+    /// Every `[{ ref }]`, by its label
+    references: Vec<(String, TextSpan)>,
+    /// Every expression with a title, by its title
+    titles: Vec<(String, TextSpan)>,
+}
+
+impl Spans {
+    fn new(ast: &Ast) -> Spans {
+        let mut spans = Spans::default();
+        for node in &ast.repr {
+            if let ast::Node::Expression(_, ref e) = *node {
+                spans.keys = properties(e);
+                for inner in &e.repr {
+                    if let ast::Node::Property(_, ref key, ref value) = *inner {
+                        if let ("meta", &ast::Node::Expression(_, ref meta)) = (&key[..], &**value) {
+                            spans.meta = properties(meta);
+                        }
+                    }
+                }
+            }
+        }
+        spans.visit_ast(ast);
+        spans
+    }
+
+    fn key(&self, name: &str) -> Option<TextSpan> {
+        find(&self.keys, name)
+    }
+
+    fn meta(&self, name: &str) -> Option<TextSpan> {
+        find(&self.meta, name)
+    }
+
+    fn variable(&self, name: &str) -> Option<TextSpan> {
+        find(&self.variables, name)
+    }
+
+    /// The last `[{ label }]` of a label, which is the one at fault when there's more than one
+    fn label(&self, name: &str) -> Option<TextSpan> {
+        self.labels.iter().rev().find(|&&(ref n, _)| n == name).map(|&(_, span)| span)
+    }
+
+    fn reference(&self, name: &str) -> Option<TextSpan> {
+        find(&self.references, name)
+    }
+
+    fn title(&self, title: &str) -> Option<TextSpan> {
+        find(&self.titles, title)
+    }
+}
+
+/// The first span recorded for a name
+fn find(list: &[(String, TextSpan)], name: &str) -> Option<TextSpan> {
+    list.iter().find(|&&(ref n, _)| n == name).map(|&(_, span)| span)
+}
+
+impl Visitor for Spans {
+    fn visit_variable(&mut self, span: TextSpan, name: &str) {
+        self.variables.push((name.to_owned(), span));
+    }
+
+    fn visit_expression(&mut self, span: TextSpan, expr: &ast::Expression) {
+        // The title and the words straight after it, like `ref intro`
+        let words: Vec<&str> = expr.repr.iter()
+            .take_while(|node| match **node { ast::Node::Text(..) => true, _ => false })
+            .filter_map(|node| match *node { ast::Node::Text(_, ref text) => Some(&text[..]), _ => None })
+            .collect();
+        if let Some(title) = words.first() {
+            self.titles.push((title.to_string(), span));
+        }
+        if words.len() > 1 {
+            let name = words[1 ..].join(" ");
+            match words[0] {
+                "label" => self.labels.push((name, span)),
+                "ref" => self.references.push((name, span)),
+                _ => {}
+            }
+        }
+        walk_expression(self, expr)
+    }
+}
+
+/// The keys of the properties directly inside an expression
+fn properties(expr: &ast::Expression) -> Vec<(String, TextSpan)> {
+    expr.repr.iter()
+        .filter_map(|node| match *node {
+            ast::Node::Property(span, ref key, _) => Some((key.clone(), span)),
+            _ => None,
+        })
+        .collect()
+}
+
 /// A grouping of a name, a field-type, a possible default value, and option-ality
 #[derive(Debug)]
-#[derive(Copy, Clone)]
-pub struct Field;
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Field {
+    name: String,
+    kind: String,
+    optional: bool,
+    /// The default, as written. Variables keep their `$$`.
+    default: Option<String>,
+}
 
-/// Text settings
-#[derive(Debug)]
-#[derive(Copy, Clone)]
-pub struct TextSettings;
+impl Field {
+    /// Read every `[{field ...}]` inside a list like `::metafields [{ ... }]`
+    fn list(node: Option<&Node>) -> Result<Vec<Field>> {
+        let mut fields = Vec::new();
+        if let Some(e) = node.and_then(Node::as_expr) {
+            for child in e.children() {
+                if let Some(field) = try!(Field::from_expression(child)) {
+                    fields.push(field);
+                }
+            }
+        }
+        Ok(fields)
+    }
 
-/// Header settings
-#[derive(Debug)]
-#[derive(Copy, Clone)]
-pub struct Header;
+    fn from_expression(expr: &Expression) -> Result<Option<Field>> {
+        if expr.title() != Some("field") {
+            return Ok(None);
+        }
+        let name = match expr.prop("name").and_then(Node::as_text) {
+            Some(name) => name.to_owned(),
+            None => return Ok(None),
+        };
+        let kind = expr.prop("kind").and_then(Node::as_text).unwrap_or("text").to_owned();
+        let default = match expr.prop("default") {
+            Some(&Node::Atom(ref s)) => Some(s.clone()),
+            Some(&Node::Var(ref s)) => Some(format!("$${}", s)),
+            _ => None,
+        };
+        // If a default is specified, the field is automatically optional.
+        let optional = match expr.prop("optional").and_then(Node::as_value) {
+            Some(value) => try!(value.as_bool()) || default.is_some(),
+            None => default.is_some(),
+        };
+        Ok(Some(Field {
+            name: name,
+            kind: kind,
+            optional: optional,
+            default: default,
+        }))
+    }
 
-/// Footer settings
+    /// The field's name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The kind of value the field holds, `text` unless otherwise given
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Whether documents may leave the field out
+    pub fn is_optional(&self) -> bool {
+        self.optional
+    }
+
+    /// The default value, as written
+    pub fn default(&self) -> Option<&str> {
+        self.default.as_ref().map(|s| &s[..])
+    }
+}
+
+/// Where frontmatter goes
 #[derive(Debug)]
 #[derive(Copy, Clone)]
-pub struct Footer;
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FrontmatterPage {
+    /// On pages of its own, with the body starting a new page and section after it
+    BeforeFirst,
+    /// At the top of the first page, with the body following straight on
+    First,
+}
 
 /// Frontmatter (like a title page, page of contents, etc)
 #[derive(Debug)]
-#[derive(Copy, Clone)]
-pub struct Frontmatter;
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Frontmatter {
+    page: FrontmatterPage,
+    /// Set like body text, with the document's variables filled in
+    content: Expression,
+}
+
+impl Frontmatter {
+    /// Read a strategy's `::frontmatter`, which is `none` or a block with `::page before-first`,
+    /// `first` or `none`, and its `::content`
+    fn from_node(node: &Node) -> Result<Option<Frontmatter>> {
+        let (page, content) = match try!(matter(node, "frontmatter", &["before-first", "first"])) {
+            Some(matter) => matter,
+            None => return Ok(None),
+        };
+        Ok(Some(Frontmatter {
+            page: if page == "first" { FrontmatterPage::First } else { FrontmatterPage::BeforeFirst },
+            content: content,
+        }))
+    }
 
-/// Backmatter (like a glossery of terms, or index)
+    /// Where it goes
+    pub fn page(&self) -> FrontmatterPage {
+        self.page
+    }
+}
+
+/// Where backmatter goes
 #[derive(Debug)]
 #[derive(Copy, Clone)]
-pub struct Backmatter;
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BackmatterPage {
+    /// Straight after the body, on its last page
+    Last,
+    /// Starting a new page after the body
+    AfterLast,
+}
+
+/// Backmatter (like a glossary of terms, or index)
+#[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Backmatter {
+    page: BackmatterPage,
+    /// Set like body text, where `[{ glossary }]` and `[{ index }]` list what the body marked
+    content: Expression,
+}
+
+impl Backmatter {
+    /// Read a strategy's `::backmatter`, which is `none` or a block with `::page last`,
+    /// `after-last` or `none`, and its `::content`
+    fn from_node(node: &Node) -> Result<Option<Backmatter>> {
+        let (page, content) = match try!(matter(node, "backmatter", &["last", "after-last"])) {
+            Some(matter) => matter,
+            None => return Ok(None),
+        };
+        Ok(Some(Backmatter {
+            page: if page == "last" { BackmatterPage::Last } else { BackmatterPage::AfterLast },
+            content: content,
+        }))
+    }
+
+    /// Where it goes
+    pub fn page(&self) -> BackmatterPage {
+        self.page
+    }
+}
+
+/// Read frontmatter or backmatter: `none`, or a block with a `::page`, which may also be `none`,
+/// and `::content`. Without content, there's nothing to set.
+fn matter(node: &Node, name: &str, pages: &[&str]) -> Result<Option<(String, Expression)>> {
+    let expr = match *node {
+        Node::Expr(ref expr) => expr,
+        _ => match node.as_value() {
+            Some(Value::Word(ref w)) if w == "none" => return Ok(None),
+            other => {
+                let found = other.map_or_else(String::new, |v| v.to_string());
+                let kind = EvaluationKind::InvalidValue(found, "a block, or none".to_owned());
+                return Err(EvaluationError::new(kind).into());
+            }
+        },
+    };
+    let mut choices = pages.to_vec();
+    choices.push("none");
+    let page = match expr.prop("page").and_then(Node::as_value) {
+        Some(value) => try!(value.as_enum(&choices)).to_owned(),
+        None => {
+            let kind = EvaluationKind::MissingField(format!("{}/page", name));
+            let note = format!("give ::page {}", choices.join(", "));
+            return Err(EvaluationError::new(kind).note(note).into());
+        }
+    };
+    match expr.prop("content").and_then(Node::as_expr) {
+        Some(content) if page != "none" => Ok(Some((page, content.clone()))),
+        _ => Ok(None),
+    }
+}
 
 /// Bibliography settings
 #[derive(Debug)]
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Bibliography;
 
 /// Body settings
 #[derive(Debug)]
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Body;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use value::{ Align, Length, Unit };
+
+    fn texts(paragraphs: &[Paragraph]) -> Vec<&str> {
+        paragraphs.iter().map(Paragraph::text).collect()
+    }
+
+    fn strategy(frontmatter: &str) -> Strategy {
+        Strategy::new(&format!("[{{strategy ::name s
+            ::metafields [{{ [{{field ::name name}}] [{{field ::name class ::optional true}}]
+                             [{{field ::name date ::default 2026-03-05}}] }}]
+            ::frontmatter {} }}]", frontmatter)).unwrap()
+    }
+
+    #[test]
+    fn sets_frontmatter_first() {
+        let strategy = strategy("[{ ::page first ::content [{ || $$meta/name\n\n\
+            [{ if-exists $$meta/class || $$meta/class }]\n\n\
+            [{ date ::format ''d MMMM yyyy'' ::source $$meta/date }]\n\n[{ align center || Title }] }] }]");
+        assert_eq!(FrontmatterPage::First, strategy.frontmatter().unwrap().page());
+        let doc = Document::new("[{document ::meta [{ ::name Ann }] || Body. }]", strategy).unwrap();
+        assert_eq!(vec!["Ann", "5 March 2026", "Title"], texts(doc.frontmatter()));
+        assert_eq!(Align::Center, doc.frontmatter()[2].style().align);
+        assert_eq!(vec!["Body."], texts(doc.body()));
+        assert!(!doc.body()[0].break_before());
+    }
+
+    #[test]
+    fn sets_frontmatter_before_first() {
+        let doc = Document::new("[{document ::meta [{ ::name Ann ::class Art }] || Body. }]",
+                                strategy("[{ ::page before-first ::content [{ || $$meta/name $$meta/class }] }]"))
+            .unwrap();
+        assert_eq!(vec!["Ann Art", "Body."], texts(doc.paragraphs()));
+        assert!(doc.body()[0].break_before() && doc.body()[0].starts_section());
+
+        for none in &["none", "[{ ::page none ::content [{ || Hi }] }]"] {
+            let doc = Document::new("[{document ::meta [{ ::name Ann }] || Body. }]", strategy(none)).unwrap();
+            assert!(doc.frontmatter().is_empty());
+        }
+        assert!(Strategy::new("[{strategy ::frontmatter [{ ::content [{ || Hi }] }] }]").is_err());
+        assert!(Strategy::new("[{strategy ::frontmatter [{ ::page last }] }]").is_err());
+    }
+
+    #[test]
+    fn lists_headings_in_tables_of_contents() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::toc [{ ::align justify }] }]
+            ::frontmatter [{ ::page before-first ::content [{ || Contents [{ toc }] }] }] }]").unwrap();
+        let src = "[{document || [{ h1 || One }] Text. [{ h2 ::indent 1in || Two }] More. }]";
+        let doc = Document::new(src, strategy).unwrap();
+        assert_eq!(vec!["Contents", "One", "Two"], texts(doc.frontmatter()));
+        assert_eq!(vec!["One", "Text.", "Two", "More."], texts(doc.body()));
+
+        let entries = &doc.frontmatter()[1..];
+        assert_eq!(vec![Some(3), Some(5)], entries.iter().map(Paragraph::contents_entry).collect::<Vec<_>>());
+        assert_eq!(Some(2), doc.paragraphs()[5].heading());
+        assert!(doc.paragraphs()[5].style().keep_with_next);
+        assert_eq!(Length::new(1.0, Unit::In), doc.paragraphs()[5].style().indent);
+        assert_eq!(Length::new(0.5, Unit::In), entries[1].style().indent);
+        assert_eq!(Align::Justify, entries[1].style().align);
+        assert!(doc.body()[0].break_before());
+    }
+
+    #[test]
+    fn collects_glossaries_and_indexes_into_backmatter() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::glossary [{ ::indent none }] }]
+            ::backmatter [{ ::page after-last ::content [{ || Terms [{ glossary }] Index [{ index }] }] }] }]")
+            .unwrap();
+        let src = "[{document || A [{ term ::def ''a small dog'' || Pup }] sat. [{ index dogs }]\n\n\
+                   [{ term || zebra }] [{ index Dogs }] [{ index cats }] [{ term ::def ''again'' || pup }] }]";
+        let doc = Document::new(src, strategy).unwrap();
+        assert_eq!(vec!["A Pup sat.", "zebra pup"], texts(doc.body()));
+        assert_eq!(vec!["Terms", "Pup: a small dog", "zebra", "Index", "cats", "dogs"], texts(doc.backmatter()));
+        assert!(doc.backmatter()[0].break_before());
+        assert_eq!(Length::new(0.0, Unit::Pt), doc.backmatter()[1].style().indent);
+        let cats: &[usize] = &[1];
+        let dogs: &[usize] = &[0, 1];
+        assert_eq!(cats, doc.backmatter()[4].page_refs());
+        assert_eq!(dogs, doc.backmatter()[5].page_refs());
+
+        let doc = Document::new(src, Strategy::new("[{strategy ::backmatter none }]").unwrap()).unwrap();
+        assert!(doc.backmatter().is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+//! Dumping parsed trees, for tooling and debugging
+//!
+//! With the `serde` feature the dumps are JSON, including every node's span.
+//! Without it they're Rust's debug output, which is only good for reading.
+
+use error::Result;
+use expression::Expression;
+use lexer::Lexer;
+use parser;
+
+/// Parse a source and dump its syntax tree
+pub fn ast(source: &str) -> Result<String> {
+    let ast = try!(parser::parse(Lexer::new(source)));
+    to_string(&ast)
+}
+
+/// Parse a source and dump the expression tree it lowers to
+pub fn expression(source: &str) -> Result<String> {
+    let ast = try!(parser::parse(Lexer::new(source)));
+    let expr = try!(Expression::from_ast(ast));
+    to_string(&expr)
+}
+
+#[cfg(feature = "serde")]
+fn to_string<T: ::serde::Serialize>(tree: &T) -> Result<String> {
+    use std::io;
+    let json = try!(::serde_json::to_string_pretty(tree).map_err(io::Error::from));
+    Ok(json)
+}
+
+#[cfg(not(feature = "serde"))]
+fn to_string<T: ::std::fmt::Debug>(tree: &T) -> Result<String> {
+    Ok(format!("{:#?}", tree))
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use ast::Ast;
+    use expression::Expression;
+    use lexer::Lexer;
+    use parser;
+    use serde_json;
+
+    #[test]
+    fn ast_round_trips() {
+        let src = include_str!("../examples/mla.litx");
+        let ast = parser::parse(Lexer::new(src)).unwrap();
+        let json = super::ast(src).unwrap();
+        let back: Ast = serde_json::from_str(&json).unwrap();
+        assert_eq!(ast, back);
+    }
+
+    #[test]
+    fn expression_round_trips() {
+        let src = include_str!("../examples/mla.litx");
+        let json = super::expression(src).unwrap();
+        let back: Expression = serde_json::from_str(&json).unwrap();
+        assert_eq!(Some("document"), back.title());
+        assert_eq!(json, serde_json::to_string_pretty(&back).unwrap());
+    }
+
+    #[test]
+    fn spans_are_kept() {
+        let json = super::ast("[{ foo }]").unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(3, value["repr"][0]["Expression"][1]["repr"][0]["Text"][0]["low"]);
+    }
+}
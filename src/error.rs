@@ -1,11 +1,13 @@
 //! Error type
 
+use lexer::TextSpan;
 use parser;
 
 use std;
 use std::io;
 use std::fmt;
 use std::fmt::{ Display };
+use std::path::{ Path, PathBuf };
 
 /// Automatic impl for wrapping $from in $member
 macro_rules! impl_from_error {
@@ -31,6 +33,15 @@ macro_rules! impl_error_cause {
                 _ => None
             }
         }
+
+        fn source(&self) -> Option<&(std::error::Error + 'static)> {
+            match *self {
+                $(
+                    $member(ref cause) => Some(cause as &(std::error::Error + 'static)),
+                )*
+                _ => None
+            }
+        }
     }
 }
 
@@ -42,31 +53,61 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     /// Error while lexing or parsing the document
     ParseFailure(parser::ParseError),
+    /// A source with nothing but blank lines and comments in it
+    EmptySource(TextSpan),
+    /// A source whose top level is something other than an expression, like a bare property
+    NotAnExpression(TextSpan),
+    /// A source with more than one top level expression. The span is the first extra one.
+    MultipleRoots(TextSpan),
+    /// A property whose value is a `||` divider
+    DividerProperty(TextSpan),
     /// Failure during evaluation and document building
-    EvaluationFailure,
+    EvaluationFailure(EvaluationError),
     /// Render failures
-    RenderFailure,
+    RenderFailure(RenderError),
     /// IO error
     Io(io::Error),
     /// Unimplemented failure. You should not see this, as a user
     Unimplemented(&'static str, u32),
 }
 
+impl Error {
+    /// Where in the source the error is, if it's tied to a place
+    pub fn span(&self) -> Option<TextSpan> {
+        match *self {
+            Error::ParseFailure((Some((_, span)), _)) => Some(span),
+            Error::EvaluationFailure(ref failure) => failure.span(),
+            Error::RenderFailure(ref failure) => failure.span(),
+            Error::EmptySource(span) |
+            Error::NotAnExpression(span) |
+            Error::MultipleRoots(span) |
+            Error::DividerProperty(span) => Some(span),
+            _ => None,
+        }
+    }
+}
+
 impl_from_error!(<io::Error> for Error as Error::Io);
 impl_from_error!(<parser::ParseError> for Error as Error::ParseFailure);
+impl_from_error!(<EvaluationError> for Error as Error::EvaluationFailure);
+impl_from_error!(<RenderError> for Error as Error::RenderFailure);
 
 impl std::error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::ParseFailure(..) => "Indicates a failure occured during lexing or parsing",
-            Error::EvaluationFailure => "Indicates a failure occured during an evaluation",
-            Error::RenderFailure => "Indicates a failure occured during rendering",
+            Error::EmptySource(_) => "Indicates a source had no expression in it",
+            Error::NotAnExpression(_) => "Indicates a source's top level wasn't an expression",
+            Error::MultipleRoots(_) => "Indicates a source had more than one top level expression",
+            Error::DividerProperty(_) => "Indicates a property was given a divider as its value",
+            Error::EvaluationFailure(_) => "Indicates a failure occured during an evaluation",
+            Error::RenderFailure(_) => "Indicates a failure occured during rendering",
             Error::Unimplemented(..) => "I haven't finished something yet. This isn't your fault.",
             Error::Io(_) => "Indicates an error occured during some io operations",
         }
     }
 
-    impl_error_cause!(Error::Io);
+    impl_error_cause!(Error::Io, Error::EvaluationFailure, Error::RenderFailure);
 }
 
 impl Display for Error {
@@ -80,11 +121,230 @@ impl Display for Error {
                     &(None, strin) => format!("Parsing Failure: {}", strin),
                 }
             }
-            Error::EvaluationFailure => "Evaluation Failure".to_string(),
-            Error::RenderFailure => "Render Failure".to_string(),
+            Error::EmptySource(span) => format!("Empty Source: no expression found @ {}", span),
+            Error::NotAnExpression(span) =>
+                format!("Not An Expression: the top level must be a [{{ ... }}] expression @ {}", span),
+            Error::MultipleRoots(span) =>
+                format!("Multiple Roots: only one top level expression is allowed @ {}", span),
+            Error::DividerProperty(span) =>
+                format!("Divider Property: a property's value can't be a divider @ {}", span),
+            Error::EvaluationFailure(ref failure) => format!("Evaluation Failure: {}", failure),
+            Error::RenderFailure(ref failure) => format!("Render Failure: {}", failure),
             Error::Unimplemented(file, line) => format!("Unimplemeted:  {}:{}", file, line),
             Error::Io(ref cause) => format!("IO Error:  {}", cause),
         };
         write!(fmt, "{}", msg)
     }
 }
+
+/// Failure while evaluating a document or strategy
+pub type EvaluationError = Failure<EvaluationKind>;
+
+/// Failure while rendering a document
+pub type RenderError = Failure<RenderKind>;
+
+/// What went wrong while evaluating
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub enum EvaluationKind {
+    /// A `$$variable` nothing defines
+    UndefinedVariable(String),
+    /// A value of the wrong kind, as (expected, found)
+    TypeMismatch(String, String),
+    /// A field the strategy requires, but the document doesn't give
+    MissingField(String),
+    /// An expression whose title nothing understands
+    UnknownExpression(String),
+    /// An `#[{include}]` whose file couldn't be found
+    IncludeNotFound(String),
+    /// An `#[{include}]` of a file which is already being included
+    IncludeCycle(String),
+    /// A value which can't be read as what it's meant to be, as (value, expected)
+    InvalidValue(String, String),
+    /// A `::strategy` which couldn't be found
+    StrategyNotFound(String),
+    /// A warning from a lint which was denied
+    Denied(String),
+    /// A `[{ ref }]` to a label nothing is named
+    UnresolvedLabel(String),
+    /// A label given to more than one thing
+    DuplicateLabel(String),
+    /// A `[{ label }]` which isn't inside anything it can name
+    MisplacedLabel(String),
+}
+
+impl Display for EvaluationKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EvaluationKind::UndefinedVariable(ref name) => write!(fmt, "undefined variable $${}", name),
+            EvaluationKind::TypeMismatch(ref expected, ref found) =>
+                write!(fmt, "expected {}, found {}", expected, found),
+            EvaluationKind::MissingField(ref name) => write!(fmt, "missing required field {}", name),
+            EvaluationKind::UnknownExpression(ref title) => write!(fmt, "unknown expression {}", title),
+            EvaluationKind::IncludeNotFound(ref file) => write!(fmt, "could not find include {}", file),
+            EvaluationKind::IncludeCycle(ref file) => write!(fmt, "{} includes itself", file),
+            EvaluationKind::InvalidValue(ref value, ref expected) =>
+                write!(fmt, "{} is not {}", value, expected),
+            EvaluationKind::StrategyNotFound(ref name) => write!(fmt, "could not find strategy {}", name),
+            EvaluationKind::Denied(ref lint) => write!(fmt, "denied warning {}", lint),
+            EvaluationKind::UnresolvedLabel(ref label) => write!(fmt, "nothing is labelled {}", label),
+            EvaluationKind::DuplicateLabel(ref label) => write!(fmt, "more than one thing is labelled {}", label),
+            EvaluationKind::MisplacedLabel(ref label) =>
+                write!(fmt, "label {} isn't inside a heading, note, table or figure", label),
+        }
+    }
+}
+
+/// What went wrong while rendering
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub enum RenderKind {
+    /// A font which couldn't be found or loaded
+    MissingFont(String),
+    /// A font file which is damaged, or uses something we can't read. The string is the table.
+    InvalidFont(String),
+    /// A file the document refers to, like an image, which couldn't be found or loaded
+    MissingResource(String),
+    /// An image file which is damaged, or stored in a way we can't read. The string is why.
+    InvalidImage(String),
+}
+
+impl Display for RenderKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderKind::MissingFont(ref name) => write!(fmt, "could not load font {}", name),
+            RenderKind::InvalidFont(ref table) => write!(fmt, "could not read the font's {} table", table),
+            RenderKind::MissingResource(ref file) => write!(fmt, "could not load {}", file),
+            RenderKind::InvalidImage(ref why) => write!(fmt, "could not read the image: {}", why),
+        }
+    }
+}
+
+/// A failure of some kind, with where it happened and anything else worth telling the user.
+///
+/// Built up from a kind, like `Failure::new(kind).at(span).note("...")`.
+#[derive(Debug)]
+pub struct Failure<K> {
+    kind: K,
+    span: Option<TextSpan>,
+    file: Option<PathBuf>,
+    notes: Vec<String>,
+    cause: Option<Box<std::error::Error + Send + Sync>>,
+}
+
+impl<K> Failure<K> {
+    /// A failure with nothing but its kind
+    pub fn new(kind: K) -> Failure<K> {
+        Failure {
+            kind: kind,
+            span: None,
+            file: None,
+            notes: Vec::new(),
+            cause: None,
+        }
+    }
+
+    /// Set where in the source the failure is
+    pub fn at(mut self, span: TextSpan) -> Failure<K> {
+        self.span = Some(span);
+        self
+    }
+
+    /// Set which file the failure is in
+    pub fn in_file<P: AsRef<Path>>(mut self, file: P) -> Failure<K> {
+        self.file = Some(file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add a note, shown after the message
+    pub fn note<S: Into<String>>(mut self, note: S) -> Failure<K> {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Set the error which caused this one
+    pub fn caused_by<E>(mut self, cause: E) -> Failure<K>
+        where E: Into<Box<std::error::Error + Send + Sync>>
+    {
+        self.cause = Some(cause.into());
+        self
+    }
+
+    /// What went wrong
+    pub fn kind(&self) -> &K {
+        &self.kind
+    }
+
+    /// Where in the source it went wrong, if known
+    pub fn span(&self) -> Option<TextSpan> {
+        self.span
+    }
+
+    /// The file it went wrong in, if known
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_ref().map(|f| f.as_path())
+    }
+
+    /// Notes, in the order they were added
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+}
+
+impl<K: Display> Display for Failure<K> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(fmt, "{}", self.kind));
+        match (self.file.as_ref(), self.span) {
+            (Some(file), Some(span)) => try!(write!(fmt, " @ {}:{}", file.display(), span)),
+            (Some(file), None) => try!(write!(fmt, " @ {}", file.display())),
+            (None, Some(span)) => try!(write!(fmt, " @ {}", span)),
+            (None, None) => {}
+        }
+        for note in &self.notes {
+            try!(write!(fmt, "\n  note: {}", note));
+        }
+        Ok(())
+    }
+}
+
+impl<K: Display + fmt::Debug> std::error::Error for Failure<K> {
+    fn description(&self) -> &str {
+        "Indicates a failure occured while building a document"
+    }
+
+    fn cause(&self) -> Option<&std::error::Error> {
+        self.cause.as_ref().map(|c| &**c as &std::error::Error)
+    }
+
+    fn source(&self) -> Option<&(std::error::Error + 'static)> {
+        self.cause.as_ref().map(|c| &**c as &(std::error::Error + 'static))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error as StdError;
+    use std::io;
+    use lexer::TextSpan;
+
+    #[test]
+    fn failure_display() {
+        let failure = EvaluationError::new(EvaluationKind::MissingField("title".to_owned()))
+            .at(TextSpan::new(3, 9))
+            .in_file("doc.litx")
+            .note("required by the mla strategy");
+        assert_eq!("missing required field title @ doc.litx:[3, 9)\n  note: required by the mla strategy",
+                   failure.to_string());
+        assert_eq!(Some(TextSpan::new(3, 9)), Error::from(failure).span());
+    }
+
+    #[test]
+    fn causes_are_exposed() {
+        let io = io::Error::new(io::ErrorKind::Other, "disk on fire");
+        let error = Error::from(RenderError::new(RenderKind::MissingFont("Times".to_owned())).caused_by(io));
+        let failure = error.source().unwrap();
+        assert_eq!("could not load font Times", failure.to_string());
+        assert_eq!("disk on fire", failure.source().unwrap().to_string());
+    }
+}
@@ -1,23 +1,51 @@
+//! The expression tree, which a syntax tree lowers to
+//!
+//! Lowering drops spans, splits each expression into its title and its sections at the `||`
+//! dividers, and joins runs of words into atoms. With the `serde` feature, trees serialize and
+//! deserialize as they are.
+
 use ast;
+use error::{ Error, Result };
+use lexer::TextSpan;
+use value::Value;
 
+/// A `[{ title ... || ... }]`
 #[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Expression {
-    title: Option<String>,
-    sections: Vec<Section>,
+    /// The leading text, if there is any
+    pub title: Option<String>,
+    /// What's between the dividers, in order
+    pub sections: Vec<Section>,
 }
 
 impl Expression {
-    pub fn from_ast(mut ast: ast::Ast) -> Option<Expression> {
-        if let ast::Node::Expression(_, e) = ast.repr.swap_remove(0) {
-            let e = Expression::from(e);
-            Some(e)
-        } else {
-            None
+    /// Lower a whole source, which must be exactly one expression (give or take blank lines).
+    pub fn from_ast(ast: ast::Ast) -> Result<Expression> {
+        let whole = ast.repr.iter().map(span_of).fold(None, |acc: Option<TextSpan>, span| {
+            Some(acc.map_or(span, |acc| TextSpan::merge(acc, span)))
+        });
+        let mut roots = ast.repr.into_iter().filter(|node| match *node {
+            ast::Node::EmptyLines(_) => false,
+            _ => true,
+        });
+        let root = match roots.next() {
+            Some(root) => root,
+            None => return Err(Error::EmptySource(whole.unwrap_or(TextSpan::new(0, 0)))),
+        };
+        if let Some(extra) = roots.next() {
+            return Err(Error::MultipleRoots(span_of(&extra)));
+        }
+        match root {
+            ast::Node::Expression(_, e) => Expression::from(e),
+            other => Err(Error::NotAnExpression(span_of(&other))),
         }
     }
 
+    /// Lower one expression of a syntax tree
     // FIXME: This method is ugly and poorly written. Can be cleaned up.
-    pub fn from(ast: ast::Expression) -> Expression {
+    pub fn from(ast: ast::Expression) -> Result<Expression> {
         let mut exp = Expression {
             title: None,
             sections: Vec::new(),
@@ -35,38 +63,85 @@ impl Expression {
         let mut section = Section::new();
         let mut text_buf: Option<String> = None;
         for node in repr {
-            if text_buf.is_some() {
-                if let &ast::Node::Text(_, ref s) = node {
-                    text_buf = text_buf.map(|buf| buf + " " + s);
-                } else {
-                    section.add_node(Node::Atom(text_buf.unwrap()));
-                    text_buf = None;
-                }
-            } else {
-                match node {
-                    &ast::Node::Divider(_) => {
-                        exp.add_section(section);
-                        section = Section::new();
-                    },
-                    &ast::Node::Text(_, ref s) => text_buf = Some(s.clone()),
-                    a @ _ => section.add_node(Node::from_ast_node(a.clone())),
-                }
+            if let &ast::Node::Text(_, ref s) = node {
+                text_buf = Some(match text_buf {
+                    Some(buf) => buf + " " + s,
+                    None => s.clone(),
+                });
+                continue;
+            }
+            if let Some(buf) = text_buf.take() {
+                section.add_node(Node::Atom(buf));
+            }
+            match node {
+                &ast::Node::Divider(_) => {
+                    exp.add_section(section);
+                    section = Section::new();
+                },
+                a @ _ => section.add_node(try!(Node::from_ast_node(a.clone()))),
             }
         }
         if text_buf.is_some() {
             section.add_node(Node::Atom(text_buf.unwrap()));
         }
         exp.add_section(section);
-        exp
+        Ok(exp)
     }
 
     fn add_section(&mut self, s: Section) {
         self.sections.push(s);
     }
+
+    /// The leading text of the expression, like `field` in `[{field ::name foo}]`
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_ref().map(|s| &s[..])
+    }
+
+    /// What's between the dividers, in order
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// Every `::key value` pair directly inside this expression, in order
+    pub fn props(&self) -> Vec<(&str, &Node)> {
+        let mut props = Vec::new();
+        for section in &self.sections {
+            for node in &section.content {
+                if let Node::Prop(ref key, ref value) = *node {
+                    props.push((&key[..], &**value));
+                }
+            }
+        }
+        props
+    }
+
+    /// The value of the first property with the given key
+    pub fn prop(&self, key: &str) -> Option<&Node> {
+        self.props().into_iter()
+            .find(|&(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Every expression directly inside this one, ignoring properties
+    pub fn children(&self) -> Vec<&Expression> {
+        let mut children = Vec::new();
+        for section in &self.sections {
+            for node in &section.content {
+                if let Node::Expr(ref e) = *node {
+                    children.push(e);
+                }
+            }
+        }
+        children
+    }
 }
 
+/// The part of an expression between two dividers, or before the first or after the last
 #[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Section {
+    /// Its nodes, in order
     pub content: Vec<Node>,
 }
 
@@ -82,24 +157,118 @@ impl Section {
     }
 }
 
+/// Anything in a section
 #[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Node {
+    /// A nested expression
     Expr(Expression),
+    /// `::key value`
     Prop(String, Box<Node>),
+    /// A run of words
     Atom(String),
+    /// `$$name`
     Var(String),
+    /// A run of blank lines
     Blank,
 }
 
 impl Node {
-    pub fn from_ast_node(node: ast::Node) -> Node {
-        match node {
-            ast::Node::Expression(_, e) => Node::Expr(Expression::from(e)),
-            ast::Node::Divider(_) => panic!("FIXME: But also you can't do ::prop ||"),
+    /// Lower one node of a syntax tree
+    pub fn from_ast_node(node: ast::Node) -> Result<Node> {
+        let node = match node {
+            ast::Node::Expression(_, e) => Node::Expr(try!(Expression::from(e))),
+            // Dividers are handled by the expression, so this is only reachable through `::prop ||`
+            ast::Node::Divider(span) => return Err(Error::DividerProperty(span)),
             ast::Node::EmptyLines(_) => Node::Blank,
             ast::Node::Variable(_, s) => Node::Var(s),
             ast::Node::Text(_, s) => Node::Atom(s),
-            ast::Node::Property(_, s, bn) => Node::Prop(s, Box::new(Node::from_ast_node(*bn))),
+            ast::Node::Property(_, s, bn) => Node::Prop(s, Box::new(try!(Node::from_ast_node(*bn)))),
+        };
+        Ok(node)
+    }
+
+    /// The words, if it's an atom
+    pub fn as_text(&self) -> Option<&str> {
+        match *self {
+            Node::Atom(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Text read as a typed value, like `12pt`
+    pub fn as_value(&self) -> Option<Value> {
+        self.as_text().map(Value::parse)
+    }
+
+    /// The expression, if it's one
+    pub fn as_expr(&self) -> Option<&Expression> {
+        match *self {
+            Node::Expr(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+fn span_of(node: &ast::Node) -> TextSpan {
+    match *node {
+        ast::Node::Expression(span, _) => span,
+        ast::Node::Divider(span) => span,
+        ast::Node::EmptyLines(span) => span,
+        ast::Node::Variable(span, _) => span,
+        ast::Node::Text(span, _) => span,
+        ast::Node::Property(span, _, _) => span,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::Error;
+    use lexer::{ Lexer, TextSpan };
+    use parser;
+
+    fn lower(source: &str) -> Result<Expression> {
+        Expression::from_ast(parser::parse(Lexer::new(source)).unwrap())
+    }
+
+    #[test]
+    fn single_root() {
+        let e = lower("\n\n[{ foo ::bar baz || quux }]\n\n").unwrap();
+        assert_eq!(Some("foo"), e.title());
+        assert_eq!(2, e.sections().len());
+    }
+
+    #[test]
+    fn empty_source() {
+        match lower("// Just a comment\n") {
+            Err(Error::EmptySource(_)) => {}
+            other => panic!("Expected EmptySource, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn multiple_roots() {
+        match lower("[{ a }]\n[{ b }]") {
+            Err(Error::MultipleRoots(span)) => assert_eq!(TextSpan::new(8, 15), span),
+            other => panic!("Expected MultipleRoots, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn not_an_expression() {
+        match lower("::text-settings [{ }]") {
+            Err(Error::NotAnExpression(span)) => assert_eq!(TextSpan::new(0, 21), span),
+            other => panic!("Expected NotAnExpression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn divider_property() {
+        match lower("[{ a ::prop || b }]") {
+            Err(Error::DividerProperty(span)) => assert_eq!(TextSpan::new(12, 14), span),
+            other => panic!("Expected DividerProperty, got {:?}", other),
         }
     }
 }
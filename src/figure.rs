@@ -0,0 +1,123 @@
+//! Figures
+//!
+//! `[{ figure ::src ''plot.png'' || ... }]` is a figure of a PNG or JPEG image, found beside the
+//! document like an included file, with anything after its `||` as its caption, which is set
+//! under it:
+//!
+//! ```text
+//! [{ figure ::src ''plot.png'' ::width 4in || Masses over time [{ label plot }] }]
+//! ```
+//!
+//! Images are as big as they are at 96 pixels an inch, unless `::width` or `::height` says
+//! otherwise. Given one, the other keeps the image's shape; given both, it's stretched to fit.
+//! Figures are set in the `figure` style, captions in the `caption` style, after the figure's
+//! name and number unless it's `::numbered false`.
+
+use std::path::PathBuf;
+
+use error::{ EvaluationError, EvaluationKind, Result };
+use expression::{ Expression, Node };
+use image::Image;
+use value::Length;
+
+/// Points in a pixel, at 96 pixels an inch
+const POINTS_PER_PIXEL: f64 = 0.75;
+
+/// A `[{ figure }]`'s image, and how big it's set
+#[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Figure {
+    /// Its `::src`, as written
+    pub src: String,
+    /// Where the image was found
+    pub path: PathBuf,
+    /// The image
+    pub image: Image,
+    /// How wide it's set, in points
+    pub width: f64,
+    /// How tall it's set, in points
+    pub height: f64,
+}
+
+impl Figure {
+    /// Its size scaled down, keeping its shape, so it's no wider or taller than it may be
+    pub fn fit(&self, width: f64, height: f64) -> (f64, f64) {
+        let scale = (width / self.width).min(height / self.height).min(1.0).max(0.0);
+        (self.width * scale, self.height * scale)
+    }
+}
+
+/// Read a figure's `::width` and `::height`, given its image and font size, as how big it's set
+/// in points
+pub fn size(expr: &Expression, image: &Image, font_size: Length) -> Result<(f64, f64)> {
+    let mut lengths = [None, None];
+    for (length, key) in lengths.iter_mut().zip(&["width", "height"]) {
+        if let Some(value) = expr.prop(key).and_then(Node::as_value) {
+            let points = try!(try!(value.as_length()).resolve(font_size).points());
+            if points <= 0.0 {
+                let kind = EvaluationKind::InvalidValue(value.to_string(), "a length more than 0".to_owned());
+                return Err(EvaluationError::new(kind).into());
+            }
+            *length = Some(points);
+        }
+    }
+    let natural = (image.width() as f64 * POINTS_PER_PIXEL, image.height() as f64 * POINTS_PER_PIXEL);
+    Ok(match (lengths[0], lengths[1]) {
+        (Some(width), Some(height)) => (width, height),
+        (Some(width), None) => (width, natural.1 * width / natural.0),
+        (None, Some(height)) => (natural.0 * height / natural.1, height),
+        (None, None) => natural,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+    use error::{ Error, RenderKind };
+    use image::test::beside_images;
+
+    fn beside_jpeg(body: &str) -> Result<Document> {
+        beside_images(&format!("[{{document || {} }}]", body), Strategy::none())
+    }
+
+    #[test]
+    fn scales_images() {
+        let doc = beside_jpeg("[{ figure ::src ''photo.jpg'' ::width 2in || A photo }] \
+                                         [{ figure ::src ''photo.jpg'' ::height 1em }] \
+                                         [{ figure ::src ''photo.jpg'' ::width 1in ::height 1in ::numbered false }]").unwrap();
+        let figures: Vec<(f64, f64)> = doc.paragraphs().iter()
+            .filter_map(|p| p.figure())
+            .map(|figure| (figure.width, figure.height))
+            .collect();
+        assert_eq!(vec![(144.0, 108.0), (16.0, 12.0), (72.0, 72.0)], figures);
+        let texts: Vec<&str> = doc.paragraphs().iter().map(|p| p.text()).collect();
+        assert_eq!(vec!["Figure 1. A photo", "Figure 2.", ""], texts);
+        // Images are as big as they are at 96 pixels an inch, and scaled down to fit
+        let figure = doc.paragraphs()[0].figure().unwrap();
+        let pixels = (figure.image.width() as f64, figure.image.height() as f64);
+        assert_eq!((3.0, 2.25), (pixels.0 * POINTS_PER_PIXEL, pixels.1 * POINTS_PER_PIXEL));
+        assert_eq!((72.0, 54.0), figure.fit(72.0, 500.0));
+        assert_eq!((144.0, 108.0), figure.fit(500.0, 500.0));
+    }
+
+    #[test]
+    fn labels_figures() {
+        let doc = beside_jpeg("[{ figure ::src ''photo.jpg'' || A photo [{ label photo }] }] \
+                                         See [{ ref photo }], [{ ref photo ::show title }].").unwrap();
+        assert_eq!("See 1, A photo.", doc.paragraphs()[1].text());
+    }
+
+    #[test]
+    fn fails_on_missing_images() {
+        match beside_jpeg("[{ figure ::src ''plot.png'' }]") {
+            Err(Error::RenderFailure(ref failure)) => {
+                assert_eq!(&RenderKind::MissingResource("plot.png".to_owned()), failure.kind());
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(beside_jpeg("[{ figure || A photo }]").is_err());
+        assert!(beside_jpeg("[{ figure ::src ''photo.jpg'' ::width -1in }]").is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Metrics for the standard PDF fonts
+//!
+//! PDF readers bring their own copies of these, so all we need is how wide each character is.
+//! The widths are from Adobe's AFM files, for printable ASCII in WinAnsiEncoding, and the bullet
+//! that marks lists, at its WinAnsiEncoding code. Anything else is drawn as `?`, so it's measured
+//! as one. Kerning pairs are left out, and bold and italic
+//! fonts share their family's ascent and descent. Helvetica's obliques are as wide as its
+//! uprights, and every Courier is monospaced.
+
+use font::{ Builtin, Metrics, Variant };
+
+/// The first character in the width tables, space
+const FIRST: u32 = 32;
+/// `?`, which stands in for anything the tables don't have
+const QUESTION: usize = '?' as usize - FIRST as usize;
+/// The bullet, which isn't ASCII but is in every font
+const BULLET: char = '\u{2022}';
+/// The bullet's code in WinAnsiEncoding
+const BULLET_CODE: u8 = 0x95;
+/// How wide the bullet is in every font but Courier
+const BULLET_WIDTH: u16 = 350;
+
+const TIMES_WIDTHS: [u16; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564, 250, 333, 250, 278,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 278, 278, 564, 564, 564, 444,
+    921, 722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722, 611, 889, 722, 722,
+    556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611, 333, 278, 333, 469, 500,
+    333, 444, 500, 444, 500, 444, 333, 500, 500, 278, 278, 500, 278, 778, 500, 500,
+    500, 500, 333, 389, 278, 500, 500, 722, 500, 500, 444, 480, 200, 480, 541,
+];
+
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+const TIMES_BOLD_WIDTHS: [u16; 95] = [
+    250, 333, 555, 500, 500, 1000, 833, 278, 333, 333, 500, 570, 250, 333, 250, 278,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 333, 333, 570, 570, 570, 500,
+    930, 722, 667, 722, 722, 667, 611, 778, 778, 389, 500, 778, 667, 944, 722, 778,
+    611, 778, 722, 556, 667, 722, 722, 1000, 722, 722, 667, 333, 278, 333, 581, 500,
+    333, 500, 556, 444, 556, 444, 333, 500, 556, 278, 333, 556, 278, 833, 556, 500,
+    556, 556, 444, 389, 333, 556, 500, 722, 500, 500, 444, 394, 220, 394, 520,
+];
+
+const TIMES_ITALIC_WIDTHS: [u16; 95] = [
+    250, 333, 420, 500, 500, 833, 778, 214, 333, 333, 500, 675, 250, 333, 250, 278,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 333, 333, 675, 675, 675, 500,
+    920, 611, 611, 667, 722, 611, 611, 722, 722, 333, 444, 667, 556, 833, 667, 722,
+    611, 722, 611, 500, 556, 722, 611, 833, 611, 556, 556, 389, 278, 389, 422, 500,
+    333, 500, 500, 444, 500, 444, 278, 500, 500, 278, 278, 444, 278, 722, 500, 500,
+    500, 500, 389, 389, 278, 500, 444, 667, 444, 444, 389, 400, 275, 400, 541,
+];
+
+const TIMES_BOLD_ITALIC_WIDTHS: [u16; 95] = [
+    250, 389, 555, 500, 500, 833, 778, 278, 333, 333, 500, 570, 250, 333, 250, 278,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 333, 333, 570, 570, 570, 500,
+    832, 667, 667, 667, 722, 667, 667, 722, 778, 389, 500, 667, 611, 889, 722, 722,
+    611, 722, 667, 556, 611, 722, 667, 889, 667, 611, 611, 333, 278, 333, 570, 500,
+    333, 500, 500, 444, 500, 444, 333, 500, 556, 278, 278, 500, 278, 778, 556, 500,
+    500, 500, 389, 389, 278, 556, 444, 667, 500, 444, 389, 348, 220, 348, 570,
+];
+
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+/// Courier is monospaced
+const COURIER_WIDTH: u16 = 600;
+
+impl Builtin {
+    /// Whether the font can draw a character, rather than a `?`
+    pub fn has(&self, c: char) -> bool {
+        (c >= ' ' && c <= '~') || c == BULLET
+    }
+
+    /// A character's code in WinAnsiEncoding, or `?`'s if the font can't draw it
+    pub fn code(&self, c: char) -> u8 {
+        match c {
+            BULLET => BULLET_CODE,
+            c if self.has(c) => c as u8,
+            _ => b'?',
+        }
+    }
+
+    fn width(&self, c: char) -> u16 {
+        if c == BULLET {
+            return match self.styled(Variant::default()) {
+                Builtin::Courier => COURIER_WIDTH,
+                _ => BULLET_WIDTH,
+            };
+        }
+        let index = if self.has(c) { (c as u32 - FIRST) as usize } else { QUESTION };
+        match *self {
+            Builtin::Times => TIMES_WIDTHS[index],
+            Builtin::TimesBold => TIMES_BOLD_WIDTHS[index],
+            Builtin::TimesItalic => TIMES_ITALIC_WIDTHS[index],
+            Builtin::TimesBoldItalic => TIMES_BOLD_ITALIC_WIDTHS[index],
+            Builtin::Helvetica | Builtin::HelveticaOblique => HELVETICA_WIDTHS[index],
+            Builtin::HelveticaBold | Builtin::HelveticaBoldOblique => HELVETICA_BOLD_WIDTHS[index],
+            _ => COURIER_WIDTH,
+        }
+    }
+}
+
+impl Metrics for Builtin {
+    fn units_per_em(&self) -> u16 {
+        1000
+    }
+
+    fn ascent(&self) -> i16 {
+        match self.styled(Variant::default()) {
+            Builtin::Times => 683,
+            Builtin::Helvetica => 718,
+            _ => 629,
+        }
+    }
+
+    fn descent(&self) -> i16 {
+        match self.styled(Variant::default()) {
+            Builtin::Times => -217,
+            Builtin::Helvetica => -207,
+            _ => -157,
+        }
+    }
+
+    fn advance(&self, c: char) -> u16 {
+        self.width(c)
+    }
+
+    fn kerning(&self, _: char, _: char) -> i16 {
+        0
+    }
+}
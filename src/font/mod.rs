@@ -0,0 +1,607 @@
+//! Fonts
+//!
+//! A style's `::face` is a list of fonts to try, like `''Times New Roman; Times; Serif''`.
+//! Each is looked for in the font directories, and the generic families (`serif`,
+//! `sans-serif` and `monospace`) map to the standard PDF fonts, which every PDF reader has,
+//! so a stack ending in one always finds something.
+//!
+//! Once resolved, a font is loaded to measure text with. Font files are parsed by `sfnt`, which
+//! can also subset them for embedding, and the standard fonts' widths are in `afm`.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use diagnostic;
+use diagnostic::Diagnostics;
+use error::Result;
+
+mod afm;
+mod sfnt;
+mod subset;
+
+pub use self::sfnt::Font;
+
+/// Measurements of a font, in font units
+pub trait Metrics {
+    /// Font units in an em
+    fn units_per_em(&self) -> u16;
+
+    /// Height above the baseline
+    fn ascent(&self) -> i16;
+
+    /// Depth below the baseline, usually negative
+    fn descent(&self) -> i16;
+
+    /// How far a character moves the pen
+    fn advance(&self, c: char) -> u16;
+
+    /// How much to move `right` when it follows `left`
+    fn kerning(&self, left: char, right: char) -> i16;
+
+    /// How wide some text is at a font size, both in points
+    fn measure(&self, text: &str, size: f64) -> f64 {
+        let mut units = 0i64;
+        let mut previous = None;
+        for c in text.chars() {
+            if let Some(p) = previous {
+                units += self.kerning(p, c) as i64;
+            }
+            units += self.advance(c) as i64;
+            previous = Some(c);
+        }
+        units as f64 * size / self.units_per_em() as f64
+    }
+}
+
+impl Metrics for Font {
+    fn units_per_em(&self) -> u16 {
+        Font::units_per_em(self)
+    }
+
+    fn ascent(&self) -> i16 {
+        Font::ascent(self)
+    }
+
+    fn descent(&self) -> i16 {
+        Font::descent(self)
+    }
+
+    /// Characters the font doesn't have are measured as `.notdef`, which is what's drawn
+    fn advance(&self, c: char) -> u16 {
+        self.glyph_advance(self.glyph(c).unwrap_or(0))
+    }
+
+    fn kerning(&self, left: char, right: char) -> i16 {
+        match (self.glyph(left), self.glyph(right)) {
+            (Some(l), Some(r)) => self.glyph_kerning(l, r),
+            _ => 0,
+        }
+    }
+}
+
+/// Colon (or semicolon, on Windows) separated directories to search before the system ones
+pub const FONT_PATH_VAR: &'static str = "LITX_FONT_PATH";
+
+/// Where fonts usually live
+const SYSTEM_FONT_DIRS: &'static [&'static str] = &[
+    "/usr/share/fonts",
+    "/usr/local/share/fonts",
+    "/Library/Fonts",
+    "/System/Library/Fonts",
+    "C:\\Windows\\Fonts",
+];
+
+/// Font files we can read
+const FONT_EXTENSIONS: &'static [&'static str] = &["ttf", "otf"];
+
+/// A list of fonts to try, in order
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FontStack {
+    faces: Vec<String>,
+}
+
+impl FontStack {
+    /// Split a `;` separated list, like `Times New Roman; Times; Serif`
+    pub fn parse(face: &str) -> FontStack {
+        FontStack {
+            faces: face.split(';').map(|f| f.trim()).filter(|f| !f.is_empty()).map(|f| f.to_owned()).collect(),
+        }
+    }
+
+    /// The fonts, most wanted first
+    pub fn faces(&self) -> &[String] {
+        &self.faces
+    }
+}
+
+/// A font's weight and slant, within its family
+#[derive(Debug)]
+#[derive(Copy, Clone, Default)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Variant {
+    /// Bold, rather than regular weight
+    pub bold: bool,
+    /// Italic or oblique, rather than upright
+    pub italic: bool,
+}
+
+impl Variant {
+    /// How font files of this variant end their names, after the family, once normalized
+    fn suffixes(&self) -> &'static [&'static str] {
+        match (self.bold, self.italic) {
+            (false, false) => &["", "regular"],
+            (true, false) => &["bold"],
+            (false, true) => &["italic", "oblique"],
+            (true, true) => &["bolditalic", "boldoblique"],
+        }
+    }
+}
+
+/// One of the standard PDF fonts
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Builtin {
+    /// Times Roman, for `serif`
+    Times,
+    /// Times Bold
+    TimesBold,
+    /// Times Italic
+    TimesItalic,
+    /// Times Bold Italic
+    TimesBoldItalic,
+    /// Helvetica, for `sans-serif`
+    Helvetica,
+    /// Helvetica Bold
+    HelveticaBold,
+    /// Helvetica Oblique
+    HelveticaOblique,
+    /// Helvetica Bold Oblique
+    HelveticaBoldOblique,
+    /// Courier, for `monospace`
+    Courier,
+    /// Courier Bold
+    CourierBold,
+    /// Courier Oblique
+    CourierOblique,
+    /// Courier Bold Oblique
+    CourierBoldOblique,
+}
+
+impl Builtin {
+    /// The builtin font for a generic family name
+    pub fn for_generic(family: &str) -> Option<Builtin> {
+        match &family.to_lowercase()[..] {
+            "serif" => Some(Builtin::Times),
+            "sans-serif" | "sans serif" | "sans" => Some(Builtin::Helvetica),
+            "monospace" | "mono" => Some(Builtin::Courier),
+            _ => None,
+        }
+    }
+
+    /// The font in the same family with another weight and slant
+    pub fn styled(&self, variant: Variant) -> Builtin {
+        use self::Builtin::*;
+        let family = match *self {
+            Times | TimesBold | TimesItalic | TimesBoldItalic => [Times, TimesBold, TimesItalic, TimesBoldItalic],
+            Helvetica | HelveticaBold | HelveticaOblique | HelveticaBoldOblique =>
+                [Helvetica, HelveticaBold, HelveticaOblique, HelveticaBoldOblique],
+            Courier | CourierBold | CourierOblique | CourierBoldOblique =>
+                [Courier, CourierBold, CourierOblique, CourierBoldOblique],
+        };
+        family[variant.bold as usize + 2 * variant.italic as usize]
+    }
+
+    /// The font's PDF name
+    pub fn pdf_name(&self) -> &'static str {
+        match *self {
+            Builtin::Times => "Times-Roman",
+            Builtin::TimesBold => "Times-Bold",
+            Builtin::TimesItalic => "Times-Italic",
+            Builtin::TimesBoldItalic => "Times-BoldItalic",
+            Builtin::Helvetica => "Helvetica",
+            Builtin::HelveticaBold => "Helvetica-Bold",
+            Builtin::HelveticaOblique => "Helvetica-Oblique",
+            Builtin::HelveticaBoldOblique => "Helvetica-BoldOblique",
+            Builtin::Courier => "Courier",
+            Builtin::CourierBold => "Courier-Bold",
+            Builtin::CourierOblique => "Courier-Oblique",
+            Builtin::CourierBoldOblique => "Courier-BoldOblique",
+        }
+    }
+}
+
+/// Where a font comes from
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FontSource {
+    /// A font file
+    File(PathBuf),
+    /// A standard PDF font
+    Builtin(Builtin),
+}
+
+/// The font picked for a stack
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ResolvedFont {
+    /// The name from the stack which matched, or the generic family used when none did
+    pub name: String,
+    /// Where to load it from
+    pub source: FontSource,
+    /// Whether this isn't the first font in the stack
+    pub fallback: bool,
+}
+
+impl ResolvedFont {
+    /// Load the font, to measure text with
+    pub fn load(&self) -> Result<LoadedFont> {
+        match self.source {
+            FontSource::File(ref path) => Font::open(path).map(LoadedFont::File),
+            FontSource::Builtin(builtin) => Ok(LoadedFont::Builtin(builtin)),
+        }
+    }
+}
+
+/// A font ready to measure text with
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum LoadedFont {
+    /// A parsed font file
+    File(Font),
+    /// A standard PDF font
+    Builtin(Builtin),
+}
+
+impl Metrics for LoadedFont {
+    fn units_per_em(&self) -> u16 {
+        match *self {
+            LoadedFont::File(ref font) => Metrics::units_per_em(font),
+            LoadedFont::Builtin(ref builtin) => builtin.units_per_em(),
+        }
+    }
+
+    fn ascent(&self) -> i16 {
+        match *self {
+            LoadedFont::File(ref font) => Metrics::ascent(font),
+            LoadedFont::Builtin(ref builtin) => builtin.ascent(),
+        }
+    }
+
+    fn descent(&self) -> i16 {
+        match *self {
+            LoadedFont::File(ref font) => Metrics::descent(font),
+            LoadedFont::Builtin(ref builtin) => builtin.descent(),
+        }
+    }
+
+    fn advance(&self, c: char) -> u16 {
+        match *self {
+            LoadedFont::File(ref font) => font.advance(c),
+            LoadedFont::Builtin(ref builtin) => builtin.advance(c),
+        }
+    }
+
+    fn kerning(&self, left: char, right: char) -> i16 {
+        match *self {
+            LoadedFont::File(ref font) => font.kerning(left, right),
+            LoadedFont::Builtin(ref builtin) => builtin.kerning(left, right),
+        }
+    }
+}
+
+/// The fonts available to a build
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct FontLibrary {
+    /// Every font file found, with its normalized name
+    files: Vec<(String, PathBuf)>,
+}
+
+impl FontLibrary {
+    /// No fonts but the builtin ones
+    pub fn empty() -> FontLibrary {
+        FontLibrary { files: Vec::new() }
+    }
+
+    /// Fonts in some directories, searched in order
+    pub fn new<P: AsRef<Path>>(dirs: &[P]) -> FontLibrary {
+        let mut library = FontLibrary::empty();
+        for dir in dirs {
+            library.add_dir(dir.as_ref());
+        }
+        library
+    }
+
+    /// Fonts in `LITX_FONT_PATH`, then the usual system directories
+    pub fn system() -> FontLibrary {
+        FontLibrary::new(&FontLibrary::system_dirs())
+    }
+
+    /// `LITX_FONT_PATH`'s directories, then the usual system ones
+    pub fn system_dirs() -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = match env::var_os(FONT_PATH_VAR) {
+            Some(path) => env::split_paths(&path).collect(),
+            None => Vec::new(),
+        };
+        dirs.extend(SYSTEM_FONT_DIRS.iter().map(PathBuf::from));
+        if let Some(home) = env::var_os("HOME") {
+            dirs.push(Path::new(&home).join(".fonts"));
+            dirs.push(Path::new(&home).join(".local/share/fonts"));
+        }
+        dirs
+    }
+
+    /// Add every font under a directory, after the ones already found
+    pub fn add_dir(&mut self, dir: &Path) {
+        self.add_tree(dir, &mut HashSet::new());
+    }
+
+    /// Add the fonts under a directory not already in `seen`. Links are followed, but each
+    /// directory is only read once, so links back up the tree can't loop.
+    fn add_tree(&mut self, dir: &Path, seen: &mut HashSet<PathBuf>) {
+        let real = match fs::canonicalize(dir) {
+            Ok(real) => real,
+            // Missing directories are normal, most systems only have a few of them
+            Err(_) => return,
+        };
+        if !seen.insert(real) {
+            return;
+        }
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                self.add_tree(&path, seen);
+            } else if is_font_file(&path) {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    self.files.push((normalize(stem), path.clone()));
+                }
+            }
+        }
+    }
+
+    /// Find a font file by family name. `Times New Roman` matches files like
+    /// `Times New Roman.ttf` and `TimesNewRoman-Regular.otf`.
+    pub fn find(&self, family: &str) -> Option<&Path> {
+        self.find_variant(family, Variant::default())
+    }
+
+    /// Find a bold or italic font file by family name. Bold italic `Times New Roman` matches
+    /// files like `Times New Roman Bold Italic.ttf` and `TimesNewRoman-BoldOblique.otf`.
+    pub fn find_variant(&self, family: &str, variant: Variant) -> Option<&Path> {
+        let family = normalize(family);
+        let names: Vec<String> = variant.suffixes().iter().map(|suffix| format!("{}{}", family, suffix)).collect();
+        self.files.iter()
+            .find(|&&(ref name, _)| names.contains(name))
+            .map(|&(_, ref path)| path.as_path())
+    }
+
+    /// Pick the first font in a stack that's available, warning when it isn't the first one.
+    /// Stacks which run out fall back on `serif`.
+    pub fn resolve(&self, stack: &FontStack, diagnostics: &mut Diagnostics) -> ResolvedFont {
+        self.resolve_variant(stack, Variant::default(), diagnostics)
+    }
+
+    /// Pick the first font in a stack that's available in a weight and slant, like `resolve`.
+    /// Families with no file for the variant are set in their regular file instead.
+    pub fn resolve_variant(&self, stack: &FontStack, variant: Variant, diagnostics: &mut Diagnostics)
+        -> ResolvedFont
+    {
+        let wanted = stack.faces().first().map(|f| &f[..]).unwrap_or("serif");
+        for (i, face) in stack.faces().iter().enumerate() {
+            let source = match self.find_variant(face, variant).or_else(|| self.find(face)) {
+                Some(path) => FontSource::File(path.to_path_buf()),
+                None => match Builtin::for_generic(face) {
+                    Some(builtin) => FontSource::Builtin(builtin.styled(variant)),
+                    None => continue,
+                },
+            };
+            if i > 0 {
+                let msg = format!("{} isn't available, using {}", wanted, describe(face, &source));
+                diagnostics.warn(diagnostic::FONT_FALLBACK, None, msg);
+            }
+            return ResolvedFont { name: face.clone(), source: source, fallback: i > 0 };
+        }
+        let source = FontSource::Builtin(Builtin::Times.styled(variant));
+        let msg = format!("none of {} are available, using {}", stack.faces().join(", "), describe("serif", &source));
+        diagnostics.warn(diagnostic::FONT_FALLBACK, None, msg);
+        ResolvedFont { name: "serif".to_owned(), source: source, fallback: true }
+    }
+}
+
+fn describe(face: &str, source: &FontSource) -> String {
+    match *source {
+        FontSource::File(ref path) => format!("{} ({})", face, path.display()),
+        FontSource::Builtin(builtin) => format!("{} ({})", face, builtin.pdf_name()),
+    }
+}
+
+fn is_font_file(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => FONT_EXTENSIONS.contains(&&ext.to_lowercase()[..]),
+        None => false,
+    }
+}
+
+/// Lowercase letters and digits only, so names match however they're spaced
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::fs;
+    use std::fs::File;
+    use diagnostic::Diagnostics;
+    use font::subset::write_sfnt;
+    use scratch::Scratch;
+
+    fn be(words: &[i32]) -> Vec<u8> {
+        words.iter().flat_map(|&w| vec![(w >> 8) as u8, w as u8]).collect()
+    }
+
+    /// A font with glyphs for `A`, `V` and `Ä`, which is built from `A`, and the pair `AV` kerned
+    pub fn tiny_font() -> Vec<u8> {
+        write_sfnt(0x0001_0000, &tiny_tables())
+    }
+
+    fn tiny_tables() -> Vec<([u8; 4], Vec<u8>)> {
+        let mut head = be(&[1, 0, 0, 0, 0, 0, 0x5F0F, 0x3CF5, 0, 1000]);
+        head.extend(vec![0; 16]);
+        head.extend(be(&[0, -200, 700, 800, 0, 8, 2, 0, 0]));
+        let mut hhea = be(&[1, 0, 800, -200, 0]);
+        hhea.extend(vec![0; 24]);
+        hhea.extend(be(&[3]));
+        let maxp = be(&[0, 0x5000, 4]);
+        let hmtx = be(&[500, 0, 600, 0, 700, 0]);
+        // Segments for A, V and Ä, each straight to its glyph, then the required end segment
+        let mut cmap = be(&[0, 1, 3, 1, 0, 12]);
+        cmap.extend(be(&[4, 48, 0, 8, 8, 2, 0]));
+        cmap.extend(be(&[65, 86, 196, 0xFFFF, 0, 65, 86, 196, 0xFFFF]));
+        cmap.extend(be(&[1 - 65, 2 - 86, 3 - 196, 1, 0, 0, 0, 0]));
+        let kern = be(&[0, 1, 0, 20, 1, 1, 6, 0, 0, 1, 2, -80]);
+        let mut glyf = be(&[1, 0, 0, 600, 700, 0x1111]);
+        glyf.extend(be(&[1, 0, 0, 700, 700, 0x2222]));
+        glyf.extend(be(&[-1, 0, 0, 600, 900, 0, 1, 0]));
+        let loca = be(&[0, 0, 6, 12, 20]);
+        vec![
+            (*b"cmap", cmap), (*b"glyf", glyf), (*b"head", head), (*b"hhea", hhea),
+            (*b"hmtx", hmtx), (*b"kern", kern), (*b"loca", loca), (*b"maxp", maxp),
+        ]
+    }
+
+    /// The tiny font with `table` added, or put in place of the one with the same tag
+    fn tiny_font_with(tag: &[u8; 4], table: Vec<u8>) -> Vec<u8> {
+        let mut tables = tiny_tables();
+        tables.retain(|&(t, _)| &t != tag);
+        tables.push((*tag, table));
+        tables.sort_by(|a, b| a.0.cmp(&b.0));
+        write_sfnt(0x0001_0000, &tables)
+    }
+
+    #[test]
+    fn reads_fonts() {
+        let font = Font::parse(tiny_font()).unwrap();
+        assert_eq!(Some(1), font.glyph('A'));
+        assert_eq!(Some(3), font.glyph('Ä'));
+        assert_eq!(None, font.glyph('B'));
+        assert_eq!(700, font.advance('Ä'));
+        assert_eq!(500, font.advance('B'));
+        assert_eq!(-80, font.kerning('A', 'V'));
+        assert_eq!(0, font.kerning('V', 'A'));
+        assert_eq!((800, -200), (Metrics::ascent(&font), Metrics::descent(&font)));
+        assert!((font.measure("AV", 10.0) - 12.2).abs() < 1e-9);
+        assert!(Font::parse(tiny_font()[..40].to_vec()).is_err());
+    }
+
+    #[test]
+    fn survives_hostile_tables() {
+        // A format 12 cmap whose groups run to the end of the code space, one of them from the
+        // last glyph number there is
+        let mut cmap = be(&[0, 1, 3, 10, 0, 12, 12, 0, 0, 52, 0, 0, 0, 3]);
+        cmap.extend(be(&[0, 65, 0, 66, 0, 1]));
+        cmap.extend(be(&[0, 86, -1, -1, 0, 2]));
+        cmap.extend(be(&[0, 90, -1, -1, -1, -1]));
+        let font = Font::parse(tiny_font_with(b"cmap", cmap)).unwrap();
+        assert_eq!((Some(1), Some(2), Some(2)), (font.glyph('A'), font.glyph('B'), font.glyph('V')));
+        assert_eq!((Some(3), None), (font.glyph('W'), font.glyph('X')));
+        let backwards = be(&[0, 1, 3, 10, 0, 12, 12, 0, 0, 28, 0, 0, 0, 1, 0, 66, 0, 65, 0, 1]);
+        assert!(Font::parse(tiny_font_with(b"cmap", backwards)).is_err());
+
+        // A class pair adjustment claiming 65535 by 65535 classes, with no room for any of them
+        let mut gpos = be(&[1, 0, 0, 10, 24]);
+        gpos.extend(be(&[1, 0x6B65, 0x726E, 8, 0, 1, 0]));
+        gpos.extend(be(&[1, 4, 2, 0, 1, 8]));
+        gpos.extend(be(&[2, 16, 4, 0, 22, 22, -1, -1]));
+        gpos.extend(be(&[1, 1, 1, 1, 1, 0]));
+        assert!(Font::parse(tiny_font_with(b"GPOS", gpos)).is_err());
+    }
+
+    #[test]
+    fn builtin_metrics() {
+        assert_eq!(6.0, Builtin::Courier.measure("Hello", 2.0));
+        assert!((Builtin::Times.measure("Hi", 10.0) - 10.0).abs() < 1e-9);
+        assert_eq!(Builtin::Helvetica.advance('?'), Builtin::Helvetica.advance('é'));
+    }
+
+    #[test]
+    fn parses_stacks() {
+        let stack = FontStack::parse("Times New Roman; Times;Serif; ");
+        assert_eq!(&["Times New Roman".to_owned(), "Times".to_owned(), "Serif".to_owned()], stack.faces());
+    }
+
+    #[test]
+    fn falls_back_with_warnings() {
+        let dir = Scratch::new("font");
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        File::create(dir.join("nested").join("Times-Regular.ttf")).unwrap();
+        let library = FontLibrary::new(&[&dir]);
+
+        let mut d = Diagnostics::new();
+        let font = library.resolve(&FontStack::parse("Times New Roman; Times; Serif"), &mut d);
+        assert_eq!(FontSource::File(dir.join("nested").join("Times-Regular.ttf")), font.source);
+        assert!(font.fallback);
+        assert_eq!(1, d.len());
+
+        let mut d = Diagnostics::new();
+        let font = FontLibrary::empty().resolve(&FontStack::parse("Sans-Serif"), &mut d);
+        assert_eq!(FontSource::Builtin(Builtin::Helvetica), font.source);
+        assert!(d.is_empty());
+
+        let font = FontLibrary::empty().resolve(&FontStack::parse("Nope"), &mut d);
+        assert_eq!(FontSource::Builtin(Builtin::Times), font.source);
+        assert_eq!(1, d.len());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn survives_link_loops() {
+        use std::os::unix::fs::symlink;
+        let dir = Scratch::new("font-loop");
+        fs::create_dir_all(dir.join("inner")).unwrap();
+        File::create(dir.join("inner").join("Garamond.ttf")).unwrap();
+        let _ = symlink(&dir, dir.join("inner").join("up"));
+        let library = FontLibrary::new(&[&dir]);
+        assert_eq!(Some(dir.join("inner").join("Garamond.ttf").as_path()), library.find("Garamond"));
+        assert_eq!(1, library.files.len());
+    }
+
+    #[test]
+    fn finds_bold_and_italic() {
+        let dir = Scratch::new("font-variant");
+        File::create(dir.join("Garamond-Regular.ttf")).unwrap();
+        File::create(dir.join("Garamond Italic.ttf")).unwrap();
+        let library = FontLibrary::new(&[&dir]);
+        let italic = Variant { bold: false, italic: true };
+        let bold = Variant { bold: true, italic: false };
+
+        let mut d = Diagnostics::new();
+        let font = library.resolve_variant(&FontStack::parse("Garamond"), italic, &mut d);
+        assert_eq!(FontSource::File(dir.join("Garamond Italic.ttf")), font.source);
+        // There's no bold, so it's set in the regular
+        let font = library.resolve_variant(&FontStack::parse("Garamond"), bold, &mut d);
+        assert_eq!(FontSource::File(dir.join("Garamond-Regular.ttf")), font.source);
+        let font = library.resolve_variant(&FontStack::parse("Sans"), Variant { bold: true, italic: true }, &mut d);
+        assert_eq!(FontSource::Builtin(Builtin::HelveticaBoldOblique), font.source);
+        assert!(d.is_empty());
+
+        assert_eq!(Builtin::Times, Builtin::TimesBoldItalic.styled(Variant::default()));
+        assert_eq!("Courier-Oblique", Builtin::Courier.styled(italic).pdf_name());
+        assert!((Builtin::TimesBold.measure("Hi", 10.0) - 10.56).abs() < 1e-9);
+        assert_eq!(Builtin::Helvetica.ascent(), Builtin::HelveticaBold.ascent());
+    }
+}
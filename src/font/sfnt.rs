@@ -0,0 +1,719 @@
+//! TrueType and OpenType font files
+//!
+//! Only the tables needed to lay out text are read: `cmap` for glyphs, `hmtx` for advances,
+//! `head` and `hhea` for the em size and line metrics, `name` for the font's names, and pair
+//! kerning from `GPOS` or, failing that, the old `kern` table. Outlines are never looked at,
+//! they're only copied when subsetting.
+
+use std::collections::{ BTreeMap, HashMap, HashSet };
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use error::{ Error, RenderError, RenderKind, Result };
+
+/// `sfnt` version of fonts with TrueType outlines
+const TRUETYPE: u32 = 0x0001_0000;
+/// Apple's version of the same
+const TRUETYPE_APPLE: u32 = 0x7472_7565;
+/// `sfnt` version of fonts with CFF outlines
+const OPENTYPE_CFF: u32 = 0x4F54_544F;
+/// A font collection, which we don't read
+const COLLECTION: u32 = 0x7474_6366;
+
+/// `name` IDs
+const NAME_FAMILY: u16 = 1;
+const NAME_POSTSCRIPT: u16 = 6;
+
+/// GPOS lookup types
+const LOOKUP_PAIR: u16 = 2;
+const LOOKUP_EXTENSION: u16 = 9;
+/// The ValueRecord bit for a horizontal advance adjustment
+const VALUE_X_ADVANCE: u16 = 0x0004;
+
+/// A font file, parsed enough to measure text and embed it
+#[derive(Clone)]
+pub struct Font {
+    data: Vec<u8>,
+    tables: BTreeMap<[u8; 4], (usize, usize)>,
+    family: String,
+    postscript_name: String,
+    units_per_em: u16,
+    ascent: i16,
+    descent: i16,
+    line_gap: i16,
+    bbox: [i16; 4],
+    long_loca: bool,
+    advances: Vec<u16>,
+    glyphs: HashMap<char, u16>,
+    kerning: Kerning,
+}
+
+impl Font {
+    /// Read a font file
+    pub fn open(path: &Path) -> Result<Font> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) => {
+                let name = path.display().to_string();
+                return Err(RenderError::new(RenderKind::MissingFont(name)).in_file(path).caused_by(e).into());
+            }
+        };
+        Font::parse(data).map_err(|e| match e {
+            Error::RenderFailure(failure) => Error::RenderFailure(failure.in_file(path)),
+            other => other,
+        })
+    }
+
+    /// Parse a font from its bytes
+    pub fn parse(data: Vec<u8>) -> Result<Font> {
+        let tables = try!(read_directory(&data));
+        let mut font = Font {
+            data: Vec::new(),
+            tables: tables,
+            family: String::new(),
+            postscript_name: String::new(),
+            units_per_em: 0,
+            ascent: 0,
+            descent: 0,
+            line_gap: 0,
+            bbox: [0; 4],
+            long_loca: false,
+            advances: Vec::new(),
+            glyphs: HashMap::new(),
+            kerning: Kerning::default(),
+        };
+        font.data = data;
+
+        let (units_per_em, bbox, long_loca) = {
+            let mut head = try!(font.reader("head"));
+            head.seek(18);
+            let units_per_em = try!(head.u16());
+            head.seek(36);
+            let mut bbox = [0; 4];
+            for corner in &mut bbox {
+                *corner = try!(head.i16());
+            }
+            head.seek(50);
+            (units_per_em, bbox, try!(head.i16()) == 1)
+        };
+        font.units_per_em = units_per_em;
+        font.bbox = bbox;
+        font.long_loca = long_loca;
+        if font.units_per_em == 0 {
+            return Err(invalid("head", "the em size is zero"));
+        }
+
+        let num_glyphs = {
+            let mut maxp = try!(font.reader("maxp"));
+            maxp.seek(4);
+            try!(maxp.u16())
+        };
+
+        let (ascent, descent, line_gap, num_metrics) = {
+            let mut hhea = try!(font.reader("hhea"));
+            hhea.seek(4);
+            let ascent = try!(hhea.i16());
+            let descent = try!(hhea.i16());
+            let line_gap = try!(hhea.i16());
+            hhea.seek(34);
+            (ascent, descent, line_gap, try!(hhea.u16()))
+        };
+        font.ascent = ascent;
+        font.descent = descent;
+        font.line_gap = line_gap;
+
+        font.advances = try!(font.read_advances(num_glyphs, num_metrics));
+        font.glyphs = try!(font.read_cmap(num_glyphs));
+        try!(font.read_names());
+        font.kerning = match font.table(b"GPOS") {
+            Some(_) => try!(font.read_gpos()),
+            None => Kerning::default(),
+        };
+        if font.kerning.is_empty() && font.table(b"kern").is_some() {
+            font.kerning = try!(font.read_kern());
+        }
+        Ok(font)
+    }
+
+    /// The family name, like `DejaVu Serif`
+    pub fn family(&self) -> &str {
+        &self.family
+    }
+
+    /// The PostScript name, like `DejaVuSerif`, which PDFs call the font by
+    pub fn postscript_name(&self) -> &str {
+        &self.postscript_name
+    }
+
+    /// Font units in an em
+    pub fn units_per_em(&self) -> u16 {
+        self.units_per_em
+    }
+
+    /// Height above the baseline, in font units
+    pub fn ascent(&self) -> i16 {
+        self.ascent
+    }
+
+    /// Depth below the baseline, in font units. Usually negative.
+    pub fn descent(&self) -> i16 {
+        self.descent
+    }
+
+    /// Extra space the font asks for between lines, in font units
+    pub fn line_gap(&self) -> i16 {
+        self.line_gap
+    }
+
+    /// The box every glyph fits in, as [x min, y min, x max, y max] in font units
+    pub fn bbox(&self) -> [i16; 4] {
+        self.bbox
+    }
+
+    /// How many glyphs there are
+    pub fn num_glyphs(&self) -> u16 {
+        self.advances.len() as u16
+    }
+
+    /// Whether the outlines are TrueType, rather than CFF
+    pub fn is_truetype(&self) -> bool {
+        self.table(b"glyf").is_some()
+    }
+
+    /// The file's bytes
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The glyph for a character, if the font has one
+    pub fn glyph(&self, c: char) -> Option<u16> {
+        self.glyphs.get(&c).cloned()
+    }
+
+    /// A glyph's advance, in font units
+    pub fn glyph_advance(&self, glyph: u16) -> u16 {
+        self.advances.get(glyph as usize).cloned().unwrap_or(0)
+    }
+
+    /// How much to move `right` when it follows `left`, in font units
+    pub fn glyph_kerning(&self, left: u16, right: u16) -> i16 {
+        self.kerning.get(left, right)
+    }
+
+    /// A table's bytes
+    pub fn table(&self, tag: &[u8; 4]) -> Option<&[u8]> {
+        self.tables.get(tag).map(|&(offset, length)| &self.data[offset..offset + length])
+    }
+
+    /// Every table's tag, in order
+    pub fn tags(&self) -> Vec<[u8; 4]> {
+        self.tables.keys().cloned().collect()
+    }
+
+    /// Whether `loca` holds 32 bit offsets
+    pub fn long_loca(&self) -> bool {
+        self.long_loca
+    }
+
+    fn reader<'a>(&'a self, tag: &'static str) -> Result<Reader<'a>> {
+        let mut key = [0; 4];
+        key.copy_from_slice(tag.as_bytes());
+        match self.table(&key) {
+            Some(data) => Ok(Reader::new(data, tag)),
+            None => Err(invalid(tag, "the font has no such table")),
+        }
+    }
+
+    fn read_advances(&self, num_glyphs: u16, num_metrics: u16) -> Result<Vec<u16>> {
+        if num_metrics == 0 {
+            return Err(invalid("hhea", "there are no horizontal metrics"));
+        }
+        let mut hmtx = try!(self.reader("hmtx"));
+        let mut advances = Vec::with_capacity(num_glyphs as usize);
+        for _ in 0..num_metrics.min(num_glyphs) {
+            advances.push(try!(hmtx.u16()));
+            hmtx.skip(2);
+        }
+        // Glyphs after the last metric, usually monospaced ones, share its advance
+        let last = advances.last().cloned().unwrap_or(0);
+        while advances.len() < num_glyphs as usize {
+            advances.push(last);
+        }
+        Ok(advances)
+    }
+
+    fn read_cmap(&self, num_glyphs: u16) -> Result<HashMap<char, u16>> {
+        let cmap = try!(self.reader("cmap"));
+        let mut header = cmap.at(2);
+        let count = try!(header.u16());
+        let mut best = None;
+        for _ in 0..count {
+            let platform = try!(header.u16());
+            let encoding = try!(header.u16());
+            let offset = try!(header.u32()) as usize;
+            let format = try!(cmap.at(offset).u16());
+            let score = match (platform, encoding, format) {
+                (3, 10, 12) | (0, _, 12) => 3,
+                (3, 1, 4) | (0, _, 4) => 2,
+                (3, 0, 4) => 1,
+                _ => continue,
+            };
+            if best.map_or(true, |(s, _)| score > s) {
+                best = Some((score, offset));
+            }
+        }
+        let offset = match best {
+            Some((_, offset)) => offset,
+            None => return Err(invalid("cmap", "there's no Unicode subtable in format 4 or 12")),
+        };
+
+        let mut glyphs = HashMap::new();
+        let mut add = |code: u32, glyph: u32| {
+            if let Some(c) = ::std::char::from_u32(code) {
+                if glyph != 0 && glyph < num_glyphs as u32 {
+                    glyphs.entry(c).or_insert(glyph as u16);
+                }
+            }
+        };
+        let mut sub = cmap.at(offset);
+        if try!(sub.u16()) == 12 {
+            sub.seek(offset + 12);
+            let groups = try!(sub.u32());
+            for _ in 0..groups {
+                let start = try!(sub.u32());
+                let end = try!(sub.u32());
+                let glyph = try!(sub.u32());
+                if end < start {
+                    return Err(invalid("cmap", "a group's range is backwards"));
+                }
+                // Codes past Unicode or past the last glyph map to nothing, so the loop stops
+                // short of them instead of running up to four billion times
+                let glyphs_left = match (num_glyphs as u32).checked_sub(glyph) {
+                    Some(left) if left > 0 => left - 1,
+                    _ => continue,
+                };
+                let last = end.min(0x10FFFF).min(start.saturating_add(glyphs_left));
+                if start > last {
+                    continue;
+                }
+                for code in start..last + 1 {
+                    add(code, glyph + (code - start));
+                }
+            }
+        } else {
+            sub.seek(offset + 6);
+            let segments = (try!(sub.u16()) / 2) as usize;
+            let ends = offset + 14;
+            let starts = ends + segments * 2 + 2;
+            let deltas = starts + segments * 2;
+            let ranges = deltas + segments * 2;
+            for i in 0..segments {
+                let end = try!(cmap.at(ends + i * 2).u16()) as u32;
+                let start = try!(cmap.at(starts + i * 2).u16()) as u32;
+                let delta = try!(cmap.at(deltas + i * 2).u16()) as u32;
+                let range = try!(cmap.at(ranges + i * 2).u16()) as usize;
+                for code in start..end + 1 {
+                    if code == 0xFFFF {
+                        break;
+                    }
+                    let glyph = if range == 0 {
+                        code + delta
+                    } else {
+                        // Offsets are from the range offset itself, into the glyph array after it
+                        let at = ranges + i * 2 + range + (code - start) as usize * 2;
+                        match try!(cmap.at(at).u16()) as u32 {
+                            0 => 0,
+                            g => g + delta,
+                        }
+                    };
+                    add(code, glyph & 0xFFFF);
+                }
+            }
+        }
+        Ok(glyphs)
+    }
+
+    fn read_names(&mut self) -> Result<()> {
+        let mut names: HashMap<u16, (u8, String)> = HashMap::new();
+        if let Ok(name) = self.reader("name") {
+            let mut header = name.at(2);
+            let count = try!(header.u16());
+            let strings = try!(header.u16()) as usize;
+            for _ in 0..count {
+                let platform = try!(header.u16());
+                let encoding = try!(header.u16());
+                let language = try!(header.u16());
+                let id = try!(header.u16());
+                let length = try!(header.u16()) as usize;
+                let offset = try!(header.u16()) as usize;
+                if id != NAME_FAMILY && id != NAME_POSTSCRIPT {
+                    continue;
+                }
+                let bytes = try!(name.at(strings + offset).bytes(length));
+                // Prefer Windows English, then any Unicode, then Mac Roman, which is ASCII enough
+                let (rank, text) = match (platform, encoding) {
+                    (3, 0) | (3, 1) | (3, 10) | (0, _) => {
+                        let rank = if platform == 3 && language == 0x409 { 3 } else { 2 };
+                        (rank, utf16(bytes))
+                    }
+                    (1, 0) => (1, bytes.iter().map(|&b| b as char).collect()),
+                    _ => continue,
+                };
+                if names.get(&id).map_or(true, |&(r, _)| rank > r) {
+                    names.insert(id, (rank, text));
+                }
+            }
+        }
+        self.family = names.remove(&NAME_FAMILY).map(|(_, n)| n).unwrap_or_default();
+        self.postscript_name = match names.remove(&NAME_POSTSCRIPT) {
+            Some((_, name)) => name,
+            // PostScript names can't have spaces
+            None => self.family.chars().filter(|c| !c.is_whitespace()).collect(),
+        };
+        Ok(())
+    }
+
+    /// Pair adjustments from the lookups of GPOS's `kern` feature
+    fn read_gpos(&self) -> Result<Kerning> {
+        let gpos = try!(self.reader("GPOS"));
+        let mut header = gpos.at(6);
+        let features = try!(header.u16()) as usize;
+        let lookups = try!(header.u16()) as usize;
+
+        let mut indices = Vec::new();
+        let mut list = gpos.at(features);
+        for _ in 0..try!(list.u16()) {
+            let tag = try!(list.bytes(4));
+            let offset = try!(list.u16()) as usize;
+            if tag != b"kern" {
+                continue;
+            }
+            let mut feature = gpos.at(features + offset + 2);
+            for _ in 0..try!(feature.u16()) {
+                indices.push(try!(feature.u16()) as usize);
+            }
+        }
+        indices.sort();
+        indices.dedup();
+
+        let mut kerning = Kerning::default();
+        let count = try!(gpos.at(lookups).u16()) as usize;
+        for index in indices {
+            if index >= count {
+                return Err(invalid("GPOS", "a feature uses a lookup which doesn't exist"));
+            }
+            let lookup = lookups + try!(gpos.at(lookups + 2 + index * 2).u16()) as usize;
+            let mut header = gpos.at(lookup);
+            let kind = try!(header.u16());
+            header.skip(2);
+            for _ in 0..try!(header.u16()) {
+                let mut subtable = lookup + try!(header.u16()) as usize;
+                let mut kind = kind;
+                if kind == LOOKUP_EXTENSION {
+                    let mut extension = gpos.at(subtable + 2);
+                    kind = try!(extension.u16());
+                    subtable += try!(extension.u32()) as usize;
+                }
+                if kind == LOOKUP_PAIR {
+                    try!(read_pair_adjustment(&gpos, subtable, &mut kerning));
+                }
+            }
+        }
+        Ok(kerning)
+    }
+
+    /// Horizontal format 0 subtables from the old `kern` table
+    fn read_kern(&self) -> Result<Kerning> {
+        let kern = try!(self.reader("kern"));
+        let mut kerning = Kerning::default();
+        let mut header = kern.at(0);
+        // Apple's version 1 tables start with a 32 bit version, which we don't read
+        if try!(header.u16()) != 0 {
+            return Ok(kerning);
+        }
+        let mut at = 4;
+        for _ in 0..try!(header.u16()) {
+            let mut subtable = kern.at(at);
+            subtable.skip(2);
+            let length = try!(subtable.u16()) as usize;
+            let coverage = try!(subtable.u16());
+            // Format 0, horizontal, not minimums, not cross stream
+            if coverage >> 8 == 0 && coverage & 0x7 == 0x1 {
+                let pairs = try!(subtable.u16());
+                subtable.skip(6);
+                for _ in 0..pairs {
+                    let left = try!(subtable.u16());
+                    let right = try!(subtable.u16());
+                    let value = try!(subtable.i16());
+                    kerning.pairs.entry((left, right)).or_insert(value);
+                }
+            }
+            at += length;
+        }
+        Ok(kerning)
+    }
+}
+
+impl fmt::Debug for Font {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Font")
+            .field("postscript_name", &self.postscript_name)
+            .field("glyphs", &self.advances.len())
+            .field("units_per_em", &self.units_per_em)
+            .finish()
+    }
+}
+
+/// Pair kerning, either per glyph or per class of glyph
+#[derive(Debug)]
+#[derive(Clone, Default)]
+struct Kerning {
+    pairs: HashMap<(u16, u16), i16>,
+    classes: Vec<ClassKerning>,
+}
+
+impl Kerning {
+    fn get(&self, left: u16, right: u16) -> i16 {
+        if let Some(&value) = self.pairs.get(&(left, right)) {
+            return value;
+        }
+        for classes in &self.classes {
+            if classes.covered.contains(&left) {
+                let first = classes.first.get(&left).cloned().unwrap_or(0) as usize;
+                let second = classes.second.get(&right).cloned().unwrap_or(0) as usize;
+                return classes.values.get(first * classes.second_count + second).cloned().unwrap_or(0);
+            }
+        }
+        0
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pairs.is_empty() && self.classes.is_empty()
+    }
+}
+
+/// A class based pair adjustment, kept as classes since expanding them can make millions of pairs
+#[derive(Debug)]
+#[derive(Clone)]
+struct ClassKerning {
+    covered: HashSet<u16>,
+    first: HashMap<u16, u16>,
+    second: HashMap<u16, u16>,
+    second_count: usize,
+    values: Vec<i16>,
+}
+
+/// Read a GPOS pair adjustment subtable, in either format
+fn read_pair_adjustment(gpos: &Reader, at: usize, kerning: &mut Kerning) -> Result<()> {
+    let mut header = gpos.at(at);
+    let format = try!(header.u16());
+    let coverage = try!(read_coverage(gpos, at + try!(header.u16()) as usize));
+    let first_format = try!(header.u16());
+    let second_format = try!(header.u16());
+    let first_size = value_size(first_format);
+    let record = first_size + value_size(second_format);
+    let advance = advance_offset(first_format);
+
+    match format {
+        1 => {
+            let sets = try!(header.u16()) as usize;
+            if sets > coverage.len() {
+                return Err(invalid("GPOS", "there are more pair sets than covered glyphs"));
+            }
+            for &left in coverage.iter().take(sets) {
+                let set = at + try!(header.u16()) as usize;
+                let mut pairs = gpos.at(set);
+                let count = try!(pairs.u16()) as usize;
+                for j in 0..count {
+                    let at = set + 2 + j * (2 + record);
+                    let right = try!(gpos.at(at).u16());
+                    let value = match advance {
+                        Some(offset) => try!(gpos.at(at + 2 + offset).i16()),
+                        None => 0,
+                    };
+                    kerning.pairs.entry((left, right)).or_insert(value);
+                }
+            }
+        }
+        2 => {
+            let first = try!(read_classes(gpos, at + try!(header.u16()) as usize));
+            let second = try!(read_classes(gpos, at + try!(header.u16()) as usize));
+            let first_count = try!(header.u16()) as usize;
+            let second_count = try!(header.u16()) as usize;
+            // Without advances every value is 0, which is what a missing value reads as anyway
+            let mut values = Vec::new();
+            if let Some(offset) = advance {
+                // The records have to be there before anything is allocated for them
+                let size = first_count.checked_mul(second_count).and_then(|n| n.checked_mul(record));
+                match size {
+                    Some(size) => try!(gpos.at(at + 16).bytes(size)),
+                    None => return Err(invalid("GPOS", "a class pair table is too big")),
+                };
+                let count = first_count * second_count;
+                values.reserve(count);
+                for i in 0..count {
+                    values.push(try!(gpos.at(at + 16 + i * record + offset).i16()));
+                }
+            }
+            kerning.classes.push(ClassKerning {
+                covered: coverage.into_iter().collect(),
+                first: first,
+                second: second,
+                second_count: second_count,
+                values: values,
+            });
+        }
+        _ => return Err(invalid("GPOS", "a pair adjustment is in an unknown format")),
+    }
+    Ok(())
+}
+
+/// The glyphs a coverage table covers, in coverage index order
+fn read_coverage(table: &Reader, at: usize) -> Result<Vec<u16>> {
+    let mut coverage = table.at(at);
+    let format = try!(coverage.u16());
+    let count = try!(coverage.u16());
+    let mut glyphs = Vec::new();
+    for _ in 0..count {
+        match format {
+            1 => glyphs.push(try!(coverage.u16())),
+            2 => {
+                let start = try!(coverage.u16());
+                let end = try!(coverage.u16());
+                coverage.skip(2);
+                glyphs.extend(start..end.saturating_add(1));
+            }
+            _ => return Err(invalid(table.name, "a coverage table is in an unknown format")),
+        }
+    }
+    Ok(glyphs)
+}
+
+/// A class definition table, as glyph to class. Glyphs left out are class 0.
+fn read_classes(table: &Reader, at: usize) -> Result<HashMap<u16, u16>> {
+    let mut classes = HashMap::new();
+    let mut def = table.at(at);
+    match try!(def.u16()) {
+        1 => {
+            let start = try!(def.u16());
+            for i in 0..try!(def.u16()) {
+                classes.insert(start.wrapping_add(i), try!(def.u16()));
+            }
+        }
+        2 => {
+            for _ in 0..try!(def.u16()) {
+                let start = try!(def.u16());
+                let end = try!(def.u16());
+                let class = try!(def.u16());
+                for glyph in start..end.saturating_add(1) {
+                    classes.insert(glyph, class);
+                }
+            }
+        }
+        _ => return Err(invalid(table.name, "a class definition is in an unknown format")),
+    }
+    Ok(classes)
+}
+
+/// Bytes in a ValueRecord, two for each field present
+fn value_size(format: u16) -> usize {
+    format.count_ones() as usize * 2
+}
+
+/// Where a ValueRecord's x advance is, after the placement fields before it
+fn advance_offset(format: u16) -> Option<usize> {
+    if format & VALUE_X_ADVANCE != 0 {
+        Some(value_size(format & (VALUE_X_ADVANCE - 1)))
+    } else {
+        None
+    }
+}
+
+/// The table directory, as tag to (offset, length)
+fn read_directory(data: &[u8]) -> Result<BTreeMap<[u8; 4], (usize, usize)>> {
+    let mut header = Reader::new(data, "sfnt");
+    match try!(header.u32()) {
+        TRUETYPE | TRUETYPE_APPLE | OPENTYPE_CFF => {}
+        COLLECTION => return Err(invalid("sfnt", "font collections (.ttc) aren't supported")),
+        _ => return Err(invalid("sfnt", "this isn't a TrueType or OpenType font")),
+    }
+    let count = try!(header.u16());
+    header.skip(6);
+    let mut tables = BTreeMap::new();
+    for _ in 0..count {
+        let mut tag = [0; 4];
+        tag.copy_from_slice(try!(header.bytes(4)));
+        header.skip(4);
+        let offset = try!(header.u32()) as usize;
+        let length = try!(header.u32()) as usize;
+        if offset.checked_add(length).map_or(true, |end| end > data.len()) {
+            return Err(invalid("sfnt", format!("the {} table runs past the end of the file",
+                                               String::from_utf8_lossy(&tag))));
+        }
+        tables.insert(tag, (offset, length));
+    }
+    Ok(tables)
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks(2).filter(|c| c.len() == 2).map(|c| (c[0] as u16) << 8 | c[1] as u16).collect();
+    String::from_utf16_lossy(&units)
+}
+
+pub fn invalid<S: Into<String>>(table: &str, note: S) -> Error {
+    RenderError::new(RenderKind::InvalidFont(table.to_owned())).note(note).into()
+}
+
+/// Big endian reads from a table, which fail rather than run off its end
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    name: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8], name: &'static str) -> Reader<'a> {
+        Reader { data: data, pos: 0, name: name }
+    }
+
+    /// Another reader over the same table, starting at `pos`
+    pub fn at(&self, pos: usize) -> Reader<'a> {
+        Reader { data: self.data, pos: pos, name: self.name }
+    }
+
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    pub fn skip(&mut self, n: usize) {
+        self.pos += n;
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        match self.pos.checked_add(n) {
+            Some(end) if end <= self.data.len() => {
+                let bytes = &self.data[self.pos..end];
+                self.pos = end;
+                Ok(bytes)
+            }
+            _ => Err(invalid(self.name, "it ends too soon")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        let b = try!(self.bytes(2));
+        Ok((b[0] as u16) << 8 | b[1] as u16)
+    }
+
+    pub fn i16(&mut self) -> Result<i16> {
+        self.u16().map(|n| n as i16)
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        let b = try!(self.bytes(4));
+        Ok((b[0] as u32) << 24 | (b[1] as u32) << 16 | (b[2] as u32) << 8 | b[3] as u32)
+    }
+}
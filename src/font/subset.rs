@@ -0,0 +1,222 @@
+//! Font subsetting, for embedding
+//!
+//! Glyph IDs are kept as they are, so text shown with the subset uses the same IDs as with the
+//! whole font. Glyphs which aren't used are emptied rather than removed, which leaves `hmtx`
+//! alone and costs four bytes each in `loca`.
+
+use std::collections::BTreeSet;
+
+use error::Result;
+use font::sfnt::{ invalid, Font, Reader };
+
+/// The tables a PDF needs from an embedded TrueType font, plus `cmap` for readers which want it
+const KEPT_TABLES: &'static [&'static [u8; 4]] = &[
+    b"cmap", b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"prep",
+];
+
+/// Composite glyph flags
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+/// What the whole file's checksum should come to
+const CHECKSUM_MAGIC: u32 = 0xB1B0_AFBA;
+
+impl Font {
+    /// A copy of the font with only some glyphs, and the glyphs they're built from.
+    ///
+    /// Fonts with CFF outlines are returned whole, since we can't subset CFF.
+    pub fn subset(&self, glyphs: &BTreeSet<u16>) -> Result<Vec<u8>> {
+        let (glyf, loca) = match (self.table(b"glyf"), self.table(b"loca")) {
+            (Some(glyf), Some(loca)) => (glyf, loca),
+            _ => return Ok(self.data().to_vec()),
+        };
+        let offsets = try!(read_loca(loca, self.long_loca(), self.num_glyphs(), glyf.len()));
+        let outline = |g: u16| &glyf[offsets[g as usize]..offsets[g as usize + 1]];
+
+        // .notdef is always kept, then every component of every composite
+        let mut keep: BTreeSet<u16> = glyphs.iter().cloned().filter(|&g| g < self.num_glyphs()).collect();
+        keep.insert(0);
+        let mut todo: Vec<u16> = keep.iter().cloned().collect();
+        while let Some(glyph) = todo.pop() {
+            for component in try!(components(outline(glyph))) {
+                if component < self.num_glyphs() && keep.insert(component) {
+                    todo.push(component);
+                }
+            }
+        }
+
+        let mut new_glyf = Vec::new();
+        let mut new_loca = Vec::new();
+        for glyph in 0..self.num_glyphs() {
+            push_u32(&mut new_loca, new_glyf.len() as u32);
+            if keep.contains(&glyph) {
+                new_glyf.extend_from_slice(outline(glyph));
+                while new_glyf.len() % 4 != 0 {
+                    new_glyf.push(0);
+                }
+            }
+        }
+        push_u32(&mut new_loca, new_glyf.len() as u32);
+
+        let mut tables = Vec::new();
+        for tag in KEPT_TABLES {
+            let data = match &tag[..] {
+                b"glyf" => new_glyf.clone(),
+                b"loca" => new_loca.clone(),
+                b"head" => {
+                    let mut head = self.table(b"head").unwrap_or(&[]).to_vec();
+                    if head.len() < 54 {
+                        return Err(invalid("head", "it ends too soon"));
+                    }
+                    // The loca we wrote is long, and the checksum is set once everything's written
+                    head[50] = 0;
+                    head[51] = 1;
+                    head[8..12].copy_from_slice(&[0; 4]);
+                    head
+                }
+                _ => match self.table(tag) {
+                    Some(data) => data.to_vec(),
+                    None => continue,
+                },
+            };
+            tables.push((**tag, data));
+        }
+        Ok(write_sfnt(0x0001_0000, &tables))
+    }
+}
+
+/// Glyph offsets into `glyf`, with one more at the end for the last glyph's end
+fn read_loca(loca: &[u8], long: bool, num_glyphs: u16, glyf_len: usize) -> Result<Vec<usize>> {
+    let mut reader = Reader::new(loca, "loca");
+    let mut offsets = Vec::with_capacity(num_glyphs as usize + 1);
+    for _ in 0..num_glyphs as usize + 1 {
+        let offset = if long {
+            try!(reader.u32()) as usize
+        } else {
+            try!(reader.u16()) as usize * 2
+        };
+        if offset > glyf_len || offsets.last().map_or(false, |&last| offset < last) {
+            return Err(invalid("loca", "a glyph's offset is out of order or past the end of glyf"));
+        }
+        offsets.push(offset);
+    }
+    Ok(offsets)
+}
+
+/// The glyphs a composite glyph is built from. Simple glyphs have none.
+fn components(outline: &[u8]) -> Result<Vec<u16>> {
+    let mut glyph = Reader::new(outline, "glyf");
+    if outline.is_empty() || try!(glyph.i16()) >= 0 {
+        return Ok(Vec::new());
+    }
+    glyph.seek(10);
+    let mut components = Vec::new();
+    loop {
+        let flags = try!(glyph.u16());
+        components.push(try!(glyph.u16()));
+        glyph.skip(if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 });
+        if flags & WE_HAVE_A_SCALE != 0 {
+            glyph.skip(2);
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            glyph.skip(4);
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            glyph.skip(8);
+        }
+        if flags & MORE_COMPONENTS == 0 {
+            return Ok(components);
+        }
+    }
+}
+
+/// Write a font file from its tables, which must be sorted by tag.
+/// Sets `head`'s checksum adjustment, if there's a `head`.
+pub fn write_sfnt(version: u32, tables: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let count = tables.len() as u16;
+    let mut power = 1;
+    let mut selector = 0;
+    while power * 2 <= count {
+        power *= 2;
+        selector += 1;
+    }
+
+    let mut out = Vec::new();
+    push_u32(&mut out, version);
+    push_u16(&mut out, count);
+    push_u16(&mut out, power * 16);
+    push_u16(&mut out, selector);
+    push_u16(&mut out, count * 16 - power * 16);
+
+    let mut offset = 12 + 16 * tables.len();
+    let mut head = None;
+    for &(tag, ref data) in tables {
+        if &tag == b"head" {
+            head = Some(offset);
+        }
+        out.extend_from_slice(&tag);
+        push_u32(&mut out, checksum(data));
+        push_u32(&mut out, offset as u32);
+        push_u32(&mut out, data.len() as u32);
+        offset += (data.len() + 3) / 4 * 4;
+    }
+    for &(_, ref data) in tables {
+        out.extend_from_slice(data);
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+    }
+
+    if let Some(head) = head {
+        let adjustment = CHECKSUM_MAGIC.wrapping_sub(checksum(&out));
+        out[head + 8..head + 12].copy_from_slice(&be_u32(adjustment));
+    }
+    out
+}
+
+/// The sum of a table's big endian u32s, padded with zeroes
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add((word[0] as u32) << 24 | (word[1] as u32) << 16 | (word[2] as u32) << 8 | word[3] as u32)
+    })
+}
+
+fn be_u32(n: u32) -> [u8; 4] {
+    [(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+fn push_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&be_u32(n));
+}
+
+fn push_u16(out: &mut Vec<u8>, n: u16) {
+    out.push((n >> 8) as u8);
+    out.push(n as u8);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeSet;
+    use font::Font;
+    use font::test::tiny_font;
+
+    #[test]
+    fn keeps_used_glyphs_and_components() {
+        let font = Font::parse(tiny_font()).unwrap();
+        let used: BTreeSet<u16> = font.glyph('Ä').into_iter().collect();
+        let data = font.subset(&used).unwrap();
+        assert_eq!(CHECKSUM_MAGIC, checksum(&data));
+
+        let subset = Font::parse(data).unwrap();
+        assert_eq!(4, subset.num_glyphs());
+        assert_eq!(None, subset.table(b"kern"));
+        // A, then Ä, with V emptied out
+        let offsets = read_loca(subset.table(b"loca").unwrap(), true, 4, 28).unwrap();
+        assert_eq!(vec![0, 0, 12, 12, 28], offsets);
+        assert_eq!(&font.table(b"glyf").unwrap()[24..], &subset.table(b"glyf").unwrap()[12..]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Source formatting
+//!
+//! Formatting is deliberately conservative: lines are only re-indented to their `[{ }]` depth,
+//! stripped of trailing whitespace, and runs of blank lines are collapsed. Comments and line
+//! breaks inside paragraphs are kept as written.
+
+use lexer::{ Lexer, TextSpan, Token };
+
+const INDENT: &'static str = "    ";
+
+/// Format a litx source file
+pub fn format(source: &str) -> String {
+    let tokens: Vec<(Token, TextSpan)> = Lexer::new(source).collect();
+    let mut out = String::with_capacity(source.len());
+    let mut depth = 0usize;
+    let mut next_token = 0;
+    let mut offset = 0;
+    let mut pending_blank = false;
+
+    for line in source.split('\n') {
+        let start = offset;
+        let end = start + line.len();
+        offset = end + 1;
+
+        // Lines inside a multi-line quote are content, so leave them be
+        let in_quote = tokens.iter().any(|&(ref t, span)| {
+            if let Token::Quote(_) = *t { span.low < start && span.high > start } else { false }
+        });
+        if in_quote {
+            out.push_str(line);
+            out.push('\n');
+            while next_token < tokens.len() && tokens[next_token].1.low < end {
+                depth = adjust(depth, &tokens[next_token].0);
+                next_token += 1;
+            }
+            continue;
+        }
+
+        // Leading closes belong to the outer level
+        let mut line_depth = depth;
+        let mut leading = true;
+        while next_token < tokens.len() && tokens[next_token].1.low < end {
+            let token = &tokens[next_token].0;
+            if leading && *token == Token::Close {
+                line_depth = line_depth.saturating_sub(1);
+            } else if *token != Token::BlankLine {
+                leading = false;
+            }
+            depth = adjust(depth, token);
+            next_token += 1;
+        }
+
+        let quote_open_at_end = tokens.iter().any(|&(ref t, span)| {
+            if let Token::Quote(_) = *t { span.low < end && span.high > end } else { false }
+        });
+        let content = if quote_open_at_end { line.trim_left() } else { line.trim() };
+        if content.is_empty() {
+            pending_blank = !out.is_empty();
+            continue;
+        }
+        if pending_blank {
+            out.push('\n');
+            pending_blank = false;
+        }
+        for _ in 0 .. line_depth {
+            out.push_str(INDENT);
+        }
+        out.push_str(content);
+        out.push('\n');
+    }
+    out
+}
+
+fn adjust(depth: usize, token: &Token) -> usize {
+    match *token {
+        Token::Open => depth + 1,
+        Token::Close => depth.saturating_sub(1),
+        _ => depth,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::format;
+
+    #[test]
+    fn reindents() {
+        let src = "[{document\n  ::meta [{\n::title Foo\n      }]\n||\n  Some text\n}]";
+        let expected = "[{document\n    ::meta [{\n        ::title Foo\n    }]\n    ||\n    Some text\n}]\n";
+        assert_eq!(expected, format(src));
+    }
+
+    #[test]
+    fn collapses_blank_lines() {
+        let src = "\n\n[{ a\n\n\n\n    b   \n}]\n\n";
+        let expected = "[{ a\n\n    b\n}]\n";
+        assert_eq!(expected, format(src));
+    }
+
+    #[test]
+    fn keeps_comments_and_quotes() {
+        let src = "[{ a\n// [{ not an open\n::q ''two\n   lines'' }]";
+        let expected = "[{ a\n    // [{ not an open\n    ::q ''two\n   lines'' }]\n";
+        assert_eq!(expected, format(src));
+    }
+
+    #[test]
+    fn idempotent() {
+        let src = include_str!("../examples/strategy/mla.litx");
+        let once = format(src);
+        assert_eq!(once, format(&once));
+    }
+}
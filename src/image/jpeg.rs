@@ -0,0 +1,147 @@
+//! JPEG files
+//!
+//! Only the frame header is read, for the image's size and color components, and whether Adobe
+//! made it, since Adobe stores CMYK inverted. The compressed data is embedded as it is, since PDF
+//! readers decode JPEGs themselves, though only with 8 bit samples.
+
+use error::Result;
+use super::{ invalid, ColorSpace };
+
+/// What every JPEG file starts with, the start of image marker
+pub const SIGNATURE: &'static [u8] = b"\xFF\xD8";
+
+/// Start of scan, after which there's no frame header to find
+const SOS: u8 = 0xDA;
+/// The application segment Adobe marks its JPEGs with
+const APP14: u8 = 0xEE;
+
+/// A JPEG's frame header
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub precision: u8,
+    pub color_space: ColorSpace,
+    /// Whether there's an Adobe segment before the frame header
+    pub adobe: bool,
+}
+
+/// Find a JPEG's frame header
+pub fn frame(data: &[u8]) -> Result<Frame> {
+    if !data.starts_with(SIGNATURE) {
+        return Err(invalid("it isn't a JPEG"));
+    }
+    let mut pos = SIGNATURE.len();
+    let mut adobe = false;
+    loop {
+        // Markers may be padded with any number of 0xFFs
+        while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let marker = match (data.get(pos), data.get(pos + 1)) {
+            (Some(&0xFF), Some(&marker)) => marker,
+            _ => return Err(invalid("it has no frame header")),
+        };
+        pos += 2;
+        // Restart markers and the like stand alone
+        if marker == 0x01 || (marker >= 0xD0 && marker <= 0xD7) {
+            continue;
+        }
+        if marker == SOS {
+            return Err(invalid("its scan starts before its frame header"));
+        }
+        let length = match (data.get(pos), data.get(pos + 1)) {
+            (Some(&high), Some(&low)) => (high as usize) << 8 | low as usize,
+            _ => return Err(invalid("it ends too soon")),
+        };
+        if marker == APP14 && data.get(pos + 2..pos + 7) == Some(&b"Adobe"[..]) {
+            adobe = true;
+        }
+        // SOF0 to SOF15, less DHT, JPG and DAC, which share the range
+        let is_frame = marker >= 0xC0 && marker <= 0xCF && marker != 0xC4 && marker != 0xC8 && marker != 0xCC;
+        if is_frame {
+            let header = match data.get(pos + 2..pos + 8) {
+                Some(header) => header,
+                None => return Err(invalid("its frame header ends too soon")),
+            };
+            let height = (header[1] as u32) << 8 | header[2] as u32;
+            let width = (header[3] as u32) << 8 | header[4] as u32;
+            let color_space = match header[5] {
+                1 => ColorSpace::Gray,
+                3 => ColorSpace::Rgb,
+                4 => ColorSpace::Cmyk,
+                n => return Err(invalid(format!("it has {} color components", n))),
+            };
+            if width == 0 || height == 0 {
+                return Err(invalid("its height is only given after its frame header"));
+            }
+            if header[0] != 8 {
+                return Err(invalid(format!("it has {} bit samples, and only 8 bit JPEGs can be embedded", header[0])));
+            }
+            return Ok(Frame {
+                width: width,
+                height: height,
+                precision: header[0],
+                color_space: color_space,
+                adobe: adobe,
+            });
+        }
+        pos += length;
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use error::{ Error, RenderKind };
+
+    /// The start of a baseline JPEG, up to its frame header
+    pub fn jpeg(width: u16, height: u16, components: u8) -> Vec<u8> {
+        let mut data = SIGNATURE.to_vec();
+        // A JFIF APP0 segment, for something to skip
+        data.extend_from_slice(b"\xFF\xE0\x00\x10JFIF\x00\x01\x01\x00\x00\x01\x00\x01\x00\x00");
+        data.extend_from_slice(&[0xFF, 0xFF, 0xC0, 0, 8 + 3 * components as u8, 8,
+                                 (height >> 8) as u8, height as u8, (width >> 8) as u8, width as u8, components]);
+        for id in 0..components {
+            data.extend_from_slice(&[id + 1, 0x11, 0]);
+        }
+        data.extend_from_slice(b"\xFF\xD9");
+        data
+    }
+
+    /// A JPEG with an Adobe segment after its start, saying its colors are transformed
+    pub fn adobe(jpeg: Vec<u8>) -> Vec<u8> {
+        let mut data = SIGNATURE.to_vec();
+        data.extend_from_slice(b"\xFF\xEE\x00\x0EAdobe\x00\x64\x00\x00\x00\x00\x02");
+        data.extend_from_slice(&jpeg[SIGNATURE.len() ..]);
+        data
+    }
+
+    #[test]
+    fn finds_the_frame_header() {
+        let found = frame(&jpeg(640, 480, 3)).unwrap();
+        assert_eq!((640, 480, 8, ColorSpace::Rgb), (found.width, found.height, found.precision, found.color_space));
+        assert_eq!(ColorSpace::Cmyk, frame(&jpeg(1, 1, 4)).unwrap().color_space);
+        assert!(frame(&jpeg(1, 1, 2)).is_err());
+        assert!(frame(b"\xFF\xD8\xFF\xDA\x00\x02").is_err());
+        assert!(frame(b"\xFF\xD8").is_err());
+    }
+
+    #[test]
+    fn notices_adobe_and_refuses_12_bits() {
+        assert!(!frame(&jpeg(1, 1, 4)).unwrap().adobe);
+        assert!(frame(&adobe(jpeg(1, 1, 4))).unwrap().adobe);
+
+        let mut deep = jpeg(1, 1, 3);
+        let at = deep.windows(2).position(|pair| pair == [0xFF, 0xC0]).unwrap() + 4;
+        deep[at] = 12;
+        match frame(&deep) {
+            Err(Error::RenderFailure(ref failure)) => {
+                let why = "it has 12 bit samples, and only 8 bit JPEGs can be embedded".to_owned();
+                assert_eq!(&RenderKind::InvalidImage(why), failure.kind());
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Images
+//!
+//! PNG and JPEG files are read far enough to know how many pixels they are and how their colors
+//! are stored, and kept whole to be embedded. JPEGs are embedded as they are. PNGs' pixels are
+//! too, unless they have alpha, which is split from their colors to embed apart, so `zlib` can
+//! inflate and deflate them. Interlaced PNGs aren't read.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use error::{ Error, RenderError, RenderKind, Result };
+
+mod jpeg;
+mod png;
+mod zlib;
+
+/// An image file's format
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ImageFormat {
+    /// PNG
+    Png,
+    /// JPEG
+    Jpeg,
+}
+
+/// How an image's colors are stored
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ColorSpace {
+    /// Shades of gray
+    Gray,
+    /// Red, green and blue
+    Rgb,
+    /// Cyan, magenta, yellow and black
+    Cmyk,
+    /// Indexes into a palette of red, green and blue
+    Indexed(Vec<u8>),
+}
+
+impl ColorSpace {
+    /// How many components each pixel's color has
+    pub fn components(&self) -> usize {
+        match *self {
+            ColorSpace::Gray | ColorSpace::Indexed(_) => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+        }
+    }
+}
+
+/// A PNG or JPEG file, with its size
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Image {
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    color_space: ColorSpace,
+    bits: u8,
+    alpha: bool,
+    inverted: bool,
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// Read an image file
+    pub fn open(path: &Path) -> Result<Image> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) => {
+                let name = path.display().to_string();
+                return Err(RenderError::new(RenderKind::MissingResource(name)).in_file(path).caused_by(e).into());
+            }
+        };
+        Image::parse(data).map_err(|e| match e {
+            Error::RenderFailure(failure) => Error::RenderFailure(failure.in_file(path)),
+            other => other,
+        })
+    }
+
+    /// Parse an image from its bytes
+    pub fn parse(data: Vec<u8>) -> Result<Image> {
+        if data.starts_with(png::SIGNATURE) {
+            let header = try!(png::header(&data));
+            Ok(Image {
+                format: ImageFormat::Png,
+                width: header.width,
+                height: header.height,
+                color_space: header.color_space,
+                bits: header.bit_depth,
+                alpha: header.alpha,
+                inverted: false,
+                data: data,
+            })
+        } else if data.starts_with(jpeg::SIGNATURE) {
+            let frame = try!(jpeg::frame(&data));
+            // Adobe's CMYK JPEGs store how little of each ink there is
+            let inverted = frame.adobe && frame.color_space == ColorSpace::Cmyk;
+            Ok(Image {
+                format: ImageFormat::Jpeg,
+                width: frame.width,
+                height: frame.height,
+                color_space: frame.color_space,
+                bits: frame.precision,
+                alpha: false,
+                inverted: inverted,
+                data: data,
+            })
+        } else {
+            Err(invalid("only PNG and JPEG images can be used"))
+        }
+    }
+
+    /// Its format
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    /// How many pixels wide it is
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// How many pixels tall it is
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// How its colors are stored
+    pub fn color_space(&self) -> &ColorSpace {
+        &self.color_space
+    }
+
+    /// Bits in each color component, or palette index
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    /// Whether it has alpha, so parts of it are see-through
+    pub fn has_alpha(&self) -> bool {
+        self.alpha
+    }
+
+    /// Whether its color components are stored inverted, as in Adobe's CMYK JPEGs
+    pub fn is_inverted(&self) -> bool {
+        self.inverted
+    }
+
+    /// The whole file
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Its media type, like `image/png`
+    pub fn media_type(&self) -> &'static str {
+        match self.format {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    /// A PNG's pixels, compressed with zlib, with a filter type byte before each row
+    pub fn scanlines(&self) -> Result<Vec<u8>> {
+        png::scanlines(&self.data)
+    }
+
+    /// A PNG with alpha's colors and alpha, unfiltered, apart and each compressed with zlib
+    pub fn split_alpha(&self) -> Result<(Vec<u8>, Vec<u8>)> {
+        let (color, alpha) = try!(png::split_alpha(&self.data));
+        Ok((zlib::deflate(&color), zlib::deflate(&alpha)))
+    }
+}
+
+impl fmt::Debug for Image {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Image({:?}, {}x{})", self.format, self.width, self.height)
+    }
+}
+
+fn invalid<S: Into<String>>(why: S) -> Error {
+    RenderError::new(RenderKind::InvalidImage(why.into())).into()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::fs;
+    use document::{ Document, Strategy };
+    use lexer::Lexer;
+    use parser;
+    use scratch::Scratch;
+
+    /// A 2x1 PNG, a red pixel and a see-through one
+    pub fn tiny_png() -> Vec<u8> {
+        png::test::png(2, 1, 6, &[0, 255, 0, 0, 255, 0, 0, 0, 0], None)
+    }
+
+    /// The frame header of a 4x3 RGB JPEG
+    pub fn tiny_jpeg() -> Vec<u8> {
+        jpeg::test::jpeg(4, 3, 3)
+    }
+
+    /// The frame header of a 2x2 CMYK JPEG, which may be from Adobe
+    pub fn cmyk_jpeg(adobe: bool) -> Vec<u8> {
+        let data = jpeg::test::jpeg(2, 2, 4);
+        if adobe { jpeg::test::adobe(data) } else { data }
+    }
+
+    /// Build a document in a new directory of its own, beside `photo.jpg`, a `tiny_jpeg`, and
+    /// `dot.png`, a `tiny_png`. The directory is gone again by the time this returns.
+    pub fn beside_images(src: &str, strategy: Strategy) -> Result<Document> {
+        let dir = Scratch::new("images");
+        fs::write(dir.join("photo.jpg"), tiny_jpeg()).unwrap();
+        fs::write(dir.join("dot.png"), tiny_png()).unwrap();
+        Document::from_ast_in(parser::parse(Lexer::new(src)).unwrap(), strategy, &dir)
+    }
+
+    #[test]
+    fn reads_sizes() {
+        let png = Image::parse(tiny_png()).unwrap();
+        assert_eq!((ImageFormat::Png, 2, 1, true), (png.format(), png.width(), png.height(), png.has_alpha()));
+        assert_eq!("image/png", png.media_type());
+        let (color, alpha) = png.split_alpha().unwrap();
+        assert_eq!(vec![255, 0, 0, 0, 0, 0], zlib::inflate(&color, 6).unwrap());
+        assert_eq!(vec![255, 0], zlib::inflate(&alpha, 2).unwrap());
+
+        let jpeg = Image::parse(tiny_jpeg()).unwrap();
+        assert_eq!((ImageFormat::Jpeg, 4, 3), (jpeg.format(), jpeg.width(), jpeg.height()));
+        assert_eq!(3, jpeg.color_space().components());
+        match Image::parse(b"GIF89a".to_vec()) {
+            Err(Error::RenderFailure(ref failure)) => {
+                assert_eq!(&RenderKind::InvalidImage("only PNG and JPEG images can be used".to_owned()), failure.kind());
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
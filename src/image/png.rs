@@ -0,0 +1,253 @@
+//! PNG files
+//!
+//! Only the chunks needed to embed the image are read: `IHDR` for its size and pixel layout,
+//! `PLTE` for a palette, and `IDAT` for its pixels. Interlaced images aren't read, and neither are
+//! images of more pixels than `MAX_PIXELS`, so a damaged or hostile header can't exhaust memory.
+
+use error::Result;
+use super::{ invalid, ColorSpace };
+use super::zlib;
+
+/// What every PNG file starts with
+pub const SIGNATURE: &'static [u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The most pixels an image may have, so its size can't overflow or exhaust memory
+const MAX_PIXELS: u64 = 1 << 28;
+
+/// Color types
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const INDEXED: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGB_ALPHA: u8 = 6;
+
+/// A PNG's `IHDR`, and its palette
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_space: ColorSpace,
+    pub alpha: bool,
+}
+
+/// Read a PNG's size and how its pixels are laid out
+pub fn header(data: &[u8]) -> Result<Header> {
+    let chunks = try!(chunks(data));
+    let ihdr = match chunks.first() {
+        Some(&(name, ihdr)) if name == b"IHDR" && ihdr.len() >= 13 => ihdr,
+        _ => return Err(invalid("it doesn't start with an IHDR chunk")),
+    };
+    let width = u32_at(ihdr, 0);
+    let height = u32_at(ihdr, 4);
+    let (bit_depth, color_type) = (ihdr[8], ihdr[9]);
+    if width == 0 || height == 0 {
+        return Err(invalid("it has no pixels"));
+    }
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(invalid(format!("{}x{} is more pixels than can be embedded", width, height)));
+    }
+    if ihdr[12] != 0 {
+        return Err(invalid("interlaced PNGs can't be embedded"));
+    }
+    let color_space = match color_type {
+        GRAY | GRAY_ALPHA => ColorSpace::Gray,
+        RGB | RGB_ALPHA => ColorSpace::Rgb,
+        INDEXED => match chunks.iter().find(|&&(name, _)| name == b"PLTE") {
+            Some(&(_, palette)) => ColorSpace::Indexed(palette.to_vec()),
+            None => return Err(invalid("it has indexed colors, but no palette")),
+        },
+        _ => return Err(invalid(format!("{} isn't a PNG color type", color_type))),
+    };
+    let depths: &[u8] = match color_type {
+        GRAY => &[1, 2, 4, 8, 16],
+        INDEXED => &[1, 2, 4, 8],
+        _ => &[8, 16],
+    };
+    if !depths.contains(&bit_depth) {
+        return Err(invalid(format!("{} bits isn't a depth its color type can have", bit_depth)));
+    }
+    let alpha = color_type == GRAY_ALPHA || color_type == RGB_ALPHA;
+    Ok(Header { width: width, height: height, bit_depth: bit_depth, color_space: color_space, alpha: alpha })
+}
+
+/// A PNG's pixels, still compressed, with a filter type byte before each row
+pub fn scanlines(data: &[u8]) -> Result<Vec<u8>> {
+    let chunks = try!(chunks(data));
+    let mut idat = Vec::new();
+    for (_, chunk) in chunks.into_iter().filter(|&(name, _)| name == b"IDAT") {
+        idat.extend_from_slice(chunk);
+    }
+    Ok(idat)
+}
+
+/// A PNG's colors and alpha, unfiltered and apart, for images with alpha
+pub fn split_alpha(data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let header = try!(header(data));
+    if !header.alpha {
+        return Err(invalid("it has no alpha to split"));
+    }
+    let colors = match header.color_space {
+        ColorSpace::Rgb => 3,
+        _ => 1,
+    };
+    let channels = colors + 1;
+    let sample = header.bit_depth as usize / 8;
+    let (width, height) = (header.width as usize, header.height as usize);
+    let size = try!(filtered_size(width, height, channels * sample));
+    let filtered = try!(zlib::inflate(&try!(scanlines(data)), size));
+    let pixels = try!(unfilter(&filtered, width, height, channels * sample));
+    let mut color = Vec::with_capacity(pixels.len() / channels * colors);
+    let mut alpha = Vec::with_capacity(pixels.len() / channels);
+    for pixel in pixels.chunks(channels * sample) {
+        color.extend_from_slice(&pixel[..colors * sample]);
+        alpha.extend_from_slice(&pixel[colors * sample..]);
+    }
+    Ok((color, alpha))
+}
+
+/// How many bytes rows of pixels take, with a filter type byte before each
+fn filtered_size(width: usize, height: usize, bytes_per_pixel: usize) -> Result<usize> {
+    if width.checked_mul(height).map_or(true, |pixels| pixels as u64 > MAX_PIXELS) {
+        return Err(invalid("it has more pixels than can be embedded"));
+    }
+    width.checked_mul(bytes_per_pixel)
+        .and_then(|stride| stride.checked_add(1))
+        .and_then(|row| row.checked_mul(height))
+        .ok_or_else(|| invalid("it has more pixels than can be embedded"))
+}
+
+/// Undo each row's filter, for pixels whole bytes wide
+fn unfilter(data: &[u8], width: usize, height: usize, bytes_per_pixel: usize) -> Result<Vec<u8>> {
+    let size = try!(filtered_size(width, height, bytes_per_pixel));
+    if data.len() < size {
+        return Err(invalid("its pixels end too soon"));
+    }
+    let stride = width * bytes_per_pixel;
+    let mut out = vec![0u8; size - height];
+    for y in 0..height {
+        let filter = data[y * (stride + 1)];
+        let row = &data[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        for x in 0..stride {
+            let left = if x >= bytes_per_pixel { out[y * stride + x - bytes_per_pixel] } else { 0 };
+            let up = if y > 0 { out[(y - 1) * stride + x] } else { 0 };
+            let up_left = if y > 0 && x >= bytes_per_pixel { out[(y - 1) * stride + x - bytes_per_pixel] } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(invalid(format!("{} isn't a PNG filter", filter))),
+            };
+            out[y * stride + x] = row[x].wrapping_add(predicted);
+        }
+    }
+    Ok(out)
+}
+
+/// Whichever neighbour is closest to left + up - up left
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (a, b, c) = ((estimate - left as i16).abs(), (estimate - up as i16).abs(), (estimate - up_left as i16).abs());
+    if a <= b && a <= c { left } else if b <= c { up } else { up_left }
+}
+
+/// Each chunk's name and data, in order, up to `IEND`
+fn chunks(data: &[u8]) -> Result<Vec<(&[u8], &[u8])>> {
+    if !data.starts_with(SIGNATURE) {
+        return Err(invalid("it isn't a PNG"));
+    }
+    let mut chunks = Vec::new();
+    let mut pos = SIGNATURE.len();
+    while pos + 8 <= data.len() {
+        let length = u32_at(data, pos) as usize;
+        let name = &data[pos + 4..pos + 8];
+        let end = match (pos + 8).checked_add(length) {
+            Some(end) if end + 4 <= data.len() => end,
+            _ => return Err(invalid(format!("its {} chunk ends too soon", String::from_utf8_lossy(name)))),
+        };
+        if name == b"IEND" {
+            break;
+        }
+        chunks.push((name, &data[pos + 8..end]));
+        // The CRC isn't checked
+        pos = end + 4;
+    }
+    Ok(chunks)
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    (data[pos] as u32) << 24 | (data[pos + 1] as u32) << 16 | (data[pos + 2] as u32) << 8 | data[pos + 3] as u32
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use image::zlib::deflate;
+
+    /// A PNG of filtered rows, with no CRCs
+    pub fn png(width: u32, height: u32, color_type: u8, rows: &[u8], palette: Option<&[u8]>) -> Vec<u8> {
+        let mut data = SIGNATURE.to_vec();
+        let mut chunk = |name: &[u8], body: &[u8]| {
+            let length = body.len() as u32;
+            data.extend_from_slice(&[(length >> 24) as u8, (length >> 16) as u8, (length >> 8) as u8, length as u8]);
+            data.extend_from_slice(name);
+            data.extend_from_slice(body);
+            data.extend_from_slice(&[0; 4]);
+        };
+        let mut ihdr = Vec::new();
+        for n in &[width, height] {
+            ihdr.extend_from_slice(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, *n as u8]);
+        }
+        ihdr.extend_from_slice(&[8, color_type, 0, 0, 0]);
+        chunk(b"IHDR", &ihdr);
+        if let Some(palette) = palette {
+            chunk(b"PLTE", palette);
+        }
+        chunk(b"IDAT", &deflate(rows));
+        chunk(b"IEND", &[]);
+        data
+    }
+
+    #[test]
+    fn splits_alpha_from_filtered_rows() {
+        // Two gray and alpha pixels a row: unfiltered, then up, then sub, then average
+        let rows = [0, 10, 255, 20, 128, 2, 1, 0, 1, 1, 1, 5, 1, 3, 0, 3, 4, 1, 200, 4];
+        let data = png(2, 4, GRAY_ALPHA, &rows, None);
+        let header = header(&data).unwrap();
+        assert_eq!((2, 4, 8, true), (header.width, header.height, header.bit_depth, header.alpha));
+        let (color, alpha) = split_alpha(&data).unwrap();
+        assert_eq!(vec![10, 20, 11, 21, 5, 8, 6, 207], color);
+        assert_eq!(vec![255, 128, 255, 129, 1, 1, 1, 5], alpha);
+    }
+
+    #[test]
+    fn rejects_huge_images() {
+        let mut data = png(1, 1, RGB_ALPHA, &[0, 1, 2, 3, 4], None);
+        // An IHDR claiming 65536x65536 pixels
+        data[16..24].copy_from_slice(&[0, 1, 0, 0, 0, 1, 0, 0]);
+        assert!(header(&data).is_err());
+        assert!(split_alpha(&data).is_err());
+        assert!(filtered_size(usize::max_value(), 2, 4).is_err());
+        assert!(unfilter(&[0; 8], usize::max_value() / 2, 1, 4).is_err());
+    }
+
+    #[test]
+    fn reads_palettes() {
+        let data = png(1, 1, INDEXED, &[0, 0], Some(&[255, 0, 0]));
+        assert_eq!(ColorSpace::Indexed(vec![255, 0, 0]), header(&data).unwrap().color_space);
+        assert!(header(&png(1, 1, INDEXED, &[0, 0], None)).is_err());
+        assert!(header(&data[..20]).is_err());
+        assert!(header(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn predicts_with_paeth() {
+        assert_eq!(10, paeth(10, 10, 10));
+        assert_eq!(20, paeth(10, 20, 5));
+        assert_eq!(10, paeth(10, 20, 30));
+        assert_eq!(20, paeth(30, 10, 20));
+    }
+}
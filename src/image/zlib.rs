@@ -0,0 +1,379 @@
+//! zlib streams, which PNGs compress their pixels with
+//!
+//! Inflating reads any deflate stream. Deflating is only good enough for embedding: matches are
+//! found with a single hash probe and written with the fixed Huffman codes, in one block.
+
+use error::{ Error, Result };
+use super::invalid;
+
+/// The base length of each length code from 257, and how many extra bits follow it
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+/// The base distance of each distance code, and how many extra bits follow it
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// The order a dynamic block's code length code lengths are in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// How far back matches may be
+const WINDOW: usize = 32768;
+/// The longest match
+const MAX_MATCH: usize = 258;
+/// Bits in the hash of the three bytes starting a match
+const HASH_BITS: u32 = 15;
+
+/// Inflate a zlib stream, which mustn't inflate to more than `limit` bytes
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    if data.len() < 2 || data[0] & 0x0F != 8 || (data[0] as u16 * 256 + data[1] as u16) % 31 != 0 {
+        return Err(invalid("its pixels aren't compressed with deflate"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(invalid("its pixels need a preset dictionary"));
+    }
+    let mut bits = Bits { data: &data[2..], pos: 0, buffer: 0, count: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = try!(bits.read(1)) == 1;
+        match try!(bits.read(2)) {
+            0 => try!(stored(&mut bits, &mut out, limit)),
+            1 => {
+                let (lengths, distances) = fixed();
+                try!(codes(&mut bits, &mut out, limit, &lengths, &distances));
+            }
+            2 => {
+                let (lengths, distances) = try!(dynamic(&mut bits));
+                try!(codes(&mut bits, &mut out, limit, &lengths, &distances));
+            }
+            _ => return Err(invalid("its pixels have a damaged deflate block")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// Deflate data into a zlib stream
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter { out: vec![0x78, 0x01], buffer: 0, count: 0 };
+    // The last block, with fixed codes
+    out.write(1, 1);
+    out.write(1, 2);
+    let mut head = vec![usize::max_value(); 1 << HASH_BITS];
+    let mut i = 0;
+    while i < data.len() {
+        let mut length = 0;
+        let mut distance = 0;
+        if i + 3 <= data.len() {
+            let hash = hash(&data[i..i + 3]);
+            let candidate = head[hash];
+            head[hash] = i;
+            if candidate != usize::max_value() && i - candidate <= WINDOW {
+                let longest = (data.len() - i).min(MAX_MATCH);
+                while length < longest && data[candidate + length] == data[i + length] {
+                    length += 1;
+                }
+                distance = i - candidate;
+            }
+        }
+        if length >= 3 {
+            let code = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap_or(0);
+            out.symbol(257 + code as u16);
+            out.write((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+            let code = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap_or(0);
+            out.code(code as u32, 5);
+            out.write((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+            // Later matches can start inside this one
+            for j in i + 1..(i + length).min(data.len().saturating_sub(2)) {
+                head[hash(&data[j..j + 3])] = j;
+            }
+            i += length;
+        } else {
+            out.symbol(data[i] as u16);
+            i += 1;
+        }
+    }
+    out.symbol(256);
+    let mut out = out.finish();
+    let checksum = adler32(data);
+    out.extend_from_slice(&[(checksum >> 24) as u8, (checksum >> 16) as u8, (checksum >> 8) as u8, checksum as u8]);
+    out
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let n = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    (n.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+/// Bits read from the least significant end of each byte first
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn read(&mut self, n: u32) -> Result<u32> {
+        while self.count < n {
+            let byte = match self.data.get(self.pos) {
+                Some(&byte) => byte,
+                None => return Err(invalid("its pixels end too soon")),
+            };
+            self.buffer |= (byte as u32) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u32 << n) - 1);
+        self.buffer = if n == 32 { 0 } else { self.buffer >> n };
+        self.count -= n;
+        Ok(value)
+    }
+}
+
+/// A canonical Huffman code, as how many codes there are of each length and the symbols in order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0; 16];
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate().filter(|&(_, &length)| length > 0) {
+            symbols[offsets[length as usize] as usize] = symbol as u16;
+            offsets[length as usize] += 1;
+        }
+        Huffman { counts: counts, symbols: symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= try!(bits.read(1)) as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("its pixels have a damaged Huffman code"))
+    }
+}
+
+fn stored(bits: &mut Bits, out: &mut Vec<u8>, limit: usize) -> Result<()> {
+    // Stored blocks start on a byte
+    bits.buffer = 0;
+    bits.count = 0;
+    let header = match bits.data.get(bits.pos..bits.pos + 4) {
+        Some(header) => header,
+        None => return Err(invalid("its pixels end too soon")),
+    };
+    let length = header[0] as usize | (header[1] as usize) << 8;
+    if length != !(header[2] as usize | (header[3] as usize) << 8) & 0xFFFF {
+        return Err(invalid("its pixels have a damaged stored block"));
+    }
+    bits.pos += 4;
+    if out.len() + length > limit {
+        return Err(too_long());
+    }
+    match bits.data.get(bits.pos..bits.pos + length) {
+        Some(stored) => out.extend_from_slice(stored),
+        None => return Err(invalid("its pixels end too soon")),
+    }
+    bits.pos += length;
+    Ok(())
+}
+
+fn fixed() -> (Huffman, Huffman) {
+    let mut lengths = [8; 288];
+    for length in &mut lengths[144..256] {
+        *length = 9;
+    }
+    for length in &mut lengths[256..280] {
+        *length = 7;
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic(bits: &mut Bits) -> Result<(Huffman, Huffman)> {
+    let literals = try!(bits.read(5)) as usize + 257;
+    let distances = try!(bits.read(5)) as usize + 1;
+    let code_lengths = try!(bits.read(4)) as usize + 4;
+    let mut lengths = [0; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[i] = try!(bits.read(3)) as u8;
+    }
+    let code = Huffman::new(&lengths);
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (length, repeat) = match try!(code.decode(bits)) {
+            symbol @ 0...15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(&previous) => (previous, 3 + try!(bits.read(2))),
+                None => return Err(invalid("its pixels have a damaged Huffman code")),
+            },
+            17 => (0, 3 + try!(bits.read(3))),
+            _ => (0, 11 + try!(bits.read(7))),
+        };
+        for _ in 0..repeat {
+            lengths.push(length);
+        }
+    }
+    if lengths.len() > literals + distances {
+        return Err(invalid("its pixels have a damaged Huffman code"));
+    }
+    Ok((Huffman::new(&lengths[..literals]), Huffman::new(&lengths[literals..])))
+}
+
+fn codes(bits: &mut Bits, out: &mut Vec<u8>, limit: usize, lengths: &Huffman, distances: &Huffman) -> Result<()> {
+    loop {
+        let symbol = try!(lengths.decode(bits)) as usize;
+        if symbol < 256 {
+            if out.len() >= limit {
+                return Err(too_long());
+            }
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let code = symbol - 257;
+        if code >= LENGTH_BASE.len() {
+            return Err(invalid("its pixels have a damaged length"));
+        }
+        let length = LENGTH_BASE[code] as usize + try!(bits.read(LENGTH_EXTRA[code] as u32)) as usize;
+        let code = try!(distances.decode(bits)) as usize;
+        if code >= DISTANCE_BASE.len() {
+            return Err(invalid("its pixels have a damaged distance"));
+        }
+        let distance = DISTANCE_BASE[code] as usize + try!(bits.read(DISTANCE_EXTRA[code] as u32)) as usize;
+        if distance > out.len() {
+            return Err(invalid("its pixels refer back too far"));
+        }
+        if out.len() + length > limit {
+            return Err(too_long());
+        }
+        // Matches may overlap what they copy
+        let start = out.len() - distance;
+        for i in 0..length {
+            let byte = out[start + i];
+            out.push(byte);
+        }
+    }
+}
+
+fn too_long() -> Error {
+    invalid("its pixels inflate to more than its size says")
+}
+
+/// Bits written from the least significant end of each byte first
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, n: u32) {
+        self.buffer |= value << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Write a Huffman code, which goes most significant bit first
+    fn code(&mut self, code: u32, n: u32) {
+        let reversed = (0..n).fold(0, |reversed, bit| reversed << 1 | (code >> bit) & 1);
+        self.write(reversed, n);
+    }
+
+    /// Write a literal or length symbol's fixed code
+    fn symbol(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+        match symbol {
+            0...143 => self.code(0x30 + symbol, 8),
+            144...255 => self.code(0x190 + symbol - 144, 9),
+            256...279 => self.code(symbol - 256, 7),
+            _ => self.code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn inflates_what_it_deflates() {
+        let data: Vec<u8> = b"abcabcabcabc the quick brown fox, the quick brown fox".iter().cloned()
+            .chain((0..2000).map(|n| (n % 7) as u8))
+            .collect();
+        let deflated = deflate(&data);
+        assert!(deflated.len() < data.len() / 4);
+        assert_eq!(data, inflate(&deflated, data.len()).unwrap());
+        assert_eq!(Vec::<u8>::new(), inflate(&deflate(&[]), 0).unwrap());
+        assert!(inflate(&deflated[..deflated.len() / 2], data.len()).is_err());
+        // Nothing is inflated past the limit
+        assert!(inflate(&deflated, data.len() - 1).is_err());
+    }
+
+    #[test]
+    fn inflates_dynamic_and_stored_blocks() {
+        // zlib.compress(text, 9) of some random letters, which it gives dynamic codes
+        let text = "abdccaaabcbbbacaaba daabccacbaccaba aaaabba abaacabacbbbaaabacba aad b baabcdaaca ccb abd \
+                    baaab bdbabdabaccaabaab ccbcaa";
+        let dynamic = [
+            0x78, 0xDA, 0x1D, 0x8C, 0x41, 0x01, 0x00, 0x40, 0x08, 0xC2, 0xAA, 0x50, 0x8D, 0x41, 0xFF, 0x0C, 0x27, 0xF7,
+            0x92, 0x39, 0xD4, 0x34, 0xB1, 0x4D, 0x00, 0x5F, 0xC2, 0xEA, 0x30, 0xCE, 0x71, 0xC6, 0x9E, 0xDF, 0xC4, 0xDE,
+            0xE2, 0x57, 0xFD, 0xC3, 0x64, 0x85, 0xD8, 0x49, 0xA7, 0x95, 0x70, 0xCD, 0xEA, 0x57, 0x44, 0x39, 0xF0, 0x7F,
+            0xB5, 0x7B, 0xE6, 0x2F, 0x3E, 0x48, 0xA2, 0x2B, 0x0D,
+        ];
+        assert_eq!(text.as_bytes(), &inflate(&dynamic, text.len()).unwrap()[..]);
+        // zlib.compress(b"abc", 0), a stored block
+        let stored = [0x78, 0x01, 0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c', 0x02, 0x4D, 0x01, 0x27];
+        assert_eq!(&b"abc"[..], &inflate(&stored, 3).unwrap()[..]);
+        assert!(inflate(&stored, 2).is_err());
+    }
+}
@@ -0,0 +1,335 @@
+//! Finding included files and strategies
+
+use std::fs::File;
+use std::io::Read;
+use std::mem;
+use std::path::{ Path, PathBuf };
+
+use ast;
+use ast::Ast;
+use error::{ Error, EvaluationError, EvaluationKind, RenderError, RenderKind, Result };
+use lexer::{ Lexer, TextSpan, Token };
+use parser;
+use visit::ast::{ Fold, Visitor, walk_expression };
+
+/// Looks up included files and strategies by name in a list of directories.
+#[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Resolver {
+    paths: Vec<PathBuf>,
+}
+
+impl Resolver {
+    /// Create a resolver searching `base`
+    pub fn new<P: AsRef<Path>>(base: P) -> Resolver {
+        Resolver {
+            paths: vec![base.as_ref().to_path_buf()],
+        }
+    }
+
+    /// Add a directory to search after the existing ones
+    pub fn add_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.paths.push(path.as_ref().to_path_buf());
+    }
+
+    /// Find an included file, like `default-text-settings.litxpart`
+    pub fn resolve(&self, name: &str) -> Result<PathBuf> {
+        let name = Path::new(name);
+        if name.is_absolute() && name.is_file() {
+            return Ok(name.to_path_buf());
+        }
+        for dir in &self.paths {
+            let candidate = dir.join(name);
+            if candidate.is_file() {
+                return Ok(candidate);
+            }
+        }
+        Err(self.not_found(EvaluationKind::IncludeNotFound(name.display().to_string())))
+    }
+
+    /// Find a file the document refers to, like an image
+    pub fn resource(&self, name: &str) -> Result<PathBuf> {
+        self.resolve(name).map_err(|_| {
+            let failure = self.paths.iter().fold(RenderError::new(RenderKind::MissingResource(name.to_owned())), |failure, dir| {
+                failure.note(format!("searched {}", dir.display()))
+            });
+            Error::RenderFailure(failure)
+        })
+    }
+
+    /// Find a strategy by the name documents use, like `mla`.
+    ///
+    /// Each directory's `strategy` subdirectory is searched before the directories themselves,
+    /// so a document named `mla.litx` doesn't shadow the `mla` strategy.
+    pub fn strategy(&self, name: &str) -> Result<PathBuf> {
+        let file = format!("{}.litx", name);
+        for dir in &self.paths {
+            let candidate = dir.join("strategy").join(&file);
+            if candidate.is_file() {
+                return Ok(candidate);
+            }
+        }
+        self.resolve(&file).map_err(|_| self.not_found(EvaluationKind::StrategyNotFound(name.to_owned())))
+    }
+
+    fn not_found(&self, kind: EvaluationKind) -> Error {
+        let failure = self.paths.iter().fold(EvaluationError::new(kind), |failure, dir| {
+            failure.note(format!("searched {}", dir.display()))
+        });
+        Error::EvaluationFailure(failure)
+    }
+}
+
+/// An `#[{include ''file''}]` directive
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Include {
+    /// Span of the whole `[{include ...}]` expression
+    pub span: TextSpan,
+    /// The file, as written
+    pub path: String,
+}
+
+/// Find every include directive in a tree, however deeply nested.
+pub fn find_includes(ast: &Ast) -> Vec<Include> {
+    let mut finder = IncludeFinder { found: Vec::new() };
+    finder.visit_ast(ast);
+    finder.found
+}
+
+/// The file an `[{include ...}]` expression names, if it is one
+fn include_path(expr: &ast::Expression) -> Option<&str> {
+    match (expr.repr.get(0), expr.repr.get(1)) {
+        (Some(&ast::Node::Text(_, ref title)), Some(&ast::Node::Text(_, ref path))) if title == "include" => {
+            Some(path)
+        }
+        _ => None,
+    }
+}
+
+struct IncludeFinder {
+    found: Vec<Include>,
+}
+
+impl Visitor for IncludeFinder {
+    fn visit_expression(&mut self, span: TextSpan, expr: &ast::Expression) {
+        match include_path(expr) {
+            Some(path) => self.found.push(Include { span: span, path: path.to_owned() }),
+            None => walk_expression(self, expr),
+        }
+    }
+}
+
+/// Replace every `#[{include ''file''}]` with the contents of the file, so the included nodes
+/// become siblings of the ones around the directive. Includes inside included files are
+/// found relative to the file they're in.
+///
+/// Spans in spliced nodes are from the file they came from, which is listed in `Ast::files`.
+pub fn expand(ast: Ast, resolver: &Resolver) -> Result<Ast> {
+    let mut expander = Expander {
+        resolver: resolver.clone(),
+        stack: Vec::new(),
+        files: ast.files.clone(),
+        file: 0,
+        error: None,
+    };
+    let ast = expander.fold_ast(ast);
+    match expander.error {
+        Some(e) => Err(e),
+        None => Ok(ast),
+    }
+}
+
+/// Name the included file an error is in, if its span is in one of `files`, as in `Ast::files`
+pub fn locate(error: Error, files: &[PathBuf]) -> Error {
+    let file = match error.span().and_then(|span| span.file.checked_sub(1)).and_then(|i| files.get(i)) {
+        Some(file) => file.clone(),
+        None => return error,
+    };
+    match error {
+        Error::EvaluationFailure(failure) => match failure.file() {
+            Some(_) => Error::EvaluationFailure(failure),
+            None => Error::EvaluationFailure(failure.in_file(file)),
+        },
+        Error::RenderFailure(failure) => match failure.file() {
+            Some(_) => Error::RenderFailure(failure),
+            None => Error::RenderFailure(failure.in_file(file)),
+        },
+        other => other,
+    }
+}
+
+struct Expander {
+    resolver: Resolver,
+    /// Files being expanded, to catch include cycles
+    stack: Vec<PathBuf>,
+    /// Every file spliced in so far
+    files: Vec<PathBuf>,
+    /// Which file the nodes being folded are from, as a `TextSpan::file`
+    file: usize,
+    /// The first thing that went wrong. Folds can't fail part way, so this is checked after.
+    error: Option<Error>,
+}
+
+impl Expander {
+    fn included(&mut self, name: &str) -> Result<Vec<ast::Node>> {
+        let path = try!(self.resolver.resolve(name));
+        if self.stack.contains(&path) {
+            let failure = EvaluationError::new(EvaluationKind::IncludeCycle(name.to_owned())).in_file(&path);
+            return Err(Error::EvaluationFailure(failure));
+        }
+        let source = try!(read(&path));
+        let ast = try!(parser::parse(Lexer::new(&source)));
+
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let outer = mem::replace(&mut self.resolver, Resolver::new(dir));
+        let file = match self.files.iter().position(|f| *f == path) {
+            Some(i) => i + 1,
+            None => {
+                self.files.push(path.clone());
+                self.files.len()
+            }
+        };
+        let outer_file = mem::replace(&mut self.file, file);
+        self.stack.push(path);
+        let nodes = self.fold_nodes(ast.repr);
+        self.stack.pop();
+        self.file = outer_file;
+        self.resolver = outer;
+        Ok(nodes)
+    }
+}
+
+impl Fold for Expander {
+    fn fold_ast(&mut self, ast: Ast) -> Ast {
+        let repr = self.fold_nodes(ast.repr);
+        Ast { repr: repr, files: mem::replace(&mut self.files, Vec::new()) }
+    }
+
+    fn fold_span(&mut self, span: TextSpan) -> TextSpan {
+        match self.file {
+            0 => span,
+            file => TextSpan { file: file, ..span },
+        }
+    }
+
+    fn fold_nodes(&mut self, nodes: Vec<ast::Node>) -> Vec<ast::Node> {
+        let mut folded = Vec::with_capacity(nodes.len());
+        let mut nodes = nodes.into_iter().peekable();
+        while let Some(node) = nodes.next() {
+            // The `#` marking a directive goes with it
+            let marker = match node {
+                ast::Node::Text(_, ref text) if text == "#" => true,
+                _ => false,
+            };
+            let directive = match nodes.peek() {
+                Some(&ast::Node::Expression(_, ref e)) => include_path(e).is_some(),
+                _ => false,
+            };
+            if marker && directive {
+                continue;
+            }
+
+            let name = match node {
+                ast::Node::Expression(_, ref e) => include_path(e).map(|p| p.to_owned()),
+                _ => None,
+            };
+            match name {
+                Some(name) => match self.included(&name) {
+                    Ok(nodes) => folded.extend(nodes),
+                    Err(e) => {
+                        if self.error.is_none() {
+                            self.error = Some(e);
+                        }
+                    }
+                },
+                None => folded.push(self.fold_node(node)),
+            }
+        }
+        folded
+    }
+}
+
+/// Every file `path` includes, directly or through other includes.
+///
+/// This is for watching files, so files which can't be read or parsed are still listed,
+/// but aren't looked into.
+pub fn dependencies(path: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    add_dependencies(path, &mut found);
+    found
+}
+
+fn add_dependencies(path: &Path, found: &mut Vec<PathBuf>) {
+    let source = match read(path) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+    let ast = match parser::parse(Lexer::new(&source)) {
+        Ok(ast) => ast,
+        Err(_) => return,
+    };
+    let resolver = Resolver::new(path.parent().unwrap_or(Path::new(".")));
+    for inc in find_includes(&ast) {
+        if let Ok(included) = resolver.resolve(&inc.path) {
+            // Guard against include cycles
+            if !found.contains(&included) && included != path {
+                found.push(included.clone());
+                add_dependencies(&included, found);
+            }
+        }
+    }
+}
+
+/// Read a whole file
+pub fn read(path: &Path) -> Result<String> {
+    let mut source = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut source));
+    Ok(source)
+}
+
+/// The strategy a document asks for with `::strategy name`.
+/// Works from tokens, so it doesn't mind a half-written document.
+pub fn strategy_name(source: &str) -> Option<String> {
+    let tokens: Vec<Token> = Lexer::new(source).map(|(t, _)| t).collect();
+    tokens.windows(2)
+        .filter_map(|pair| match (&pair[0], &pair[1]) {
+            (&Token::Key(ref k), &Token::Word(ref name)) if k == "strategy" => Some(name.clone()),
+            _ => None,
+        })
+        .next()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use document::Document;
+    use scratch::Scratch;
+
+    #[test]
+    fn spans_point_into_included_files() {
+        let dir = Scratch::new("include-spans");
+        // Longer than the document, so its offsets would be past the document's end
+        let part = format!("{} $$meta/author", "Padding. ".repeat(20));
+        fs::write(dir.join("part.litxpart"), &part).unwrap();
+        fs::write(dir.join("broken.litxpart"), format!("{} [{{ ref nowhere }}]", part)).unwrap();
+
+        fs::write(dir.join("doc.litx"), "[{document || #[{include ''part.litxpart''}] }]").unwrap();
+        let doc = Document::open(&dir.join("doc.litx")).unwrap();
+        let span = doc.diagnostics().iter().next().and_then(|d| d.span).unwrap();
+        assert_eq!(1, span.file);
+        assert_eq!(Some(dir.join("part.litxpart").as_path()), doc.file_of(span));
+        assert_eq!("$$meta/author", &part[span.low .. span.high]);
+
+        fs::write(dir.join("doc.litx"), "[{document || #[{include ''broken.litxpart''}] }]").unwrap();
+        match Document::open(&dir.join("doc.litx")) {
+            Err(Error::EvaluationFailure(ref failure)) => {
+                assert_eq!(Some(dir.join("broken.litxpart").as_path()), failure.file());
+                assert!(!failure.span().unwrap().is_root());
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
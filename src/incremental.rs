@@ -0,0 +1,274 @@
+//! Incremental reparsing, for editors which re-parse on every keystroke
+//!
+//! An edit only reparses the innermost `[{ ... }]` which contains it. Everything outside that
+//! expression is reused, with its spans shifted past the edit. When the edit could change how
+//! the text around the expression lexes, we fall back on parsing the whole source.
+
+use ast::{ Ast, Node };
+use lexer::{ Lexer, TextSpan };
+use parser;
+use parser::ParseError;
+use visit::ast::{ Visitor, VisitorMut };
+
+/// A replacement of the text in `span` (byte offsets into the old source) with `text`
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Edit {
+    /// The replaced range of the old source
+    pub span: TextSpan,
+    /// The new text
+    pub text: String,
+}
+
+/// Source text, kept parsed as edits are applied to it.
+#[derive(Debug)]
+pub struct ParsedSource {
+    text: String,
+    parsed: Result<Ast, ParseError>,
+}
+
+impl ParsedSource {
+    /// Parse a source from scratch
+    pub fn new(text: String) -> ParsedSource {
+        let parsed = parser::parse(Lexer::new(&text));
+        ParsedSource {
+            text: text,
+            parsed: parsed,
+        }
+    }
+
+    /// The current source text
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The tree for the current text, or the reason it doesn't parse
+    pub fn parsed(&self) -> &Result<Ast, ParseError> {
+        &self.parsed
+    }
+
+    /// Apply an edit and bring the tree up to date.
+    ///
+    /// Returns the span (in the new text) of the expression which was reparsed,
+    /// or None if the whole source had to be parsed again.
+    ///
+    /// A span which is backwards, runs past the end, or splits a character (as a stale one from
+    /// an editor can) is ordered and trimmed to fit, and the whole source is parsed again.
+    pub fn edit(&mut self, edit: &Edit) -> Option<TextSpan> {
+        let (low, high) = (self.boundary(edit.span.low), self.boundary(edit.span.high));
+        let fits = low <= high && (low, high) == (edit.span.low, edit.span.high);
+        let (low, high) = if low <= high { (low, high) } else { (high, low) };
+        let safe = fits && !changes_quoting(&self.text[low .. high]) && !changes_quoting(&edit.text);
+
+        let mut text = String::with_capacity(self.text.len() - (high - low) + edit.text.len());
+        text.push_str(&self.text[.. low]);
+        text.push_str(&edit.text);
+        text.push_str(&self.text[high ..]);
+        self.text = text;
+
+        // Joining two lone quotes makes a quote, so edits next to one are never safe
+        let end = low + edit.text.len();
+        let safe = safe
+            && !self.text[.. low].ends_with('\'')
+            && !self.text[end ..].starts_with('\'');
+
+        let delta = edit.text.len() as isize - (high - low) as isize;
+        let reparsed = match self.parsed {
+            Ok(ref mut ast) if safe => reparse(&mut ast.repr, &self.text, TextSpan::new(low, high), delta),
+            _ => None,
+        };
+        if reparsed.is_none() {
+            self.parsed = parser::parse(Lexer::new(&self.text));
+        }
+        reparsed
+    }
+
+    /// The nearest character boundary at or before `offset` in the current text
+    fn boundary(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+}
+
+/// Quotes are the only tokens which can reach across `[{ }]`, and only quote marks and parens
+/// change where they end.
+fn changes_quoting(text: &str) -> bool {
+    text.contains(|c| c == '\'' || c == '(' || c == ')')
+}
+
+/// Reparse the innermost expression in `nodes` which contains `edit`, then shift everything after it.
+/// Returns the new span of the reparsed expression.
+fn reparse(nodes: &mut Vec<Node>, text: &str, edit: TextSpan, delta: isize) -> Option<TextSpan> {
+    let found = nodes.iter().position(|node| contains(node, edit));
+    let index = match found {
+        Some(index) => index,
+        None => return None,
+    };
+    let reparsed = match reparse_node(&mut nodes[index], text, edit, delta) {
+        Some(span) => span,
+        None => return None,
+    };
+    for node in &mut nodes[index + 1 ..] {
+        shift(node, delta);
+    }
+    Some(reparsed)
+}
+
+fn reparse_node(node: &mut Node, text: &str, edit: TextSpan, delta: isize) -> Option<TextSpan> {
+    let span = match *node {
+        Node::Property(ref mut span, _, ref mut value) => {
+            let reparsed = reparse_node(value, text, edit, delta);
+            if reparsed.is_some() {
+                span.high = offset(span.high, delta);
+            }
+            return reparsed;
+        }
+        Node::Expression(ref mut span, ref mut e) => {
+            // Prefer the smallest expression we can get away with
+            if let Some(reparsed) = reparse(&mut e.repr, text, edit, delta) {
+                span.high = offset(span.high, delta);
+                return Some(reparsed);
+            }
+            *span
+        }
+        _ => return None,
+    };
+
+    let new_span = TextSpan::new(span.low, offset(span.high, delta));
+    let mut ast = match parser::parse(Lexer::new(&text[new_span.low .. new_span.high])) {
+        Ok(ast) => ast,
+        Err(_) => return None,
+    };
+    // The expression has to still be exactly one expression, and mustn't open a quote which
+    // could close somewhere after it.
+    if ast.repr.len() != 1 || unterminated_quote(&ast.repr[0]) {
+        return None;
+    }
+    let mut replacement = ast.repr.pop().unwrap();
+    match replacement {
+        Node::Expression(s, _) if s.low == 0 && s.high == new_span.high - new_span.low => {}
+        _ => return None,
+    }
+    shift_all(&mut replacement, new_span.low as isize);
+    *node = replacement;
+    Some(new_span)
+}
+
+/// Whether the edit is strictly inside an expression in this node, not touching its brackets
+fn contains(node: &Node, edit: TextSpan) -> bool {
+    match *node {
+        Node::Expression(span, _) => span.low + 2 <= edit.low && edit.high + 2 <= span.high,
+        Node::Property(_, _, ref value) => contains(value, edit),
+        _ => false,
+    }
+}
+
+fn unterminated_quote(node: &Node) -> bool {
+    let mut finder = QuoteFinder { found: false };
+    finder.visit_node(node);
+    finder.found
+}
+
+/// Looks for words which start like a quote, but don't end like one
+struct QuoteFinder {
+    found: bool,
+}
+
+impl Visitor for QuoteFinder {
+    fn visit_text(&mut self, _span: TextSpan, text: &str) {
+        self.found |= text.starts_with("''");
+    }
+}
+
+fn offset(x: usize, delta: isize) -> usize {
+    (x as isize + delta) as usize
+}
+
+/// Move a node which comes after the edit
+fn shift(node: &mut Node, delta: isize) {
+    if delta != 0 {
+        shift_all(node, delta);
+    }
+}
+
+fn shift_all(node: &mut Node, delta: isize) {
+    Shift(delta).visit_node_mut(node);
+}
+
+/// Moves every span in a tree
+struct Shift(isize);
+
+impl VisitorMut for Shift {
+    fn visit_span_mut(&mut self, span: &mut TextSpan) {
+        span.low = offset(span.low, self.0);
+        span.high = offset(span.high, self.0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lexer::{ Lexer, TextSpan };
+    use parser;
+
+    /// Apply an edit incrementally, check it against a full parse, and return what was reparsed
+    fn test(source: &str, low: usize, high: usize, text: &str) -> Option<TextSpan> {
+        let mut parsed = ParsedSource::new(source.to_owned());
+        let reparsed = parsed.edit(&Edit { span: TextSpan::new(low, high), text: text.to_owned() });
+        let expected = parser::parse(Lexer::new(parsed.text())).unwrap();
+        assert_eq!(&expected, parsed.parsed().as_ref().unwrap());
+        reparsed
+    }
+
+    #[test]
+    fn reparses_innermost() {
+        let src = "[{ a [{ b c }] d }] [{ e }]";
+        assert_eq!(Some(TextSpan::new(5, 16)), test(src, 8, 9, "bee"));
+    }
+
+    #[test]
+    fn shifts_following_nodes() {
+        let src = "[{ a ::p [{ b }] ::q $$v || d }]\n\n[{ e }]";
+        assert_eq!(Some(TextSpan::new(9, 15)), test(src, 12, 13, ""));
+    }
+
+    #[test]
+    fn fits_bad_spans() {
+        // Backwards
+        assert_eq!(None, test("[{ a [{ bb }] }]", 10, 8, "c"));
+        // Past the end
+        assert_eq!(None, test("[{ a }] ", 7, 40, "[{ b }]"));
+        // Inside the `é`
+        assert_eq!(None, test("[{ é }]", 4, 4, "x"));
+        let mut parsed = ParsedSource::new("[{ é }]".to_owned());
+        parsed.edit(&Edit { span: TextSpan::new(4, 5), text: "e".to_owned() });
+        assert_eq!("[{ e }]", parsed.text());
+    }
+
+    #[test]
+    fn grows_new_expressions() {
+        let src = "[{ a [{ b }] }]";
+        assert_eq!(Some(TextSpan::new(5, 20)), test(src, 8, 8, "[{ x }] "));
+    }
+
+    #[test]
+    fn widens_on_broken_structure() {
+        let src = "[{ a [{ b }] c }]";
+        assert_eq!(Some(TextSpan::new(0, 22)), test(src, 8, 8, "}] [{"));
+    }
+
+    #[test]
+    fn falls_back_on_quotes() {
+        let src = "[{ a [{ b }] }]";
+        assert_eq!(None, test(src, 8, 9, "''c''"));
+    }
+
+    #[test]
+    fn falls_back_outside_expressions() {
+        let src = "[{ a }] b";
+        assert_eq!(None, test(src, 8, 9, "c"));
+    }
+}
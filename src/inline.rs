@@ -0,0 +1,240 @@
+//! Inline formatting
+//!
+//! `[{ i || ... }]`, `[{ b || ... }]`, `[{ sc || ... }]` and `[{ sup || ... }]` set their text in
+//! italics, bold, small capitals or superscript, without starting a paragraph. Punctuation after
+//! one follows it without a space, as superscript follows the word before it. They nest:
+//!
+//! ```text
+//! [{ i || The [{ b || Very }] Hungry Caterpillar }], 1969.
+//! ```
+//!
+//! Paragraphs keep them as a tree, which renderers with markup of their own walk, and flatten it
+//! into runs of text for renderers which set the text themselves.
+
+use font::Variant;
+use reference::{ Reference, Target };
+
+/// How big small capitals are, next to capitals
+pub const SMALL_CAPS_SCALE: f64 = 0.8;
+/// How big superscript is, next to the text around it
+pub const SUPERSCRIPT_SCALE: f64 = 0.6;
+/// How far superscript is raised, in ems of the text around it
+pub const SUPERSCRIPT_RISE: f64 = 0.35;
+
+/// An inline expression's formatting
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Format {
+    /// `[{ i || ... }]`
+    Italic,
+    /// `[{ b || ... }]`
+    Bold,
+    /// `[{ sc || ... }]`
+    SmallCaps,
+    /// `[{ sup || ... }]`
+    Superscript,
+}
+
+impl Format {
+    /// The format an expression sets, from its title
+    pub fn from_title(title: Option<&str>) -> Option<Format> {
+        match title {
+            Some("i") => Some(Format::Italic),
+            Some("b") => Some(Format::Bold),
+            Some("sc") => Some(Format::SmallCaps),
+            Some("sup") => Some(Format::Superscript),
+            _ => None,
+        }
+    }
+}
+
+/// Every format some text is in
+#[derive(Debug)]
+#[derive(Copy, Clone, Default)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Formats {
+    /// In italics
+    pub italic: bool,
+    /// In bold
+    pub bold: bool,
+    /// In small capitals
+    pub small_caps: bool,
+    /// In superscript
+    pub superscript: bool,
+    /// A note's number, where it's referred to
+    pub note: bool,
+    /// What the text links to, for cross-references
+    pub link: Option<Target>,
+}
+
+impl Formats {
+    /// These formats, and one more
+    pub fn with(mut self, format: Format) -> Formats {
+        match format {
+            Format::Italic => self.italic = true,
+            Format::Bold => self.bold = true,
+            Format::SmallCaps => self.small_caps = true,
+            Format::Superscript => self.superscript = true,
+        }
+        self
+    }
+
+    /// The font variant to set the text in
+    pub fn variant(&self) -> Variant {
+        Variant { bold: self.bold, italic: self.italic }
+    }
+
+    /// The text's size, as a share of its paragraph's
+    pub fn scale(&self) -> f64 {
+        let mut scale = 1.0;
+        if self.small_caps {
+            scale *= SMALL_CAPS_SCALE;
+        }
+        if self.superscript {
+            scale *= SUPERSCRIPT_SCALE;
+        }
+        scale
+    }
+
+    /// How far the text is raised, in ems of its paragraph's size
+    pub fn rise(&self) -> f64 {
+        if self.superscript { SUPERSCRIPT_RISE } else { 0.0 }
+    }
+}
+
+/// Formatted text
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Inline {
+    /// Text, with the spaces around its words
+    Text(String),
+    /// Text in a format
+    Styled(Format, Vec<Inline>),
+    /// A reference to a note, by number, which is set as its number in superscript
+    Note(usize),
+    /// A cross-reference, set as its text
+    Ref(Reference),
+}
+
+/// Text all in the same formats
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+pub struct Run {
+    /// The text, with the spaces around its words
+    pub text: String,
+    /// Its formats
+    pub formats: Formats,
+}
+
+impl Run {
+    /// Unformatted text
+    pub fn plain(text: &str) -> Run {
+        Run { text: text.to_owned(), formats: Formats::default() }
+    }
+}
+
+/// Flatten inlines into runs of text, with neighbours in the same formats joined
+pub fn runs(inlines: &[Inline]) -> Vec<Run> {
+    let mut runs = Vec::new();
+    add_runs(inlines, Formats::default(), &mut runs);
+    runs
+}
+
+fn add_runs(inlines: &[Inline], formats: Formats, runs: &mut Vec<Run>) {
+    for inline in inlines {
+        match *inline {
+            Inline::Text(ref text) => {
+                if let Some(last) = runs.last_mut() {
+                    if last.formats == formats {
+                        last.text.push_str(text);
+                        continue;
+                    }
+                }
+                runs.push(Run { text: text.clone(), formats: formats });
+            }
+            Inline::Styled(format, ref inner) => add_runs(inner, formats.with(format), runs),
+            Inline::Note(number) => {
+                let formats = Formats { note: true, ..formats.with(Format::Superscript) };
+                runs.push(Run { text: number.to_string(), formats: formats });
+            }
+            Inline::Ref(ref reference) => {
+                let formats = Formats { link: reference.target, ..formats };
+                runs.push(Run { text: reference.text.clone(), formats: formats });
+            }
+        }
+    }
+}
+
+/// Inlines as plain text
+pub fn plain(inlines: &[Inline]) -> String {
+    runs(inlines).into_iter().map(|run| run.text).collect()
+}
+
+/// The numbers of the notes some inlines refer to, in order
+pub fn notes(inlines: &[Inline]) -> Vec<usize> {
+    let mut found = Vec::new();
+    for inline in inlines {
+        match *inline {
+            Inline::Text(_) | Inline::Ref(_) => {}
+            Inline::Styled(_, ref inner) => found.extend(notes(inner)),
+            Inline::Note(number) => found.push(number),
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+
+    #[test]
+    fn keeps_formats() {
+        let src = "[{document || Read [{ i || The [{ b || Very }] Hungry }], [{ sc || now }]. }]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        let paragraph = &doc.body()[0];
+        assert_eq!("Read The Very Hungry, now.", paragraph.text());
+        assert_eq!(&[
+            Inline::Text("Read ".to_owned()),
+            Inline::Styled(Format::Italic, vec![
+                Inline::Text("The ".to_owned()),
+                Inline::Styled(Format::Bold, vec![Inline::Text("Very".to_owned())]),
+                Inline::Text(" Hungry".to_owned()),
+            ]),
+            Inline::Text(", ".to_owned()),
+            Inline::Styled(Format::SmallCaps, vec![Inline::Text("now".to_owned())]),
+            Inline::Text(".".to_owned()),
+        ][..], paragraph.inlines());
+
+        let italic = Formats::default().with(Format::Italic);
+        let runs = paragraph.runs();
+        assert_eq!(Run::plain("Read "), runs[0]);
+        assert_eq!(Run { text: "The ".to_owned(), formats: italic }, runs[1]);
+        assert_eq!(Run { text: "Very".to_owned(), formats: italic.with(Format::Bold) }, runs[2]);
+    }
+
+    #[test]
+    fn formats_carry_across_paragraphs() {
+        let doc = Document::new("[{document || [{ i || One\n\nTwo }] Three }]", Strategy::none()).unwrap();
+        assert_eq!(&[Inline::Styled(Format::Italic, vec![Inline::Text("One".to_owned())])][..], doc.body()[0].inlines());
+        assert_eq!(&[
+            Inline::Styled(Format::Italic, vec![Inline::Text("Two".to_owned())]),
+            Inline::Text(" Three".to_owned()),
+        ][..], doc.body()[1].inlines());
+    }
+
+    #[test]
+    fn scales_and_raises() {
+        let sup = Formats::default().with(Format::Superscript);
+        assert_eq!(SUPERSCRIPT_SCALE, sup.scale());
+        assert_eq!(SUPERSCRIPT_RISE, sup.rise());
+        assert_eq!(0.0, Formats::default().rise());
+        assert_eq!(Variant { bold: true, italic: false }, Formats::default().with(Format::Bold).variant());
+    }
+}
@@ -0,0 +1,408 @@
+//! Minimal JSON values, just enough to speak JSON-RPC
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{ Display, Formatter };
+
+/// How deeply arrays and objects may nest, so a hostile message can't overflow the stack
+const MAX_DEPTH: usize = 128;
+
+/// A JSON value
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub enum Json {
+    /// null
+    Null,
+    /// true or false
+    Bool(bool),
+    /// Any number. JSON doesn't distinguish, so neither do we.
+    Number(f64),
+    /// "string"
+    String(String),
+    /// [ ... ]
+    Array(Vec<Json>),
+    /// { ... }
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    /// Parse a JSON value from a string
+    pub fn parse(source: &str) -> Option<Json> {
+        let mut parser = Parser { source: source.as_bytes(), pos: 0, depth: 0 };
+        let value = parser.value();
+        parser.whitespace();
+        if parser.pos == parser.source.len() { value } else { None }
+    }
+
+    /// Build an object from a list of key-value pairs
+    pub fn object(pairs: Vec<(&str, Json)>) -> Json {
+        let mut map = BTreeMap::new();
+        for (key, value) in pairs {
+            map.insert(key.to_owned(), value);
+        }
+        Json::Object(map)
+    }
+
+    /// Look up a key, if this is an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /// Follow a path of keys through nested objects
+    pub fn find(&self, path: &[&str]) -> Option<&Json> {
+        let mut current = self;
+        for key in path {
+            current = match current.get(key) {
+                Some(next) => next,
+                None => return None,
+            };
+        }
+        Some(current)
+    }
+
+    /// The contained string, if any
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The contained number as an unsigned integer, if it is one
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Json::Number(n) if n >= 0.0 && n.fract() == 0.0 => Some(n as u64),
+            _ => None,
+        }
+    }
+
+    /// The contained array, if any
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match *self {
+            Json::Array(ref v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl<'a> From<&'a str> for Json {
+    fn from(s: &'a str) -> Json {
+        Json::String(s.to_owned())
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => {
+                if n.fract() == 0.0 && n.abs() < 1e15 {
+                    write!(f, "{}", n as i64)
+                } else {
+                    write!(f, "{}", n)
+                }
+            }
+            Json::String(ref s) => write_string(f, s),
+            Json::Array(ref v) => {
+                try!(write!(f, "["));
+                for (i, item) in v.iter().enumerate() {
+                    if i > 0 { try!(write!(f, ",")); }
+                    try!(write!(f, "{}", item));
+                }
+                write!(f, "]")
+            }
+            Json::Object(ref map) => {
+                try!(write!(f, "{{"));
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 { try!(write!(f, ",")); }
+                    try!(write_string(f, key));
+                    try!(write!(f, ":{}", value));
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter, s: &str) -> fmt::Result {
+    try!(write!(f, "\""));
+    for c in s.chars() {
+        match c {
+            '"' => try!(write!(f, "\\\"")),
+            '\\' => try!(write!(f, "\\\\")),
+            '\n' => try!(write!(f, "\\n")),
+            '\r' => try!(write!(f, "\\r")),
+            '\t' => try!(write!(f, "\\t")),
+            c if (c as u32) < 0x20 => try!(write!(f, "\\u{:04x}", c as u32)),
+            c => try!(write!(f, "{}", c)),
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Recursive descent over the raw bytes
+struct Parser<'a> {
+    source: &'a [u8],
+    pos: usize,
+    /// How many arrays and objects we're inside
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.source.get(self.pos).cloned()
+    }
+
+    fn whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(b' ') | Some(b'\t') | Some(b'\r') | Some(b'\n') => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn literal(&mut self, lit: &str, value: Json) -> Option<Json> {
+        if self.source[self.pos ..].starts_with(lit.as_bytes()) {
+            self.pos += lit.len();
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.whitespace();
+        match self.peek() {
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => self.nested(Parser::array),
+            Some(b'{') => self.nested(Parser::object),
+            Some(_) => self.number(),
+            None => None,
+        }
+    }
+
+    /// Parse an array or object, unless it's nested too deeply
+    fn nested(&mut self, parse: fn(&mut Parser<'a>) -> Option<Json>) -> Option<Json> {
+        if self.depth == MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(b'0' ... b'9') | Some(b'-') | Some(b'+') | Some(b'.') | Some(b'e') | Some(b'E') =>
+                    self.pos += 1,
+                _ => break,
+            }
+        }
+        ::std::str::from_utf8(&self.source[start .. self.pos]).ok()
+            .and_then(|s| s.parse().ok())
+            .map(Json::Number)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        // Skip the opening quote
+        self.pos += 1;
+        let mut buf: Vec<u8> = Vec::new();
+        loop {
+            match self.peek() {
+                None => return None,
+                Some(b'"') => {
+                    self.pos += 1;
+                    return String::from_utf8(buf).ok();
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut code = match self.hex4() {
+                                Some(code) => code,
+                                None => return None,
+                            };
+                            // Surrogate pairs come as two escapes. A high surrogate without a low
+                            // one after it is replaced, and whatever follows is read on its own.
+                            let next = self.source.get(self.pos + 1 ..).unwrap_or(&[]);
+                            if code >= 0xD800 && code < 0xDC00 && next.starts_with(b"\\u") {
+                                let high = self.pos;
+                                self.pos += 2;
+                                match self.hex4() {
+                                    Some(low) if low >= 0xDC00 && low <= 0xDFFF => {
+                                        code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                                    }
+                                    _ => self.pos = high,
+                                }
+                            }
+                            ::std::char::from_u32(code).unwrap_or('\u{FFFD}')
+                        }
+                        _ => return None,
+                    };
+                    let mut utf8 = String::new();
+                    utf8.push(escaped);
+                    buf.extend_from_slice(utf8.as_bytes());
+                    self.pos += 1;
+                }
+                Some(b) => {
+                    buf.push(b);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Reads the four hex digits after a \u, leaving pos on the last digit
+    fn hex4(&mut self) -> Option<u32> {
+        let start = self.pos + 1;
+        if start + 4 > self.source.len() {
+            return None;
+        }
+        let digits = match ::std::str::from_utf8(&self.source[start .. start + 4]) {
+            Ok(digits) => digits,
+            Err(_) => return None,
+        };
+        self.pos = start + 3;
+        u32::from_str_radix(digits, 16).ok()
+    }
+
+    fn array(&mut self) -> Option<Json> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Some(Json::Array(items));
+        }
+        loop {
+            match self.value() {
+                Some(v) => items.push(v),
+                None => return None,
+            }
+            self.whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Some(Json::Array(items));
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn object(&mut self) -> Option<Json> {
+        self.pos += 1;
+        let mut map = BTreeMap::new();
+        self.whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Some(Json::Object(map));
+        }
+        loop {
+            self.whitespace();
+            if self.peek() != Some(b'"') {
+                return None;
+            }
+            let key = match self.string() {
+                Some(key) => key,
+                None => return None,
+            };
+            self.whitespace();
+            if self.peek() != Some(b':') {
+                return None;
+            }
+            self.pos += 1;
+            match self.value() {
+                Some(v) => { map.insert(key, v); }
+                None => return None,
+            }
+            self.whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Some(Json::Object(map));
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let src = r#"{"a":[1,2.5,true,null],"b":"x\"y\n"}"#;
+        let json = Json::parse(src).unwrap();
+        assert_eq!(src, json.to_string());
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        let json = Json::parse(r#""\u00e9\ud83d\ude00""#).unwrap();
+        assert_eq!(Some("\u{e9}\u{1F600}"), json.as_str());
+    }
+
+    #[test]
+    fn replaces_unpaired_surrogates() {
+        assert_eq!(Some(Json::from("\u{FFFD}A")), Json::parse(r#""\ud800\u0041""#));
+        assert_eq!(Some(Json::from("\u{FFFD}\u{FFFD}")), Json::parse(r#""\udc00\ud800""#));
+        assert_eq!(Some(Json::from("\u{FFFD}x")), Json::parse(r#""\ud800x""#));
+        assert_eq!(None, Json::parse(r#""\ud800\u00"#));
+        assert_eq!(None, Json::parse(r#""\ud800"#));
+    }
+
+    #[test]
+    fn rejects_trailing_garbage() {
+        assert_eq!(None, Json::parse("{} {}"));
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_some());
+        assert_eq!(None, Json::parse(&nested(MAX_DEPTH + 1)));
+        assert_eq!(None, Json::parse(&"{\"a\":[".repeat(100000)));
+    }
+}
@@ -0,0 +1,254 @@
+//! Hyphenation
+//!
+//! Words are hyphenated with Frank Liang's patterns, the way TeX does it. Each pattern is a few
+//! letters with levels between them; every pattern found in a word votes on the gaps it covers,
+//! and gaps whose highest vote is odd may be broken. Patterns for American English are bundled.
+//!
+//! Strategies can list exception words under `::hyphenation`, hyphenated by hand, and set how
+//! many letters must be left on either side of a hyphen.
+
+use std::collections::HashMap;
+
+use error::Result;
+use expression::Expression;
+
+/// The language used when nothing says otherwise
+pub const DEFAULT_LANGUAGE: &'static str = "en-us";
+/// The language which turns hyphenation off
+pub const NO_LANGUAGE: &'static str = "none";
+
+const EN_US_PATTERNS: &'static str = include_str!("patterns/en-us.pat");
+/// The words plain TeX lists as exceptions to its English patterns
+const EN_US_EXCEPTIONS: &'static [&'static str] = &[
+    "as-so-ciate", "as-so-ciates", "dec-li-na-tion", "oblig-a-tory", "phil-an-thropic", "present",
+    "presents", "project", "projects", "reci-procity", "re-cog-ni-zance", "ref-or-ma-tion",
+    "ret-ri-bu-tion", "ta-ble",
+];
+
+/// The bundled patterns and exceptions for a language
+fn bundled(language: &str) -> Option<(&'static str, &'static [&'static str])> {
+    match &language.to_lowercase()[..] {
+        "en" | "en-us" => Some((EN_US_PATTERNS, EN_US_EXCEPTIONS)),
+        _ => None,
+    }
+}
+
+/// Whether text in a language can be hyphenated, or is `none` and isn't meant to be
+pub fn is_supported(language: &str) -> bool {
+    language == NO_LANGUAGE || bundled(language).is_some()
+}
+
+/// A strategy's `::hyphenation` settings
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Hyphenation {
+    /// The fewest letters left before a hyphen
+    pub left: usize,
+    /// The fewest letters carried over after one
+    pub right: usize,
+    /// Words hyphenated by hand, like `ta-ble`, which are used instead of the patterns
+    pub exceptions: Vec<String>,
+}
+
+impl Default for Hyphenation {
+    /// TeX's limits for English
+    fn default() -> Hyphenation {
+        Hyphenation { left: 2, right: 3, exceptions: Vec::new() }
+    }
+}
+
+impl Hyphenation {
+    /// Read `[{ ::left 2 ::right 3 ::exceptions ''ta-ble pro-ject'' }]`
+    pub fn from_expression(expr: &Expression) -> Result<Hyphenation> {
+        let mut hyphenation = Hyphenation::default();
+        for (key, value) in expr.props() {
+            let value = match value.as_value() {
+                Some(value) => value,
+                None => continue,
+            };
+            match key {
+                "left" => hyphenation.left = try!(value.as_count()),
+                "right" => hyphenation.right = try!(value.as_count()),
+                "exceptions" => {
+                    hyphenation.exceptions = value.to_string().split_whitespace().map(|w| w.to_owned()).collect();
+                }
+                _ => {}
+            }
+        }
+        Ok(hyphenation)
+    }
+
+    /// A hyphenator for a language, or None when it's `none` or there are no patterns for it
+    pub fn hyphenator(&self, language: &str) -> Option<Hyphenator> {
+        let (patterns, exceptions) = match bundled(language) {
+            Some(bundled) => bundled,
+            None => return None,
+        };
+        let mut hyphenator = Hyphenator::new(patterns, self.left, self.right);
+        for word in exceptions.iter().cloned().chain(self.exceptions.iter().map(|w| &w[..])) {
+            hyphenator.add_exception(word);
+        }
+        Some(hyphenator)
+    }
+}
+
+/// Finds where words may be hyphenated
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Hyphenator {
+    /// Pattern letters, to the levels before, between and after them
+    patterns: HashMap<String, Vec<u8>>,
+    /// The most letters in any pattern
+    longest: usize,
+    left: usize,
+    right: usize,
+    /// Lowercase words, to the letters breaks go before
+    exceptions: HashMap<String, Vec<usize>>,
+}
+
+impl Hyphenator {
+    /// Read patterns, separated by whitespace. `%` starts a comment.
+    pub fn new(patterns: &str, left: usize, right: usize) -> Hyphenator {
+        let mut hyphenator = Hyphenator {
+            patterns: HashMap::new(),
+            longest: 0,
+            left: left,
+            right: right,
+            exceptions: HashMap::new(),
+        };
+        for line in patterns.lines() {
+            let line = line.split('%').next().unwrap_or("");
+            for pattern in line.split_whitespace() {
+                let mut letters = String::new();
+                let mut levels = vec![0];
+                for c in pattern.chars() {
+                    match c.to_digit(10) {
+                        Some(level) => *levels.last_mut().unwrap() = level as u8,
+                        None => {
+                            letters.push(c);
+                            levels.push(0);
+                        }
+                    }
+                }
+                hyphenator.longest = hyphenator.longest.max(levels.len() - 1);
+                hyphenator.patterns.insert(letters, levels);
+            }
+        }
+        hyphenator
+    }
+
+    /// Hyphenate a word by hand, like `ta-ble`. A word with no hyphens is never hyphenated.
+    pub fn add_exception(&mut self, word: &str) {
+        let mut letters = String::new();
+        let mut breaks = Vec::new();
+        for c in word.chars() {
+            if c == '-' {
+                breaks.push(letters.chars().count());
+            } else {
+                letters.extend(c.to_lowercase());
+            }
+        }
+        self.exceptions.insert(letters, breaks);
+    }
+
+    /// Where a word may be hyphenated, as byte offsets. Punctuation around the word is skipped,
+    /// and words with anything but letters in them aren't hyphenated.
+    pub fn points(&self, word: &str) -> Vec<usize> {
+        let start = match word.find(char::is_alphabetic) {
+            Some(start) => start,
+            None => return Vec::new(),
+        };
+        let end = word.rfind(char::is_alphabetic).map_or(start, |i| i + word[i..].chars().next().map_or(0, char::len_utf8));
+        let core = &word[start..end];
+        if !core.chars().all(char::is_alphabetic) {
+            return Vec::new();
+        }
+
+        let letters: Vec<char> = core.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect();
+        let n = letters.len();
+        if n < self.left + self.right {
+            return Vec::new();
+        }
+        let lower: String = letters.iter().cloned().collect();
+        let breaks = match self.exceptions.get(&lower) {
+            Some(breaks) => breaks.clone(),
+            None => self.liang(&letters),
+        };
+
+        let offsets: Vec<usize> = core.char_indices().map(|(i, _)| start + i).collect();
+        breaks.into_iter()
+            .filter(|&b| b >= self.left.max(1) && b + self.right.max(1) <= n)
+            .map(|b| offsets[b])
+            .collect()
+    }
+
+    /// The letters a break may go before, by the patterns
+    fn liang(&self, letters: &[char]) -> Vec<usize> {
+        let mut dotted = vec!['.'];
+        dotted.extend_from_slice(letters);
+        dotted.push('.');
+
+        let mut levels = vec![0u8; dotted.len() + 1];
+        for i in 0..dotted.len() {
+            let mut part = String::new();
+            for j in i..dotted.len().min(i + self.longest) {
+                part.push(dotted[j]);
+                if let Some(pattern) = self.patterns.get(&part) {
+                    for (k, &level) in pattern.iter().enumerate() {
+                        levels[i + k] = levels[i + k].max(level);
+                    }
+                }
+            }
+        }
+        // The gap before letter b is after the dot and b letters
+        (1..letters.len()).filter(|&b| levels[b + 1] % 2 == 1).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use expression::Expression;
+    use lexer::Lexer;
+    use parser;
+
+    fn hyphenate(hyphenator: &Hyphenator, word: &str) -> String {
+        let mut out = String::new();
+        let points = hyphenator.points(word);
+        for (i, c) in word.char_indices() {
+            if points.contains(&i) {
+                out.push('-');
+            }
+            out.push(c);
+        }
+        out
+    }
+
+    #[test]
+    fn english_patterns() {
+        let en = Hyphenation::default().hyphenator("en-us").unwrap();
+        assert_eq!("hy-phen-ation", hyphenate(&en, "hyphenation"));
+        assert_eq!("al-go-rithm", hyphenate(&en, "algorithm"));
+        assert_eq!("Beau-ti-ful;", hyphenate(&en, "Beautiful;"));
+        assert_eq!("ta-ble", hyphenate(&en, "table"));
+        assert_eq!("project", hyphenate(&en, "project"));
+        assert_eq!("don't", hyphenate(&en, "don't"));
+        assert!(Hyphenation::default().hyphenator("tlh").is_none());
+        assert!(Hyphenation::default().hyphenator(NO_LANGUAGE).is_none());
+    }
+
+    #[test]
+    fn strategy_settings() {
+        let expr = Expression::from_ast(parser::parse(Lexer::new(
+            "[{ ::left 3 ::right 2 ::exceptions ''litx-doc hy-phen-a-tion'' }]")).unwrap()).unwrap();
+        let settings = Hyphenation::from_expression(&expr).unwrap();
+        let en = settings.hyphenator("en").unwrap();
+        assert_eq!("litx-doc", hyphenate(&en, "litxdoc"));
+        assert_eq!("hyphen-a-tion", hyphenate(&en, "hyphenation"));
+        assert_eq!("exten-sive", hyphenate(&en, "extensive"));
+
+        let bad = Expression::from_ast(parser::parse(Lexer::new("[{ ::left 1.5 }]")).unwrap()).unwrap();
+        assert!(Hyphenation::from_expression(&bad).is_err());
+    }
+}
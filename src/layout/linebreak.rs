@@ -0,0 +1,643 @@
+//! Line breaking
+//!
+//! Paragraphs are broken with Knuth and Plass's total-fit algorithm, from "Breaking Paragraphs
+//! into Lines" (1981). Rather than filling each line as full as it'll go, it picks the breaks
+//! which make the whole paragraph look best, so justified spacing stays even from line to line.
+//!
+//! A paragraph becomes a list of items: boxes of text, glue which stretches and shrinks, and
+//! penalties, which are places to break at a cost. Lines may only break at glue which follows a
+//! box, or at a penalty. Ragged alignments use the paper's trick of glue which only stretches at
+//! line ends, so they're broken to look evenly ragged rather than greedily.
+//!
+//! Words may also break where they're hyphenated, at a cost, and after hyphens they already have.
+//!
+//! Formatted text is measured in its own font and size. Small capitals are capitals, set smaller.
+
+use std::f64;
+
+use document::Paragraph;
+use font::{ Metrics, Variant };
+use inline::{ Formats, Run };
+use layout::hyphenate::Hyphenator;
+use style::ComputedStyle;
+use value::Align;
+
+/// Penalties at least this high are never broken at, and ones at least this low always are
+pub const INFINITY: f64 = 10000.0;
+
+/// Added to each line's badness, so fewer lines are better
+const LINE_PENALTY: f64 = 10.0;
+/// Added when two lines in a row end at flagged penalties, like hyphens
+const FLAGGED_DEMERITS: f64 = 3000.0;
+/// Added when a line is much looser or tighter than the one before it
+const FITNESS_DEMERITS: f64 = 3000.0;
+/// The most badness a line within tolerance can have
+const MAX_BADNESS: f64 = 10000.0;
+/// The least badness of an overfull line, which is only allowed in the last resort pass
+const OVERFULL_BADNESS: f64 = 100000.0;
+/// How much space a ragged line may leave at its end, in spaces
+const RAGGED_STRETCH: f64 = 6.0;
+/// The cost of breaking a word, TeX's `\hyphenpenalty`
+const HYPHEN_PENALTY: f64 = 50.0;
+
+/// The fonts a paragraph is set in, for each weight and slant
+pub trait Fonts {
+    /// The font for a variant
+    fn font(&self, variant: Variant) -> &Metrics;
+}
+
+/// One font is every variant
+impl<M: Metrics> Fonts for M {
+    fn font(&self, _: Variant) -> &Metrics {
+        self
+    }
+}
+
+/// Something in a paragraph, in Knuth and Plass's terms. Widths are in points.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub enum Item {
+    /// Text, which never changes width
+    Box {
+        /// Its width
+        width: f64,
+        /// The text. Boxes with no glue between them are one word.
+        text: String,
+        /// How it's formatted
+        formats: Formats,
+    },
+    /// Space, which stretches and shrinks to fill lines, and is dropped at line breaks
+    Glue {
+        /// Its natural width
+        width: f64,
+        /// How much it can grow, infinite for glue which fills the rest of a line
+        stretch: f64,
+        /// How much it can shrink
+        shrink: f64,
+    },
+    /// A place to break
+    Penalty {
+        /// Width added to the line if it breaks here, like a hyphen's
+        width: f64,
+        /// The cost of breaking here, see `INFINITY`
+        penalty: f64,
+        /// Whether it's a hyphen, or something else two lines in a row shouldn't end with
+        flagged: bool,
+    },
+}
+
+impl Item {
+    fn is_box(&self) -> bool {
+        match *self {
+            Item::Box { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// A word, placed on a line
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub struct Word {
+    /// From the line's left edge, in points
+    pub x: f64,
+    /// In points
+    pub width: f64,
+    /// The word, with a hyphen if the line broke inside it
+    pub text: String,
+    /// How it's formatted. Words in more than one format are split where it changes, with no
+    /// space between the parts.
+    pub formats: Formats,
+}
+
+/// A broken line
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub struct Line {
+    /// The words on it, left to right
+    pub words: Vec<Word>,
+    /// How much its glue stretched (positive) or shrank (negative), as a share of how much it could
+    pub ratio: f64,
+}
+
+/// Break a paragraph into lines `width` points wide, in its style.
+/// Words are only hyphenated when there's a hyphenator for the paragraph's language.
+pub fn break_paragraph(paragraph: &Paragraph, fonts: &Fonts, width: f64, hyphenator: Option<&Hyphenator>)
+    -> Vec<Line>
+{
+    let style = paragraph.style();
+    let items = items(&paragraph.runs(), style, fonts, hyphenator);
+    let breaks = breakpoints(&items, width, style.tolerance);
+    set_lines(&items, &breaks, width, style.align)
+}
+
+/// A paragraph's items: its indent, its words, and the spaces between them
+pub fn items(runs: &[Run], style: &ComputedStyle, fonts: &Fonts, hyphenator: Option<&Hyphenator>) -> Vec<Item> {
+    let size = style.size_pt();
+    let indent = style.indent_pt();
+    let font = fonts.font(Variant::default());
+    let space = font.advance(' ') as f64 * size / font.units_per_em() as f64;
+
+    let mut items = Vec::new();
+    if indent != 0.0 {
+        items.push(Item::Box { width: indent, text: String::new(), formats: Formats::default() });
+    }
+    for (i, word) in words(runs).iter().enumerate() {
+        if i > 0 {
+            items.extend(gap(style.align, space));
+        }
+        for &(formats, ref part) in word {
+            items.extend(word_items(part, formats, size * formats.scale(), fonts.font(formats.variant()), hyphenator));
+        }
+    }
+    // Fill the last line, without making the fill a place to break
+    items.push(Item::Penalty { width: 0.0, penalty: INFINITY, flagged: false });
+    items.push(Item::Glue { width: 0.0, stretch: f64::INFINITY, shrink: 0.0 });
+    items.push(Item::Penalty { width: 0.0, penalty: -INFINITY, flagged: false });
+    items
+}
+
+/// The words in some runs, each split into its parts in different formats. Small capitals are
+/// capitals, so lowercase letters in them are made capitals, and only those are set smaller.
+fn words(runs: &[Run]) -> Vec<Vec<(Formats, String)>> {
+    let mut words: Vec<Vec<(Formats, String)>> = Vec::new();
+    let mut in_word = false;
+    for run in runs {
+        for c in run.text.chars() {
+            if c.is_whitespace() {
+                in_word = false;
+                continue;
+            }
+            if !in_word {
+                words.push(Vec::new());
+                in_word = true;
+            }
+            let formats = Formats { small_caps: run.formats.small_caps && c.is_lowercase(), ..run.formats };
+            let word = words.last_mut().unwrap();
+            if word.last().map_or(true, |&(last, _)| last != formats) {
+                word.push((formats, String::new()));
+            }
+            let part = &mut word.last_mut().unwrap().1;
+            if formats.small_caps {
+                part.extend(c.to_uppercase());
+            } else {
+                part.push(c);
+            }
+        }
+    }
+    words
+}
+
+/// A word's boxes, with penalties wherever it may break: after hyphens it already has, and where
+/// the hyphenator says. Breaking at those adds a hyphen.
+fn word_items(word: &str, formats: Formats, size: f64, font: &Metrics, hyphenator: Option<&Hyphenator>)
+    -> Vec<Item>
+{
+    let mut items = Vec::new();
+    let push = |items: &mut Vec<Item>, text: &str| {
+        if !text.is_empty() {
+            items.push(Item::Box { width: font.measure(text, size), text: text.to_owned(), formats: formats });
+        }
+    };
+    let hyphen = font.measure("-", size);
+
+    let mut start = 0;
+    let parts: Vec<&str> = word.split_terminator('-').collect();
+    for (i, part) in parts.iter().enumerate() {
+        let mut from = 0;
+        for point in hyphenator.map_or(Vec::new(), |h| h.points(part)) {
+            push(&mut items, &part[from..point]);
+            items.push(Item::Penalty { width: hyphen, penalty: HYPHEN_PENALTY, flagged: true });
+            from = point;
+        }
+        push(&mut items, &part[from..]);
+        start += part.len();
+        // A hyphen between two parts, which is somewhere to break that doesn't need another
+        if start < word.len() {
+            push(&mut items, "-");
+            start += 1;
+            if i + 1 < parts.len() && !part.is_empty() && !parts[i + 1].is_empty() {
+                items.push(Item::Penalty { width: 0.0, penalty: HYPHEN_PENALTY, flagged: true });
+            }
+        }
+    }
+    items
+}
+
+/// The items between two words
+pub fn gap(align: Align, space: f64) -> Vec<Item> {
+    match align {
+        Align::Justify => vec![Item::Glue { width: space, stretch: space / 2.0, shrink: space / 3.0 }],
+        // Stretch which is only there if the line breaks here
+        _ => vec![
+            Item::Glue { width: 0.0, stretch: RAGGED_STRETCH * space, shrink: 0.0 },
+            Item::Penalty { width: 0.0, penalty: 0.0, flagged: false },
+            Item::Glue { width: space, stretch: -RAGGED_STRETCH * space, shrink: 0.0 },
+        ],
+    }
+}
+
+/// Where to break, as item indices, the last being the end of the paragraph.
+///
+/// `tolerance` is the most a line's glue may stretch, as a multiple of its stretchability.
+/// When no breaks fit within it, lines are allowed to be as loose, or as overfull, as they must.
+pub fn breakpoints(items: &[Item], width: f64, tolerance: f64) -> Vec<usize> {
+    find_breaks(items, width, tolerance, false)
+        .or_else(|| find_breaks(items, width, tolerance, true))
+        .unwrap_or_default()
+}
+
+/// Running totals of widths, stretch and shrink
+#[derive(Debug)]
+#[derive(Copy, Clone, Default)]
+struct Totals {
+    width: f64,
+    stretch: f64,
+    shrink: f64,
+}
+
+/// A feasible break, and the best way of getting to it
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+struct Node {
+    position: usize,
+    fitness: usize,
+    /// Totals up to the start of the next line
+    totals: Totals,
+    demerits: f64,
+    previous: Option<usize>,
+}
+
+fn find_breaks(items: &[Item], width: f64, tolerance: f64, last_resort: bool) -> Option<Vec<usize>> {
+    // sums[i] is the totals of everything before item i
+    let mut sums = vec![Totals::default()];
+    for item in items {
+        let mut next = *sums.last().unwrap();
+        match *item {
+            Item::Box { width, .. } => next.width += width,
+            Item::Glue { width, stretch, shrink } => {
+                next.width += width;
+                next.stretch += stretch;
+                next.shrink += shrink;
+            }
+            Item::Penalty { .. } => {}
+        }
+        sums.push(next);
+    }
+
+    let mut nodes = vec![Node { position: 0, fitness: 1, totals: Totals::default(), demerits: 0.0, previous: None }];
+    let mut active = vec![0];
+    for (b, item) in items.iter().enumerate() {
+        let (penalty, penalty_width, flagged) = match *item {
+            Item::Box { .. } => continue,
+            Item::Glue { .. } if b > 0 && items[b - 1].is_box() => (0.0, 0.0, false),
+            Item::Glue { .. } => continue,
+            Item::Penalty { penalty, .. } if penalty >= INFINITY => continue,
+            Item::Penalty { width, penalty, flagged } => (penalty, width, flagged),
+        };
+
+        // The best way to each fitness class, as (demerits, node)
+        let mut best: [Option<(f64, usize)>; 4] = [None; 4];
+        let mut i = 0;
+        while i < active.len() {
+            let a = nodes[active[i]];
+            let length = sums[b].width - a.totals.width + penalty_width;
+            let ratio = adjustment_ratio(length, width, sums[b].stretch - a.totals.stretch,
+                                         sums[b].shrink - a.totals.shrink);
+            if (ratio >= -1.0 && ratio <= tolerance) || last_resort {
+                let badness = if ratio < -1.0 {
+                    // The further over, the worse, so long words get lines of their own
+                    OVERFULL_BADNESS * (1.0 + (length - width) / width.max(1.0))
+                } else if ratio > tolerance {
+                    // Still prefer the least loose of the too loose lines
+                    (100.0 * ratio.powi(3)).min(OVERFULL_BADNESS)
+                } else {
+                    (100.0 * ratio.abs().powi(3)).min(MAX_BADNESS)
+                };
+                let mut demerits = (LINE_PENALTY + badness).powi(2);
+                if penalty >= 0.0 {
+                    demerits += penalty * penalty;
+                } else if penalty > -INFINITY {
+                    demerits -= penalty * penalty;
+                }
+                if flagged && is_flagged(&items[a.position]) {
+                    demerits += FLAGGED_DEMERITS;
+                }
+                let fitness = fitness(ratio);
+                if (fitness as isize - a.fitness as isize).abs() > 1 {
+                    demerits += FITNESS_DEMERITS;
+                }
+                demerits += a.demerits;
+                if best[fitness].map_or(true, |(d, _)| demerits < d) {
+                    best[fitness] = Some((demerits, active[i]));
+                }
+            }
+            // Lines from here can only get longer, so once one's overfull this node's done. As a
+            // last resort, the last node left stays, so there's always somewhere to break from.
+            let overfull = ratio < -1.0 && (!last_resort || active.len() > 1);
+            if overfull || penalty <= -INFINITY {
+                active.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        let totals = sums[start_of_line(items, b)];
+        for (fitness, candidate) in best.iter().enumerate() {
+            if let Some((demerits, previous)) = *candidate {
+                nodes.push(Node {
+                    position: b,
+                    fitness: fitness,
+                    totals: totals,
+                    demerits: demerits,
+                    previous: Some(previous),
+                });
+                active.push(nodes.len() - 1);
+            }
+        }
+        if active.is_empty() {
+            return None;
+        }
+    }
+
+    // The paragraph ends in a forced break, so everything still active ends there
+    let mut node = active.iter().map(|&a| &nodes[a]).fold(None, |best: Option<&Node>, n| match best {
+        Some(b) if b.demerits <= n.demerits => Some(b),
+        _ => Some(n),
+    });
+    let mut breaks = Vec::new();
+    while let Some(n) = node {
+        if n.previous.is_none() {
+            break;
+        }
+        breaks.push(n.position);
+        node = n.previous.map(|p| &nodes[p]);
+    }
+    breaks.reverse();
+    Some(breaks)
+}
+
+/// How much a line's glue has to stretch or shrink, as a share of how much it can
+fn adjustment_ratio(length: f64, width: f64, stretch: f64, shrink: f64) -> f64 {
+    if length < width {
+        if stretch > 0.0 { (width - length) / stretch } else { f64::INFINITY }
+    } else if length > width {
+        if shrink > 0.0 { (width - length) / shrink } else { f64::NEG_INFINITY }
+    } else {
+        0.0
+    }
+}
+
+/// Tight, decent, loose or very loose
+fn fitness(ratio: f64) -> usize {
+    if ratio < -0.5 {
+        0
+    } else if ratio <= 0.5 {
+        1
+    } else if ratio <= 1.0 {
+        2
+    } else {
+        3
+    }
+}
+
+fn is_flagged(item: &Item) -> bool {
+    match *item {
+        Item::Penalty { flagged, .. } => flagged,
+        _ => false,
+    }
+}
+
+/// Where the line after a break at `b` starts: glue and penalties after a break are dropped
+fn start_of_line(items: &[Item], b: usize) -> usize {
+    let mut i = b;
+    while i < items.len() {
+        match items[i] {
+            Item::Box { .. } => break,
+            Item::Penalty { penalty, .. } if penalty <= -INFINITY && i > b => break,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+/// Place the words of each line. Justified lines stretch their spaces to fill the width, and
+/// other alignments keep them natural and move the line instead.
+pub fn set_lines(items: &[Item], breaks: &[usize], width: f64, align: Align) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for &end in breaks {
+        let mut totals = Totals::default();
+        for item in &items[start..end] {
+            match *item {
+                Item::Box { width, .. } => totals.width += width,
+                Item::Glue { width, stretch, shrink } => {
+                    totals.width += width;
+                    totals.stretch += stretch;
+                    totals.shrink += shrink;
+                }
+                Item::Penalty { .. } => {}
+            }
+        }
+        let hyphen = match items[end] {
+            Item::Penalty { width, .. } => width,
+            _ => 0.0,
+        };
+        let ratio = match align {
+            Align::Justify => adjustment_ratio(totals.width + hyphen, width, totals.stretch, totals.shrink).max(-1.0),
+            _ => 0.0,
+        };
+
+        let mut words: Vec<Word> = Vec::new();
+        let mut x = 0.0;
+        let mut in_word = false;
+        for item in &items[start..end] {
+            match *item {
+                Item::Box { width, ref text, formats } => {
+                    if in_word && words.last().map(|w| w.formats) == Some(formats) {
+                        let word = words.last_mut().unwrap();
+                        word.text.push_str(text);
+                        word.width += width;
+                    } else if !text.is_empty() {
+                        words.push(Word { x: x, width: width, text: text.clone(), formats: formats });
+                        in_word = true;
+                    }
+                    x += width;
+                }
+                Item::Glue { width, stretch, shrink } => {
+                    x += width + if ratio > 0.0 && stretch.is_finite() {
+                        ratio * stretch
+                    } else if ratio < 0.0 {
+                        ratio * shrink
+                    } else {
+                        0.0
+                    };
+                    in_word = false;
+                }
+                Item::Penalty { .. } => {}
+            }
+        }
+        if hyphen > 0.0 {
+            if let Some(word) = words.last_mut() {
+                word.text.push('-');
+                word.width += hyphen;
+            }
+        }
+
+        let used = words.last().map_or(0.0, |w| w.x + w.width);
+        let shift = match align {
+            Align::Right => width - used,
+            Align::Center => (width - used) / 2.0,
+            Align::Left | Align::Justify => 0.0,
+        };
+        for word in &mut words {
+            word.x += shift.max(0.0);
+        }
+        lines.push(Line { words: words, ratio: ratio });
+        start = start_of_line(items, end);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+    use font::{ Builtin, Variant };
+    use inline::{ Formats, Run };
+    use layout::hyphenate::Hyphenation;
+    use style::ComputedStyle;
+    use value::{ Align, Length, Unit };
+
+    const TEXT: &'static str = "In olden times when wishing still helped one, there lived a king whose \
+        daughters were all beautiful; and the youngest was so beautiful that the sun itself, which has \
+        seen so much, was astonished whenever it shone in her face.";
+
+    fn style(align: Align) -> ComputedStyle {
+        let mut style = ComputedStyle::initial();
+        style.align = align;
+        style
+    }
+
+    fn lay(text: &str, style: &ComputedStyle, width: f64, tolerance: f64) -> Vec<Line> {
+        let items = items(&[Run::plain(text)], style, &Builtin::Courier, None);
+        set_lines(&items, &breakpoints(&items, width, tolerance), width, style.align)
+    }
+
+    fn text(lines: &[Line]) -> Vec<String> {
+        lines.iter().map(|l| l.words.iter().map(|w| &w.text[..]).collect::<Vec<_>>().join(" ")).collect()
+    }
+
+    #[test]
+    fn justifies_evenly() {
+        let items = items(&[Run::plain(TEXT)], &style(Align::Justify), &Builtin::Times, None);
+        let breaks = breakpoints(&items, 200.0, 2.0);
+        let lines = set_lines(&items, &breaks, 200.0, Align::Justify);
+        assert_eq!(TEXT, text(&lines).join(" "));
+        let (last, rest) = lines.split_last().unwrap();
+        for line in rest {
+            assert!(line.ratio >= -1.0 && line.ratio <= 2.0, "ratio {}", line.ratio);
+            let end = line.words.last().map(|w| w.x + w.width).unwrap();
+            assert!((end - 200.0).abs() < 1e-6);
+        }
+        assert_eq!(0.0, last.ratio);
+    }
+
+    #[test]
+    fn total_fit_beats_first_fit() {
+        // Courier is 7.2pt a character at 12pt, so these are 10 character lines. Filling the
+        // first line leaves `cc` alone on the second, while total fit evens them out.
+        let lines = lay("aaaa bbbb cc dddddddd", &style(Align::Left), 72.0, 3.0);
+        assert_eq!(vec!["aaaa", "bbbb cc", "dddddddd"], text(&lines));
+    }
+
+    #[test]
+    fn aligns_and_indents() {
+        let mut indented = style(Align::Left);
+        indented.indent = Length::new(14.4, Unit::Pt);
+        let lines = lay("ab cd", &indented, 72.0, 2.0);
+        assert_eq!(14.4, lines[0].words[0].x);
+        assert!((lines[0].words[1].x - 7.2 * 5.0).abs() < 1e-9);
+
+        let lines = lay("ab", &style(Align::Right), 72.0, 2.0);
+        assert!((lines[0].words[0].x - 57.6).abs() < 1e-9);
+        let lines = lay("ab", &style(Align::Center), 72.0, 2.0);
+        assert!((lines[0].words[0].x - 28.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn overlong_words_still_break() {
+        let lines = lay("a supercalifragilistic b", &style(Align::Justify), 36.0, 1.0);
+        assert_eq!(vec!["a", "supercalifragilistic", "b"], text(&lines));
+    }
+
+    #[test]
+    fn long_paragraphs_break_as_a_last_resort() {
+        // No line of this can be justified with its spaces exactly as they are
+        let long = vec![TEXT; 40].join(" ");
+        let items = items(&[Run::plain(&long)], &style(Align::Justify), &Builtin::Times, None);
+        assert_eq!(None, find_breaks(&items, 200.0, 0.0, false));
+        let breaks = breakpoints(&items, 200.0, 0.0);
+        let lines = set_lines(&items, &breaks, 200.0, Align::Justify);
+        assert_eq!(long, text(&lines).join(" "));
+        assert!(lines.iter().all(|line| line.ratio >= -1.0), "a line is overfull");
+    }
+
+    #[test]
+    fn paragraphs_use_their_style() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::default [{ ::align justify ::indent regular }] }] }]")
+            .unwrap();
+        let doc = Document::new(&format!("[{{document || {} }}]", TEXT), strategy).unwrap();
+        let lines = break_paragraph(&doc.body()[0], &Builtin::Times, 300.0, None);
+        assert_eq!(36.0, lines[0].words[0].x);
+        assert!(lines.len() > 2);
+    }
+
+    /// Times, in every variant
+    struct Times;
+
+    impl Fonts for Times {
+        fn font(&self, variant: Variant) -> &Metrics {
+            match (variant.bold, variant.italic) {
+                (false, false) => &Builtin::Times,
+                (true, false) => &Builtin::TimesBold,
+                (false, true) => &Builtin::TimesItalic,
+                (true, true) => &Builtin::TimesBoldItalic,
+            }
+        }
+    }
+
+    #[test]
+    fn sets_formats_in_their_own_fonts() {
+        let src = "[{document || [{ i || Dune }], by Frank [{ sc || Herbert }] }]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        let lines = break_paragraph(&doc.body()[0], &Times, 300.0, None);
+        let words: Vec<(&str, Formats)> = lines[0].words.iter().map(|w| (&w.text[..], w.formats)).collect();
+        let italic = Formats { italic: true, ..Formats::default() };
+        let small_caps = Formats { small_caps: true, ..Formats::default() };
+        assert_eq!(vec![("Dune", italic), (",", Formats::default()), ("by", Formats::default()),
+                        ("Frank", Formats::default()), ("H", Formats::default()), ("ERBERT", small_caps)], words);
+        // The comma follows straight on, and the small capitals are smaller
+        let dune = Builtin::TimesItalic.measure("Dune", 12.0);
+        assert_eq!(dune, lines[0].words[0].width);
+        assert_eq!(dune, lines[0].words[1].x);
+        assert_eq!(Builtin::Times.measure("ERBERT", 12.0 * 0.8), lines[0].words[5].width);
+        assert_eq!(lines[0].words[4].x + lines[0].words[4].width, lines[0].words[5].x);
+    }
+
+    #[test]
+    fn breaks_inside_words() {
+        let en = Hyphenation::default().hyphenator("en-us").unwrap();
+        let items = items(&[Run::plain("a hyphenation well-known")], &style(Align::Left), &Builtin::Courier, Some(&en));
+        let boxes: Vec<&str> = items.iter().filter_map(|i| match *i {
+            Item::Box { ref text, .. } => Some(&text[..]),
+            _ => None,
+        }).collect();
+        assert_eq!(vec!["a", "hy", "phen", "ation", "well", "-", "known"], boxes);
+
+        // 8 characters a line. `a hy-` and `phen-` would fit too, but not two hyphens in a row.
+        let breaks = breakpoints(&items, 7.2 * 8.0, 10.0);
+        let lines = set_lines(&items, &breaks, 7.2 * 8.0, Align::Left);
+        assert_eq!(vec!["a", "hyphen-", "ation", "well-", "known"], text(&lines));
+    }
+}
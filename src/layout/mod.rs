@@ -0,0 +1,5 @@
+//! Layout, which turns paragraphs into positioned lines for renderers which place text themselves
+
+pub mod hyphenate;
+pub mod linebreak;
+pub mod page;
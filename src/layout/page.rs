@@ -0,0 +1,566 @@
+//! Pagination
+//!
+//! Pages are set up by a `page` field, like `page/letter/1in`: a paper size, then margins.
+//! Broken paragraphs are flowed down the space inside the margins, a line at a time, onto as
+//! many pages as they need. A paragraph is split across pages only where that leaves at least
+//! `::orphans` of its lines at the bottom of one page and `::widows` at the top of the next, and
+//! one which is `::keep-with-next` (like a heading) moves to the next page rather than be parted
+//! from the paragraph after it. `[{page-break}]` starts a new page, and `[{section-break}]` starts
+//! a new page and a new section, whose pages are numbered again from 1.
+//!
+//! Footnotes go at the foot of the page the line referring to them is on, under a gap, and take
+//! their room from that page's body text. They aren't split across pages. Lines can be taller
+//! than their block's leading, like a figure's image, which is a line of its own.
+
+use document::Paragraph;
+use error::{ Error, EvaluationError, EvaluationKind, Result };
+use layout::linebreak::Line;
+use value::{ Length, Value };
+
+/// Lines are this many times the font size apart when single spaced
+pub const LINE_HEIGHT: f64 = 1.2;
+
+/// The page when nothing says otherwise
+const DEFAULT_PAGE: &'static str = "page/letter/1in";
+/// Paper sizes, in points
+const PAPER_SIZES: &'static [(&'static str, f64, f64)] = &[
+    ("letter", 612.0, 792.0),
+    ("legal", 612.0, 1008.0),
+    ("a4", 595.28, 841.89),
+    ("a5", 419.53, 595.28),
+];
+/// How far pages may be overfilled, so rounding doesn't push the last line off
+const EPSILON: f64 = 1e-6;
+/// Space between the body and the footnotes, in points
+pub const FOOTNOTE_GAP: f64 = 12.0;
+
+/// A rectangle on a page, in points from its top left corner
+#[derive(Debug)]
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rect {
+    /// From the left edge
+    pub x: f64,
+    /// From the top edge
+    pub y: f64,
+    /// Width
+    pub width: f64,
+    /// Height
+    pub height: f64,
+}
+
+/// Space around the edges of a page, in points
+#[derive(Debug)]
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Margins {
+    /// Top
+    pub top: f64,
+    /// Right
+    pub right: f64,
+    /// Bottom
+    pub bottom: f64,
+    /// Left
+    pub left: f64,
+}
+
+/// The size of the paper, and its margins
+#[derive(Debug)]
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PageSetup {
+    /// Paper width, in points
+    pub width: f64,
+    /// Paper height, in points
+    pub height: f64,
+    /// Margins
+    pub margins: Margins,
+}
+
+impl Default for PageSetup {
+    /// US letter, with inch margins
+    fn default() -> PageSetup {
+        PageSetup::parse(&Value::parse(DEFAULT_PAGE)).unwrap()
+    }
+}
+
+impl PageSetup {
+    /// Read a page like `page/letter/1in`. The size is a paper name (letter, legal, a4 or a5) or
+    /// `<width>x<height>`, like `6inx9in`. Margins may be one length for all of them, two for
+    /// top and bottom then left and right, or four, clockwise from the top.
+    pub fn parse(value: &Value) -> Result<PageSetup> {
+        let parts = try!(value.as_path());
+        let parts = if parts[0] == "page" { &parts[1..] } else { parts };
+        let (width, height) = match parts.first() {
+            Some(size) => try!(paper_size(size)),
+            None => return Err(invalid_page(value, "it has no paper size")),
+        };
+        let mut lengths = Vec::new();
+        for part in &parts[1..] {
+            // Pages have no font size, so ems are refused here
+            lengths.push(try!(try!(Length::parse(part)).points()));
+        }
+        let margins = match lengths.len() {
+            0 => return Err(invalid_page(value, "it has no margins")),
+            1 => Margins { top: lengths[0], right: lengths[0], bottom: lengths[0], left: lengths[0] },
+            2 => Margins { top: lengths[0], right: lengths[1], bottom: lengths[0], left: lengths[1] },
+            4 => Margins { top: lengths[0], right: lengths[1], bottom: lengths[2], left: lengths[3] },
+            _ => return Err(invalid_page(value, "margins are one, two or four lengths")),
+        };
+        if margins.left + margins.right >= width || margins.top + margins.bottom >= height {
+            return Err(invalid_page(value, "its margins leave no room on the page"));
+        }
+        Ok(PageSetup { width: width, height: height, margins: margins })
+    }
+
+    /// The whole page
+    pub fn media(&self) -> Rect {
+        Rect { x: 0.0, y: 0.0, width: self.width, height: self.height }
+    }
+
+    /// The space inside the margins, where body text goes
+    pub fn content(&self) -> Rect {
+        Rect {
+            x: self.margins.left,
+            y: self.margins.top,
+            width: self.width - self.margins.left - self.margins.right,
+            height: self.height - self.margins.top - self.margins.bottom,
+        }
+    }
+}
+
+fn paper_size(size: &str) -> Result<(f64, f64)> {
+    let lower = size.to_lowercase();
+    if let Some(&(_, width, height)) = PAPER_SIZES.iter().find(|&&(name, _, _)| name == lower) {
+        return Ok((width, height));
+    }
+    // `6inx9in`: the width's unit ends at the `x`
+    let split = lower.char_indices().skip(1).find(|&(i, c)| c == 'x' && lower[..i].ends_with(char::is_alphabetic));
+    match split {
+        Some((i, _)) => Ok((try!(try!(Length::parse(&lower[..i])).points()),
+                            try!(try!(Length::parse(&lower[i + 1..])).points()))),
+        None => {
+            let names: Vec<&str> = PAPER_SIZES.iter().map(|&(name, _, _)| name).collect();
+            let kind = EvaluationKind::InvalidValue(size.to_owned(), "a paper size".to_owned());
+            Err(EvaluationError::new(kind)
+                .note(format!("sizes are {}, or a width and height like 6inx9in", names.join(", ")))
+                .into())
+        }
+    }
+}
+
+fn invalid_page(value: &Value, why: &str) -> Error {
+    let kind = EvaluationKind::InvalidValue(value.to_string(), "a page, like page/letter/1in".to_owned());
+    EvaluationError::new(kind).note(why).into()
+}
+
+/// A broken paragraph, ready to be paginated
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Block {
+    /// Its lines
+    pub lines: Vec<Line>,
+    /// How far apart its lines are, in points
+    pub leading: f64,
+    /// The fewest lines it may carry over to the top of a page
+    pub widows: usize,
+    /// The fewest lines it may leave at the bottom of a page
+    pub orphans: usize,
+    /// Whether it stays on the same page as the start of the next block
+    pub keep_with_next: bool,
+    /// Whether it starts a new page
+    pub break_before: bool,
+    /// Whether it starts a new section, on a new page
+    pub starts_section: bool,
+    /// Footnotes, after the index of the line referring to them
+    pub footnotes: Vec<(usize, Block)>,
+    /// How far it's set in from the left of the page's content, in points
+    pub margin: f64,
+    /// Lines taller than its leading, by index, with their heights
+    pub tall: Vec<(usize, f64)>,
+}
+
+impl Block {
+    /// A paragraph's lines, with its style's spacing and page breaking settings
+    pub fn new(paragraph: &Paragraph, lines: Vec<Line>) -> Block {
+        let style = paragraph.style();
+        let size = style.size_pt();
+        Block {
+            lines: lines,
+            leading: size * LINE_HEIGHT * style.line_spacing,
+            widows: style.widows,
+            orphans: style.orphans,
+            keep_with_next: style.keep_with_next,
+            break_before: paragraph.break_before(),
+            starts_section: paragraph.starts_section(),
+            footnotes: Vec::new(),
+            margin: style.margin_pt(),
+            tall: Vec::new(),
+        }
+    }
+
+    /// The height of its first `lines` lines
+    fn height(&self, lines: usize) -> f64 {
+        (0..lines).map(|index| self.line_height(index)).sum()
+    }
+
+    /// The height of one of its lines
+    fn line_height(&self, index: usize) -> f64 {
+        self.tall.iter().find(|&&(l, _)| l == index).map_or(self.leading, |&(_, height)| height)
+    }
+
+    /// The height of the footnotes a line refers to
+    fn footnote_height(&self, line: usize) -> f64 {
+        self.footnotes.iter().filter(|&&(l, _)| l == line).map(|&(_, ref note)| note.height(note.lines.len())).sum()
+    }
+
+    /// How many of its lines must be on the page it starts on. Blocks too short to split
+    /// without breaking the widow or orphan limits can't be split at all.
+    fn first_lines(&self) -> usize {
+        let n = self.lines.len();
+        if n < self.orphans + self.widows { n } else { self.orphans.max(1).min(n) }
+    }
+}
+
+/// A line, placed on a page
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct PlacedLine {
+    /// The block it's from, which for documents is the paragraph's index in the body
+    pub block: usize,
+    /// For footnotes, which of the block's footnotes it's in
+    pub footnote: Option<usize>,
+    /// Which of the block's lines it is
+    pub index: usize,
+    /// The top left of the line's box, in points from the top left of the page
+    pub x: f64,
+    /// See `x`
+    pub y: f64,
+    /// The line box's height, the block's leading unless the line is taller
+    pub height: f64,
+    /// The line
+    pub line: Line,
+}
+
+/// A page of laid out text
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Page {
+    /// Page number, from 1
+    pub number: usize,
+    /// How many pages there are
+    pub count: usize,
+    /// Which section it's in, from 0
+    pub section: usize,
+    /// Page number in its section, from 1
+    pub section_number: usize,
+    /// How many pages its section has
+    pub section_count: usize,
+    /// The whole page
+    pub media: Rect,
+    /// The space inside the margins
+    pub content: Rect,
+    /// Lines on the page, top to bottom
+    pub lines: Vec<PlacedLine>,
+    /// Footnote lines at the foot of the page, top to bottom
+    pub footnotes: Vec<PlacedLine>,
+}
+
+impl Page {
+    fn new(number: usize, section: usize, setup: &PageSetup) -> Page {
+        Page {
+            number: number,
+            count: 0,
+            section: section,
+            section_number: 0,
+            section_count: 0,
+            media: setup.media(),
+            content: setup.content(),
+            lines: Vec::new(),
+            footnotes: Vec::new(),
+        }
+    }
+
+    /// How far down the content box the next line goes
+    fn used(&self) -> f64 {
+        self.lines.last().map_or(0.0, |l| l.y + l.height - self.content.y)
+    }
+
+    /// How much of the content box the footnotes take, with the gap above them
+    fn footnote_height(&self) -> f64 {
+        if self.footnotes.is_empty() {
+            return 0.0;
+        }
+        self.footnotes.iter().map(|l| l.height).sum::<f64>() + FOOTNOTE_GAP
+    }
+
+    fn room(&self) -> f64 {
+        self.content.height - self.used() - self.footnote_height() + EPSILON
+    }
+
+    /// How many of a block's lines from `start` fit, with their footnotes
+    fn fits(&self, block: &Block, start: usize) -> usize {
+        let mut room = self.room();
+        let mut gap = self.footnotes.is_empty();
+        let mut fits = 0;
+        for index in start..block.lines.len() {
+            let mut height = block.line_height(index);
+            let notes = block.footnote_height(index);
+            if notes > 0.0 {
+                height += notes;
+                if gap {
+                    height += FOOTNOTE_GAP;
+                    gap = false;
+                }
+            }
+            if height > room {
+                break;
+            }
+            room -= height;
+            fits += 1;
+        }
+        fits
+    }
+}
+
+/// Flow blocks onto pages. There's always at least one page, even with nothing on it.
+pub fn paginate(blocks: &[Block], setup: &PageSetup) -> Vec<Page> {
+    let mut pages = vec![Page::new(1, 0, setup)];
+    for (b, block) in blocks.iter().enumerate() {
+        if (block.break_before || block.starts_section) && !pages.last().unwrap().lines.is_empty() {
+            new_page(&mut pages, setup);
+        }
+        if block.starts_section && b > 0 {
+            pages.last_mut().unwrap().section += 1;
+        }
+
+        // Blocks kept with the next move together, with the next's first lines
+        if block.keep_with_next && !pages.last().unwrap().lines.is_empty() {
+            let mut needed = 0.0;
+            for next in &blocks[b..] {
+                if !next.keep_with_next {
+                    needed += next.height(next.first_lines());
+                    break;
+                }
+                needed += next.height(next.lines.len());
+            }
+            if needed > pages.last().unwrap().room() && needed <= setup.content().height {
+                new_page(&mut pages, setup);
+            }
+        }
+
+        let n = block.lines.len();
+        let mut start = 0;
+        while start < n {
+            let fits = pages.last().unwrap().fits(block, start);
+            let mut take = fits.min(n - start);
+            if take < n - start {
+                // Leave enough for the widows, and don't leave too few for the orphans
+                take = take.min((n - start).saturating_sub(block.widows));
+                if start == 0 && take < block.orphans {
+                    take = 0;
+                }
+                // A page too short to honour the limits gets what it can hold
+                if take == 0 && pages.last().unwrap().lines.is_empty() {
+                    take = fits.max(1).min(n - start);
+                }
+            }
+            {
+                let page = pages.last_mut().unwrap();
+                for index in start..start + take {
+                    let y = page.content.y + page.used();
+                    page.lines.push(PlacedLine {
+                        block: b,
+                        footnote: None,
+                        index: index,
+                        x: page.content.x + block.margin,
+                        y: y,
+                        height: block.line_height(index),
+                        line: block.lines[index].clone(),
+                    });
+                    for (n, &(_, ref note)) in block.footnotes.iter().enumerate().filter(|&(_, &(l, _))| l == index) {
+                        for (i, line) in note.lines.iter().enumerate() {
+                            // Set at the foot of the page once it's full
+                            page.footnotes.push(PlacedLine {
+                                block: b,
+                                footnote: Some(n),
+                                index: i,
+                                x: page.content.x + note.margin,
+                                y: 0.0,
+                                height: note.leading,
+                                line: line.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+            start += take;
+            if start < n {
+                new_page(&mut pages, setup);
+            }
+        }
+    }
+
+    for page in &mut pages {
+        let mut y = page.content.y + page.content.height - page.footnote_height() + FOOTNOTE_GAP;
+        for line in &mut page.footnotes {
+            line.y = y;
+            y += line.height;
+        }
+    }
+
+    let count = pages.len();
+    let mut first = 0;
+    for i in 0..count {
+        if i + 1 == count || pages[i + 1].section != pages[i].section {
+            for (n, page) in pages[first..i + 1].iter_mut().enumerate() {
+                page.count = count;
+                page.section_number = n + 1;
+                page.section_count = i + 1 - first;
+            }
+            first = i + 1;
+        }
+    }
+    pages
+}
+
+fn new_page(pages: &mut Vec<Page>, setup: &PageSetup) {
+    let number = pages.len() + 1;
+    let section = pages.last().map_or(0, |p| p.section);
+    pages.push(Page::new(number, section, setup));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use layout::linebreak::Line;
+    use value::Value;
+
+    /// Ten lines a page
+    fn setup() -> PageSetup {
+        PageSetup::parse(&Value::parse("page/100ptx140pt/20pt")).unwrap()
+    }
+
+    fn block(lines: usize) -> Block {
+        Block {
+            lines: vec![Line { words: Vec::new(), ratio: 0.0 }; lines],
+            leading: 10.0,
+            widows: 2,
+            orphans: 2,
+            keep_with_next: false,
+            break_before: false,
+            starts_section: false,
+            footnotes: Vec::new(),
+            margin: 0.0,
+            tall: Vec::new(),
+        }
+    }
+
+    /// Each page's lines, as (block, line)
+    fn layout(blocks: &[Block]) -> Vec<Vec<(usize, usize)>> {
+        paginate(blocks, &setup()).iter().map(|p| p.lines.iter().map(|l| (l.block, l.index)).collect()).collect()
+    }
+
+    #[test]
+    fn reads_page_setups() {
+        let letter = PageSetup::default();
+        assert_eq!((612.0, 792.0), (letter.width, letter.height));
+        assert_eq!(Rect { x: 72.0, y: 72.0, width: 468.0, height: 648.0 }, letter.content());
+
+        let a4 = PageSetup::parse(&Value::parse("page/A4/2cm/1in")).unwrap();
+        assert_eq!(595.28, a4.width);
+        assert!((a4.margins.top - 56.69).abs() < 0.01);
+        assert_eq!(72.0, a4.margins.left);
+
+        let book = PageSetup::parse(&Value::parse("page/6inx9in/1in/0.5in/0.75in/0.5in")).unwrap();
+        assert_eq!((432.0, 648.0, 54.0), (book.width, book.height, book.margins.bottom));
+
+        for bad in &["page/folio/1in", "page/letter", "page/letter/1em", "page/letter/5in", "letter"] {
+            assert!(PageSetup::parse(&Value::parse(bad)).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn fills_pages_in_order() {
+        let pages = paginate(&[block(6), block(6)], &setup());
+        assert_eq!(2, pages.len());
+        assert_eq!(10, pages[0].lines.len());
+        assert_eq!((20.0, 110.0), (pages[0].lines[9].x, pages[0].lines[9].y));
+        assert_eq!(20.0, pages[1].lines[0].y);
+        assert_eq!(2, pages[1].number);
+        assert_eq!(1, paginate(&[], &setup()).len());
+    }
+
+    #[test]
+    fn keeps_widows_and_orphans() {
+        // Four lines of room: the second block would leave one line at the top of the next page
+        let pages = layout(&[block(6), block(5)]);
+        assert_eq!(vec![(1, 0), (1, 1), (1, 2)], pages[0][6..].to_vec());
+        assert_eq!(vec![(1, 3), (1, 4)], pages[1]);
+
+        // One line of room isn't enough to start a paragraph in
+        let pages = layout(&[block(9), block(5)]);
+        assert_eq!(9, pages[0].len());
+        assert_eq!(5, pages[1].len());
+
+        // Three lines can't be split without leaving one alone
+        let pages = layout(&[block(8), block(3)]);
+        assert_eq!((8, 3), (pages[0].len(), pages[1].len()));
+    }
+
+    #[test]
+    fn keeps_headings_with_the_next_paragraph_and_forces_breaks() {
+        let mut heading = block(1);
+        heading.keep_with_next = true;
+        let pages = layout(&[block(8), heading.clone(), block(4)]);
+        assert_eq!(8, pages[0].len());
+        assert_eq!(vec![(1, 0), (2, 0), (2, 1), (2, 2), (2, 3)], pages[1]);
+
+        let mut broken = block(1);
+        broken.break_before = true;
+        let pages = layout(&[broken.clone(), block(1), broken]);
+        assert_eq!(vec![vec![(0, 0), (1, 0)], vec![(2, 0)]], pages);
+    }
+
+    #[test]
+    fn makes_room_for_tall_lines() {
+        // A 45pt image over two lines of caption, which can't be parted from it
+        let mut figure = block(3);
+        figure.tall = vec![(0, 45.0)];
+        figure.orphans = 3;
+        let pages = paginate(&[block(5), figure.clone(), figure], &setup());
+        let placed: Vec<_> = pages.iter().map(|p| p.lines.iter().map(|l| (l.block, l.index, l.y, l.height)).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(vec![
+            vec![(0, 0, 20.0, 10.0), (0, 1, 30.0, 10.0), (0, 2, 40.0, 10.0), (0, 3, 50.0, 10.0), (0, 4, 60.0, 10.0)],
+            vec![(1, 0, 20.0, 45.0), (1, 1, 65.0, 10.0), (1, 2, 75.0, 10.0)],
+            vec![(2, 0, 20.0, 45.0), (2, 1, 65.0, 10.0), (2, 2, 75.0, 10.0)],
+        ], placed);
+    }
+
+    #[test]
+    fn numbers_pages_by_section() {
+        let mut section = block(1);
+        section.starts_section = true;
+        let pages = paginate(&[section.clone(), block(15), section.clone(), block(1), section], &setup());
+        let numbers: Vec<_> = pages.iter().map(|p| (p.number, p.count, p.section, p.section_number, p.section_count))
+            .collect();
+        assert_eq!(vec![(1, 4, 0, 1, 2), (2, 4, 0, 2, 2), (3, 4, 1, 1, 1), (4, 4, 2, 1, 1)], numbers);
+    }
+
+    #[test]
+    fn sets_footnotes_at_the_foot_of_the_page() {
+        let mut noted = block(4);
+        noted.footnotes = vec![(1, block(2)), (3, block(1))];
+        let pages = paginate(&[block(1), noted.clone()], &setup());
+        let notes: Vec<_> = pages[0].footnotes.iter().map(|l| (l.block, l.footnote, l.index, l.y)).collect();
+        assert_eq!(vec![(1, Some(0), 0, 90.0), (1, Some(0), 1, 100.0), (1, Some(1), 0, 110.0)], notes);
+        assert_eq!(5, pages[0].lines.len());
+
+        // The third line doesn't fit above the first note, and the last carries the second over
+        let pages = paginate(&[block(4), noted], &setup());
+        assert_eq!((6, 2), (pages[0].lines.len(), pages[0].footnotes.len()));
+        assert_eq!(vec![(1, 2), (1, 3)], pages[1].lines.iter().map(|l| (l.block, l.index)).collect::<Vec<_>>());
+        assert_eq!((Some(1), 110.0), (pages[1].footnotes[0].footnote, pages[1].footnotes[0].y));
+    }
+}
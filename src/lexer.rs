@@ -127,23 +127,40 @@ lexer! {
 #[derive(Debug)]
 #[derive(Copy, Clone)]
 #[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TextSpan {
+    /// The offset of its first byte
     pub low: usize,
+    /// The offset just past its last byte
     pub high: usize,
+    /// Which file the offsets are in: 0 for the file that was parsed, or `n` for `Ast::files[n - 1]`
+    /// once includes are expanded
+    pub file: usize,
 }
 
 impl TextSpan {
+    /// Create a text span in the file that was parsed
+    pub fn new(low: usize, high: usize) -> TextSpan {
+        TextSpan { low: low, high: high, file: 0 }
+    }
+
     /// Create a text span from a string and a slice of it.
     pub fn from(source: &str, remaining: &str, token: &str) -> TextSpan {
         let high = source.len() - remaining.len();
         let low = high - token.len();
-        TextSpan { low: low, high: high }
+        TextSpan::new(low, high)
     }
 
+    /// The smallest span covering both, in the first's file
     pub fn merge(a: TextSpan, b: TextSpan) -> TextSpan {
         let low = if a.low < b.low { a.low } else { b.low };
         let high = if a.high > b.high { a.high } else { b.high };
-        TextSpan { low: low, high: high }
+        TextSpan { low: low, high: high, file: a.file }
+    }
+
+    /// Whether it's in the file that was parsed, rather than one it includes
+    pub fn is_root(&self) -> bool {
+        self.file == 0
     }
 }
 
@@ -9,13 +9,64 @@
 #![feature(plugin)]
 #![plugin(plex)]
 
-mod ast;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+mod caption;
+mod date;
+mod diagnostic;
 mod document;
 mod error;
-mod expression;
+mod figure;
+mod font;
+mod format;
+mod image;
+mod include;
+mod inline;
+mod incremental;
+mod json;
 mod lexer;
+mod list;
+mod note;
+mod outline;
 mod parser;
+mod reference;
+mod running;
+#[cfg(test)]
+mod scratch;
+mod style;
+mod table;
+mod value;
+
+pub mod ast;
+pub mod dump;
+pub mod expression;
+pub mod layout;
+pub mod lsp;
+pub mod render;
+pub mod visit;
+pub mod watch;
 
 // That said, re-export the important stuff anyways
-pub use document::{ Document, Strategy };
-pub use error::{ Error, Result };
+pub use caption::Captions;
+pub use diagnostic::{ Diagnostic, Diagnostics, Severity };
+pub use document::{ Backmatter, BackmatterPage, Document, Frontmatter, FrontmatterPage, Paragraph, Strategy };
+pub use font::{ Builtin, Font, FontLibrary, FontSource, FontStack, LoadedFont, Metrics, ResolvedFont, Variant };
+pub use error::{ Error, Result, Failure, EvaluationError, EvaluationKind, RenderError, RenderKind };
+pub use figure::Figure;
+pub use image::{ ColorSpace, Image, ImageFormat };
+pub use inline::{ Format, Formats, Inline, Run };
+pub use lexer::TextSpan;
+pub use list::{ List, ListItem, ListStyle };
+pub use note::{ Note, NotePlacement, Notes };
+pub use outline::{ NumberStyle, Numbering, OutlineEntry };
+pub use reference::{ Reference, Show, Target };
+pub use running::{ PageSelector, Running };
+pub use style::{ ComputedStyle, Indent, Style, TextSettings };
+pub use table::{ Cell, Row, Table };
+pub use value::{ Align, Length, Unit, Value };
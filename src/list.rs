@@ -0,0 +1,165 @@
+//! Lists
+//!
+//! `[{ list || ... || ... }]` is a list, and each section after a `||` is an item. Items may have
+//! paragraphs of their own, and lists in them, which are nested a level deeper:
+//!
+//! ```text
+//! [{ list ::style number || Gather the samples || Weigh them
+//!     [{ list ::style letter || dry || wet }] || Write it down }]
+//! ```
+//!
+//! `::style` is `bullet` (the default), `number`, `letter`, `roman` or `none`, and `::marker`
+//! changes a bulleted list's bullet. Numbered lists count from `::start`, or 1. Items are set in
+//! the `list` style, each level in by its `::list-indent`, with their markers hanging in the
+//! indent, and without first line indents.
+
+use error::Result;
+use expression::{ Expression, Node };
+use outline::{ alphabetic, roman };
+
+/// The bullet, when nothing says otherwise
+const DEFAULT_BULLET: &'static str = "\u{2022}";
+
+/// How a list's items are marked
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ListStyle {
+    /// With a bullet
+    Bullet,
+    /// 1., 2., 3.
+    Number,
+    /// a., b., c.
+    Letter,
+    /// i., ii., iii.
+    Roman,
+    /// Not at all
+    None,
+}
+
+/// A `[{ list }]`'s settings
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct List {
+    /// How its items are marked
+    pub style: ListStyle,
+    /// A bulleted list's bullet
+    pub bullet: String,
+    /// The first item's number
+    pub start: usize,
+}
+
+impl Default for List {
+    fn default() -> List {
+        List { style: ListStyle::Bullet, bullet: DEFAULT_BULLET.to_owned(), start: 1 }
+    }
+}
+
+impl List {
+    /// Read `[{ list ::style number ::start 3 || ... }]`
+    pub fn from_expression(expr: &Expression) -> Result<List> {
+        let mut list = List::default();
+        if let Some(value) = expr.prop("style").and_then(Node::as_value) {
+            list.style = match try!(value.as_enum(&["bullet", "number", "letter", "roman", "none"])) {
+                "number" => ListStyle::Number,
+                "letter" => ListStyle::Letter,
+                "roman" => ListStyle::Roman,
+                "none" => ListStyle::None,
+                _ => ListStyle::Bullet,
+            };
+        }
+        if let Some(marker) = expr.prop("marker").and_then(Node::as_text) {
+            list.bullet = marker.to_owned();
+        }
+        if let Some(value) = expr.prop("start").and_then(Node::as_value) {
+            list.start = try!(value.as_count());
+        }
+        Ok(list)
+    }
+
+    /// Whether its items are counted
+    pub fn is_ordered(&self) -> bool {
+        match self.style {
+            ListStyle::Number | ListStyle::Letter | ListStyle::Roman => true,
+            ListStyle::Bullet | ListStyle::None => false,
+        }
+    }
+
+    /// The marker of the `n`th item, from 0
+    pub fn marker(&self, n: usize) -> String {
+        let number = self.start + n;
+        match self.style {
+            ListStyle::Bullet => self.bullet.clone(),
+            ListStyle::Number => format!("{}.", number),
+            ListStyle::Letter => format!("{}.", alphabetic(number)),
+            ListStyle::Roman => format!("{}.", roman(number)),
+            ListStyle::None => String::new(),
+        }
+    }
+}
+
+/// Where a paragraph is in a list
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ListItem {
+    /// How deeply its list is nested, from 1
+    pub depth: usize,
+    /// If the paragraph starts an item, the item's marker
+    pub marker: Option<String>,
+    /// If the paragraph starts a list, the list
+    pub list: Option<List>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+
+    #[test]
+    fn marks_items() {
+        let mut list = List { style: ListStyle::Roman, start: 3, ..List::default() };
+        assert_eq!("iv.", list.marker(1));
+        list.style = ListStyle::Letter;
+        assert_eq!("c.", list.marker(0));
+        list.style = ListStyle::Bullet;
+        assert_eq!(("\u{2022}".to_owned(), false), (list.marker(5), list.is_ordered()));
+    }
+
+    #[test]
+    fn nests_lists() {
+        let src = "[{document || Before [{ list ::style number || One || Two [{ list ::marker ''-'' || a || b }] \
+                   Still two || Three }] After }]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        let items: Vec<(&str, Option<(usize, Option<String>)>)> = doc.paragraphs().iter()
+            .map(|p| (p.text(), p.list_item().map(|item| (item.depth, item.marker.clone()))))
+            .collect();
+        assert_eq!(vec![
+            ("Before", None),
+            ("One", Some((1, Some("1.".to_owned())))),
+            ("Two", Some((1, Some("2.".to_owned())))),
+            ("a", Some((2, Some("-".to_owned())))),
+            ("b", Some((2, Some("-".to_owned())))),
+            ("Still two", Some((1, None))),
+            ("Three", Some((1, Some("3.".to_owned())))),
+            ("After", None),
+        ], items);
+        let first = doc.paragraphs()[1].list_item().unwrap();
+        assert_eq!(Some(ListStyle::Number), first.list.as_ref().map(|l| l.style));
+        assert!(doc.paragraphs()[2].list_item().unwrap().list.is_none());
+        // Each level is set in by the list indent, and items aren't indented
+        let margins: Vec<f64> = doc.paragraphs().iter().map(|p| p.style().margin.points().unwrap()).collect();
+        assert_eq!(vec![0.0, 36.0, 36.0, 72.0, 72.0, 36.0, 36.0, 0.0], margins);
+
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::default [{ ::indent regular }] \
+                                      ::list [{ ::list-indent 0.25in }] }] }]").unwrap();
+        let doc = Document::new("[{document || [{ list || One }] }]", strategy).unwrap();
+        assert_eq!((18.0, 0.0), (doc.paragraphs()[0].style().margin.points().unwrap(),
+                                 doc.paragraphs()[0].style().indent.points().unwrap()));
+        assert!(Document::new("[{document || [{ list ::style squares || One }] }]", Strategy::none()).is_err());
+    }
+}
@@ -0,0 +1,830 @@
+//! Language server for litx files, speaking the Language Server Protocol over any stream.
+//!
+//! Run `litx-lsp` to serve over stdio.
+
+use std::collections::HashMap;
+use std::io;
+use std::io::{ BufRead, Write };
+use std::path::{ Path, PathBuf };
+
+use ast;
+use ast::Ast;
+use diagnostic::{ Diagnostics, Severity };
+use document::{ Document, Strategy, STRATEGY_KEYS };
+use error::{ Error, Result };
+use format;
+use include;
+use include::Resolver;
+use incremental::{ Edit, ParsedSource };
+use json::Json;
+use lexer::{ Lexer, TextSpan, Token };
+use parser;
+use visit::ast::Visitor;
+
+/// Variables provided by litx itself, under `$$special/`
+const SPECIAL_VARIABLES: &'static [&'static str] = &[
+    "today", "page-no", "page-count", "section-page-no", "section-page-count", "body",
+];
+
+/// The longest message we'll read, so a bad `Content-Length` can't ask for any amount of memory
+const MAX_MESSAGE_LENGTH: usize = 8 * 1024 * 1024;
+
+// Values from the LSP specification
+const SYNC_INCREMENTAL: usize = 2;
+const SEVERITY_ERROR: usize = 1;
+const SEVERITY_WARNING: usize = 2;
+const SEVERITY_INFORMATION: usize = 3;
+const COMPLETION_FIELD: usize = 5;
+const COMPLETION_VARIABLE: usize = 6;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+const INVALID_REQUEST: f64 = -32600.0;
+
+/// Serve a single client, reading from `input` and writing to `output` until it exits.
+pub fn serve<R: BufRead, W: Write>(input: R, output: W) -> Result<()> {
+    let mut server = Server {
+        input: input,
+        output: output,
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+    server.run()
+}
+
+/// What a file turned out to be
+enum Evaluated {
+    Document(Document),
+    Strategy(Strategy),
+    /// Something to be included, which can't be built on its own
+    Fragment,
+}
+
+struct Server<R, W> {
+    input: R,
+    output: W,
+    /// Open documents by uri
+    documents: HashMap<String, ParsedSource>,
+    shutdown: bool,
+}
+
+impl<R: BufRead, W: Write> Server<R, W> {
+    fn run(&mut self) -> Result<()> {
+        loop {
+            let message = match try!(self.read_message()) {
+                Some(message) => message,
+                None => return Ok(()),
+            };
+            let method = message.get("method").and_then(Json::as_str).unwrap_or("").to_owned();
+            let params = message.get("params").cloned().unwrap_or(Json::Null);
+            match message.get("id").cloned() {
+                Some(id) => {
+                    let response = match self.request(&method, &params) {
+                        Ok(result) => Json::object(vec![
+                            ("jsonrpc", Json::from("2.0")),
+                            ("id", id),
+                            ("result", result),
+                        ]),
+                        Err((code, msg)) => Json::object(vec![
+                            ("jsonrpc", Json::from("2.0")),
+                            ("id", id),
+                            ("error", Json::object(vec![
+                                ("code", Json::Number(code)),
+                                ("message", Json::from(msg)),
+                            ])),
+                        ]),
+                    };
+                    try!(self.write_message(&response));
+                }
+                None => {
+                    if method == "exit" {
+                        return Ok(());
+                    }
+                    try!(self.notification(&method, &params));
+                }
+            }
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Json)
+            -> ::std::result::Result<Json, (f64, String)> {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "Server is shutting down".to_owned()));
+        }
+        let result = match method {
+            "initialize" => capabilities(),
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            }
+            "textDocument/hover" => self.with_position(params, Self::hover),
+            "textDocument/completion" => self.with_position(params, Self::completion),
+            "textDocument/definition" => self.with_position(params, Self::definition),
+            "textDocument/formatting" => self.formatting(params),
+            _ => return Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        };
+        Ok(result)
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Result<()> {
+        let uri = params.find(&["textDocument", "uri"]).and_then(Json::as_str).unwrap_or("").to_owned();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params.find(&["textDocument", "text"]).and_then(Json::as_str).unwrap_or("");
+                self.documents.insert(uri.clone(), ParsedSource::new(text.to_owned()));
+                self.publish_diagnostics(&uri)
+            }
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").and_then(Json::as_array).cloned().unwrap_or(Vec::new());
+                for change in changes {
+                    self.change(&uri, &change);
+                }
+                self.publish_diagnostics(&uri)
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.notify("textDocument/publishDiagnostics", Json::object(vec![
+                    ("uri", Json::from(uri)),
+                    ("diagnostics", Json::Array(Vec::new())),
+                ]))
+            }
+            // Includes initialized, and anything we don't understand
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply one of the changes from a didChange. Changes without a range replace everything.
+    fn change(&mut self, uri: &str, change: &Json) {
+        let text = change.get("text").and_then(Json::as_str).unwrap_or("").to_owned();
+        let range = change.get("range");
+        let source = match self.documents.get_mut(uri) {
+            Some(source) => source,
+            None => return,
+        };
+        let span = range.map(|range| {
+            let lines = LineIndex::new(source.text());
+            TextSpan::new(lines.offset_of(range.get("start")), lines.offset_of(range.get("end")))
+        });
+        match span {
+            Some(span) => { source.edit(&Edit { span: span, text: text }); }
+            None => *source = ParsedSource::new(text),
+        }
+    }
+
+    /// Run a position based request against an open document
+    fn with_position<F>(&self, params: &Json, f: F) -> Json
+            where F: Fn(&Self, &str, &ParsedSource, usize) -> Json {
+        let uri = params.find(&["textDocument", "uri"]).and_then(Json::as_str).unwrap_or("");
+        let source = match self.documents.get(uri) {
+            Some(source) => source,
+            None => return Json::Null,
+        };
+        let offset = LineIndex::new(source.text()).offset_of(params.get("position"));
+        f(self, uri, source, offset)
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> Result<()> {
+        let diagnostics = match self.documents.get(uri) {
+            Some(source) => self.diagnostics(uri, source),
+            None => Vec::new(),
+        };
+        self.notify("textDocument/publishDiagnostics", Json::object(vec![
+            ("uri", Json::from(uri)),
+            ("diagnostics", Json::Array(diagnostics)),
+        ]))
+    }
+
+    fn diagnostics(&self, uri: &str, source: &ParsedSource) -> Vec<Json> {
+        let text = source.text();
+        let lines = LineIndex::new(text);
+        let ast = match *source.parsed() {
+            Ok(ref ast) => ast,
+            Err((Some((ref token, span)), msg)) => {
+                let msg = format!("{}: {}", msg, token);
+                return vec![diagnostic(lines.range(span), SEVERITY_ERROR, &msg)];
+            }
+            Err((None, msg)) => {
+                let end = TextSpan::new(text.len(), text.len());
+                return vec![diagnostic(lines.range(end), SEVERITY_ERROR, msg)];
+            }
+        };
+
+        let mut found = Vec::new();
+        let resolver = resolver_for(uri);
+        for inc in include::find_includes(ast) {
+            if let Err(e) = resolver.resolve(&inc.path) {
+                found.push(diagnostic(lines.range(inc.span), SEVERITY_WARNING, &e.to_string()));
+            }
+        }
+        // Spans in included files are offsets into them, so what's found there is put at the start
+        let start = TextSpan::new(0, 0);
+        let (warnings, files) = match self.evaluate(uri, text, ast) {
+            Ok(Evaluated::Document(doc)) => {
+                let files: Vec<_> = doc.diagnostics().iter()
+                    .map(|d| d.span.and_then(|span| doc.file_of(span)).map(|f| f.display().to_string()))
+                    .collect();
+                (doc.diagnostics().clone(), files)
+            }
+            Ok(Evaluated::Strategy(strategy)) => (strategy.diagnostics().clone(), Vec::new()),
+            Ok(Evaluated::Fragment) => (Diagnostics::new(), Vec::new()),
+            Err(e) => {
+                let span = match e.span() {
+                    Some(span) if span.is_root() => span,
+                    _ => start,
+                };
+                found.push(diagnostic(lines.range(span), SEVERITY_ERROR, &e.to_string()));
+                (Diagnostics::new(), Vec::new())
+            }
+        };
+        for (i, d) in warnings.iter().enumerate() {
+            let severity = match d.severity {
+                Severity::Error => SEVERITY_ERROR,
+                Severity::Warning => SEVERITY_WARNING,
+                Severity::Note => SEVERITY_INFORMATION,
+            };
+            let span = match d.span {
+                Some(span) if span.is_root() => span,
+                _ => start,
+            };
+            let message = match files.get(i) {
+                Some(&Some(ref file)) => format!("{}: {}", file, d.message),
+                _ => d.message.clone(),
+            };
+            found.push(diagnostic(lines.range(span), severity, &message));
+        }
+        found
+    }
+
+    /// Build the strategy or document in a file, if it has one
+    fn evaluate(&self, uri: &str, text: &str, ast: &Ast) -> Result<Evaluated> {
+        match ast.repr.first() {
+            Some(&ast::Node::Expression(_, ref e)) => {
+                let expanded = try!(include::expand(ast.clone(), &resolver_for(uri)));
+                if let Some(&ast::Node::Text(_, ref title)) = e.repr.first() {
+                    if title == "strategy" {
+                        return Strategy::from_ast(expanded).map(Evaluated::Strategy);
+                    }
+                }
+                let strategy = self.strategy_for(uri, text);
+                Document::from_ast_in(expanded, strategy, &dir_for(uri)).map(Evaluated::Document)
+            }
+            // Fragments, like .litxpart files, aren't evaluated on their own
+            _ => Ok(Evaluated::Fragment),
+        }
+    }
+
+    /// Load the strategy a document asks for, or the empty strategy
+    fn strategy_for(&self, uri: &str, text: &str) -> Strategy {
+        let name = match include::strategy_name(text) {
+            Some(ref name) if name != "none" => name.clone(),
+            _ => return Strategy::none(),
+        };
+        let path = match resolver_for(uri).strategy(&name) {
+            Ok(path) => path,
+            Err(_) => return Strategy::none(),
+        };
+        let source = match self.read(&path) {
+            Ok(source) => source,
+            Err(_) => return Strategy::none(),
+        };
+        let resolver = Resolver::new(path.parent().unwrap_or(Path::new(".")));
+        parser::parse(Lexer::new(&source)).map_err(Error::from)
+            .and_then(|ast| include::expand(ast, &resolver))
+            .and_then(Strategy::from_ast)
+            .unwrap_or_else(|_| Strategy::none())
+    }
+
+    /// Read a file, preferring the editor's copy if it's open
+    fn read(&self, path: &Path) -> Result<String> {
+        if let Some(source) = self.documents.get(&path_to_uri(path)) {
+            return Ok(source.text().to_owned());
+        }
+        include::read(path)
+    }
+
+    fn hover(&self, uri: &str, source: &ParsedSource, offset: usize) -> Json {
+        let text = source.text();
+        let ast = match *source.parsed() {
+            Ok(ref ast) => ast,
+            Err(_) => return Json::Null,
+        };
+        let (span, name) = match variable_at(ast, offset) {
+            Some(found) => found,
+            None => return Json::Null,
+        };
+        let value = match self.evaluate(uri, text, ast) {
+            Ok(Evaluated::Document(doc)) => doc.variable(&name),
+            _ => None,
+        };
+        let contents = match value {
+            Some(value) => format!("`$${}` = {}", name, value),
+            None if name.starts_with("special/") => format!("`$${}` is filled in while rendering", name),
+            None => format!("`$${}` is not defined", name),
+        };
+        Json::object(vec![
+            ("contents", Json::object(vec![
+                ("kind", Json::from("markdown")),
+                ("value", Json::from(contents)),
+            ])),
+            ("range", LineIndex::new(text).range(span)),
+        ])
+    }
+
+    fn completion(&self, uri: &str, source: &ParsedSource, offset: usize) -> Json {
+        let text = source.text();
+        // Find the start of the word under the cursor
+        let before = &text[.. offset];
+        let start = before.rfind(|c: char| c.is_whitespace()).map(|i| i + 1).unwrap_or(0);
+        let word = &before[start ..];
+        if !word.starts_with("::") && !word.starts_with("$$") {
+            return Json::Array(Vec::new());
+        }
+        // Only the name after the `::` or `$$` is replaced
+        let replace = LineIndex::new(text).range(TextSpan::new((start + 2).min(offset), offset));
+
+        let mut items = Vec::new();
+        if word.starts_with("::") {
+            let strategy = self.strategy_for(uri, text);
+            if is_strategy(text) {
+                for key in STRATEGY_KEYS {
+                    items.push(completion_item(key, None, COMPLETION_FIELD, &replace));
+                }
+            } else if enclosing_key(text, offset).as_ref().map(|s| &s[..]) == Some("meta") {
+                for field in strategy.meta_fields() {
+                    let detail = describe(field.kind(), field.is_optional());
+                    items.push(completion_item(field.name(), Some(&detail), COMPLETION_FIELD, &replace));
+                }
+            } else {
+                items.push(completion_item("strategy", None, COMPLETION_FIELD, &replace));
+                items.push(completion_item("meta", None, COMPLETION_FIELD, &replace));
+                for field in strategy.fields() {
+                    let detail = describe(field.kind(), field.is_optional());
+                    items.push(completion_item(field.name(), Some(&detail), COMPLETION_FIELD, &replace));
+                }
+            }
+        } else if word.starts_with("$$") {
+            let strategy = self.strategy_for(uri, text);
+            for field in strategy.meta_fields() {
+                let name = format!("meta/{}", field.name());
+                let detail = describe(field.kind(), field.is_optional());
+                items.push(completion_item(&name, Some(&detail), COMPLETION_VARIABLE, &replace));
+            }
+            for special in SPECIAL_VARIABLES {
+                let name = format!("special/{}", special);
+                items.push(completion_item(&name, None, COMPLETION_VARIABLE, &replace));
+            }
+        }
+        Json::Array(items)
+    }
+
+    fn definition(&self, uri: &str, source: &ParsedSource, offset: usize) -> Json {
+        let ast = match *source.parsed() {
+            Ok(ref ast) => ast,
+            Err(_) => return Json::Null,
+        };
+        let target = include::find_includes(ast).into_iter()
+            .find(|inc| inc.span.low <= offset && offset <= inc.span.high);
+        let path = match target.map(|inc| resolver_for(uri).resolve(&inc.path)) {
+            Some(Ok(path)) => path,
+            _ => return Json::Null,
+        };
+        Json::object(vec![
+            ("uri", Json::from(path_to_uri(&path))),
+            ("range", LineIndex::new("").range(TextSpan::new(0, 0))),
+        ])
+    }
+
+    fn formatting(&self, params: &Json) -> Json {
+        let uri = params.find(&["textDocument", "uri"]).and_then(Json::as_str).unwrap_or("");
+        let text = match self.documents.get(uri) {
+            Some(source) => source.text(),
+            None => return Json::Null,
+        };
+        let formatted = format::format(text);
+        if formatted == text {
+            return Json::Array(Vec::new());
+        }
+        let whole = TextSpan::new(0, text.len());
+        Json::Array(vec![Json::object(vec![
+            ("range", LineIndex::new(text).range(whole)),
+            ("newText", Json::from(formatted)),
+        ])])
+    }
+
+    fn notify(&mut self, method: &str, params: Json) -> Result<()> {
+        let message = Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("method", Json::from(method)),
+            ("params", params),
+        ]);
+        self.write_message(&message)
+    }
+
+    /// Read one `Content-Length` framed message. None at end of input.
+    fn read_message(&mut self) -> Result<Option<Json>> {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if try!(self.input.read_line(&mut header)) == 0 {
+                return Ok(None);
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            let mut parts = header.splitn(2, ':');
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+        let length = match length {
+            Some(length) => length,
+            None => return Err(invalid_data("Message without a Content-Length")),
+        };
+        if length > MAX_MESSAGE_LENGTH {
+            return Err(invalid_data("Message is too long"));
+        }
+        let mut body = vec![0; length];
+        try!(self.input.read_exact(&mut body));
+        let body = match String::from_utf8(body) {
+            Ok(body) => body,
+            Err(_) => return Err(invalid_data("Message is not utf-8")),
+        };
+        match Json::parse(&body) {
+            Some(json) => Ok(Some(json)),
+            None => Err(invalid_data("Message is not valid json")),
+        }
+    }
+
+    fn write_message(&mut self, message: &Json) -> Result<()> {
+        let body = message.to_string();
+        try!(write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body));
+        try!(self.output.flush());
+        Ok(())
+    }
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        ("capabilities", Json::object(vec![
+            ("textDocumentSync", Json::from(SYNC_INCREMENTAL)),
+            ("hoverProvider", Json::from(true)),
+            ("completionProvider", Json::object(vec![
+                ("triggerCharacters", Json::Array(vec![Json::from(":"), Json::from("$")])),
+            ])),
+            ("definitionProvider", Json::from(true)),
+            ("documentFormattingProvider", Json::from(true)),
+        ])),
+    ])
+}
+
+fn diagnostic(range: Json, severity: usize, msg: &str) -> Json {
+    Json::object(vec![
+        ("range", range),
+        ("severity", Json::from(severity)),
+        ("source", Json::from("litx")),
+        ("message", Json::from(msg)),
+    ])
+}
+
+fn completion_item(label: &str, detail: Option<&str>, kind: usize, replace: &Json) -> Json {
+    let mut pairs = vec![
+        ("label", Json::from(label)),
+        ("kind", Json::from(kind)),
+        ("textEdit", Json::object(vec![
+            ("range", replace.clone()),
+            ("newText", Json::from(label)),
+        ])),
+    ];
+    if let Some(detail) = detail {
+        pairs.push(("detail", Json::from(detail)));
+    }
+    Json::object(pairs)
+}
+
+fn describe(kind: &str, optional: bool) -> String {
+    if optional { format!("{} (optional)", kind) } else { kind.to_owned() }
+}
+
+fn is_strategy(text: &str) -> bool {
+    let mut tokens = Lexer::new(text).filter(|&(ref t, _)| !is_trivia(t));
+    match (tokens.next(), tokens.next()) {
+        (Some((Token::Open, _)), Some((Token::Word(ref w), _))) => w == "strategy",
+        _ => false,
+    }
+}
+
+fn is_trivia(token: &Token) -> bool {
+    match *token {
+        Token::Comment(_) | Token::BlankLine => true,
+        _ => false,
+    }
+}
+
+/// The key of the innermost `::key [{ ... }]` surrounding an offset
+fn enclosing_key(text: &str, offset: usize) -> Option<String> {
+    let mut stack: Vec<Option<String>> = Vec::new();
+    let mut last_key = None;
+    for (token, span) in Lexer::new(text) {
+        if span.low >= offset {
+            break;
+        }
+        match token {
+            Token::Open => stack.push(last_key.take()),
+            Token::Close => { stack.pop(); }
+            Token::Key(k) => last_key = Some(k),
+            _ => last_key = None,
+        }
+    }
+    stack.pop().and_then(|key| key)
+}
+
+/// The variable under an offset, with its span
+fn variable_at(ast: &Ast, offset: usize) -> Option<(TextSpan, String)> {
+    let mut finder = VariableFinder { offset: offset, found: None };
+    finder.visit_ast(ast);
+    finder.found
+}
+
+struct VariableFinder {
+    offset: usize,
+    found: Option<(TextSpan, String)>,
+}
+
+impl Visitor for VariableFinder {
+    fn visit_variable(&mut self, span: TextSpan, name: &str) {
+        if self.found.is_none() && span.low <= self.offset && self.offset <= span.high {
+            self.found = Some((span, name.to_owned()));
+        }
+    }
+}
+
+/// Find the directory a document lives in, and search for includes there
+fn resolver_for(uri: &str) -> Resolver {
+    Resolver::new(dir_for(uri))
+}
+
+/// The directory a document lives in, where the files it refers to are found
+fn dir_for(uri: &str) -> PathBuf {
+    uri_to_path(uri)
+        .and_then(|p| p.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    if !uri.starts_with("file://") {
+        return None;
+    }
+    let encoded = uri["file://".len() ..].as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] == b'%' && i + 2 < encoded.len() {
+            let hex = ::std::str::from_utf8(&encoded[i + 1 .. i + 3]).ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok());
+            if let Some(b) = hex {
+                bytes.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        bytes.push(encoded[i]);
+        i += 1;
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = "file://".to_owned();
+    for b in path.to_string_lossy().bytes() {
+        match b {
+            b'a' ... b'z' | b'A' ... b'Z' | b'0' ... b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+/// Converts between byte offsets and LSP's line and utf-16 character positions
+struct LineIndex<'a> {
+    text: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> LineIndex<'a> {
+        let mut starts = vec![0];
+        starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        LineIndex { text: text, starts: starts }
+    }
+
+    fn position(&self, offset: usize) -> Json {
+        let line = match self.starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        let start = self.starts[line];
+        let character: usize = self.text[start .. offset].chars().map(char::len_utf16).sum();
+        Json::object(vec![
+            ("line", Json::from(line)),
+            ("character", Json::from(character)),
+        ])
+    }
+
+    fn range(&self, span: TextSpan) -> Json {
+        Json::object(vec![
+            ("start", self.position(span.low)),
+            ("end", self.position(span.high)),
+        ])
+    }
+
+    /// The offset of an LSP position object
+    fn offset_of(&self, position: Option<&Json>) -> usize {
+        let line = position.and_then(|p| p.get("line")).and_then(Json::as_u64).unwrap_or(0);
+        let character = position.and_then(|p| p.get("character")).and_then(Json::as_u64).unwrap_or(0);
+        self.offset(line as usize, character as usize)
+    }
+
+    fn offset(&self, line: usize, character: usize) -> usize {
+        let start = match self.starts.get(line) {
+            Some(&start) => start,
+            None => return self.text.len(),
+        };
+        let mut units = 0;
+        for (i, c) in self.text[start ..].char_indices() {
+            if units >= character || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use json::Json;
+    use scratch::Scratch;
+
+    /// Play a list of messages to the server, returning everything it says back
+    fn session(messages: &[Json]) -> Vec<Json> {
+        let mut input = Vec::new();
+        for message in messages {
+            let body = message.to_string();
+            input.extend(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).bytes());
+        }
+        let mut output = Vec::new();
+        serve(&input[..], &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let mut replies = Vec::new();
+        let mut rest = &output[..];
+        while let Some(split) = rest.find("\r\n\r\n") {
+            let length: usize = rest[.. split]["Content-Length: ".len() ..].parse().unwrap();
+            let body = &rest[split + 4 .. split + 4 + length];
+            replies.push(Json::parse(body).unwrap());
+            rest = &rest[split + 4 + length ..];
+        }
+        replies
+    }
+
+    fn request(id: usize, method: &str, params: Json) -> Json {
+        Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("id", Json::from(id)),
+            ("method", Json::from(method)),
+            ("params", params),
+        ])
+    }
+
+    fn open(text: &str) -> Json {
+        open_at("file:///nowhere/test.litx", text)
+    }
+
+    fn open_at(uri: &str, text: &str) -> Json {
+        Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("method", Json::from("textDocument/didOpen")),
+            ("params", Json::object(vec![
+                ("textDocument", Json::object(vec![
+                    ("uri", Json::from(uri)),
+                    ("text", Json::from(text)),
+                ])),
+            ])),
+        ])
+    }
+
+    fn at(line: usize, character: usize) -> Json {
+        Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::from("file:///nowhere/test.litx"))])),
+            ("position", at_position(line, character)),
+        ])
+    }
+
+    fn at_position(line: usize, character: usize) -> Json {
+        Json::object(vec![
+            ("line", Json::from(line)),
+            ("character", Json::from(character)),
+        ])
+    }
+
+    #[test]
+    fn initialize() {
+        let replies = session(&[request(1, "initialize", Json::object(vec![]))]);
+        assert_eq!(1, replies.len());
+        let hover = replies[0].find(&["result", "capabilities", "hoverProvider"]);
+        assert_eq!(Some(&Json::Bool(true)), hover);
+    }
+
+    #[test]
+    fn refuses_huge_messages() {
+        let input = format!("Content-Length: {}\r\n\r\n{{}}", usize::max_value());
+        match serve(input.as_bytes(), Vec::new()) {
+            Err(Error::Io(ref e)) => assert_eq!(io::ErrorKind::InvalidData, e.kind()),
+            other => panic!("Expected invalid data, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_errors_are_diagnosed() {
+        let replies = session(&[open("[{ document || }] }]")]);
+        let diagnostics = replies[0].find(&["params", "diagnostics"]).and_then(Json::as_array).unwrap();
+        assert_eq!(1, diagnostics.len());
+        let start = diagnostics[0].find(&["range", "start", "character"]).and_then(Json::as_u64);
+        assert_eq!(Some(18), start);
+    }
+
+    #[test]
+    fn diagnoses_included_files_at_the_start() {
+        let dir = Scratch::new("lsp-include");
+        let part = dir.join("part.litxpart");
+        fs::write(&part, format!("{} $$meta/author", "Padding. ".repeat(20))).unwrap();
+        let uri = format!("file://{}", dir.join("doc.litx").display());
+        let replies = session(&[open_at(&uri, "[{document || #[{include ''part.litxpart''}] }]")]);
+        let diagnostics = replies[0].find(&["params", "diagnostics"]).and_then(Json::as_array).unwrap();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Some(0), diagnostics[0].find(&["range", "end", "character"]).and_then(Json::as_u64));
+        let message = diagnostics[0].get("message").and_then(Json::as_str).unwrap();
+        assert!(message.starts_with(&format!("{}: ", part.display())));
+    }
+
+    #[test]
+    fn hover_resolves_meta() {
+        let src = "[{document\n    ::meta [{ ::lastname Cooke }]\n    ||\n    $$meta/lastname\n}]";
+        let replies = session(&[open(src), request(2, "textDocument/hover", at(3, 8))]);
+        let value = replies[1].find(&["result", "contents", "value"]).and_then(Json::as_str);
+        assert_eq!(Some("`$$meta/lastname` = Cooke"), value);
+    }
+
+    #[test]
+    fn applies_incremental_changes() {
+        let src = "[{document\n    ::meta [{ ::lastname Cooke }]\n    ||\n    $$meta/lastname\n}]";
+        let change = Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("method", Json::from("textDocument/didChange")),
+            ("params", Json::object(vec![
+                ("textDocument", Json::object(vec![("uri", Json::from("file:///nowhere/test.litx"))])),
+                ("contentChanges", Json::Array(vec![Json::object(vec![
+                    ("range", Json::object(vec![("start", at_position(1, 25)), ("end", at_position(1, 30))])),
+                    ("text", Json::from("Smith")),
+                ])])),
+            ])),
+        ]);
+        let replies = session(&[open(src), change, request(2, "textDocument/hover", at(3, 8))]);
+        let value = replies[2].find(&["result", "contents", "value"]).and_then(Json::as_str);
+        assert_eq!(Some("`$$meta/lastname` = Smith"), value);
+    }
+
+    #[test]
+    fn completes_document_keys() {
+        let src = "[{document\n    ::\n}]";
+        let replies = session(&[open(src), request(2, "textDocument/completion", at(1, 6))]);
+        let items = replies[1].get("result").and_then(Json::as_array).unwrap();
+        let labels: Vec<_> = items.iter().filter_map(|i| i.get("label").and_then(Json::as_str)).collect();
+        assert_eq!(vec!["strategy", "meta"], labels);
+    }
+
+    #[test]
+    fn completes_nothing_after_spaces() {
+        let src = "[{document || Text }] ";
+        let replies = session(&[open(src), request(2, "textDocument/completion", at(0, 22)),
+                                request(3, "textDocument/completion", at(0, 13))]);
+        assert_eq!(Some(&Json::Array(Vec::new())), replies[1].get("result"));
+        assert_eq!(Some(&Json::Array(Vec::new())), replies[2].get("result"));
+    }
+
+    #[test]
+    fn unknown_methods_are_errors() {
+        let replies = session(&[request(7, "textDocument/frobnicate", Json::Null)]);
+        let code = replies[0].find(&["error", "code"]);
+        assert_eq!(Some(&Json::Number(METHOD_NOT_FOUND)), code);
+    }
+}
@@ -0,0 +1,120 @@
+//! Notes
+//!
+//! `[{ note || ... }]` is a note on the text it follows, which is marked with the note's number,
+//! counting through the document. A strategy's `::notes` block says where they're set:
+//!
+//! ```text
+//! ::notes [{ ::placement endnotes ::title ''Notes'' }]
+//! ```
+//!
+//! `footnotes` (the default) go at the foot of the page they're referred to on, in outputs with
+//! pages, and after everything else in those without. `endnotes` are set after the body, under an
+//! unnumbered heading, `Notes` unless `::title` says otherwise. Note text is in the `note` style.
+
+use document::Paragraph;
+use error::Result;
+use expression::{ Expression, Node };
+
+/// The endnotes' heading, when nothing says otherwise
+const DEFAULT_TITLE: &'static str = "Notes";
+
+/// Where notes are set
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum NotePlacement {
+    /// At the foot of the page
+    Footnotes,
+    /// After the body
+    Endnotes,
+}
+
+/// A strategy's `::notes`
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Notes {
+    /// Where they're set
+    pub placement: NotePlacement,
+    /// The heading over endnotes
+    pub title: String,
+}
+
+impl Default for Notes {
+    fn default() -> Notes {
+        Notes { placement: NotePlacement::Footnotes, title: DEFAULT_TITLE.to_owned() }
+    }
+}
+
+impl Notes {
+    /// Read `[{ ::placement endnotes ::title ''Notes'' }]`
+    pub fn from_expression(expr: &Expression) -> Result<Notes> {
+        let mut notes = Notes::default();
+        if let Some(value) = expr.prop("placement").and_then(Node::as_value) {
+            notes.placement = match try!(value.as_enum(&["footnotes", "endnotes"])) {
+                "endnotes" => NotePlacement::Endnotes,
+                _ => NotePlacement::Footnotes,
+            };
+        }
+        if let Some(title) = expr.prop("title").and_then(Node::as_text) {
+            notes.title = title.to_owned();
+        }
+        Ok(notes)
+    }
+}
+
+/// A note's text
+#[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Note {
+    /// Its number, from 1
+    pub number: usize,
+    /// Its paragraphs, the first starting with its number
+    pub paragraphs: Vec<Paragraph>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+    use inline::{ Format, Inline };
+
+    #[test]
+    fn numbers_notes() {
+        let src = "[{document || Text.[{ note || See [{ i || Dune }]. }] More[{ note || Again. }] }]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        assert_eq!(1, doc.paragraphs().len());
+        assert_eq!("Text.1 More2", doc.paragraphs()[0].text());
+        assert_eq!(vec![1, 2], doc.paragraphs()[0].notes());
+
+        let notes = doc.footnotes();
+        assert_eq!(2, notes.len());
+        let first = &notes[0].paragraphs[0];
+        assert_eq!((1, Some(1)), (notes[0].number, first.note()));
+        assert_eq!("1 See Dune.", first.text());
+        assert_eq!(Inline::Styled(Format::Superscript, vec![Inline::Text("1".to_owned())]), first.inlines()[0]);
+        assert_eq!("2 Again.", notes[1].paragraphs[0].text());
+    }
+
+    #[test]
+    fn sets_endnotes_after_the_body() {
+        let strategy = Strategy::new("[{strategy ::notes [{ ::placement endnotes ::title ''End Notes'' }]
+            ::text-settings [{ ::note [{ ::size 10pt }] }]
+            ::backmatter [{ ::page last ::content [{ || The end. }] }] }]").unwrap();
+        let doc = Document::new("[{document || Text.[{ note || A note. }] }]", strategy).unwrap();
+        assert_eq!(NotePlacement::Endnotes, doc.strategy().notes().placement);
+        assert!(doc.footnotes().is_empty());
+        let texts: Vec<&str> = doc.body().iter().map(|p| p.text()).collect();
+        assert_eq!(vec!["Text.1", "End Notes", "1 A note."], texts);
+        assert_eq!(Some(1), doc.body()[1].heading());
+        assert_eq!(None, doc.body()[1].number());
+        assert_eq!(Some(1), doc.body()[2].note());
+        assert_eq!(10.0, doc.body()[2].style().size.points().unwrap());
+        assert_eq!("The end.", doc.backmatter()[0].text());
+
+        assert!(Strategy::new("[{strategy ::notes [{ ::placement sidenotes }] }]").is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Headings and the document outline
+//!
+//! Headings are `[{ h1 || ... }]` to `[{ h6 || ... }]`, or `[{ section ::title ''...'' || ... }]`,
+//! whose level is how deeply it's nested in other sections. A strategy's `::headings` block says
+//! how they're numbered:
+//!
+//! ```text
+//! ::headings [{ ::numbering decimal ::depth 2 }]    // 1, 1.1, but not 1.1.1
+//! ```
+//!
+//! `::numbering` is `none` (the default), `decimal`, or `outline` (I., A., 1., a., i.), and any heading
+//! with `::numbered false` is left unnumbered, and doesn't count.
+
+use document::Paragraph;
+use error::Result;
+use expression::Expression;
+
+/// The deepest heading level
+pub const MAX_LEVEL: usize = 6;
+
+/// How headings are numbered
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum NumberStyle {
+    /// Not at all
+    None,
+    /// 1, 1.1, 1.1.1
+    Decimal,
+    /// I., A., 1., a., i., then around again, each level only giving its own number
+    Outline,
+}
+
+/// A strategy's `::headings` numbering
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Numbering {
+    /// How numbers are written
+    pub style: NumberStyle,
+    /// The deepest level that's numbered
+    pub depth: usize,
+}
+
+impl Default for Numbering {
+    fn default() -> Numbering {
+        Numbering { style: NumberStyle::None, depth: MAX_LEVEL }
+    }
+}
+
+impl Numbering {
+    /// Read `[{ ::numbering decimal ::depth 3 }]`
+    pub fn from_expression(expr: &Expression) -> Result<Numbering> {
+        let mut numbering = Numbering::default();
+        for (key, value) in expr.props() {
+            let value = match value.as_value() {
+                Some(value) => value,
+                None => continue,
+            };
+            match key {
+                "numbering" => {
+                    numbering.style = match try!(value.as_enum(&["none", "decimal", "outline"])) {
+                        "decimal" => NumberStyle::Decimal,
+                        "outline" => NumberStyle::Outline,
+                        _ => NumberStyle::None,
+                    };
+                }
+                "depth" => numbering.depth = try!(value.as_count()),
+                _ => {}
+            }
+        }
+        Ok(numbering)
+    }
+
+    /// The number of a heading, given how many headings there have been at each level down to
+    /// its own, counting it
+    pub fn number(&self, counts: &[usize]) -> Option<String> {
+        if counts.is_empty() || counts.len() > self.depth {
+            return None;
+        }
+        match self.style {
+            NumberStyle::None => None,
+            NumberStyle::Decimal => {
+                let parts: Vec<String> = counts.iter().map(|n| n.to_string()).collect();
+                Some(parts.join("."))
+            }
+            NumberStyle::Outline => {
+                let n = counts[counts.len() - 1];
+                let number = match (counts.len() - 1) % 5 {
+                    0 => roman(n).to_uppercase(),
+                    1 => alphabetic(n).to_uppercase(),
+                    3 => alphabetic(n),
+                    4 => roman(n),
+                    _ => n.to_string(),
+                };
+                Some(number + ".")
+            }
+        }
+    }
+}
+
+/// A number in lowercase roman numerals
+pub fn roman(mut n: usize) -> String {
+    const NUMERALS: &'static [(usize, &'static str)] = &[
+        (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+        (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+    ];
+    let mut out = String::new();
+    for &(value, numeral) in NUMERALS {
+        while n >= value {
+            out.push_str(numeral);
+            n -= value;
+        }
+    }
+    out
+}
+
+/// A number as lowercase letters: a to z, then aa, ab, and so on
+pub fn alphabetic(mut n: usize) -> String {
+    let mut letters = Vec::new();
+    while n > 0 {
+        n -= 1;
+        letters.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    letters.iter().rev().cloned().collect()
+}
+
+/// A heading, and the headings under it
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OutlineEntry {
+    /// From 1
+    pub level: usize,
+    /// Its number, if it's numbered
+    pub number: Option<String>,
+    /// Its text, without the number
+    pub title: String,
+    /// Its index in `Document::paragraphs`
+    pub paragraph: usize,
+    /// Headings under it, until the next heading at its level or above
+    pub children: Vec<OutlineEntry>,
+}
+
+/// The headings among some paragraphs, as a tree. Headings which skip levels go under the
+/// nearest heading above them.
+pub fn outline(paragraphs: &[Paragraph]) -> Vec<OutlineEntry> {
+    let mut roots = Vec::new();
+    for (i, paragraph) in paragraphs.iter().enumerate() {
+        let level = match paragraph.heading() {
+            Some(level) => level,
+            None => continue,
+        };
+        let number = paragraph.number().map(|n| n.to_owned());
+        let title = match number {
+            Some(ref n) => paragraph.text()[n.len() ..].trim_start().to_owned(),
+            None => paragraph.text().to_owned(),
+        };
+        let entry = OutlineEntry { level: level, number: number, title: title, paragraph: i, children: Vec::new() };
+        let mut siblings = &mut roots;
+        while siblings.last().map_or(false, |last: &OutlineEntry| last.level < level) {
+            siblings = &mut { siblings }.last_mut().unwrap().children;
+        }
+        siblings.push(entry);
+    }
+    roots
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+
+    #[test]
+    fn numbers_headings() {
+        let decimal = Numbering { style: NumberStyle::Decimal, depth: 2 };
+        assert_eq!(Some("2.1".to_owned()), decimal.number(&[2, 1]));
+        assert_eq!(None, decimal.number(&[2, 1, 1]));
+        let outline = Numbering { style: NumberStyle::Outline, depth: MAX_LEVEL };
+        let numbers: Vec<String> = (1..7).map(|l| outline.number(&vec![4; l]).unwrap()).collect();
+        assert_eq!(vec!["IV.", "D.", "4.", "d.", "iv.", "IV."], numbers);
+        assert_eq!("mcmxc", roman(1990));
+        assert_eq!("ab", alphabetic(28));
+        assert_eq!(None, Numbering::default().number(&[1]));
+    }
+
+    #[test]
+    fn builds_the_outline() {
+        let strategy = Strategy::new("[{strategy ::headings [{ ::numbering decimal }] }]").unwrap();
+        let src = "[{document || [{ section ::title Intro || Text. [{ section ::title Why || More. }] }] \
+                   [{ h3 || Deep }] [{ h1 ::numbered false || Thanks }] [{ h1 || End }] }]";
+        let doc = Document::new(src, strategy).unwrap();
+        let outline = doc.outline();
+        assert_eq!(3, outline.len());
+        assert_eq!((Some("1".to_owned()), "Intro".to_owned(), 0), (outline[0].number.clone(), outline[0].title.clone(),
+                                                                  outline[0].paragraph));
+        let why = &outline[0].children[0];
+        assert_eq!((Some("1.1".to_owned()), "Why", 2), (why.number.clone(), &why.title[..], why.level));
+        // It skips a level, so it's under the last heading above it
+        assert_eq!(Some("1.1.1".to_owned()), why.children[0].number);
+        assert_eq!(None, outline[1].number);
+        assert_eq!("Thanks", doc.paragraphs()[outline[1].paragraph].text());
+        assert_eq!("2 End", doc.paragraphs()[outline[2].paragraph].text());
+    }
+}
@@ -13,7 +13,7 @@ parser! {
     }
 
     ast: Ast {
-        nodes[e] => Ast { repr: e }
+        nodes[e] => Ast { repr: e, files: Vec::new() }
     }
 
     nodes: Vec<Node> {
@@ -66,7 +66,7 @@ mod test {
     #[test]
     fn nothing() {
         let src = "";
-        let expected = Ast { repr: Vec::new()};
+        let expected = Ast { repr: Vec::new(), files: Vec::new() };
         test(src, expected);
     }
 
@@ -75,10 +75,11 @@ mod test {
         let src = "[{ foo }]";
         let expected = Ast {
             repr: vec![
-                Node::Expression ( TextSpan { low: 0, high: 9 }, Expression { repr: vec![
-                    Node::Text( TextSpan { low: 3, high: 6 }, "foo".to_string())
+                Node::Expression ( TextSpan::new(0, 9), Expression { repr: vec![
+                    Node::Text( TextSpan::new(3, 6), "foo".to_string())
                 ]})
-            ]
+            ],
+            files: Vec::new(),
         };
         test(src, expected);
     }
@@ -0,0 +1,195 @@
+//! Cross-references
+//!
+//! `[{ label intro }]` inside a heading, a note, a table or a figure names it, and `[{ ref intro }]`
+//! anywhere in the document refers to it, before or after it's named. What a reference is set as
+//! depends on its `::show`:
+//!
+//! ```text
+//! See section [{ ref intro }], [{ ref intro ::show title }], on page [{ ref intro ::show page }].
+//! ```
+//!
+//! `number` (the default) is the heading's, note's, table's or figure's number, or its title if it
+//! isn't numbered, and `page` is the page it starts on, in outputs with pages; others set its
+//! number instead. References link to what they refer to where the output can.
+
+use std::collections::BTreeMap;
+
+use error::Result;
+use expression::{ Expression, Node };
+use inline::Inline;
+
+/// What a label is on
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Target {
+    /// The paragraph at an index in `Document::paragraphs`, like a heading, table or figure
+    Paragraph(usize),
+    /// A note, by number
+    Note(usize),
+}
+
+/// What a reference is set as
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Show {
+    /// Its target's number, or title if it has no number
+    Number,
+    /// Its target's title
+    Title,
+    /// The page its target starts on
+    Page,
+}
+
+/// A `[{ ref ... }]`
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Reference {
+    /// The label it refers to
+    pub label: String,
+    /// What it's set as
+    pub show: Show,
+    /// What the label is on, once every label is known
+    pub target: Option<Target>,
+    /// Its text, once every label is known
+    pub text: String,
+}
+
+impl Reference {
+    /// Read `[{ ref intro ::show title }]`
+    pub fn from_expression(expr: &Expression, label: String) -> Result<Reference> {
+        let show = match expr.prop("show").and_then(Node::as_value) {
+            Some(value) => match try!(value.as_enum(&["number", "title", "page"])) {
+                "title" => Show::Title,
+                "page" => Show::Page,
+                _ => Show::Number,
+            },
+            None => Show::Number,
+        };
+        Ok(Reference { label: label, show: show, target: None, text: String::new() })
+    }
+}
+
+/// Fill in the references in some inlines, given each target's number and title. Returns the
+/// first label nothing is named, if there is one.
+pub fn resolve<F>(inlines: &mut [Inline], labels: &BTreeMap<String, Target>, describe: &F) -> Option<String>
+    where F: Fn(Target) -> (Option<String>, String)
+{
+    let mut unresolved = None;
+    for inline in inlines {
+        match *inline {
+            Inline::Text(_) | Inline::Note(_) => {}
+            Inline::Styled(_, ref mut inner) => {
+                let missing = resolve(inner, labels, describe);
+                unresolved = unresolved.or(missing);
+            }
+            Inline::Ref(ref mut reference) => match labels.get(&reference.label) {
+                Some(&target) => {
+                    let (number, title) = describe(target);
+                    reference.target = Some(target);
+                    reference.text = match reference.show {
+                        Show::Title => title,
+                        Show::Number | Show::Page => number.unwrap_or(title),
+                    };
+                }
+                None => {
+                    if unresolved.is_none() {
+                        unresolved = Some(reference.label.clone());
+                    }
+                }
+            },
+        }
+    }
+    unresolved
+}
+
+/// Set the page references in some inlines as the pages their targets start on, where they're
+/// known
+pub fn set_pages<F>(inlines: &mut [Inline], page: &F) where F: Fn(Target) -> Option<usize> {
+    for inline in inlines {
+        match *inline {
+            Inline::Text(_) | Inline::Note(_) => {}
+            Inline::Styled(_, ref mut inner) => set_pages(inner, page),
+            Inline::Ref(ref mut reference) => {
+                if let (Show::Page, Some(n)) = (reference.show, reference.target.and_then(|t| page(t))) {
+                    reference.text = n.to_string();
+                }
+            }
+        }
+    }
+}
+
+/// Whether some inlines have references in them
+pub fn has_references(inlines: &[Inline]) -> bool {
+    any_reference(inlines, &|_| true)
+}
+
+/// Whether some inlines refer to pages
+pub fn refers_to_pages(inlines: &[Inline]) -> bool {
+    any_reference(inlines, &|reference| reference.show == Show::Page)
+}
+
+fn any_reference<F: Fn(&Reference) -> bool>(inlines: &[Inline], matches: &F) -> bool {
+    inlines.iter().any(|inline| match *inline {
+        Inline::Styled(_, ref inner) => any_reference(inner, matches),
+        Inline::Ref(ref reference) => matches(reference),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+    use error::{ Error, EvaluationKind };
+
+    #[test]
+    fn resolves_references_before_and_after_their_labels() {
+        let strategy = Strategy::new("[{strategy ::headings [{ ::numbering decimal }] }]").unwrap();
+        let src = "[{document || See [{ ref why }], [{ ref why ::show title }] and [{ ref dune }]. \
+                   [{ h1 || Intro }] [{ h2 || Why [{ label why }] }] \
+                   Text.[{ note || [{ label dune }] See Dune. }] }]";
+        let doc = Document::new(src, strategy).unwrap();
+        assert_eq!("See 1.1, Why and 1.", doc.paragraphs()[0].text());
+        let reference = doc.paragraphs()[0].inlines().iter().filter_map(|inline| match *inline {
+            Inline::Ref(ref reference) => Some(reference.clone()),
+            _ => None,
+        }).next().unwrap();
+        assert_eq!((Some(Target::Paragraph(2)), Show::Number), (reference.target, reference.show));
+        assert_eq!("1.1 Why", doc.paragraphs()[2].text());
+    }
+
+    #[test]
+    fn sets_page_references_as_numbers_until_there_are_pages() {
+        let src = "[{document || [{ h1 || Intro [{ label intro }] }] On page [{ ref intro ::show page }]. }]";
+        let mut doc = Document::new(src, Strategy::none()).unwrap();
+        let paragraph = doc.paragraphs()[1].clone();
+        assert_eq!("On page Intro.", paragraph.text());
+        assert!(paragraph.refers_to_pages());
+        assert_eq!("On page 3.", paragraph.with_pages(&|_| Some(3)).text());
+        doc = Document::new("[{document || [{ h1 || Intro }] }]", Strategy::none()).unwrap();
+        assert!(!doc.paragraphs()[0].refers_to_pages());
+    }
+
+    #[test]
+    fn fails_on_unresolved_labels() {
+        let src = "[{document || [{ h1 || A [{ label a }] }] See [{ ref b }]. }]";
+        match Document::new(src, Strategy::none()) {
+            Err(Error::EvaluationFailure(ref failure)) => {
+                assert_eq!(&EvaluationKind::UnresolvedLabel("b".to_owned()), failure.kind());
+                let span = failure.span().unwrap();
+                assert_eq!("[{ ref b }]", &src[span.low .. span.high]);
+            }
+            other => panic!("{:?}", other),
+        }
+        let twice = "[{document || [{ h1 || A [{ label a }] }] [{ h1 || B [{ label a }] }] }]";
+        assert!(Document::new(twice, Strategy::none()).is_err());
+        assert!(Document::new("[{document || Text [{ label a }] }]", Strategy::none()).is_err());
+        assert!(Document::new("[{document || [{ ref a ::show colour }] }]", Strategy::none()).is_err());
+    }
+}
@@ -0,0 +1,433 @@
+//! HTML output
+//!
+//! Headings are `<h1>` to `<h6>`, with ids that entries in tables of contents link to.
+//! Formatted text is `<i>`, `<b>`, `<sup>`, or a small caps `<span>`. Note numbers link to their
+//! notes, which link back, and footnotes are listed after everything else. Cross-references link
+//! to what they refer to. Lists are `<ol>` or `<ul>`, with their markers, and items are `<li>`s.
+//! Tables are `<table>`s, with their captions, header rows in a `<thead>`, and spanning cells.
+//! Figures are `<figure>`s, with their images inlined as `data:` URIs, so the page stands alone.
+
+use document::{ Document, Paragraph };
+use error::Result;
+use figure::Figure;
+use inline::{ Format, Inline };
+use list::{ List, ListStyle };
+use reference::Target;
+use table::Table;
+use value::Align;
+
+const STYLE: &'static str = "\
+body { max-width: 40em; margin: 2em auto; }
+p, h1, h2, h3, h4, h5, h6 { margin: 0; font-weight: inherit; }
+a { color: inherit; }
+table { border-collapse: collapse; width: 100%; border-top: 1px solid; border-bottom: 1px solid; }
+thead { border-bottom: 1px solid; }
+th, td { padding: 0 6pt; vertical-align: top; }
+figure { margin: 0; }
+img { max-width: 100%; object-fit: contain; }";
+
+/// Render a document as a standalone HTML page
+pub fn render(doc: &Document) -> Result<String> {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    if let Some(title) = doc.title() {
+        out.push_str(&format!("<title>{}</title>\n", escape(&title)));
+    }
+    out.push_str(&format!("<style>\n{}\n</style>\n", STYLE));
+    out.push_str("</head>\n<body>\n<article>\n");
+    // The lists open around the paragraph, outermost first
+    let mut lists = Vec::new();
+    for (i, paragraph) in doc.paragraphs().iter().enumerate() {
+        nest(&mut out, &mut lists, Some(paragraph));
+        out.push_str(&block(i, paragraph));
+    }
+    nest(&mut out, &mut lists, None);
+    if !doc.footnotes().is_empty() {
+        out.push_str("<section class=\"footnotes\">\n<hr>\n");
+        for note in doc.footnotes() {
+            for paragraph in &note.paragraphs {
+                // Footnotes are never headings, so their index doesn't matter
+                out.push_str(&block(0, paragraph));
+            }
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</article>\n</body>\n</html>\n");
+    Ok(out)
+}
+
+/// Close and open lists and items, so the next paragraph is where it is in them, or in none
+fn nest(out: &mut String, lists: &mut Vec<&'static str>, paragraph: Option<&Paragraph>) {
+    let item = paragraph.and_then(Paragraph::list_item);
+    let depth = item.map_or(0, |item| item.depth);
+    let starts_list = item.map_or(false, |item| item.list.is_some());
+    while lists.len() > depth || (starts_list && lists.len() == depth) {
+        out.push_str(&format!("</li>\n</{}>\n", lists.pop().unwrap_or("ul")));
+    }
+    let (item, paragraph) = match (item, paragraph) {
+        (Some(item), Some(paragraph)) => (item, paragraph),
+        _ => return,
+    };
+    if lists.len() == depth && item.marker.is_some() {
+        out.push_str("</li>\n<li>\n");
+    }
+    while lists.len() < depth {
+        let default = List::default();
+        let list = item.list.as_ref().unwrap_or(&default);
+        let mut css = format!("margin: 0; padding-left: {}pt", paragraph.style().list_indent_pt());
+        let (tag, attributes) = match list.style {
+            ListStyle::Number => ("ol", " type=\"1\""),
+            ListStyle::Letter => ("ol", " type=\"a\""),
+            ListStyle::Roman => ("ol", " type=\"i\""),
+            ListStyle::Bullet => {
+                css.push_str(&format!("; list-style-type: '{} '", list.bullet.replace('\\', "\\\\").replace('\'', "\\'")));
+                ("ul", "")
+            }
+            ListStyle::None => {
+                css.push_str("; list-style-type: none");
+                ("ul", "")
+            }
+        };
+        let start = if list.is_ordered() && list.start != 1 { format!(" start=\"{}\"", list.start) } else { String::new() };
+        out.push_str(&format!("<{}{}{} style=\"{}\">\n<li>\n", tag, attributes, start, escape(&css)));
+        lists.push(tag);
+    }
+}
+
+/// A paragraph, the `i`th of the document's, as an element
+fn block(i: usize, paragraph: &Paragraph) -> String {
+    if let Some(table) = paragraph.table() {
+        return table_element(i, paragraph, table);
+    }
+    if let Some(figure) = paragraph.figure() {
+        return figure_element(i, paragraph, figure);
+    }
+    let css = escape(&css(paragraph));
+    let mut text = formatted(paragraph.inlines());
+    if let Some(heading) = paragraph.contents_entry() {
+        text = format!("<a href=\"#{}\">{}</a>", anchor(heading), text);
+    }
+    let id = match (paragraph.heading(), paragraph.note()) {
+        (Some(_), _) => format!(" id=\"{}\"", anchor(i)),
+        (None, Some(note)) => {
+            text.push_str(&format!(" <a href=\"#{}\">\u{21A9}</a>", note_ref_anchor(note)));
+            format!(" id=\"{}\"", note_anchor(note))
+        }
+        (None, None) => String::new(),
+    };
+    let tag = paragraph.heading().map_or_else(|| "p".to_owned(), |level| format!("h{}", level));
+    format!("<{0}{1} style=\"{2}\">{3}</{0}>\n", tag, id, css, text)
+}
+
+/// A table, the `i`th of the document's paragraphs, as a `<table>` under its caption
+fn table_element(i: usize, paragraph: &Paragraph, table: &Table) -> String {
+    let mut out = format!("<table id=\"{}\" style=\"{}\">\n", anchor(i), escape(&css(paragraph)));
+    if !paragraph.text().is_empty() {
+        out.push_str(&format!("<caption>{}</caption>\n", formatted(paragraph.inlines())));
+    }
+    if !table.is_even() {
+        let columns: Vec<String> = table.widths.iter()
+            .map(|width| format!("<col style=\"width: {}%\">", (width * 1000.0).round() / 10.0))
+            .collect();
+        out.push_str(&format!("<colgroup>{}</colgroup>\n", columns.concat()));
+    }
+    let header = table.header_rows();
+    for (r, row) in table.rows.iter().enumerate() {
+        if r == 0 && header > 0 {
+            out.push_str("<thead>\n");
+        } else if r == header {
+            out.push_str("<tbody>\n");
+        }
+        out.push_str("<tr>\n");
+        for cell in &row.cells {
+            let tag = if row.header { "th" } else { "td" };
+            let mut spans = String::new();
+            if cell.colspan > 1 {
+                spans.push_str(&format!(" colspan=\"{}\"", cell.colspan));
+            }
+            if cell.rowspan > 1 {
+                spans.push_str(&format!(" rowspan=\"{}\"", cell.rowspan));
+            }
+            // Cells are never headings, so their paragraphs' indexes don't matter
+            let content: Vec<String> = cell.paragraphs.iter().map(|p| block(0, p)).collect();
+            out.push_str(&format!("<{0}{1}>\n{2}</{0}>\n", tag, spans, content.concat()));
+        }
+        out.push_str("</tr>\n");
+        if r + 1 == header {
+            out.push_str("</thead>\n");
+        }
+    }
+    if table.rows.len() > header {
+        out.push_str("</tbody>\n");
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+/// A figure, the `i`th of the document's paragraphs, as a `<figure>` over its caption
+fn figure_element(i: usize, paragraph: &Paragraph, figure: &Figure) -> String {
+    let mut out = format!("<figure id=\"{}\" style=\"{}\">\n", anchor(i), escape(&css(paragraph)));
+    let alt = if paragraph.text().is_empty() { &figure.src[..] } else { paragraph.text() };
+    out.push_str(&format!("<img src=\"data:{};base64,{}\" alt=\"{}\" style=\"width: {}pt; height: {}pt\">\n",
+                          figure.image.media_type(), base64(figure.image.data()), escape(alt),
+                          figure.width, figure.height));
+    if !paragraph.text().is_empty() {
+        out.push_str(&format!("<figcaption>{}</figcaption>\n", formatted(paragraph.inlines())));
+    }
+    out.push_str("</figure>\n");
+    out
+}
+
+/// Bytes in base64, padded
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for group in data.chunks(3) {
+        let bits = group.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// The id of the paragraph at an index in `Document::paragraphs`
+fn anchor(paragraph: usize) -> String {
+    format!("p{}", paragraph)
+}
+
+/// The id of a note
+fn note_anchor(note: usize) -> String {
+    format!("note-{}", note)
+}
+
+/// The id of where a note is referred to
+fn note_ref_anchor(note: usize) -> String {
+    format!("note-ref-{}", note)
+}
+
+/// The id of what a cross-reference refers to
+fn target_anchor(target: Target) -> String {
+    match target {
+        Target::Paragraph(paragraph) => anchor(paragraph),
+        Target::Note(note) => note_anchor(note),
+    }
+}
+
+/// Formatted text as HTML
+fn formatted(inlines: &[Inline]) -> String {
+    let mut out = String::new();
+    for inline in inlines {
+        match *inline {
+            Inline::Text(ref text) => out.push_str(&escape(text)),
+            Inline::Styled(format, ref inner) => {
+                let (open, close) = match format {
+                    Format::Italic => ("<i>", "</i>"),
+                    Format::Bold => ("<b>", "</b>"),
+                    Format::SmallCaps => ("<span style=\"font-variant: small-caps\">", "</span>"),
+                    Format::Superscript => ("<sup>", "</sup>"),
+                };
+                out.push_str(open);
+                out.push_str(&formatted(inner));
+                out.push_str(close);
+            }
+            Inline::Note(note) => out.push_str(&format!("<sup><a id=\"{}\" href=\"#{}\">{}</a></sup>",
+                                                        note_ref_anchor(note), note_anchor(note), note)),
+            Inline::Ref(ref reference) => match reference.target {
+                Some(target) => out.push_str(&format!("<a href=\"#{}\">{}</a>", target_anchor(target),
+                                                      escape(&reference.text))),
+                None => out.push_str(&escape(&reference.text)),
+            },
+        }
+    }
+    out
+}
+
+/// A paragraph's computed style, as an inline CSS declaration list
+fn css(paragraph: &Paragraph) -> String {
+    let style = paragraph.style();
+    let align = match style.align {
+        Align::Left => "left",
+        Align::Center => "center",
+        Align::Right => "right",
+        Align::Justify => "justify",
+    };
+    let css = format!("text-align: {}; text-indent: {}pt; font-family: {}; font-size: {}pt; line-height: {}",
+                      align, style.indent_pt(), font_family(&style.face), style.size_pt(), style.line_spacing);
+    // Lists set their items in themselves
+    let margin = style.margin_pt();
+    let css = if margin != 0.0 && paragraph.list_item().is_none() { format!("{}; margin-left: {}pt", css, margin) } else { css };
+    if paragraph.break_before() { css + "; break-before: page" } else { css }
+}
+
+/// A `;` separated list of fonts as a CSS font-family
+fn font_family(face: &str) -> String {
+    let fonts: Vec<String> = face.split(';')
+        .map(|f| f.trim())
+        .filter(|f| !f.is_empty())
+        .map(|f| match &f.to_lowercase()[..] {
+            "serif" | "sans-serif" | "monospace" => f.to_lowercase(),
+            _ => format!("'{}'", f),
+        })
+        .collect();
+    fonts.join(", ")
+}
+
+/// Escape text for use in HTML content or attribute values
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+    use image::test::{ beside_images, tiny_jpeg };
+
+    #[test]
+    fn paragraphs() {
+        let src = "[{document\n    ::meta [{ ::title ''A & B'' }]\n    ||\n    One\n    two.\n\n    Three.\n}]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        let html = render(&doc).unwrap();
+        assert!(html.contains("<title>A &amp; B</title>"));
+        assert!(html.contains(">One two.</p>\n<p style=\""));
+        assert!(html.contains(">Three.</p>"));
+    }
+
+    #[test]
+    fn styles() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ \
+            ::default [{ ::indent regular ::line-spacing 2.0 ::font [{ ::face ''Times New Roman; Serif'' }] }] }] }]")
+            .unwrap();
+        let doc = Document::new("[{document || Body. [{ align center || Title }] }]", strategy).unwrap();
+        let html = render(&doc).unwrap();
+        assert!(html.contains("<p style=\"text-align: left; text-indent: 36pt; \
+                               font-family: &#39;Times New Roman&#39;, serif; font-size: 12pt; line-height: 2\">Body.</p>"));
+        assert!(html.contains("<p style=\"text-align: center; text-indent: 36pt;"));
+    }
+
+    #[test]
+    fn headings() {
+        let strategy = Strategy::new("[{strategy ::headings [{ ::numbering decimal }]
+            ::frontmatter [{ ::page first ::content [{ || [{ toc }] }] }] }]").unwrap();
+        let doc = Document::new("[{document || [{ h1 || A & B }] Text. [{ h2 || C }] }]", strategy).unwrap();
+        let html = render(&doc).unwrap();
+        assert!(html.contains("<p style=\"text-align: left; text-indent: 0pt; \
+                               font-family: serif; font-size: 12pt; line-height: 1\"><a href=\"#p2\">1 A &amp; B</a></p>"));
+        assert!(html.contains("<h1 id=\"p2\" style=\"text-align: left;"));
+        assert!(html.contains(">1 A &amp; B</h1>"));
+        assert!(html.contains("<h2 id=\"p4\" style=\"text-align: left;"));
+        assert!(html.contains(">1.1 C</h2>"));
+    }
+
+    #[test]
+    fn formatting() {
+        let src = "[{document || [{ h1 || On [{ i || Dune }] }] See [{ i || A [{ b || & }] B }], [{ sc || Herbert }] 1965. }]";
+        let strategy = Strategy::new("[{strategy ::frontmatter [{ ::page first ::content [{ || [{ toc }] }] }] }]").unwrap();
+        let html = render(&Document::new(src, strategy).unwrap()).unwrap();
+        assert!(html.contains("><a href=\"#p1\">On <i>Dune</i></a></p>"));
+        assert!(html.contains(">On <i>Dune</i></h1>"));
+        assert!(html.contains(">See <i>A <b>&amp;</b> B</i>, <span style=\"font-variant: small-caps\">Herbert</span> 1965.</p>"));
+    }
+
+    #[test]
+    fn notes() {
+        let src = "[{document || Text.[{ note || A [{ i || note }]. }] }]";
+        let html = render(&Document::new(src, Strategy::none()).unwrap()).unwrap();
+        assert!(html.contains(">Text.<sup><a id=\"note-ref-1\" href=\"#note-1\">1</a></sup></p>"));
+        assert!(html.contains("<section class=\"footnotes\">\n<hr>\n<p id=\"note-1\" style=\""));
+        assert!(html.contains("><sup>1</sup> A <i>note</i>. <a href=\"#note-ref-1\">\u{21A9}</a></p>\n</section>"));
+
+        let strategy = Strategy::new("[{strategy ::notes [{ ::placement endnotes }] }]").unwrap();
+        let html = render(&Document::new(src, strategy).unwrap()).unwrap();
+        assert!(!html.contains("footnotes"));
+        assert!(html.contains("<h1 id=\"p1\" style=\"text-align: left;"));
+        assert!(html.contains(">Notes</h1>\n<p id=\"note-1\" style=\""));
+    }
+
+    #[test]
+    fn links_references() {
+        let src = "[{document || See [{ ref intro ::show title }].[{ note || [{ label n }] A note. }] \
+                   [{ h1 || Intro [{ label intro }] }] See [{ ref n }]. }]";
+        let html = render(&Document::new(src, Strategy::none()).unwrap()).unwrap();
+        assert!(html.contains(">See <a href=\"#p1\">Intro</a>.<sup>"));
+        assert!(html.contains("<h1 id=\"p1\""));
+        assert!(html.contains(">See <a href=\"#note-1\">1</a>.</p>"));
+    }
+
+    #[test]
+    fn marks_up_tables() {
+        let src = "[{document || [{ table ::widths ''3 1'' || Masses [{ label masses }]
+            [{ row ::header true || Sample || Mass }]
+            [{ row || [{ cell ::colspan 2 || Lost }] }] }] See [{ ref masses }]. }]";
+        let html = render(&Document::new(src, Strategy::none()).unwrap()).unwrap();
+        let tags: Vec<&str> = html.lines()
+            .filter(|l| !l.starts_with("<p") && !l.starts_with("<table"))
+            .skip_while(|l| *l != "<article>")
+            .collect();
+        assert_eq!(vec![
+            "<article>",
+            "<caption>Table 1. Masses</caption>",
+            "<colgroup><col style=\"width: 75%\"><col style=\"width: 25%\"></colgroup>",
+            "<thead>", "<tr>", "<th>", "</th>", "<th>", "</th>", "</tr>", "</thead>",
+            "<tbody>", "<tr>", "<td colspan=\"2\">", "</td>", "</tr>", "</tbody>",
+            "</table>",
+            "</article>", "</body>", "</html>",
+        ], tags);
+        assert!(html.contains("<table id=\"p0\" style=\"text-align: left;"));
+        assert!(html.contains("<th>\n<p style=\"text-align: left; text-indent: 0pt; font-family: serif; font-size: 12pt; \
+                               line-height: 1\"><b>Sample</b></p>\n</th>"));
+        assert!(html.contains(">See <a href=\"#p0\">1</a>.</p>"));
+    }
+
+    #[test]
+    fn inlines_figures() {
+        let src = "[{document || [{ figure ::src ''photo.jpg'' || A <photo> }]
+            [{ figure ::src ''photo.jpg'' ::numbered false }] }]";
+        let doc = beside_images(src, Strategy::none()).unwrap();
+        let html = render(&doc).unwrap();
+        let img = format!("<img src=\"data:image/jpeg;base64,{}\" alt=\"Figure 1. A &lt;photo&gt;\" \
+                           style=\"width: 3pt; height: 2.25pt\">\n<figcaption>Figure 1. A &lt;photo&gt;</figcaption>",
+                          base64(&tiny_jpeg()));
+        assert!(html.contains(&img));
+        assert!(html.contains("<figure id=\"p0\" style=\"text-align: left;"));
+        // Without a caption, the image's name stands in for it
+        assert!(html.contains("alt=\"photo.jpg\""));
+        assert_eq!(1, html.matches("<figcaption>").count());
+        assert_eq!(vec!["TWFu", "TWE=", "TQ==", ""], vec![base64(b"Man"), base64(b"Ma"), base64(b"M"), base64(b"")]);
+    }
+
+    #[test]
+    fn nests_lists() {
+        let src = "[{document || [{ list ::style roman ::start 2 || One [{ list || Two }] || Three }] \
+                   [{ list ::style none || Four }] }]";
+        let html = render(&Document::new(src, Strategy::none()).unwrap()).unwrap();
+        let tags: Vec<&str> = html.lines().filter(|l| !l.starts_with("<p")).skip_while(|l| *l != "<article>").collect();
+        assert_eq!(vec![
+            "<article>",
+            "<ol type=\"i\" start=\"2\" style=\"margin: 0; padding-left: 36pt\">", "<li>",
+            "<ul style=\"margin: 0; padding-left: 36pt; list-style-type: &#39;\u{2022} &#39;\">", "<li>",
+            "</li>", "</ul>",
+            "</li>", "<li>",
+            "</li>", "</ol>",
+            "<ul style=\"margin: 0; padding-left: 36pt; list-style-type: none\">", "<li>",
+            "</li>", "</ul>",
+            "</article>", "</body>", "</html>",
+        ], tags);
+    }
+}
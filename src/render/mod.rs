@@ -0,0 +1,5 @@
+//! Renderers, which turn a built document into an output format
+
+pub mod html;
+pub mod pdf;
+pub mod text;
@@ -0,0 +1,1169 @@
+//! PDF output
+//!
+//! Paragraphs are broken into lines with the fonts their styles resolve to, then paginated, and
+//! each page's lines are drawn where pagination put them. The standard fonts are referred to by
+//! name; font files are subset to the glyphs used and embedded, with text written as glyph IDs
+//! and a ToUnicode map so it can still be copied and searched. Headers and footers are set on
+//! each page they select, at their borders from the top and bottom of the page, and entries in
+//! tables of contents end with the page their heading starts on, set flush right. Index entries
+//! are listed with their pages and set again once those are known, which can move what they
+//! refer to, so they're set until the pages settle. Headings are bookmarked in the document outline.
+//!
+//! Bold and italic text is set in the bold and italic fonts of its paragraph's family, small
+//! capitals and superscript smaller, and superscript raised with the text rise.
+//!
+//! Footnotes are set at the foot of the page their reference is on, under a short rule.
+//! Cross-references are links to where their targets start, and those to pages are set again,
+//! like index entries, until the pages are known. List items are set in by their margins, with
+//! their markers hanging in the list indent before their first lines.
+//!
+//! Tables are set under their captions, a row at a time, with each cell's lines in its columns
+//! and a row as tall as its tallest cell. Their lines break across pages like a paragraph's, with
+//! rules over and under the table and under its header rows.
+//!
+//! Figures' images are a line of their own, over their captions, and scaled down if they'd be
+//! wider than the text or taller than the page. Each image file is embedded once, JPEGs as they
+//! are and PNGs with their alpha, if they have any, as a soft mask.
+
+use std::collections::{ BTreeMap, BTreeSet };
+use std::fmt::Write;
+use std::mem;
+use std::path::Path;
+
+use diagnostic::Diagnostics;
+use document::{ Document, Paragraph };
+use error::Result;
+use figure::Figure;
+use font::{ Font, FontLibrary, FontSource, FontStack, LoadedFont, Metrics, Variant };
+use image::{ ColorSpace, Image, ImageFormat };
+use inline::Formats;
+use layout::hyphenate::Hyphenator;
+use layout::linebreak;
+use layout::linebreak::{ Fonts, Line, Word };
+use layout::page::{ self, Block, Page, PlacedLine, FOOTNOTE_GAP, LINE_HEIGHT };
+use outline::OutlineEntry;
+use reference::Target;
+use table::Table;
+use value::Align;
+
+/// Glyph space units in a text space unit, which is what PDF widths are in
+const GLYPH_UNITS: f64 = 1000.0;
+/// FontDescriptor flags: symbolic, since embedded fonts are addressed by glyph ID
+const SYMBOLIC: u32 = 4;
+/// Room kept clear for page numbers at the end of contents entries, in ems
+const PAGE_NUMBER_EMS: f64 = 3.0;
+/// How much of the width of the page's content the rule over footnotes spans
+const FOOTNOTE_RULE: f64 = 1.0 / 3.0;
+/// Points between the columns of a table
+const TABLE_GAP: f64 = 12.0;
+/// How many times page numbers in the text are set again, at most, before they settle
+const PAGE_NUMBER_PASSES: usize = 4;
+
+/// Render a document as a PDF, with its fonts found in `library`.
+/// Font fallbacks aren't reported here; `litx check` reports them.
+pub fn render(doc: &Document, library: &FontLibrary) -> Result<Vec<u8>> {
+    let mut setter = Setter::new(doc, library);
+    let width = doc.page().content().width;
+    let mut blocks = Vec::new();
+    // Each block's table, set, if it's a table's
+    let mut tables = Vec::new();
+    for paragraph in doc.paragraphs() {
+        let size = paragraph.style().size_pt();
+        let width = if paragraph.contents_entry().is_some() { width - size * PAGE_NUMBER_EMS } else { width };
+        let (block, table) = try!(set_block(&mut setter, paragraph, width));
+        blocks.push(block);
+        tables.push(table);
+    }
+    // Each block's footnote paragraphs, in the order they're attached to it
+    let mut footnotes = Vec::new();
+    for (paragraph, block) in doc.paragraphs().iter().zip(&mut blocks) {
+        footnotes.push(try!(attach_footnotes(&mut setter, paragraph, block, width)));
+    }
+    let mut pages = page::paginate(&blocks, doc.page());
+    let refers = |p: &Paragraph| !p.page_refs().is_empty() || p.refers_to_pages();
+    // Setting page numbers in the text can move what they refer to onto another page, so they're
+    // set again until the pages they say stop changing
+    let mut numbered = None;
+    for _ in 0..PAGE_NUMBER_PASSES {
+        if !doc.paragraphs().iter().any(&refers) {
+            break;
+        }
+        let starts = first_pages(&pages, blocks.len());
+        let note_pages: BTreeMap<usize, usize> = note_starts(&pages, doc, &footnotes).into_iter()
+            .map(|(n, (page, _))| (n, pages[page].number))
+            .collect();
+        match numbered {
+            Some((ref before, ref notes_before)) if *before == starts && *notes_before == note_pages => break,
+            _ => {}
+        }
+        let page_of = |target: Target| match target {
+            Target::Paragraph(i) => starts.get(i).cloned().filter(|&n| n > 0),
+            Target::Note(n) => note_pages.get(&n).cloned(),
+        };
+        for (i, paragraph) in doc.paragraphs().iter().enumerate().filter(|&(_, p)| refers(p)) {
+            let mut listed = paragraph.with_pages(&page_of);
+            if !paragraph.page_refs().is_empty() {
+                let mut numbers: Vec<usize> = paragraph.page_refs().iter()
+                    .filter_map(|&r| starts.get(r).cloned())
+                    .filter(|&n| n > 0)
+                    .collect();
+                numbers.dedup();
+                let mut text = paragraph.text().to_owned();
+                for n in numbers {
+                    text.push_str(&format!(", {}", n));
+                }
+                listed = paragraph.with_text(text);
+            }
+            let (block, table) = try!(set_block(&mut setter, &listed, width));
+            // Only page numbers changed, so footnotes stay on the same lines
+            let notes = mem::replace(&mut blocks[i].footnotes, Vec::new());
+            blocks[i] = block;
+            blocks[i].footnotes = notes;
+            tables[i] = table;
+        }
+        pages = page::paginate(&blocks, doc.page());
+        numbered = Some((starts, note_pages));
+    }
+    let starts = first_pages(&pages, blocks.len());
+
+    let mut pdf = Writer::new();
+    let catalog = pdf.reserve();
+    let page_tree = pdf.reserve();
+    let font_resources = pdf.reserve();
+
+    // Each paragraph's image, if it's a figure, as its XObject's number; figures of the same file
+    // share it
+    let mut images = Vec::new();
+    let mut embedded: BTreeMap<&Path, usize> = BTreeMap::new();
+    let mut xobjects = String::new();
+    for paragraph in doc.paragraphs() {
+        let figure = match paragraph.figure() {
+            Some(figure) => figure,
+            None => {
+                images.push(None);
+                continue;
+            }
+        };
+        let n = match embedded.get(figure.path.as_path()) {
+            Some(&n) => n,
+            None => {
+                let n = embedded.len() + 1;
+                let _ = write!(xobjects, "/Im{} {} 0 R ", n, try!(embed_image(&mut pdf, &figure.image)));
+                embedded.insert(figure.path.as_path(), n);
+                n
+            }
+        };
+        images.push(Some(n));
+    }
+    let xobject_resources = if xobjects.is_empty() {
+        String::new()
+    } else {
+        format!(" /XObject {} 0 R", pdf.add(format!("<< {}>>", xobjects)))
+    };
+
+    let mut kids = Vec::new();
+    let objects: Vec<usize> = pages.iter().map(|_| pdf.reserve()).collect();
+    // Where each paragraph and note starts, as a destination
+    let destination = |page: usize, placed: &PlacedLine| format!(
+        "[{} 0 R /XYZ {} {} null]", objects[page], number(placed.x), number(pages[page].media.height - placed.y));
+    let mut destinations = vec![None; blocks.len()];
+    for (n, page) in pages.iter().enumerate() {
+        for placed in page.lines.iter().filter(|placed| placed.index == 0) {
+            destinations[placed.block] = Some(destination(n, placed));
+        }
+    }
+    let note_destinations: BTreeMap<usize, String> = note_starts(&pages, doc, &footnotes).into_iter()
+        .map(|(note, (page, placed))| (note, destination(page, placed)))
+        .collect();
+    let target = |target: Target| match target {
+        Target::Paragraph(i) => destinations.get(i).and_then(|d| d.clone()),
+        Target::Note(n) => note_destinations.get(&n).cloned(),
+    };
+
+    for (page, &object) in pages.iter().zip(&objects) {
+        let mut content = String::new();
+        let mut links = Vec::new();
+        for placed in &page.lines {
+            links.extend(line_links(placed, page.media.height));
+            let paragraph = &doc.paragraphs()[placed.block];
+            match tables[placed.block] {
+                Some(ref table) => {
+                    for &(p, ref line, x) in &table.lines[placed.index] {
+                        setter.draw(&mut content, &table.paragraphs[p], line, placed.x + x, placed.y, page.media.height);
+                    }
+                    // Rules over its lines, or under its last
+                    for &rule in &table.rules {
+                        let y = if rule == placed.index {
+                            placed.y
+                        } else if rule == placed.index + 1 && rule == table.lines.len() {
+                            placed.y + placed.height
+                        } else {
+                            continue;
+                        };
+                        draw_rule(&mut content, placed.x, page.media.height - y, table.width);
+                    }
+                }
+                None => match (placed.index, images[placed.block], paragraph.figure()) {
+                    (0, Some(n), Some(figure)) => {
+                        let (width, height) = image_size(setter.doc, paragraph, figure, page.content.width);
+                        // Aligned in the room its margin leaves
+                        let room = page.content.x + page.content.width - placed.x;
+                        let x = placed.x + match paragraph.style().align {
+                            Align::Center => (room - width) / 2.0,
+                            Align::Right => room - width,
+                            Align::Left | Align::Justify => 0.0,
+                        };
+                        let _ = write!(content, "q {} 0 0 {} {} {} cm /Im{} Do Q\n", number(width), number(height),
+                                       number(x), number(page.media.height - placed.y - height), n);
+                    }
+                    _ => setter.draw(&mut content, paragraph, &placed.line, placed.x, placed.y, page.media.height),
+                },
+            }
+            if let (0, Some(marker)) = (placed.index, paragraph.list_item().and_then(|item| item.marker.as_ref())) {
+                if !marker.is_empty() {
+                    let style = paragraph.style();
+                    let font = &setter.fonts[setter.font(&style.face, Variant::default())];
+                    let width = font.measure(marker, style.size_pt());
+                    let word = Word { x: 0.0, width: width, text: marker.clone(), formats: Formats::default() };
+                    let line = Line { words: vec![word], ratio: 0.0 };
+                    let x = placed.x - style.list_indent_pt();
+                    setter.draw(&mut content, paragraph, &line, x, placed.y, page.media.height);
+                }
+            }
+            if let Some(heading) = paragraph.contents_entry() {
+                if placed.index + 1 == blocks[placed.block].lines.len() {
+                    let text = starts[heading].to_string();
+                    let font = &setter.fonts[setter.font(&paragraph.style().face, Variant::default())];
+                    let width = font.measure(&text, paragraph.style().size_pt());
+                    let word = Word { x: page.content.width - width, width: width, text: text, formats: Formats::default() };
+                    let number = Line { words: vec![word], ratio: 0.0 };
+                    setter.draw(&mut content, paragraph, &number, page.content.x, placed.y, page.media.height);
+                }
+            }
+        }
+        if let Some(first) = page.footnotes.first() {
+            let y = page.media.height - first.y + FOOTNOTE_GAP / 2.0;
+            draw_rule(&mut content, page.content.x, y, page.content.width * FOOTNOTE_RULE);
+        }
+        for placed in &page.footnotes {
+            links.extend(line_links(placed, page.media.height));
+            let paragraph = footnotes[placed.block][placed.footnote.unwrap_or(0)];
+            setter.draw(&mut content, paragraph, &placed.line, placed.x, placed.y, page.media.height);
+        }
+        try!(draw_running(&mut content, &mut setter, page));
+        let stream = pdf.stream("", content.as_bytes());
+        let mut annotations = Vec::new();
+        for (to, rect) in links {
+            if let Some(dest) = target(to) {
+                let rect: Vec<String> = rect.iter().map(|&n| number(n)).collect();
+                let link = pdf.add(format!("<< /Type /Annot /Subtype /Link /Rect [{}] /Border [0 0 0] /Dest {} >>",
+                                           rect.join(" "), dest));
+                annotations.push(format!("{} 0 R", link));
+            }
+        }
+        let annots = if annotations.is_empty() { String::new() } else { format!(" /Annots [{}]", annotations.join(" ")) };
+        pdf.set(object, format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources << /Font {} 0 R{} >> /Contents {} 0 R{} >>",
+            page_tree, number(page.media.width), number(page.media.height), font_resources, xobject_resources, stream,
+            annots));
+        kids.push(format!("{} 0 R", object));
+    }
+
+    let mut resources = String::new();
+    for (i, font) in setter.fonts.iter().enumerate() {
+        let dict = match *font {
+            LoadedFont::Builtin(builtin) => format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>", builtin.pdf_name()),
+            LoadedFont::File(ref font) => try!(embed(&mut pdf, font, &setter.used[i])),
+        };
+        let _ = write!(resources, "/F{} {} 0 R ", i + 1, pdf.add(dict));
+    }
+    pdf.set(font_resources, format!("<< {}>>", resources));
+
+    pdf.set(page_tree, format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), kids.len()));
+    let outline = doc.outline();
+    if outline.is_empty() {
+        pdf.set(catalog, format!("<< /Type /Catalog /Pages {} 0 R >>", page_tree));
+    } else {
+        let root = pdf.reserve();
+        let (items, count) = bookmarks(&mut pdf, doc, &outline, root, &destinations);
+        pdf.set(root, format!("<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>",
+                              items[0], items[items.len() - 1], count));
+        pdf.set(catalog, format!("<< /Type /Catalog /Pages {} 0 R /Outlines {} 0 R /PageMode /UseOutlines >>",
+                                 page_tree, root));
+    }
+    let info = match doc.title() {
+        Some(title) => pdf.add(format!("<< /Title {} /Producer (litx) >>", text_string(&title))),
+        None => pdf.add("<< /Producer (litx) >>".to_owned()),
+    };
+    Ok(pdf.finish(catalog, info))
+}
+
+/// Write outline items for headings under `parent`, returning their ids and how many items
+/// there are under `parent` altogether. Every item starts open.
+fn bookmarks(pdf: &mut Writer, doc: &Document, entries: &[OutlineEntry], parent: usize,
+             destinations: &[Option<String>]) -> (Vec<usize>, usize)
+{
+    let ids: Vec<usize> = entries.iter().map(|_| pdf.reserve()).collect();
+    let mut count = entries.len();
+    for (n, entry) in entries.iter().enumerate() {
+        let mut item = format!("<< /Title {} /Parent {} 0 R",
+                               text_string(doc.paragraphs()[entry.paragraph].text()), parent);
+        if n > 0 {
+            let _ = write!(item, " /Prev {} 0 R", ids[n - 1]);
+        }
+        if n + 1 < ids.len() {
+            let _ = write!(item, " /Next {} 0 R", ids[n + 1]);
+        }
+        let (children, under) = bookmarks(pdf, doc, &entry.children, ids[n], destinations);
+        if !children.is_empty() {
+            let _ = write!(item, " /First {} 0 R /Last {} 0 R /Count {}", children[0], children[children.len() - 1], under);
+        }
+        if let Some(ref destination) = destinations[entry.paragraph] {
+            let _ = write!(item, " /Dest {}", destination);
+        }
+        item.push_str(" >>");
+        pdf.set(ids[n], item);
+        count += under;
+    }
+    (ids, count)
+}
+
+/// A paragraph's block of lines and, if it's a table's, the table set line by line
+fn set_block(setter: &mut Setter, paragraph: &Paragraph, width: f64) -> Result<(Block, Option<SetTable>)> {
+    match (paragraph.table(), paragraph.figure()) {
+        (Some(table), _) => {
+            let table = try!(set_table(setter, paragraph, table, width));
+            let mut block = Block::new(paragraph, table.merged());
+            block.leading = table.leading;
+            Ok((block, Some(table)))
+        }
+        (None, Some(figure)) => {
+            // The image is a line of its own, over the caption's, which it isn't parted from
+            let mut lines = vec![Line { words: Vec::new(), ratio: 0.0 }];
+            if !paragraph.text().is_empty() {
+                lines.extend(try!(setter.break_paragraph(paragraph, width)));
+            }
+            let mut block = Block::new(paragraph, lines);
+            block.tall = vec![(0, image_size(setter.doc, paragraph, figure, width).1)];
+            block.orphans = block.lines.len();
+            Ok((block, None))
+        }
+        (None, None) => {
+            let lines = try!(setter.break_paragraph(paragraph, width));
+            Ok((Block::new(paragraph, lines), None))
+        }
+    }
+}
+
+/// How big a figure's image is set, scaled down so it fits in a width less its margin, and on a
+/// page
+fn image_size(doc: &Document, paragraph: &Paragraph, figure: &Figure, width: f64) -> (f64, f64) {
+    let margin = paragraph.style().margin_pt();
+    figure.fit(width - margin, doc.page().content().height)
+}
+
+/// Embed an image as an XObject, returning its object number. JPEGs are embedded as they are,
+/// and so are PNGs' compressed rows, which PDF readers unfilter, unless they have alpha.
+fn embed_image(pdf: &mut Writer, image: &Image) -> Result<usize> {
+    let size = format!("/Type /XObject /Subtype /Image /Width {} /Height {}", image.width(), image.height());
+    let color_space = match *image.color_space() {
+        ColorSpace::Gray => "/DeviceGray".to_owned(),
+        ColorSpace::Rgb => "/DeviceRGB".to_owned(),
+        ColorSpace::Cmyk => "/DeviceCMYK".to_owned(),
+        ColorSpace::Indexed(ref palette) => {
+            let hex: Vec<String> = palette.iter().map(|byte| format!("{:02X}", byte)).collect();
+            format!("[/Indexed /DeviceRGB {} <{}>]", (palette.len() / 3).saturating_sub(1), hex.concat())
+        }
+    };
+    let mut entries = format!("{} /ColorSpace {} /BitsPerComponent {}", size, color_space, image.bits());
+    let data = match image.format() {
+        ImageFormat::Jpeg => {
+            entries.push_str(" /Filter /DCTDecode");
+            // Only CMYK is ever inverted
+            if image.is_inverted() {
+                entries.push_str(" /Decode [1 0 1 0 1 0 1 0]");
+            }
+            image.data().to_vec()
+        }
+        ImageFormat::Png if image.has_alpha() => {
+            let (color, alpha) = try!(image.split_alpha());
+            let mask = pdf.stream(&format!("{} /ColorSpace /DeviceGray /BitsPerComponent {} /Filter /FlateDecode",
+                                           size, image.bits()), &alpha);
+            let _ = write!(entries, " /Filter /FlateDecode /SMask {} 0 R", mask);
+            color
+        }
+        ImageFormat::Png => {
+            let _ = write!(entries, " /Filter /FlateDecode /DecodeParms << /Predictor 15 /Colors {} /BitsPerComponent {} \
+                                     /Columns {} >>", image.color_space().components(), image.bits(), image.width());
+            try!(image.scanlines())
+        }
+    };
+    Ok(pdf.stream(&entries, &data))
+}
+
+/// A table, set: its caption's and cells' paragraphs, and their lines on each of its lines
+struct SetTable {
+    /// The caption, then each cell's paragraphs
+    paragraphs: Vec<Paragraph>,
+    /// Each line's lines of those paragraphs, with how far in they're set
+    lines: Vec<Vec<(usize, Line, f64)>>,
+    /// The lines with rules over them, where a rule over the line after the last is under the table
+    rules: Vec<usize>,
+    /// How far apart its lines are, the most its paragraphs need
+    leading: f64,
+    /// How wide it is
+    width: f64,
+}
+
+impl SetTable {
+    /// Each line, with its paragraphs' words side by side
+    fn merged(&self) -> Vec<Line> {
+        self.lines.iter().map(|pieces| {
+            let words = pieces.iter().flat_map(|&(_, ref line, x)| line.words.iter().map(move |word| {
+                Word { x: word.x + x, ..word.clone() }
+            }));
+            Line { words: words.collect(), ratio: 0.0 }
+        }).collect()
+    }
+}
+
+/// Set a table, in a width less its caption's margin: the caption's lines, then each row's, as
+/// many as its cells need
+fn set_table(setter: &mut Setter, paragraph: &Paragraph, table: &Table, width: f64) -> Result<SetTable> {
+    let mut paragraphs = vec![paragraph.clone()];
+    let mut lines = Vec::new();
+    if !paragraph.text().is_empty() {
+        for line in try!(setter.break_paragraph(paragraph, width)) {
+            lines.push(vec![(0, line, 0.0)]);
+        }
+    }
+    let top = lines.len();
+    let width = width - paragraph.style().margin_pt();
+    let columns = table.column_bounds(width, TABLE_GAP);
+    let (mut cells, mut heights) = (Vec::new(), Vec::new());
+    for row in &table.rows {
+        let mut row_cells = Vec::new();
+        for cell in &row.cells {
+            let (x, cell_width) = cell.bounds(&columns);
+            let mut cell_lines = Vec::new();
+            for cell_paragraph in &cell.paragraphs {
+                let p = paragraphs.len();
+                paragraphs.push(cell_paragraph.clone());
+                for line in try!(setter.break_paragraph(cell_paragraph, cell_width)) {
+                    cell_lines.push((p, line, x));
+                }
+            }
+            row_cells.push(cell_lines);
+        }
+        heights.push(row_cells.iter().map(Vec::len).collect());
+        cells.push(row_cells);
+    }
+    let starts = table.row_starts(&heights);
+    lines.resize(top + starts[starts.len() - 1], Vec::new());
+    for (r, row) in cells.into_iter().enumerate() {
+        for cell in row {
+            for (k, piece) in cell.into_iter().enumerate() {
+                lines[top + starts[r] + k].push(piece);
+            }
+        }
+    }
+    let header = table.header_rows();
+    let mut rules = vec![top];
+    if header > 0 && header < table.rows.len() {
+        rules.push(top + starts[header]);
+    }
+    rules.push(lines.len());
+    let leading = paragraphs.iter().map(leading).fold(0.0, f64::max);
+    Ok(SetTable { paragraphs: paragraphs, lines: lines, rules: rules, leading: leading, width: width })
+}
+
+/// Draw a horizontal rule, `y` points from the bottom of the page
+fn draw_rule(out: &mut String, x: f64, y: f64, width: f64) {
+    let _ = write!(out, "q 0.5 w {} {} m {} {} l S Q\n", number(x), number(y), number(x + width), number(y));
+}
+
+/// Break the footnotes a paragraph refers to, and attach them to the lines referring to them.
+/// Returns their paragraphs, in the order they're attached.
+fn attach_footnotes<'a>(setter: &mut Setter<'a>, paragraph: &Paragraph, block: &mut Block, width: f64)
+                        -> Result<Vec<&'a Paragraph>>
+{
+    let doc = setter.doc;
+    let notes = paragraph.notes();
+    if notes.is_empty() || doc.footnotes().is_empty() {
+        return Ok(Vec::new());
+    }
+    // The line each note number is on; any that can't be told apart go on the last
+    let refs: Vec<usize> = block.lines.iter().enumerate()
+        .flat_map(|(l, line)| line.words.iter().filter(|w| w.formats.note).map(move |_| l))
+        .collect();
+    let mut paragraphs = Vec::new();
+    for (n, number) in notes.into_iter().enumerate() {
+        let line = refs.get(n).or(refs.last()).cloned().unwrap_or(0);
+        for note in doc.footnotes().iter().filter(|note| note.number == number) {
+            for paragraph in &note.paragraphs {
+                let lines = try!(setter.break_paragraph(paragraph, width));
+                block.footnotes.push((line, Block::new(paragraph, lines)));
+                paragraphs.push(paragraph);
+            }
+        }
+    }
+    Ok(paragraphs)
+}
+
+/// Where each note starts: the index of its page, and its first line
+fn note_starts<'p>(pages: &'p [Page], doc: &Document, footnotes: &[Vec<&Paragraph>])
+                   -> BTreeMap<usize, (usize, &'p PlacedLine)>
+{
+    let mut starts = BTreeMap::new();
+    for (n, page) in pages.iter().enumerate() {
+        // Endnotes are among the paragraphs, and footnotes at the foot of the page
+        let endnotes = page.lines.iter().map(|placed| (placed, doc.paragraphs()[placed.block].note()));
+        let footnotes = page.footnotes.iter()
+            .map(|placed| (placed, footnotes[placed.block][placed.footnote.unwrap_or(0)].note()));
+        for (placed, note) in endnotes.chain(footnotes) {
+            if let (0, Some(note)) = (placed.index, note) {
+                starts.entry(note).or_insert((n, placed));
+            }
+        }
+    }
+    starts
+}
+
+/// The boxes of a line's links, as PDF rectangles, with what they link to. Neighbouring words
+/// linking to the same place share a box.
+fn line_links(placed: &PlacedLine, page_height: f64) -> Vec<(Target, [f64; 4])> {
+    let (top, bottom) = (page_height - placed.y, page_height - placed.y - placed.height);
+    let mut links: Vec<(Target, [f64; 4])> = Vec::new();
+    let mut previous = None;
+    for word in &placed.line.words {
+        let (left, right) = (placed.x + word.x, placed.x + word.x + word.width);
+        match (word.formats.link, links.last_mut()) {
+            (Some(target), Some(&mut (_, ref mut rect))) if previous == Some(target) => rect[2] = right,
+            (Some(target), _) => links.push((target, [left, bottom, right, top])),
+            (None, _) => {}
+        }
+        previous = word.formats.link;
+    }
+    links
+}
+
+/// The page each of `count` blocks starts on, or 0 for blocks with no lines
+fn first_pages(pages: &[Page], count: usize) -> Vec<usize> {
+    let mut starts = vec![0; count];
+    for page in pages.iter().rev() {
+        for placed in &page.lines {
+            starts[placed.block] = page.number;
+        }
+    }
+    starts
+}
+
+/// Set the header at its border from the top of the page, and the footer so its last line
+/// ends at its border from the bottom
+fn draw_running(out: &mut String, setter: &mut Setter, page: &Page) -> Result<()> {
+    let doc = setter.doc;
+    let width = page.content.width;
+    if let Some(header) = doc.strategy().header() {
+        let mut y = header.border();
+        for paragraph in header.on_page(page, doc) {
+            let lines = try!(setter.break_paragraph(&paragraph, width));
+            for line in &lines {
+                setter.draw(out, &paragraph, line, page.content.x, y, page.media.height);
+                y += leading(&paragraph);
+            }
+        }
+    }
+    if let Some(footer) = doc.strategy().footer() {
+        let mut set = Vec::new();
+        for paragraph in footer.on_page(page, doc) {
+            let lines = try!(setter.break_paragraph(&paragraph, width));
+            set.push((paragraph, lines));
+        }
+        let height: f64 = set.iter().map(|&(ref p, ref lines)| leading(p) * lines.len() as f64).sum();
+        let mut y = page.media.height - footer.border() - height;
+        for (paragraph, lines) in set {
+            for line in &lines {
+                setter.draw(out, &paragraph, line, page.content.x, y, page.media.height);
+                y += leading(&paragraph);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How far apart a paragraph's lines are
+fn leading(paragraph: &Paragraph) -> f64 {
+    paragraph.style().size_pt() * LINE_HEIGHT * paragraph.style().line_spacing
+}
+
+/// Loads fonts and hyphenators as they're needed, and sets lines with them
+struct Setter<'a> {
+    doc: &'a Document,
+    library: &'a FontLibrary,
+    /// Each face and variant's font, by index
+    faces: BTreeMap<(String, Variant), usize>,
+    fonts: Vec<LoadedFont>,
+    /// Where each font came from, so variants a family doesn't have share its regular font
+    sources: Vec<FontSource>,
+    /// The glyphs used from each font, and what for
+    used: Vec<BTreeMap<u16, char>>,
+    hyphenators: BTreeMap<String, Option<Hyphenator>>,
+}
+
+impl<'a> Setter<'a> {
+    fn new(doc: &'a Document, library: &'a FontLibrary) -> Setter<'a> {
+        Setter {
+            doc: doc,
+            library: library,
+            faces: BTreeMap::new(),
+            fonts: Vec::new(),
+            sources: Vec::new(),
+            used: Vec::new(),
+            hyphenators: BTreeMap::new(),
+        }
+    }
+
+    /// Load a face's font in a variant, if it isn't already
+    fn load(&mut self, face: &str, variant: Variant) -> Result<()> {
+        let key = (face.to_owned(), variant);
+        if self.faces.contains_key(&key) {
+            return Ok(());
+        }
+        let resolved = self.library.resolve_variant(&FontStack::parse(face), variant, &mut Diagnostics::new());
+        let index = match self.sources.iter().position(|source| *source == resolved.source) {
+            Some(index) => index,
+            None => {
+                self.fonts.push(try!(resolved.load()));
+                self.sources.push(resolved.source);
+                self.used.push(BTreeMap::new());
+                self.fonts.len() - 1
+            }
+        };
+        self.faces.insert(key, index);
+        Ok(())
+    }
+
+    /// The index of a face's font in a variant, which must be loaded
+    fn font(&self, face: &str, variant: Variant) -> usize {
+        self.faces[&(face.to_owned(), variant)]
+    }
+
+    /// Break a paragraph into lines with its fonts, in a width less its margin
+    fn break_paragraph(&mut self, paragraph: &Paragraph, width: f64) -> Result<Vec<Line>> {
+        let style = paragraph.style();
+        let width = width - style.margin_pt();
+        try!(self.load(&style.face, Variant::default()));
+        for run in paragraph.runs() {
+            try!(self.load(&style.face, run.formats.variant()));
+        }
+        let doc = self.doc;
+        let hyphenator = self.hyphenators.entry(style.language.clone())
+            .or_insert_with(|| doc.strategy().hyphenation().hyphenator(&style.language));
+        let fonts = FaceFonts { fonts: &self.fonts, faces: &self.faces, face: &style.face };
+        Ok(linebreak::break_paragraph(paragraph, &fonts, width, hyphenator.as_ref()))
+    }
+
+    /// Draw a line with its box's top left at `x` and `top`, from the top left of the page
+    fn draw(&mut self, out: &mut String, paragraph: &Paragraph, line: &Line, x: f64, top: f64, page_height: f64) {
+        let face = &paragraph.style().face;
+        let regular = self.font(face, Variant::default());
+        let size = paragraph.style().size_pt();
+        let scale = size / self.fonts[regular].units_per_em() as f64;
+        // Centre the font's height in a single spaced line, at the top of the line's box
+        let extent = self.fonts[regular].ascent() as f64 + self.fonts[regular].descent() as f64;
+        let baseline = top + (size * LINE_HEIGHT + extent * scale) / 2.0;
+
+        // Start in the first word's font, rather than switching straight away
+        let first = line.words.first().map_or(Formats::default(), |w| w.formats);
+        let (mut set_font, mut set_size, mut set_rise) = (self.font(face, first.variant()), size * first.scale(), 0.0);
+        let _ = write!(out, "BT /F{} {} Tf\n", set_font + 1, number(set_size));
+        for word in &line.words {
+            let f = self.font(face, word.formats.variant());
+            let word_size = size * word.formats.scale();
+            if (f, word_size) != (set_font, set_size) {
+                let _ = write!(out, "/F{} {} Tf\n", f + 1, number(word_size));
+                set_font = f;
+                set_size = word_size;
+            }
+            let rise = size * word.formats.rise();
+            if rise != set_rise {
+                let _ = write!(out, "{} Ts\n", number(rise));
+                set_rise = rise;
+            }
+            let font = &self.fonts[f];
+            let _ = write!(out, "1 0 0 1 {} {} Tm [", number(x + word.x), number(page_height - baseline));
+            // Runs of text, split where a pair is kerned
+            let mut run = String::new();
+            let mut previous = None;
+            for c in word.text.chars() {
+                let kern = previous.map_or(0, |p| font.kerning(p, c));
+                if kern != 0 {
+                    out.push_str(&string(font, &run));
+                    out.push_str(&number(-(kern as f64) * GLYPH_UNITS / font.units_per_em() as f64));
+                    run.clear();
+                }
+                if let LoadedFont::File(ref file) = *font {
+                    self.used[f].entry(file.glyph(c).unwrap_or(0)).or_insert(c);
+                }
+                run.push(c);
+                previous = Some(c);
+            }
+            out.push_str(&string(font, &run));
+            out.push_str("] TJ\n");
+        }
+        out.push_str("ET\n");
+    }
+}
+
+/// A face's fonts in a setter, for the line breaker
+struct FaceFonts<'s> {
+    fonts: &'s [LoadedFont],
+    faces: &'s BTreeMap<(String, Variant), usize>,
+    face: &'s str,
+}
+
+impl<'s> Fonts for FaceFonts<'s> {
+    fn font(&self, variant: Variant) -> &Metrics {
+        &self.fonts[self.faces[&(self.face.to_owned(), variant)]]
+    }
+}
+
+/// Text as a PDF string in a font: WinAnsiEncoding for the standard fonts, glyph IDs otherwise
+fn string(font: &LoadedFont, text: &str) -> String {
+    match *font {
+        LoadedFont::Builtin(builtin) => {
+            let mut out = String::from("(");
+            for c in text.chars() {
+                // Codes past ASCII are escaped, so the content stays ASCII rather than UTF-8
+                match builtin.code(c) {
+                    code @ b'(' | code @ b')' | code @ b'\\' => {
+                        out.push('\\');
+                        out.push(code as char);
+                    }
+                    code if code >= 0x80 => {
+                        let _ = write!(out, "\\{:03o}", code);
+                    }
+                    code => out.push(code as char),
+                }
+            }
+            out.push(')');
+            out
+        }
+        LoadedFont::File(ref file) => {
+            let glyphs: Vec<String> = text.chars().map(|c| format!("{:04X}", file.glyph(c).unwrap_or(0))).collect();
+            format!("<{}>", glyphs.concat())
+        }
+    }
+}
+
+/// Embed a font file, as a composite font addressed by glyph ID. Returns its font dictionary.
+fn embed(pdf: &mut Writer, font: &Font, used: &BTreeMap<u16, char>) -> Result<String> {
+    let units = |n: f64| number(n * GLYPH_UNITS / font.units_per_em() as f64);
+    let glyphs: BTreeSet<u16> = used.keys().cloned().collect();
+    let postscript_name = match font.postscript_name().replace(' ', "") {
+        ref name if name.is_empty() => "Embedded".to_owned(),
+        name => name,
+    };
+    let name = format!("{}+{}", subset_tag(&glyphs), postscript_name);
+
+    let data = try!(font.subset(&glyphs));
+    let file = if font.is_truetype() {
+        let file = pdf.stream(&format!("/Length1 {}", data.len()), &data);
+        format!("/FontFile2 {} 0 R", file)
+    } else {
+        let file = pdf.stream("/Subtype /OpenType", &data);
+        format!("/FontFile3 {} 0 R", file)
+    };
+    let bbox = font.bbox();
+    let descriptor = pdf.add(format!(
+        "<< /Type /FontDescriptor /FontName /{} /Flags {} /FontBBox [{} {} {} {}] /ItalicAngle 0 \
+         /Ascent {} /Descent {} /CapHeight {} /StemV 80 {} >>",
+        name, SYMBOLIC, units(bbox[0] as f64), units(bbox[1] as f64), units(bbox[2] as f64), units(bbox[3] as f64),
+        units(font.ascent() as f64), units(font.descent() as f64), units(font.ascent() as f64), file));
+
+    let mut widths = String::new();
+    for &glyph in used.keys() {
+        let _ = write!(widths, "{} [{}] ", glyph, units(font.glyph_advance(glyph) as f64));
+    }
+    let subtype = if font.is_truetype() { "CIDFontType2 /CIDToGIDMap /Identity" } else { "CIDFontType0" };
+    let descendant = pdf.add(format!(
+        "<< /Type /Font /Subtype /{} /BaseFont /{} \
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> \
+         /FontDescriptor {} 0 R /W [{}] >>",
+        subtype, name, descriptor, widths));
+    let to_unicode = pdf.stream("", to_unicode(used).as_bytes());
+    Ok(format!(
+        "<< /Type /Font /Subtype /Type0 /BaseFont /{} /Encoding /Identity-H /DescendantFonts [{} 0 R] \
+         /ToUnicode {} 0 R >>",
+        name, descendant, to_unicode))
+}
+
+/// Six capital letters naming a subset, which differ between subsets of the same font
+fn subset_tag(glyphs: &BTreeSet<u16>) -> String {
+    // FNV-1a
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &glyph in glyphs {
+        for byte in &[(glyph >> 8) as u8, glyph as u8] {
+            hash = (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    (0..6).map(|i| (b'A' + ((hash >> (i * 8)) % 26) as u8) as char).collect()
+}
+
+/// A CMap from glyph IDs back to the characters they were used for
+fn to_unicode(used: &BTreeMap<u16, char>) -> String {
+    let mut out = String::from("/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+        /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+        /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+        1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n");
+    let entries: Vec<(&u16, &char)> = used.iter().collect();
+    // bfchar blocks hold at most a hundred entries
+    for chunk in entries.chunks(100) {
+        let _ = write!(out, "{} beginbfchar\n", chunk.len());
+        for &(glyph, c) in chunk {
+            let mut utf16 = [0; 2];
+            let hex: Vec<String> = c.encode_utf16(&mut utf16).iter().map(|u| format!("{:04X}", u)).collect();
+            let _ = write!(out, "<{:04X}> <{}>\n", glyph, hex.concat());
+        }
+        out.push_str("endbfchar\n");
+    }
+    out.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+    out
+}
+
+/// Text for the document information dictionary: a literal when it's ASCII, UTF-16 otherwise
+fn text_string(text: &str) -> String {
+    if text.chars().all(|c| c >= ' ' && c <= '~') {
+        let escaped: String = text.chars().flat_map(|c| match c {
+            '(' | ')' | '\\' => vec!['\\', c],
+            _ => vec![c],
+        }).collect();
+        format!("({})", escaped)
+    } else {
+        let hex: Vec<String> = text.encode_utf16().map(|u| format!("{:04X}", u)).collect();
+        format!("<FEFF{}>", hex.concat())
+    }
+}
+
+/// A number as PDF writes it: no exponent, and no more than three decimals
+fn number(n: f64) -> String {
+    let text = format!("{:.3}", n);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" { "0".to_owned() } else { text.to_owned() }
+}
+
+/// Numbered objects, written out with a cross-reference table at the end
+struct Writer {
+    objects: Vec<Vec<u8>>,
+}
+
+impl Writer {
+    fn new() -> Writer {
+        Writer { objects: Vec::new() }
+    }
+
+    /// An object number for something written later with `set`
+    fn reserve(&mut self) -> usize {
+        self.objects.push(Vec::new());
+        self.objects.len()
+    }
+
+    fn set(&mut self, id: usize, object: String) {
+        self.objects[id - 1] = object.into_bytes();
+    }
+
+    fn add(&mut self, object: String) -> usize {
+        let id = self.reserve();
+        self.set(id, object);
+        id
+    }
+
+    /// A stream, with extra entries for its dictionary
+    fn stream(&mut self, entries: &str, data: &[u8]) -> usize {
+        let entries = if entries.is_empty() { String::new() } else { format!(" {}", entries) };
+        let mut object = format!("<< /Length {}{} >>\nstream\n", data.len(), entries).into_bytes();
+        object.extend_from_slice(data);
+        object.extend_from_slice(b"\nendstream");
+        let id = self.reserve();
+        self.objects[id - 1] = object;
+        id
+    }
+
+    fn finish(self, root: usize, info: usize) -> Vec<u8> {
+        // The binary comment tells transfer tools the file isn't text
+        let mut out = b"%PDF-1.6\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in self.objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend_from_slice(object);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = out.len();
+        out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", self.objects.len() + 1).as_bytes());
+        for offset in offsets {
+            out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        out.extend_from_slice(format!(
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.objects.len() + 1, root, info, xref).as_bytes());
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use document::{ Document, Strategy };
+    use font::{ Builtin, FontLibrary };
+    use font::test::tiny_font;
+    use image::test::{ beside_images, cmyk_jpeg };
+    use scratch::Scratch;
+
+    /// Each object's number, and where the cross-reference table says it is
+    fn xref(pdf: &[u8]) -> Vec<(usize, usize)> {
+        let text = String::from_utf8_lossy(pdf);
+        let start: usize = text.lines().rev().nth(1).unwrap().parse().unwrap();
+        text[start..].lines().skip(3).take_while(|l| l.ends_with(" n ")).enumerate()
+            .map(|(i, l)| (i + 1, l[..10].parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn renders_pages_of_standard_fonts() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::default [{ ::font [{ ::face Monospace }] }] }] }]")
+            .unwrap();
+        let src = "[{document ::page page/200ptx100pt/10pt ::meta [{ ::title ''A Short Test'' }] || \
+                   Hello world \n\n [{page-break}] Goodbye }]";
+        let pdf = render(&Document::new(src, strategy).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.6"));
+        assert!(text.ends_with("%%EOF\n"));
+        for (id, offset) in xref(&pdf) {
+            assert!(text[offset..].starts_with(&format!("{} 0 obj", id)));
+        }
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("/MediaBox [0 0 200 100]"));
+        assert!(text.contains("/BaseFont /Courier"));
+        assert!(text.contains("/Title (A Short Test)"));
+        // 12pt Courier's baseline sits a little over 10pt below the top margin
+        assert!(text.contains("1 0 0 1 10 79.968 Tm [(Hello)] TJ"));
+        assert!(text.contains("[(world)] TJ"));
+        assert_eq!("(\\(a\\) ?)", string(&LoadedFont::Builtin(Builtin::Courier), "(a) é"));
+        assert_eq!("(\\(\\)) <FEFF00E9>", format!("{} {}", text_string("()"), text_string("é")));
+    }
+
+    #[test]
+    fn sets_headers_and_footers() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::default [{ ::font [{ ::face Monospace }] }] }]
+            ::header [{ ::pages not-first ::border-top 10pt ::content [{ || Page $$special/page-no }] }]
+            ::footer [{ ::border-bottom 10pt ::content [{ || of $$special/page-count }] }] }]").unwrap();
+        let src = "[{document ::page page/200ptx100pt/30pt || One [{page-break}] Two }]";
+        let pdf = render(&Document::new(src, strategy).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(!text.contains("[(1)] TJ"));
+        assert!(text.contains("1 0 0 1 30 79.968 Tm [(Page)] TJ"));
+        assert!(text.contains("[(2)] TJ"));
+        // A line up from the bottom border
+        assert_eq!(2, text.matches("1 0 0 1 30 14.368 Tm [(of)] TJ").count());
+    }
+
+    #[test]
+    fn numbers_contents_entries() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::default [{ ::font [{ ::face Monospace }] }] }]
+            ::frontmatter [{ ::page before-first ::content [{ || [{ toc }] }] }] }]").unwrap();
+        let src = "[{document ::page page/200ptx100pt/10pt || [{ h1 || One }] [{page-break}] [{ h1 || Two }] }]";
+        let pdf = render(&Document::new(src, strategy).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/Count 3"));
+        // Flush right, a digit's width in from the margin
+        assert!(text.contains("1 0 0 1 10 79.968 Tm [(One)] TJ\nET\nBT /F1 12 Tf\n1 0 0 1 182.8 79.968 Tm [(2)] TJ"));
+        assert!(text.contains("1 0 0 1 182.8 65.568 Tm [(3)] TJ"));
+    }
+
+    #[test]
+    fn lists_pages_in_the_index() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::default [{ ::font [{ ::face Monospace }] }] }]
+            ::backmatter [{ ::page after-last ::content [{ || [{ index }] }] }] }]").unwrap();
+        let src = "[{document ::page page/200ptx100pt/10pt || Apples [{ index apple }] \n\n Pears                    [{ index pear }] [{page-break}] More [{ index apple }] [{ index pear }] }]";
+        let pdf = render(&Document::new(src, strategy).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/Count 3"));
+        assert!(text.contains("[(apple,)] TJ\n1 0 0 1 60.4 79.968 Tm [(1,)] TJ\n1 0 0 1 82 79.968 Tm [(2)] TJ"));
+        assert!(text.contains("[(pear,)] TJ"));
+    }
+
+    #[test]
+    fn indexes_notes_and_cells_on_their_pages() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::default [{ ::font [{ ::face Monospace }] }] }]
+            ::notes [{ ::placement endnotes }]
+            ::backmatter [{ ::page after-last ::content [{ || [{ index }] }] }] }]").unwrap();
+        let src = "[{document ::page page/200ptx100pt/10pt || First. [{page-break}] \
+                   Noted.[{ note || A fox. [{ index fox }] }] [{page-break}] \
+                   [{ table || [{ row || An owl. [{ index owl }] }] }] }]";
+        let pdf = render(&Document::new(src, strategy).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("[(fox,)] TJ\n1 0 0 1 46 79.968 Tm [(2)] TJ"));
+        assert!(text.contains("[(owl,)] TJ\n1 0 0 1 46 65.568 Tm [(3)] TJ"));
+    }
+
+    #[test]
+    fn bookmarks_headings() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::default [{ ::font [{ ::face Monospace }] }] }]
+            ::headings [{ ::numbering decimal }] }]").unwrap();
+        let src = "[{document ::page page/200ptx100pt/10pt || [{ h1 || One }] [{ h2 || Two }] \
+                   [{page-break}] [{ h1 || Three }] }]";
+        let pdf = render(&Document::new(src, strategy).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        for (id, offset) in xref(&pdf) {
+            assert!(text[offset..].starts_with(&format!("{} 0 obj", id)));
+        }
+        assert!(text.contains("/PageMode /UseOutlines"));
+        assert!(text.contains("<< /Type /Outlines /First 10 0 R /Last 11 0 R /Count 3 >>"));
+        assert!(text.contains("<< /Title (1 One) /Parent 9 0 R /Next 11 0 R /First 12 0 R /Last 12 0 R /Count 1 \
+                               /Dest [4 0 R /XYZ 10 90 null] >>"));
+        assert!(text.contains("<< /Title (1.1 Two) /Parent 10 0 R /Dest [4 0 R /XYZ 10 75.6 null] >>"));
+        assert!(text.contains("<< /Title (2 Three) /Parent 9 0 R /Prev 10 0 R /Dest [5 0 R /XYZ 10 90 null] >>"));
+    }
+
+    #[test]
+    fn links_references() {
+        let src = "[{document ::page page/200ptx100pt/10pt || See page [{ ref two ::show page }]. \
+                   [{page-break}] [{ h1 || Two [{ label two }] }] }]";
+        let pdf = render(&Document::new(src, Strategy::none()).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("[(2)] TJ"));
+        assert!(text.contains("/Annots [7 0 R]"));
+        assert!(text.contains("<< /Type /Annot /Subtype /Link /Rect [55.984 75.6 61.984 90] /Border [0 0 0] \
+                               /Dest [5 0 R /XYZ 10 90 null] >>"));
+    }
+
+    #[test]
+    fn settles_forward_page_references() {
+        // Set as the heading's title, the reference would wrap and push the heading onto page 2
+        let src = format!("[{{document ::page page/200ptx100pt/10pt || {} see [{{ ref x ::show page }}]. \
+                           [{{ h1 || Heading with quite a long title [{{ label x }}] }}] }}]", "aa ".repeat(40));
+        let pdf = render(&Document::new(&src, Strategy::none()).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/Count 1"));
+        assert!(text.contains("[(see)] TJ"));
+        assert!(text.contains("[(1)] TJ"));
+        assert!(!text.contains("[(2)] TJ"));
+    }
+
+    #[test]
+    fn sets_formatting() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::default [{ ::font [{ ::face Monospace }] }] }] }]")
+            .unwrap();
+        let src = "[{document ::page page/200ptx100pt/10pt || [{ i || A [{ b || b }] }] c[{ sup || 2 }] [{ sc || d }] }]";
+        let pdf = render(&Document::new(src, strategy).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/F2 7 0 R /F3 8 0 R"));
+        assert!(text.contains("/BaseFont /Courier-Oblique"));
+        assert!(text.contains("/BaseFont /Courier-BoldOblique"));
+        assert!(text.contains("BT /F2 12 Tf\n1 0 0 1 10 79.968 Tm [(A)] TJ\n/F3 12 Tf\n1 0 0 1 24.4 79.968 Tm [(b)] TJ\n"));
+        // Superscript is smaller and raised, and small capitals are capitals
+        assert!(text.contains("/F1 7.2 Tf\n4.2 Ts\n1 0 0 1 46 79.968 Tm [(2)] TJ\n"));
+        assert!(text.contains("/F1 9.6 Tf\n0 Ts\n1 0 0 1 57.52 79.968 Tm [(D)] TJ"));
+    }
+
+    #[test]
+    fn sets_footnotes_under_a_rule() {
+        let src = "[{document ::page page/200ptx100pt/10pt || One.[{ note || A note. }] Two. }]";
+        let pdf = render(&Document::new(src, Strategy::none()).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        let rule = text.find("q 0.5 w 10 30.4 m 70 30.4 l S Q\n").unwrap();
+        assert!(text[rule..].starts_with("q 0.5 w 10 30.4 m 70 30.4 l S Q\nBT /F1 7.2 Tf\n4.2 Ts\n"));
+        assert!(text[rule..].contains("[(note.)] TJ"));
+    }
+
+    #[test]
+    fn hangs_list_markers() {
+        let src = "[{document ::page page/200ptx100pt/10pt || [{ list ::style number || One }] }]";
+        let pdf = render(&Document::new(src, Strategy::none()).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        // The item is set in by the list indent, and its marker at the margin
+        assert!(text.contains("1 0 0 1 46 80.004 Tm [(One)] TJ"));
+        assert!(text.contains("1 0 0 1 10 80.004 Tm [(1.)] TJ"));
+    }
+
+    #[test]
+    fn draws_default_bullets() {
+        let src = "[{document ::page page/200ptx100pt/10pt || [{ list || One }] }]";
+        let pdf = render(&Document::new(src, Strategy::none()).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        // The bullet is WinAnsiEncoding's, escaped so the content stays ASCII
+        assert!(text.contains("1 0 0 1 10 80.004 Tm [(\\225)] TJ"));
+        assert!(!text.contains("[(?)] TJ"));
+        assert_eq!(350, Builtin::Times.advance('\u{2022}'));
+        assert_eq!("(\\225\\(\\))", string(&LoadedFont::Builtin(Builtin::Courier), "\u{2022}()"));
+    }
+
+    #[test]
+    fn sets_tables_in_rows() {
+        let src = "[{document ::page page/200ptx200pt/10pt || [{ table ::columns ''left right'' || Masses
+            [{ row ::header true || Sample || Mass }]
+            [{ row || [{ cell ::rowspan 2 || A B C D E F G H I J K L M N O P }] || 1.2 }]
+            [{ row || 1.3 }] }] }]";
+        let pdf = render(&Document::new(src, Strategy::none()).unwrap(), &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        // The caption, then the header in bold, with the second column flush right
+        assert!(text.contains("1 0 0 1 10 180.004 Tm [(Table)] TJ"));
+        assert!(text.contains("/F2 12 Tf\n1 0 0 1 10 165.604 Tm [(Sample)] TJ"));
+        assert!(text.contains("1 0 0 1 163.336 165.604 Tm [(Mass)] TJ"));
+        // The spanning cell takes three lines, and the rows beside it make room
+        assert!(text.contains("1 0 0 1 10 122.404 Tm [(P)] TJ"));
+        assert!(text.contains("1 0 0 1 175 136.804 Tm [(1.3)] TJ"));
+        // Rules over and under the table, and under its header
+        for y in &["175.6", "161.2", "118"] {
+            assert!(text.contains(&format!("q 0.5 w 10 {0} m 190 {0} l S Q", y)));
+        }
+    }
+
+    #[test]
+    fn inverts_adobe_cmyk() {
+        let mut pdf = Writer::new();
+        embed_image(&mut pdf, &Image::parse(cmyk_jpeg(true)).unwrap()).unwrap();
+        embed_image(&mut pdf, &Image::parse(cmyk_jpeg(false)).unwrap()).unwrap();
+        let objects: Vec<_> = pdf.objects.iter().map(|object| String::from_utf8_lossy(object).into_owned()).collect();
+        assert!(objects[0].contains("/ColorSpace /DeviceCMYK /BitsPerComponent 8 /Filter /DCTDecode /Decode [1 0 1 0 1 0 1 0]"));
+        assert!(!objects[1].contains("/Decode"));
+    }
+
+    #[test]
+    fn sets_figures_over_captions() {
+        let src = "[{document ::page page/200ptx200pt/10pt || [{ figure ::src ''dot.png'' ::width 90pt || A dot }]
+            [{ figure ::src ''dot.png'' ::width 400pt }] }]";
+        let doc = beside_images(src, Strategy::none()).unwrap();
+        let pdf = render(&doc, &FontLibrary::empty()).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        // Embedded once, with its alpha as a soft mask
+        assert_eq!(1, text.matches("/Subtype /Image /Width 2 /Height 1 /ColorSpace /DeviceRGB").count());
+        assert!(text.contains("/Width 2 /Height 1 /ColorSpace /DeviceGray /BitsPerComponent 8 /Filter /FlateDecode"));
+        assert!(text.contains("/SMask"));
+        assert!(text.contains("/XObject"));
+        // The image, then its caption under it; the second is scaled down to the text's width
+        assert!(text.contains("q 90 0 0 45 10 145 cm /Im1 Do Q"));
+        assert!(text.contains("[(Figure)] TJ"));
+        assert!(text.contains("q 180 0 0 90 10 "));
+    }
+
+    #[test]
+    fn embeds_font_subsets() {
+        let dir = Scratch::new("pdf-fonts");
+        File::create(dir.join("Tiny.ttf")).unwrap().write_all(&tiny_font()).unwrap();
+        let strategy = Strategy::new("[{strategy ::text-settings [{ ::default [{ ::font [{ ::face Tiny }] }] }] }]")
+            .unwrap();
+        let doc = Document::new("[{document || AVÄ }]", strategy).unwrap();
+        let pdf = render(&doc, &FontLibrary::new(&[&dir])).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/Subtype /CIDFontType2"));
+        assert!(text.contains("/W [1 [600] 2 [700] 3 [700] ]"));
+        // A and V are kerned together
+        assert!(text.contains("[<0001>80<00020003>] TJ"));
+        assert!(text.contains("<0003> <00C4>"));
+    }
+}
@@ -0,0 +1,263 @@
+//! Plain text output
+//!
+//! Text is laid out as if it were set in Courier at each paragraph's size, so a column is 0.6em
+//! wide and indents and line lengths in points turn into columns. Justified lines are spread by
+//! rounding each word to the nearest column. Words are hyphenated in each paragraph's language.
+//! List items are set in by their margins, with their markers hanging before them. Tables are
+//! set under their captions, with their cells' lines side by side and a rule under the header.
+//! Figures' images can't be shown, so their names are, in brackets over their captions.
+//!
+//! Formatting is marked the way it is in plain text email: `_italics_`, `*bold*`, small capitals
+//! in CAPITALS, and superscript after a `^`, like note numbers. Footnotes follow everything else.
+
+use std::collections::BTreeMap;
+
+use document::{ Document, Paragraph };
+use error::Result;
+use font::{ Builtin, Metrics };
+use inline::{ Format, Inline };
+use layout::hyphenate::Hyphenator;
+use layout::linebreak;
+use table::Table;
+use value::Align;
+
+/// Columns in a line, when nothing says otherwise
+pub const DEFAULT_COLUMNS: usize = 72;
+
+/// Columns between the columns of a table
+const TABLE_GAP: usize = 2;
+
+/// Render a document as plain text, `columns` characters wide
+pub fn render(doc: &Document, columns: usize) -> Result<String> {
+    let mut out = String::new();
+    let mut setter = Setter { doc: doc, hyphenators: BTreeMap::new() };
+    let footnotes = doc.footnotes().iter().flat_map(|note| note.paragraphs.iter());
+    for (i, paragraph) in doc.paragraphs().iter().chain(footnotes).enumerate() {
+        let spacing = (paragraph.style().line_spacing.round() as usize).saturating_sub(1);
+        if i > 0 {
+            out.push_str(&"\n".repeat(spacing.max(1)));
+        }
+        let mut lines = setter.lines(paragraph, columns);
+        if let Some(table) = paragraph.table() {
+            let margin = margin(paragraph, columns);
+            let rows = setter.table(table, columns - margin);
+            lines.extend(rows.into_iter().map(|row| format!("{}{}", " ".repeat(margin), row).trim_end().to_owned()));
+        }
+        if let Some(figure) = paragraph.figure() {
+            let margin = margin(paragraph, columns);
+            let name = format!("[{}]", figure.src);
+            let room = (columns - margin).saturating_sub(name.chars().count());
+            let at = match paragraph.style().align {
+                Align::Center => margin + room / 2,
+                Align::Right => margin + room,
+                Align::Left | Align::Justify => margin,
+            };
+            lines.insert(0, format!("{}{}", " ".repeat(at), name));
+        }
+        for (j, line) in lines.iter().enumerate() {
+            if j > 0 {
+                out.push_str(&"\n".repeat(spacing));
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+/// Sets paragraphs as lines of text
+struct Setter<'a> {
+    doc: &'a Document,
+    /// Each language's hyphenator, once it's needed
+    hyphenators: BTreeMap<String, Option<Hyphenator>>,
+}
+
+impl<'a> Setter<'a> {
+    /// A paragraph's lines, `columns` wide, with its margin and any list marker
+    fn lines(&mut self, paragraph: &Paragraph, columns: usize) -> Vec<String> {
+        let font = Builtin::Courier;
+        let style = paragraph.style();
+        let doc = self.doc;
+        let hyphenator = self.hyphenators.entry(style.language.clone())
+            .or_insert_with(|| doc.strategy().hyphenation().hyphenator(&style.language));
+        let column = column_width(paragraph);
+        let margin = margin(paragraph, columns);
+        let marked = paragraph.with_text(marked(paragraph.inlines()));
+        let width = column * (columns - margin) as f64;
+        let lines = if marked.text().is_empty() {
+            Vec::new()
+        } else {
+            linebreak::break_paragraph(&marked, &font, width, hyphenator.as_ref())
+        };
+        // The marker hangs in the list's indent, before the item's first line
+        let marker = paragraph.list_item().and_then(|item| item.marker.clone()).map(|marker| {
+            let at = margin.saturating_sub(columns_of(paragraph, style.list_indent_pt()));
+            let hanging = format!("{}{}", " ".repeat(at), marker);
+            let width = hanging.chars().count();
+            if width < margin { hanging + &" ".repeat(margin - width) } else { hanging + " " }
+        });
+
+        lines.iter().enumerate().map(|(j, line)| {
+            let mut text = match marker {
+                Some(ref marker) if j == 0 => marker.clone(),
+                _ => " ".repeat(margin),
+            };
+            let mut used = 0;
+            for word in &line.words {
+                let mut at = if column > 0.0 { (word.x / column).round() as usize } else { used };
+                // Rounding mustn't run words together
+                if used > 0 {
+                    at = at.max(used + 1);
+                }
+                text.push_str(&" ".repeat(at - used.min(at)));
+                text.push_str(&word.text);
+                used = at.max(used) + word.text.chars().count();
+            }
+            text
+        }).collect()
+    }
+
+    /// A table's rows, `columns` wide, with its cells' lines side by side
+    fn table(&mut self, table: &Table, columns: usize) -> Vec<String> {
+        // Whole columns
+        let bounds: Vec<(f64, f64)> = table.column_bounds(columns as f64, TABLE_GAP as f64).into_iter()
+            .map(|(x, width)| (x.round(), width.round()))
+            .collect();
+        let mut cells = Vec::new();
+        for row in &table.rows {
+            let mut row_cells = Vec::new();
+            for cell in &row.cells {
+                let (x, width) = cell.bounds(&bounds);
+                let mut lines = Vec::new();
+                for paragraph in &cell.paragraphs {
+                    lines.extend(self.lines(paragraph, (width as usize).max(1)));
+                }
+                row_cells.push((x as usize, lines));
+            }
+            cells.push(row_cells);
+        }
+        let heights: Vec<Vec<usize>> = cells.iter().map(|row| row.iter().map(|&(_, ref lines)| lines.len()).collect()).collect();
+        let starts = table.row_starts(&heights);
+
+        // Each line's cells' lines, as where they start and their text
+        let mut lines: Vec<Vec<(usize, String)>> = vec![Vec::new(); starts[starts.len() - 1]];
+        for (r, row) in cells.into_iter().enumerate() {
+            for (x, cell) in row {
+                for (k, text) in cell.into_iter().enumerate() {
+                    lines[starts[r] + k].push((x, text));
+                }
+            }
+        }
+        let mut out: Vec<String> = lines.into_iter().map(|mut pieces| {
+            pieces.sort_by_key(|&(x, _)| x);
+            let mut text = String::new();
+            for (x, piece) in pieces {
+                let used = text.chars().count();
+                text.push_str(&" ".repeat(x.saturating_sub(used)));
+                text.push_str(&piece);
+            }
+            text
+        }).collect();
+        if table.header_rows() > 0 {
+            let mut rule = String::new();
+            for &(x, width) in &bounds {
+                let used = rule.chars().count();
+                rule.push_str(&" ".repeat((x as usize).saturating_sub(used)));
+                rule.push_str(&"-".repeat(width as usize));
+            }
+            out.insert(starts[table.header_rows()], rule);
+        }
+        out
+    }
+}
+
+/// How wide a column is, in points, at a paragraph's size
+fn column_width(paragraph: &Paragraph) -> f64 {
+    let font = Builtin::Courier;
+    font.advance(' ') as f64 * paragraph.style().size_pt() / font.units_per_em() as f64
+}
+
+/// How many columns a length takes, at a paragraph's size
+fn columns_of(paragraph: &Paragraph, length: f64) -> usize {
+    let column = column_width(paragraph);
+    if column > 0.0 { (length / column).round() as usize } else { 0 }
+}
+
+/// How many columns a paragraph is set in by, in a line `columns` wide
+fn margin(paragraph: &Paragraph, columns: usize) -> usize {
+    columns_of(paragraph, paragraph.style().margin_pt()).min(columns.saturating_sub(1))
+}
+
+/// Text with its formatting marked
+fn marked(inlines: &[Inline]) -> String {
+    let mut out = String::new();
+    for inline in inlines {
+        match *inline {
+            Inline::Text(ref text) => out.push_str(text),
+            Inline::Styled(Format::Italic, ref inner) => out.push_str(&format!("_{}_", marked(inner))),
+            Inline::Styled(Format::Bold, ref inner) => out.push_str(&format!("*{}*", marked(inner))),
+            Inline::Styled(Format::SmallCaps, ref inner) => out.push_str(&marked(inner).to_uppercase()),
+            Inline::Styled(Format::Superscript, ref inner) => out.push_str(&format!("^{}", marked(inner))),
+            Inline::Note(note) => out.push_str(&format!("^{}", note)),
+            Inline::Ref(ref reference) => out.push_str(&reference.text),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+    use image::test::beside_images;
+
+    #[test]
+    fn wraps_and_indents() {
+        let strategy = Strategy::new("[{strategy ::text-settings [{ \
+            ::default [{ ::indent regular ::line-spacing 2.0 ::align justify }] }] }]").unwrap();
+        let src = "[{document || The quick brown fox jumps over the lazy dog. \
+                   [{ align center || End }] }]";
+        let doc = Document::new(src, strategy).unwrap();
+        let text = render(&doc, 20).unwrap();
+        assert_eq!("     The quick brown\n\nfox  jumps over  the\n\nlazy dog.\n\n           End\n", text);
+    }
+
+    #[test]
+    fn marks_formatting() {
+        let src = "[{document || See [{ i || The [{ b || Very }] Hungry }], [{ sc || Carle }] 1969[{ sup || 2 }]. }]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        assert_eq!("See _The *Very* Hungry_, CARLE 1969^2.\n", render(&doc, 72).unwrap());
+    }
+
+    #[test]
+    fn hangs_list_markers() {
+        let src = "[{document || [{ list ::style number || One two three four || Five [{ list || Six }] }] }]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        assert_eq!("1.   One two\n     three four\n\n2.   Five\n\n     \u{2022}    Six\n", render(&doc, 17).unwrap());
+    }
+
+    #[test]
+    fn sets_tables_in_columns() {
+        let src = "[{document || [{ table ::columns ''left right'' || Masses
+            [{ row ::header true || Sample || Mass }]
+            [{ row || [{ cell ::rowspan 2 || A long name }] || 1.2 }]
+            [{ row || 1.3 }] }] }]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        assert_eq!("Table 1. Masses\n*Sample*      *Mass*\n---------  ---------\nA long           1.2\nname             1.3\n",
+                   render(&doc, 20).unwrap());
+    }
+
+    #[test]
+    fn names_figures_images() {
+        let src = "[{document || [{ figure ::src ''photo.jpg'' || A photo }]
+            [{ figure ::src ''photo.jpg'' ::numbered false ::align right }] }]";
+        let doc = beside_images(src, Strategy::none()).unwrap();
+        assert_eq!("[photo.jpg]\nFigure 1. A photo\n\n         [photo.jpg]\n", render(&doc, 20).unwrap());
+    }
+
+    #[test]
+    fn ends_with_footnotes() {
+        let doc = Document::new("[{document || One.[{ note || A note. }] Two. }]", Strategy::none()).unwrap();
+        assert_eq!("One.^1 Two.\n\n^1 A note.\n", render(&doc, 72).unwrap());
+    }
+}
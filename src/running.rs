@@ -0,0 +1,247 @@
+//! Running headers and footers
+//!
+//! A strategy's `::header` and `::footer` are set on every page they select, with their
+//! `$$special/` page variables filled in for that page:
+//!
+//! ```text
+//! ::header [{
+//!     ::pages all
+//!     ::border-top 0.5in
+//!     ::content [{ ::align right || $$meta/lastname $$special/page-no }]
+//! }]
+//! ::footer none
+//! ```
+//!
+//! Pages are numbered through the document, and again through each section, which starts at
+//! every `[{section-break}]`.
+
+use document::{ Document, Paragraph };
+use error::{ EvaluationError, EvaluationKind, Result };
+use expression::Node;
+use layout::page::Page;
+use style::{ ComputedStyle, TextSettings };
+use value::{ Length, Unit, Value };
+
+/// How far a header or footer is from the edge of the page, when nothing says
+const DEFAULT_BORDER_IN: f64 = 0.5;
+
+/// Which pages a header or footer is on
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PageSelector {
+    /// Every page
+    All,
+    /// Odd numbered pages
+    Odd,
+    /// Even numbered pages
+    Even,
+    /// The first page of each section
+    First,
+    /// Every page but the first of each section
+    NotFirst,
+}
+
+impl PageSelector {
+    fn from_value(value: &Value) -> Result<PageSelector> {
+        match try!(value.as_enum(&["all", "odd", "even", "first", "not-first"])) {
+            "odd" => Ok(PageSelector::Odd),
+            "even" => Ok(PageSelector::Even),
+            "first" => Ok(PageSelector::First),
+            "not-first" => Ok(PageSelector::NotFirst),
+            _ => Ok(PageSelector::All),
+        }
+    }
+
+    /// Whether a page is selected
+    pub fn selects(&self, page: &Page) -> bool {
+        match *self {
+            PageSelector::All => true,
+            PageSelector::Odd => page.number % 2 == 1,
+            PageSelector::Even => page.number % 2 == 0,
+            PageSelector::First => page.section_number == 1,
+            PageSelector::NotFirst => page.section_number != 1,
+        }
+    }
+}
+
+/// Text, or a variable to fill in
+#[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum Piece {
+    Text(String),
+    Var(String),
+}
+
+/// A header or footer
+#[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Running {
+    pages: PageSelector,
+    /// From the edge of the page, in points
+    border: f64,
+    style: ComputedStyle,
+    paragraphs: Vec<Vec<Piece>>,
+}
+
+impl Running {
+    /// Read a strategy's `::header` or `::footer`, which is `none` or a block. Headers are
+    /// `::border-top` from the top of the page, and footers `::border-bottom` from the bottom.
+    pub fn from_node(node: &Node, settings: &TextSettings, border_key: &str) -> Result<Option<Running>> {
+        let expr = match *node {
+            Node::Expr(ref expr) => expr,
+            _ => match node.as_value() {
+                Some(Value::Word(ref w)) if w == "none" => return Ok(None),
+                other => {
+                    let found = other.map_or_else(String::new, |v| v.to_string());
+                    let kind = EvaluationKind::InvalidValue(found, "a block, or none".to_owned());
+                    return Err(EvaluationError::new(kind).into());
+                }
+            },
+        };
+
+        let mut running = Running {
+            pages: PageSelector::All,
+            border: try!(Length::new(DEFAULT_BORDER_IN, Unit::In).points()),
+            style: settings.base(),
+            paragraphs: Vec::new(),
+        };
+        if let Some(value) = expr.prop("pages").and_then(Node::as_value) {
+            running.pages = try!(PageSelector::from_value(&value));
+        }
+        if let Some(value) = expr.prop(border_key).and_then(Node::as_value) {
+            running.border = try!(try!(value.as_length()).points());
+        }
+        if let Some(content) = expr.prop("content").and_then(Node::as_expr) {
+            running.style = try!(running.style.enter(content, settings));
+            let mut paragraph = Vec::new();
+            if let Some(last) = content.sections().last() {
+                for node in &last.content {
+                    running.add_node(node, &mut paragraph);
+                }
+            }
+            running.end_paragraph(&mut paragraph);
+        }
+        Ok(Some(running))
+    }
+
+    /// Nested expressions only add their text
+    fn add_node(&mut self, node: &Node, paragraph: &mut Vec<Piece>) {
+        match *node {
+            Node::Atom(ref s) => paragraph.push(Piece::Text(s.clone())),
+            Node::Var(ref v) => paragraph.push(Piece::Var(v.clone())),
+            Node::Expr(ref e) => {
+                if let Some(last) = e.sections().last() {
+                    for inner in &last.content {
+                        self.add_node(inner, paragraph);
+                    }
+                }
+            }
+            Node::Blank => self.end_paragraph(paragraph),
+            Node::Prop(..) => {}
+        }
+    }
+
+    fn end_paragraph(&mut self, paragraph: &mut Vec<Piece>) {
+        if !paragraph.is_empty() {
+            self.paragraphs.push(paragraph.clone());
+            paragraph.clear();
+        }
+    }
+
+    /// The pages it's on
+    pub fn pages(&self) -> PageSelector {
+        self.pages
+    }
+
+    /// How far it is from the edge of the page, in points
+    pub fn border(&self) -> f64 {
+        self.border
+    }
+
+    /// Its paragraphs on a page, or nothing if it isn't on that page
+    pub fn on_page(&self, page: &Page, doc: &Document) -> Vec<Paragraph> {
+        if !self.pages.selects(page) {
+            return Vec::new();
+        }
+        self.paragraphs.iter().map(|pieces| {
+            let words: Vec<String> = pieces.iter().map(|piece| match *piece {
+                Piece::Text(ref s) => s.clone(),
+                Piece::Var(ref v) => page_variable(v, page)
+                    .or_else(|| doc.variable(v))
+                    .unwrap_or_else(|| format!("$${}", v)),
+            }).collect();
+            Paragraph::new(words.join(" "), self.style.clone())
+        }).collect()
+    }
+}
+
+/// The value of a `$$special/` variable which depends on the page
+pub fn page_variable(name: &str, page: &Page) -> Option<String> {
+    let n = match name {
+        "special/page-no" => page.number,
+        "special/page-count" => page.count,
+        "special/section-page-no" => page.section_number,
+        "special/section-page-count" => page.section_count,
+        _ => return None,
+    };
+    Some(n.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+    use layout::linebreak::Line;
+    use layout::page::{ paginate, Block };
+    use value::Align;
+
+    const STRATEGY: &'static str = "[{strategy ::name s
+        ::metafields [{ [{field ::name lastname}] }]
+        ::header [{ ::pages not-first ::border-top 0.25in
+                    ::content [{ ::align right || $$meta/lastname $$special/page-no of $$special/page-count }] }]
+        ::footer [{ ::pages odd ::content [{ || Section page $$special/section-page-no
+                    of $$special/section-page-count }] }]
+    }]";
+
+    fn texts(running: &Running, page: &Page, doc: &Document) -> Vec<String> {
+        running.on_page(page, doc).iter().map(|p| p.text().to_owned()).collect()
+    }
+
+    #[test]
+    fn fills_in_page_variables() {
+        let strategy = Strategy::new(STRATEGY).unwrap();
+        let src = "[{document ::strategy s ::meta [{ ::lastname Cooke }] || \
+                   One [{page-break}] Two [{section-break}] Three [{page-break}] Four }]";
+        let doc = Document::new(src, strategy).unwrap();
+        let line = Line { words: Vec::new(), ratio: 0.0 };
+        let blocks: Vec<Block> = doc.paragraphs().iter().map(|p| Block::new(p, vec![line.clone()])).collect();
+        let pages = paginate(&blocks, doc.page());
+        assert_eq!(4, pages.len());
+
+        let header = doc.strategy().header().unwrap();
+        assert_eq!(18.0, header.border());
+        assert_eq!(Align::Right, header.on_page(&pages[1], &doc)[0].style().align);
+        assert!(texts(header, &pages[0], &doc).is_empty());
+        assert_eq!(vec!["Cooke 2 of 4"], texts(header, &pages[1], &doc));
+        // Sections only restart `first`
+        assert!(texts(header, &pages[2], &doc).is_empty());
+
+        let footer = doc.strategy().footer().unwrap();
+        assert_eq!(36.0, footer.border());
+        assert_eq!(vec!["Section page 1 of 2"], texts(footer, &pages[2], &doc));
+        assert!(texts(footer, &pages[3], &doc).is_empty());
+    }
+
+    #[test]
+    fn reads_none() {
+        let strategy = Strategy::new("[{strategy ::header none ::footer none }]").unwrap();
+        assert!(strategy.header().is_none());
+        assert!(strategy.footer().is_none());
+        assert!(Strategy::new("[{strategy ::header sometimes }]").is_err());
+        assert!(Strategy::new("[{strategy ::header [{ ::pages most }] }]").is_err());
+    }
+}
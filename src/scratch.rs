@@ -0,0 +1,49 @@
+//! Directories for tests to write files into
+//!
+//! Each is new and only its own, so tests running at the same time don't trip over each other's
+//! files, and it's removed again when it goes out of scope, even if the test fails.
+
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{ Path, PathBuf };
+use std::process;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+
+/// A new directory, removed with everything in it when dropped
+#[derive(Debug)]
+pub struct Scratch {
+    path: PathBuf,
+}
+
+impl Scratch {
+    /// Make a directory named after what it's for
+    pub fn new(name: &str) -> Scratch {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::SeqCst);
+        let path = env::temp_dir().join(format!("litx-{}-{}-{}", name, process::id(), n));
+        fs::create_dir_all(&path).unwrap();
+        Scratch { path: path }
+    }
+}
+
+impl Deref for Scratch {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for Scratch {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        // A failed removal shouldn't hide why the test failed
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,370 @@
+//! Text settings
+//!
+//! A strategy's `::text-settings` names styles. Every paragraph starts from the `default` style,
+//! and expressions around it can switch to another named style with `::style name`, or override
+//! single settings, either as properties (`::align center`) or as the expression itself
+//! (`[{ align center || ... }]`). Settings cascade: anything an expression doesn't set comes
+//! from the expressions around it.
+
+use std::collections::BTreeMap;
+
+use error::{ EvaluationError, EvaluationKind, Result };
+use expression::{ Expression, Node };
+use layout::hyphenate::DEFAULT_LANGUAGE;
+use value::{ Align, Length, Unit, Value };
+
+/// The style every paragraph starts from
+pub const DEFAULT_STYLE: &'static str = "default";
+
+/// Settings an expression may override
+pub const STYLE_KEYS: &'static [&'static str] = &["style", "align", "indent", "margin", "list-indent", "face", "size",
+    "line-spacing", "tolerance", "language", "widows", "orphans", "keep-with-next"];
+
+/// How far `::indent regular` indents, in inches
+const REGULAR_INDENT_IN: f64 = 0.5;
+/// How far each level of a list is indented, when nothing says otherwise, in inches
+const DEFAULT_LIST_INDENT_IN: f64 = 0.5;
+/// How far spaces may stretch when breaking lines, when nothing says otherwise
+const DEFAULT_TOLERANCE: f64 = 2.0;
+/// The fewest lines of a paragraph left alone at the top or bottom of a page
+const DEFAULT_WIDOWS_AND_ORPHANS: usize = 2;
+
+/// First line indentation
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Indent {
+    /// No indent
+    None,
+    /// The usual half inch
+    Regular,
+    /// Some other indent
+    Length(Length),
+}
+
+impl Indent {
+    fn from_value(value: &Value) -> Result<Indent> {
+        match *value {
+            Value::Word(ref w) if w == "none" => Ok(Indent::None),
+            Value::Word(ref w) if w == "regular" => Ok(Indent::Regular),
+            _ => value.as_length().map(Indent::Length),
+        }
+    }
+
+    fn length(&self) -> Length {
+        match *self {
+            Indent::None => Length::new(0.0, Unit::Pt),
+            Indent::Regular => Length::new(REGULAR_INDENT_IN, Unit::In),
+            Indent::Length(l) => l,
+        }
+    }
+}
+
+/// Some settings. Anything left as None is inherited.
+#[derive(Debug)]
+#[derive(Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Style {
+    /// `::align`
+    pub align: Option<Align>,
+    /// `::indent`, the first line's
+    pub indent: Option<Indent>,
+    /// `::margin`, how far every line is set in from the left
+    pub margin: Option<Length>,
+    /// `::list-indent`, how much further in each level of a list is set
+    pub list_indent: Option<Length>,
+    /// `::font [{ ::face }]`, a `;` separated list of fonts to try
+    pub face: Option<String>,
+    /// `::font [{ ::size }]`
+    pub size: Option<Length>,
+    /// `::line-spacing`, in lines
+    pub line_spacing: Option<f64>,
+    /// `::tolerance`, how far spaces may stretch, as a multiple of how far they comfortably can
+    pub tolerance: Option<f64>,
+    /// `::language`, which picks hyphenation patterns, or `none` to not hyphenate
+    pub language: Option<String>,
+    /// `::widows`, the fewest lines a paragraph may carry over to the top of a page
+    pub widows: Option<usize>,
+    /// `::orphans`, the fewest lines a paragraph may start with at the bottom of a page
+    pub orphans: Option<usize>,
+    /// `::keep-with-next`, whether a paragraph must share a page with the start of the next
+    pub keep_with_next: Option<bool>,
+}
+
+impl Style {
+    /// Read a style block, like `[{ ::align left ::font [{ ::size 12pt }] }]`
+    pub fn from_expression(expr: &Expression) -> Result<Style> {
+        let mut style = Style::default();
+        for (key, value) in expr.props() {
+            if key == "font" {
+                if let Some(font) = value.as_expr() {
+                    for (key, value) in font.props() {
+                        try!(style.set_node(key, value));
+                    }
+                }
+            } else {
+                try!(style.set_node(key, value));
+            }
+        }
+        Ok(style)
+    }
+
+    fn set_node(&mut self, key: &str, value: &Node) -> Result<bool> {
+        match value.as_value() {
+            Some(value) => self.set(key, &value),
+            None => Ok(false),
+        }
+    }
+
+    /// Set one setting by name. Returns whether the key is a setting at all.
+    pub fn set(&mut self, key: &str, value: &Value) -> Result<bool> {
+        match key {
+            "align" => self.align = Some(try!(Align::from_value(value))),
+            "indent" => self.indent = Some(try!(Indent::from_value(value))),
+            "margin" => self.margin = Some(try!(value.as_length())),
+            "list-indent" => self.list_indent = Some(try!(value.as_length())),
+            "face" => self.face = Some(value.to_string()),
+            "size" => self.size = Some(try!(value.as_length())),
+            "line-spacing" => self.line_spacing = Some(try!(value.as_number())),
+            "tolerance" => self.tolerance = Some(try!(value.as_number())),
+            "language" => self.language = Some(value.to_string().to_lowercase()),
+            "widows" => self.widows = Some(try!(value.as_count())),
+            "orphans" => self.orphans = Some(try!(value.as_count())),
+            "keep-with-next" => self.keep_with_next = Some(try!(value.as_bool())),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Fill in everything this style leaves out from `parent`
+    pub fn apply(&self, parent: &ComputedStyle) -> ComputedStyle {
+        let size = self.size.map(|s| s.resolve(parent.size)).unwrap_or(parent.size);
+        ComputedStyle {
+            align: self.align.unwrap_or(parent.align),
+            indent: self.indent.map(|i| i.length().resolve(size)).unwrap_or(parent.indent),
+            margin: self.margin.map(|m| m.resolve(size)).unwrap_or(parent.margin),
+            list_indent: self.list_indent.map(|i| i.resolve(size)).unwrap_or(parent.list_indent),
+            face: self.face.clone().unwrap_or_else(|| parent.face.clone()),
+            size: size,
+            line_spacing: self.line_spacing.unwrap_or(parent.line_spacing),
+            tolerance: self.tolerance.unwrap_or(parent.tolerance),
+            language: self.language.clone().unwrap_or_else(|| parent.language.clone()),
+            widows: self.widows.unwrap_or(parent.widows),
+            orphans: self.orphans.unwrap_or(parent.orphans),
+            keep_with_next: self.keep_with_next.unwrap_or(parent.keep_with_next),
+        }
+    }
+}
+
+/// Settings with nothing left to inherit
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ComputedStyle {
+    /// Horizontal alignment
+    pub align: Align,
+    /// First line indent, never in ems
+    pub indent: Length,
+    /// How far every line is set in, never in ems
+    pub margin: Length,
+    /// How much further each level of a list is set in, never in ems
+    pub list_indent: Length,
+    /// Fonts to try, `;` separated
+    pub face: String,
+    /// Font size, never in ems
+    pub size: Length,
+    /// Line spacing, in lines
+    pub line_spacing: f64,
+    /// How far spaces may stretch when breaking lines
+    pub tolerance: f64,
+    /// The language, lowercase, like `en-us`
+    pub language: String,
+    /// The fewest lines carried over to the top of a page
+    pub widows: usize,
+    /// The fewest lines left at the bottom of a page
+    pub orphans: usize,
+    /// Whether the paragraph stays on the same page as the start of the next
+    pub keep_with_next: bool,
+}
+
+impl ComputedStyle {
+    /// What's used when nothing says otherwise
+    pub fn initial() -> ComputedStyle {
+        ComputedStyle {
+            align: Align::Left,
+            indent: Length::new(0.0, Unit::Pt),
+            margin: Length::new(0.0, Unit::Pt),
+            list_indent: Length::new(DEFAULT_LIST_INDENT_IN, Unit::In),
+            face: "Serif".to_owned(),
+            size: Length::new(12.0, Unit::Pt),
+            line_spacing: 1.0,
+            tolerance: DEFAULT_TOLERANCE,
+            language: DEFAULT_LANGUAGE.to_owned(),
+            widows: DEFAULT_WIDOWS_AND_ORPHANS,
+            orphans: DEFAULT_WIDOWS_AND_ORPHANS,
+            keep_with_next: false,
+        }
+    }
+
+    /// The font size in points
+    pub fn size_pt(&self) -> f64 {
+        absolute(self.size)
+    }
+
+    /// The first line indent in points
+    pub fn indent_pt(&self) -> f64 {
+        absolute(self.indent)
+    }
+
+    /// The margin in points
+    pub fn margin_pt(&self) -> f64 {
+        absolute(self.margin)
+    }
+
+    /// The list indent in points
+    pub fn list_indent_pt(&self) -> f64 {
+        absolute(self.list_indent)
+    }
+
+    /// The style inside an expression, given this is the style around it
+    pub fn enter(&self, expr: &Expression, settings: &TextSettings) -> Result<ComputedStyle> {
+        let mut computed = self.clone();
+        if let Some(name) = expr.prop("style").and_then(Node::as_text) {
+            computed = try!(settings.named(name)).apply(&computed);
+        }
+
+        let mut overrides = Style::default();
+        // `[{ align center || ... }]`, where the first section is the value
+        if let (Some(key), Some(first)) = (expr.title(), expr.sections().first()) {
+            let value: Vec<&str> = first.content.iter().filter_map(Node::as_text).collect();
+            if !value.is_empty() {
+                let value = Value::parse(&value.join(" "));
+                if key == "style" {
+                    computed = try!(settings.named(&value.to_string())).apply(&computed);
+                } else {
+                    try!(overrides.set(key, &value));
+                }
+            }
+        }
+        for (key, value) in expr.props() {
+            try!(overrides.set_node(key, value));
+        }
+        Ok(overrides.apply(&computed))
+    }
+}
+
+/// A computed length in points. Computing resolves ems, so every unit left converts.
+fn absolute(length: Length) -> f64 {
+    length.points().unwrap_or(0.0)
+}
+
+/// A strategy's named styles
+#[derive(Debug)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TextSettings {
+    styles: BTreeMap<String, Style>,
+}
+
+impl TextSettings {
+    /// No styles, so everything is the initial style
+    pub fn new() -> TextSettings {
+        TextSettings::default()
+    }
+
+    /// Read `::text-settings [{ ::default [{ ... }] ::block-quote [{ ... }] }]`
+    pub fn from_expression(expr: &Expression) -> Result<TextSettings> {
+        let mut settings = TextSettings::new();
+        for (name, value) in expr.props() {
+            if let Some(block) = value.as_expr() {
+                settings.styles.insert(name.to_owned(), try!(Style::from_expression(block)));
+            }
+        }
+        Ok(settings)
+    }
+
+    /// A style as written
+    pub fn style(&self, name: &str) -> Option<&Style> {
+        self.styles.get(name)
+    }
+
+    /// Every style's name
+    pub fn names(&self) -> Vec<&str> {
+        self.styles.keys().map(|k| &k[..]).collect()
+    }
+
+    /// The default style, computed
+    pub fn base(&self) -> ComputedStyle {
+        match self.style(DEFAULT_STYLE) {
+            Some(style) => style.apply(&ComputedStyle::initial()),
+            None => ComputedStyle::initial(),
+        }
+    }
+
+    /// A named style, which must exist
+    fn named(&self, name: &str) -> Result<&Style> {
+        match self.style(name) {
+            Some(style) => Ok(style),
+            None => {
+                let kind = EvaluationKind::InvalidValue(name.to_owned(), "a style".to_owned());
+                let note = format!("styles are {}", self.names().join(", "));
+                Err(EvaluationError::new(kind).note(note).into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use expression::Expression;
+    use lexer::Lexer;
+    use parser;
+    use value::{ Align, Length, Unit };
+
+    fn expr(source: &str) -> Expression {
+        Expression::from_ast(parser::parse(Lexer::new(source)).unwrap()).unwrap()
+    }
+
+    fn settings() -> TextSettings {
+        TextSettings::from_expression(&expr("[{ \
+            ::default [{ ::align left ::indent regular ::font [{ ::face ''Times'' ::size 12pt }] ::line-spacing 2.0 }] \
+            ::block-quote [{ ::indent none ::line-spacing 1.0 ::font [{ ::size 0.9em }] }] }]")).unwrap()
+    }
+
+    #[test]
+    fn default_style() {
+        let base = settings().base();
+        assert_eq!(Align::Left, base.align);
+        assert_eq!(Length::new(0.5, Unit::In), base.indent);
+        assert_eq!("Times", base.face);
+        assert_eq!(2.0, base.line_spacing);
+    }
+
+    #[test]
+    fn named_styles_inherit() {
+        let settings = settings();
+        let quote = settings.base().enter(&expr("[{ quote ::style block-quote || Hi }]"), &settings).unwrap();
+        assert_eq!(Length::new(0.0, Unit::Pt), quote.indent);
+        assert_eq!("Times", quote.face);
+        assert!((quote.size.points().unwrap() - 10.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn inline_overrides_cascade() {
+        let settings = settings();
+        let centered = settings.base().enter(&expr("[{ align center || Title }]"), &settings).unwrap();
+        assert_eq!(Align::Center, centered.align);
+        let inner = centered.enter(&expr("[{ emphasis ::size 2em || x }]"), &settings).unwrap();
+        assert_eq!(Align::Center, inner.align);
+        assert_eq!(Length::new(24.0, Unit::Pt), inner.size);
+    }
+
+    #[test]
+    fn bad_settings() {
+        let settings = settings();
+        assert!(settings.base().enter(&expr("[{ align middle || x }]"), &settings).is_err());
+        assert!(settings.base().enter(&expr("[{ x ::style nope || x }]"), &settings).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Tables
+//!
+//! `[{ table || ... }]` is a table, with a `[{ row || ... || ... }]` for each of its rows and a cell
+//! for each section after a row's first `||`, empty or not. Anything else in it is its caption,
+//! which is set over it:
+//!
+//! ```text
+//! [{ table ::columns ''left right'' ::widths ''3 1'' || Masses of the samples [{ label masses }]
+//!     [{ row ::header true || Sample || Mass (g) }]
+//!     [{ row || A || 1.20 }]
+//!     [{ row || [{ cell ::colspan 2 ::align center || Lost in transit }] }] }]
+//! ```
+//!
+//! `::columns` aligns each column's cells, and `::widths` shares the table's width out between its
+//! columns, which otherwise share it evenly. A `[{ cell || ... }]` alone in its section can span
+//! more than one column or row with `::colspan` and `::rowspan`, and change the cell's style. Cells
+//! are set in the `table` style, header rows in bold, and captions in the `caption` style, after
+//! the table's name and number unless it's `::numbered false`.
+
+use document::Paragraph;
+use error::{ EvaluationError, EvaluationKind, Result };
+use expression::{ Expression, Node };
+use value::{ Align, Value };
+
+/// A cell of a table
+#[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Cell {
+    /// The first column it's in, from 0, past any cells spanning down from the rows above
+    pub column: usize,
+    /// How many columns it spans
+    pub colspan: usize,
+    /// How many rows it spans
+    pub rowspan: usize,
+    /// Its paragraphs, which may be none
+    pub paragraphs: Vec<Paragraph>,
+}
+
+impl Cell {
+    /// Where it starts and how wide it is, given where its table's columns start and how wide
+    /// they are
+    pub fn bounds(&self, columns: &[(f64, f64)]) -> (f64, f64) {
+        let first = columns.get(self.column).cloned().unwrap_or((0.0, 0.0));
+        let last = columns.get(self.column + self.colspan - 1).cloned().unwrap_or(first);
+        (first.0, last.0 + last.1 - first.0)
+    }
+}
+
+/// A row of a table
+#[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Row {
+    /// Whether it's `::header true`
+    pub header: bool,
+    /// The cells starting in it, left to right
+    pub cells: Vec<Cell>,
+}
+
+/// A `[{ table }]`'s rows, and how its columns share out its width
+#[derive(Debug)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Table {
+    /// Its rows, top to bottom
+    pub rows: Vec<Row>,
+    /// Each column's share of the width, adding up to 1
+    pub widths: Vec<f64>,
+}
+
+impl Table {
+    /// A table of rows, with its columns as wide as `widths` says, relative to each other, and
+    /// any it leaves out as wide as 1
+    pub fn new(rows: Vec<Row>, widths: &[f64]) -> Table {
+        let columns = rows.iter().flat_map(|row| row.cells.iter()).map(|cell| cell.column + cell.colspan).max().unwrap_or(0);
+        let mut widths = widths.to_vec();
+        widths.resize(columns, 1.0);
+        let total: f64 = widths.iter().sum();
+        Table { rows: rows, widths: widths.into_iter().map(|w| w / total).collect() }
+    }
+
+    /// How many columns it has
+    pub fn columns(&self) -> usize {
+        self.widths.len()
+    }
+
+    /// Whether its columns all share its width evenly
+    pub fn is_even(&self) -> bool {
+        self.widths.iter().all(|&w| (w - 1.0 / self.columns() as f64).abs() < 1e-9)
+    }
+
+    /// How many header rows it starts with
+    pub fn header_rows(&self) -> usize {
+        self.rows.iter().take_while(|row| row.header).count()
+    }
+
+    /// Every cell's paragraphs, row by row
+    pub fn paragraphs(&self) -> Vec<&Paragraph> {
+        self.rows.iter().flat_map(|row| row.cells.iter()).flat_map(|cell| cell.paragraphs.iter()).collect()
+    }
+
+    /// See `paragraphs`
+    pub fn paragraphs_mut(&mut self) -> Vec<&mut Paragraph> {
+        self.rows.iter_mut().flat_map(|row| row.cells.iter_mut()).flat_map(|cell| cell.paragraphs.iter_mut()).collect()
+    }
+
+    /// Where each column starts and how wide it is, sharing out `width` with a gap between
+    /// neighbouring columns
+    pub fn column_bounds(&self, width: f64, gap: f64) -> Vec<(f64, f64)> {
+        let shared = (width - gap * self.columns().saturating_sub(1) as f64).max(0.0);
+        let mut x = 0.0;
+        self.widths.iter().map(|&share| {
+            let bounds = (x, shared * share);
+            x += shared * share + gap;
+            bounds
+        }).collect()
+    }
+
+    /// The line each row starts on and, last, how many lines the rows take, given how many lines
+    /// each row's cells take. Each row takes at least a line, and a cell spanning rows which take
+    /// fewer lines than it does makes the last of them longer.
+    pub fn row_starts(&self, heights: &[Vec<usize>]) -> Vec<usize> {
+        let mut rows = vec![1; self.rows.len()];
+        let spanning = |r: usize| self.rows[r].cells.iter().zip(heights.get(r).map_or(&[][..], |h| &h[..]));
+        for r in 0..rows.len() {
+            for (_, &height) in spanning(r).filter(|&(cell, _)| cell.rowspan <= 1) {
+                rows[r] = rows[r].max(height);
+            }
+        }
+        for r in 0..rows.len() {
+            for (cell, &height) in spanning(r).filter(|&(cell, _)| cell.rowspan > 1) {
+                let end = (r + cell.rowspan).min(rows.len());
+                let spanned: usize = rows[r..end].iter().sum();
+                if height > spanned {
+                    rows[end - 1] += height - spanned;
+                }
+            }
+        }
+        let mut starts = vec![0];
+        for height in rows {
+            let last = starts[starts.len() - 1];
+            starts.push(last + height);
+        }
+        starts
+    }
+}
+
+/// Where a table's cells go, as its rows are added, around cells spanning down from rows above
+#[derive(Debug)]
+#[derive(Default)]
+pub struct Grid {
+    /// How many more rows, counting the one being added, each column is taken in
+    taken: Vec<usize>,
+    /// The column the row being added has reached
+    column: usize,
+}
+
+impl Grid {
+    /// Place the next cell in the row, returning the first column it's in
+    pub fn place(&mut self, colspan: usize, rowspan: usize) -> usize {
+        while self.taken.get(self.column).map_or(false, |&rows| rows > 0) {
+            self.column += 1;
+        }
+        let column = self.column;
+        if self.taken.len() < column + colspan {
+            self.taken.resize(column + colspan, 0);
+        }
+        for taken in &mut self.taken[column .. column + colspan] {
+            *taken = rowspan;
+        }
+        self.column += colspan;
+        column
+    }
+
+    /// Start the next row
+    pub fn end_row(&mut self) {
+        for taken in &mut self.taken {
+            *taken = taken.saturating_sub(1);
+        }
+        self.column = 0;
+    }
+}
+
+/// Read a table's `::columns ''left right''`, each column's alignment
+pub fn alignments(expr: &Expression) -> Result<Vec<Align>> {
+    match expr.prop("columns").and_then(Node::as_text) {
+        Some(columns) => columns.split_whitespace().map(|column| Align::from_value(&Value::parse(column))).collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Read a table's `::widths ''3 1''`, each column's width relative to the others
+pub fn widths(expr: &Expression) -> Result<Vec<f64>> {
+    let mut widths = Vec::new();
+    if let Some(text) = expr.prop("widths").and_then(Node::as_text) {
+        for width in text.split_whitespace() {
+            match Value::parse(width).as_number() {
+                Ok(n) if n > 0.0 => widths.push(n),
+                _ => {
+                    let kind = EvaluationKind::InvalidValue(width.to_owned(), "a width more than 0".to_owned());
+                    return Err(EvaluationError::new(kind).into());
+                }
+            }
+        }
+    }
+    Ok(widths)
+}
+
+/// Read a `[{ cell }]`'s `::colspan` and `::rowspan`, which are at least 1
+pub fn spans(expr: &Expression) -> Result<(usize, usize)> {
+    let mut spans = [1, 1];
+    for (span, key) in spans.iter_mut().zip(&["colspan", "rowspan"]) {
+        if let Some(value) = expr.prop(key).and_then(Node::as_value) {
+            *span = try!(value.as_count()).max(1);
+        }
+    }
+    Ok((spans[0], spans[1]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use document::{ Document, Strategy };
+    use inline::{ Format, Inline };
+
+    /// Each row's cells, as (column, colspan, rowspan, text)
+    fn cells(table: &Table) -> Vec<Vec<(usize, usize, usize, String)>> {
+        table.rows.iter().map(|row| row.cells.iter().map(|cell| {
+            let text: Vec<&str> = cell.paragraphs.iter().map(|p| p.text()).collect();
+            (cell.column, cell.colspan, cell.rowspan, text.join(" / "))
+        }).collect()).collect()
+    }
+
+    #[test]
+    fn places_cells_around_spans() {
+        let src = "[{document || Before [{ table ::columns ''left right'' || Masses [{ label masses }]
+            [{ row ::header true || Sample || Mass || Notes }]
+
+            [{ row || [{ cell ::rowspan 2 || A }] || 1.2 || }]
+            [{ row || 1.3 || [{ cell ::align center || Dry }] }]
+            [{ row || [{ cell ::colspan 3 || Lost }] }] }] See table [{ ref masses }]. }]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        let texts: Vec<&str> = doc.paragraphs().iter().map(|p| p.text()).collect();
+        assert_eq!(vec!["Before", "Table 1. Masses", "See table 1."], texts);
+        let table = doc.paragraphs()[1].table().unwrap();
+        assert_eq!(vec![
+            vec![(0, 1, 1, "Sample".to_owned()), (1, 1, 1, "Mass".to_owned()), (2, 1, 1, "Notes".to_owned())],
+            vec![(0, 1, 2, "A".to_owned()), (1, 1, 1, "1.2".to_owned()), (2, 1, 1, String::new())],
+            vec![(1, 1, 1, "1.3".to_owned()), (2, 1, 1, "Dry".to_owned())],
+            vec![(0, 3, 1, "Lost".to_owned())],
+        ], cells(table));
+        assert_eq!((3, 1), (table.columns(), table.header_rows()));
+        assert!(table.is_even());
+
+        // Columns are aligned, unless a cell says otherwise, and header cells are bold
+        let align = |row: usize, cell: usize| table.rows[row].cells[cell].paragraphs[0].style().align;
+        assert_eq!((Align::Left, Align::Right, Align::Center), (align(1, 0), align(2, 0), align(2, 1)));
+        assert_eq!(Inline::Styled(Format::Bold, vec![Inline::Text("Mass".to_owned())]),
+                   table.rows[0].cells[1].paragraphs[0].inlines()[0]);
+    }
+
+    #[test]
+    fn numbers_tables() {
+        let strategy = Strategy::new("[{strategy ::captions [{ ::table ''Tab.'' }] }]").unwrap();
+        let src = "[{document || [{ table || First [{ row || a }] }] [{ table ::numbered false || [{ row || b }] }] \
+                   [{ table || Last [{ label last }] [{ row || c }] }] See [{ ref last ::show title }]. }]";
+        let doc = Document::new(src, strategy).unwrap();
+        let texts: Vec<&str> = doc.paragraphs().iter().map(|p| p.text()).collect();
+        assert_eq!(vec!["Tab. 1. First", "", "Tab. 2. Last", "See Last."], texts);
+        assert_eq!((Some("1"), None), (doc.paragraphs()[0].number(), doc.paragraphs()[1].number()));
+    }
+
+    #[test]
+    fn shares_out_widths_and_rows() {
+        let src = "[{document || [{ table ::widths ''3 1'' || [{ row || [{ cell ::rowspan 2 || a }] || b }] \
+                   [{ row || c }] [{ row || d || e }] }] }]";
+        let doc = Document::new(src, Strategy::none()).unwrap();
+        let table = doc.paragraphs()[0].table().unwrap();
+        assert_eq!(vec![(0.0, 75.0), (85.0, 25.0)], table.column_bounds(110.0, 10.0));
+        assert_eq!((0.0, 110.0), Cell { column: 0, colspan: 2, rowspan: 1, paragraphs: Vec::new() }
+            .bounds(&table.column_bounds(110.0, 10.0)));
+        // The spanning cell takes 5 lines, so the second row makes up what the first doesn't
+        assert_eq!(vec![0, 2, 5, 6], table.row_starts(&[vec![5, 2], vec![1], vec![1, 1]]));
+        assert_eq!(vec![0, 1], Table::new(vec![Row { header: false, cells: Vec::new() }], &[]).row_starts(&[]));
+
+        assert!(Document::new("[{document || [{ table ::widths ''1 0'' || [{ row || a }] }] }]", Strategy::none()).is_err());
+        assert!(Document::new("[{document || [{ table ::columns ''up'' || [{ row || a }] }] }]", Strategy::none()).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+//! Typed property values
+//!
+//! Everything after a `::key` lexes as plain text. Settings which need a number, a length, or one
+//! of a few keywords parse that text here, so `::size 12pt` and `::line-spacing 2.0` mean something.
+
+use std::fmt;
+use std::fmt::Display;
+
+use error::{ Error, EvaluationError, EvaluationKind, Result };
+
+/// Points in an inch
+const POINTS_PER_INCH: f64 = 72.0;
+/// Centimetres in an inch
+const CM_PER_INCH: f64 = 2.54;
+
+/// A unit of length
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Unit {
+    /// Points, 72 to the inch
+    Pt,
+    /// Inches
+    In,
+    /// Centimetres
+    Cm,
+    /// Millimetres
+    Mm,
+    /// The current font size
+    Em,
+}
+
+impl Unit {
+    /// Every unit, as written after a number
+    pub fn suffixes() -> &'static [&'static str] {
+        &["pt", "in", "cm", "mm", "em"]
+    }
+
+    fn from_suffix(suffix: &str) -> Option<Unit> {
+        match suffix {
+            "pt" => Some(Unit::Pt),
+            "in" => Some(Unit::In),
+            "cm" => Some(Unit::Cm),
+            "mm" => Some(Unit::Mm),
+            "em" => Some(Unit::Em),
+            _ => None,
+        }
+    }
+
+    fn suffix(&self) -> &'static str {
+        match *self {
+            Unit::Pt => "pt",
+            Unit::In => "in",
+            Unit::Cm => "cm",
+            Unit::Mm => "mm",
+            Unit::Em => "em",
+        }
+    }
+
+    /// How many points one of this unit is. Ems have no fixed size.
+    fn points(&self) -> Option<f64> {
+        match *self {
+            Unit::Pt => Some(1.0),
+            Unit::In => Some(POINTS_PER_INCH),
+            Unit::Cm => Some(POINTS_PER_INCH / CM_PER_INCH),
+            Unit::Mm => Some(POINTS_PER_INCH / CM_PER_INCH / 10.0),
+            Unit::Em => None,
+        }
+    }
+}
+
+/// A length, like `12pt` or `0.5in`
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Length {
+    value: f64,
+    unit: Unit,
+}
+
+impl Length {
+    /// A length of `value` units
+    pub fn new(value: f64, unit: Unit) -> Length {
+        Length { value: value, unit: unit }
+    }
+
+    /// Parse a length like `12pt`. A bare `0` is allowed, since zero is zero in any unit.
+    pub fn parse(text: &str) -> Result<Length> {
+        let split = text.find(|c: char| c.is_alphabetic()).unwrap_or(text.len());
+        let (number, suffix) = text.split_at(split);
+        let value = match parse_number(number) {
+            Some(value) => value,
+            None => return Err(invalid(text, "a length").into()),
+        };
+        if suffix.is_empty() {
+            return if value == 0.0 {
+                Ok(Length::new(0.0, Unit::Pt))
+            } else {
+                Err(invalid(text, "a length").note("lengths need a unit, like 12pt").into())
+            };
+        }
+        match Unit::from_suffix(suffix) {
+            Some(unit) => Ok(Length::new(value, unit)),
+            None => Err(invalid(text, "a length")
+                .note(format!("{} isn't a unit; units are {}", suffix, Unit::suffixes().join(", "))).into()),
+        }
+    }
+
+    /// The number of units
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// The unit
+    pub fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    /// Whether the length depends on the font size
+    pub fn is_relative(&self) -> bool {
+        self.unit == Unit::Em
+    }
+
+    /// Convert to another unit. Ems can only be converted to ems, see `resolve`.
+    pub fn to(&self, unit: Unit) -> Result<Length> {
+        if self.unit == unit {
+            return Ok(*self);
+        }
+        match (self.unit.points(), unit.points()) {
+            (Some(from), Some(to)) => Ok(Length::new(self.value * from / to, unit)),
+            _ => {
+                let failure = EvaluationError::new(EvaluationKind::TypeMismatch(
+                    format!("a length in {}", unit.suffix()), self.to_string()))
+                    .note("ems depend on the font size, so resolve them first");
+                Err(Error::EvaluationFailure(failure))
+            }
+        }
+    }
+
+    /// Turn ems into an absolute length, given the font size. Absolute lengths are unchanged.
+    pub fn resolve(&self, font_size: Length) -> Length {
+        match self.unit {
+            Unit::Em => Length::new(self.value * font_size.value, font_size.unit),
+            _ => *self,
+        }
+    }
+
+    /// The length in points, if it's absolute
+    pub fn points(&self) -> Result<f64> {
+        self.to(Unit::Pt).map(|l| l.value)
+    }
+}
+
+impl Display for Length {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}{}", self.value, self.unit.suffix())
+    }
+}
+
+/// Horizontal alignment
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Align {
+    /// Flush left, ragged right
+    Left,
+    /// Centred
+    Center,
+    /// Flush right, ragged left
+    Right,
+    /// Flush on both sides
+    Justify,
+}
+
+impl Align {
+    /// Read an alignment from a value like `center`
+    pub fn from_value(value: &Value) -> Result<Align> {
+        let align = match try!(value.as_enum(&["left", "center", "right", "justify"])) {
+            "left" => Align::Left,
+            "center" => Align::Center,
+            "right" => Align::Right,
+            _ => Align::Justify,
+        };
+        Ok(align)
+    }
+}
+
+/// A property value
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Value {
+    /// `12pt`
+    Length(Length),
+    /// `2.0`
+    Number(f64),
+    /// `true` or `false`
+    Bool(bool),
+    /// A single lowercase word, like `left`, which is usually one of a few choices
+    Word(String),
+    /// `page/letter/1in`
+    Path(Vec<String>),
+    /// Anything else
+    Text(String),
+}
+
+impl Value {
+    /// Work out what kind of value some text is. Text which is nothing in particular is `Text`.
+    pub fn parse(text: &str) -> Value {
+        let text = text.trim();
+        if text == "true" || text == "false" {
+            return Value::Bool(text == "true");
+        }
+        if let Some(n) = parse_number(text) {
+            return Value::Number(n);
+        }
+        if let Ok(length) = Length::parse(text) {
+            return Value::Length(length);
+        }
+        let plain = !text.is_empty() && !text.contains(char::is_whitespace);
+        if plain && text.contains('/') && text.split('/').all(|s| !s.is_empty()) {
+            return Value::Path(text.split('/').map(|s| s.to_owned()).collect());
+        }
+        let word = text.starts_with(|c: char| c.is_ascii_lowercase())
+            && text.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if word {
+            Value::Word(text.to_owned())
+        } else {
+            Value::Text(text.to_owned())
+        }
+    }
+
+    /// The value as a length
+    pub fn as_length(&self) -> Result<Length> {
+        match *self {
+            Value::Length(l) => Ok(l),
+            Value::Number(n) if n == 0.0 => Ok(Length::new(0.0, Unit::Pt)),
+            // Say why it isn't a length, rather than just that it isn't
+            Value::Number(_) | Value::Word(_) | Value::Text(_) => Length::parse(&self.to_string()),
+            _ => Err(self.mismatch("a length")),
+        }
+    }
+
+    /// The value as a number
+    pub fn as_number(&self) -> Result<f64> {
+        match *self {
+            Value::Number(n) => Ok(n),
+            _ => Err(self.mismatch("a number")),
+        }
+    }
+
+    /// The value as a count, which must be a whole number
+    pub fn as_count(&self) -> Result<usize> {
+        match *self {
+            Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => Ok(n as usize),
+            _ => Err(self.mismatch("a whole number")),
+        }
+    }
+
+    /// The value as `true` or `false`
+    pub fn as_bool(&self) -> Result<bool> {
+        match *self {
+            Value::Bool(b) => Ok(b),
+            _ => Err(self.mismatch("true or false")),
+        }
+    }
+
+    /// The value as one of a few words
+    pub fn as_enum<'a>(&'a self, choices: &[&str]) -> Result<&'a str> {
+        match *self {
+            Value::Word(ref w) if choices.contains(&&w[..]) => Ok(w),
+            _ => Err(self.mismatch(&format!("one of {}", choices.join(", ")))),
+        }
+    }
+
+    /// The parts of a slash path
+    pub fn as_path(&self) -> Result<&[String]> {
+        match *self {
+            Value::Path(ref parts) => Ok(parts),
+            _ => Err(self.mismatch("a path, like page/letter/1in")),
+        }
+    }
+
+    fn mismatch(&self, expected: &str) -> Error {
+        let kind = EvaluationKind::TypeMismatch(expected.to_owned(), format!("{}", self));
+        Error::EvaluationFailure(EvaluationError::new(kind))
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Length(l) => write!(fmt, "{}", l),
+            Value::Number(n) => write!(fmt, "{}", n),
+            Value::Bool(b) => write!(fmt, "{}", b),
+            Value::Word(ref s) | Value::Text(ref s) => write!(fmt, "{}", s),
+            Value::Path(ref parts) => write!(fmt, "{}", parts.join("/")),
+        }
+    }
+}
+
+/// Parse a plain decimal number. Rust's float parsing also takes things like `inf`, which we don't.
+fn parse_number(text: &str) -> Option<f64> {
+    let plain = !text.is_empty()
+        && text.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '-' || c == '+')
+        && text.contains(|c: char| c.is_ascii_digit());
+    if plain { text.parse().ok() } else { None }
+}
+
+fn invalid(text: &str, expected: &str) -> EvaluationError {
+    EvaluationError::new(EvaluationKind::InvalidValue(text.to_owned(), expected.to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::{ Error, EvaluationKind };
+
+    #[test]
+    fn classifies() {
+        assert_eq!(Value::Length(Length::new(12.0, Unit::Pt)), Value::parse("12pt"));
+        assert_eq!(Value::Length(Length::new(0.5, Unit::In)), Value::parse("0.5in"));
+        assert_eq!(Value::Number(2.0), Value::parse("2.0"));
+        assert_eq!(Value::Bool(true), Value::parse("true"));
+        assert_eq!(Value::Word("center".to_owned()), Value::parse("center"));
+        assert_eq!(Value::Path(vec!["page".to_owned(), "letter".to_owned(), "1in".to_owned()]),
+                   Value::parse("page/letter/1in"));
+        assert_eq!(Value::Text("Times New Roman; Times; Serif".to_owned()),
+                   Value::parse("Times New Roman; Times; Serif"));
+        assert_eq!(Value::Word("inf".to_owned()), Value::parse("inf"));
+    }
+
+    #[test]
+    fn converts_units() {
+        let inch = Length::new(1.0, Unit::In);
+        assert_eq!(72.0, inch.points().unwrap());
+        assert!((inch.to(Unit::Cm).unwrap().value() - 2.54).abs() < 1e-9);
+        assert!((Length::new(10.0, Unit::Mm).to(Unit::Cm).unwrap().value() - 1.0).abs() < 1e-9);
+
+        let em = Length::new(1.5, Unit::Em);
+        assert!(em.points().is_err());
+        assert_eq!(18.0, em.resolve(Length::new(12.0, Unit::Pt)).points().unwrap());
+    }
+
+    #[test]
+    fn invalid_values() {
+        match Value::parse("12px").as_length() {
+            Err(Error::EvaluationFailure(ref f)) => {
+                assert_eq!(&EvaluationKind::InvalidValue("12px".to_owned(), "a length".to_owned()), f.kind());
+                assert!(f.notes()[0].starts_with("px isn't a unit"));
+            }
+            other => panic!("Expected an invalid length, got {:?}", other),
+        }
+        assert!(Value::parse("12").as_length().is_err());
+        assert_eq!(Length::new(0.0, Unit::Pt), Value::parse("0").as_length().unwrap());
+        assert!(Value::parse("yes").as_bool().is_err());
+        assert_eq!(3, Value::parse("3").as_count().unwrap());
+        assert!(Value::parse("1.5").as_count().is_err());
+        assert!(Align::from_value(&Value::parse("middle")).is_err());
+        assert_eq!(Align::Center, Align::from_value(&Value::parse("center")).unwrap());
+    }
+}
@@ -0,0 +1,268 @@
+//! Visiting the syntax tree
+
+use ast::{ Ast, Expression, Node };
+use lexer::TextSpan;
+
+/// Looks at a syntax tree
+pub trait Visitor {
+    /// The whole tree, by default visiting its top level nodes
+    fn visit_ast(&mut self, ast: &Ast) {
+        walk_ast(self, ast)
+    }
+
+    /// Each node, by default handing it to the method for its kind
+    fn visit_node(&mut self, node: &Node) {
+        walk_node(self, node)
+    }
+
+    /// Each expression, by default visiting its nodes
+    fn visit_expression(&mut self, _span: TextSpan, expr: &Expression) {
+        walk_expression(self, expr)
+    }
+
+    /// Each `::key value` pair, by default visiting the value
+    fn visit_property(&mut self, _span: TextSpan, _key: &str, value: &Node) {
+        self.visit_node(value)
+    }
+
+    /// Each `||`
+    fn visit_divider(&mut self, _span: TextSpan) {}
+
+    /// Each run of blank lines
+    fn visit_empty_lines(&mut self, _span: TextSpan) {}
+
+    /// Each `$$variable`
+    fn visit_variable(&mut self, _span: TextSpan, _name: &str) {}
+
+    /// Each piece of text
+    fn visit_text(&mut self, _span: TextSpan, _text: &str) {}
+}
+
+/// Visits each top level node of a tree
+pub fn walk_ast<V: Visitor + ?Sized>(v: &mut V, ast: &Ast) {
+    for node in &ast.repr {
+        v.visit_node(node);
+    }
+}
+
+/// Visits a node with the method for its kind
+pub fn walk_node<V: Visitor + ?Sized>(v: &mut V, node: &Node) {
+    match *node {
+        Node::Expression(span, ref e) => v.visit_expression(span, e),
+        Node::Divider(span) => v.visit_divider(span),
+        Node::EmptyLines(span) => v.visit_empty_lines(span),
+        Node::Variable(span, ref name) => v.visit_variable(span, name),
+        Node::Text(span, ref text) => v.visit_text(span, text),
+        Node::Property(span, ref key, ref value) => v.visit_property(span, key, value),
+    }
+}
+
+/// Visits each node of an expression
+pub fn walk_expression<V: Visitor + ?Sized>(v: &mut V, expr: &Expression) {
+    for node in &expr.repr {
+        v.visit_node(node);
+    }
+}
+
+/// Edits a syntax tree in place.
+///
+/// Every span in the tree passes through `visit_span_mut`, which makes moving whole trees easy.
+pub trait VisitorMut {
+    /// The whole tree, by default visiting its top level nodes
+    fn visit_ast_mut(&mut self, ast: &mut Ast) {
+        walk_ast_mut(self, ast)
+    }
+
+    /// Each node, by default handing it to the method for its kind
+    fn visit_node_mut(&mut self, node: &mut Node) {
+        walk_node_mut(self, node)
+    }
+
+    /// Every span in the tree
+    fn visit_span_mut(&mut self, _span: &mut TextSpan) {}
+
+    /// Each expression, by default visiting its span and nodes
+    fn visit_expression_mut(&mut self, span: &mut TextSpan, expr: &mut Expression) {
+        self.visit_span_mut(span);
+        walk_expression_mut(self, expr)
+    }
+
+    /// Each `::key value` pair, by default visiting its span and value
+    fn visit_property_mut(&mut self, span: &mut TextSpan, _key: &mut String, value: &mut Node) {
+        self.visit_span_mut(span);
+        self.visit_node_mut(value)
+    }
+
+    /// Each `||`, by default visiting its span
+    fn visit_divider_mut(&mut self, span: &mut TextSpan) {
+        self.visit_span_mut(span)
+    }
+
+    /// Each run of blank lines, by default visiting its span
+    fn visit_empty_lines_mut(&mut self, span: &mut TextSpan) {
+        self.visit_span_mut(span)
+    }
+
+    /// Each `$$variable`, by default visiting its span
+    fn visit_variable_mut(&mut self, span: &mut TextSpan, _name: &mut String) {
+        self.visit_span_mut(span)
+    }
+
+    /// Each piece of text, by default visiting its span
+    fn visit_text_mut(&mut self, span: &mut TextSpan, _text: &mut String) {
+        self.visit_span_mut(span)
+    }
+}
+
+/// Visits each top level node of a tree
+pub fn walk_ast_mut<V: VisitorMut + ?Sized>(v: &mut V, ast: &mut Ast) {
+    for node in &mut ast.repr {
+        v.visit_node_mut(node);
+    }
+}
+
+/// Visits a node with the method for its kind
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(v: &mut V, node: &mut Node) {
+    match *node {
+        Node::Expression(ref mut span, ref mut e) => v.visit_expression_mut(span, e),
+        Node::Divider(ref mut span) => v.visit_divider_mut(span),
+        Node::EmptyLines(ref mut span) => v.visit_empty_lines_mut(span),
+        Node::Variable(ref mut span, ref mut name) => v.visit_variable_mut(span, name),
+        Node::Text(ref mut span, ref mut text) => v.visit_text_mut(span, text),
+        Node::Property(ref mut span, ref mut key, ref mut value) => v.visit_property_mut(span, key, value),
+    }
+}
+
+/// Visits each node of an expression
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(v: &mut V, expr: &mut Expression) {
+    for node in &mut expr.repr {
+        v.visit_node_mut(node);
+    }
+}
+
+/// Rebuilds a syntax tree, by value.
+///
+/// `fold_nodes` sees each list of siblings, so a fold can drop nodes or splice in new ones.
+pub trait Fold {
+    /// The whole tree, by default folding its top level nodes
+    fn fold_ast(&mut self, ast: Ast) -> Ast {
+        Ast { repr: self.fold_nodes(ast.repr), files: ast.files }
+    }
+
+    /// Each list of siblings, by default folding each node
+    fn fold_nodes(&mut self, nodes: Vec<Node>) -> Vec<Node> {
+        fold_nodes(self, nodes)
+    }
+
+    /// Each node, by default folding its parts
+    fn fold_node(&mut self, node: Node) -> Node {
+        fold_node(self, node)
+    }
+
+    /// Every span in the tree
+    fn fold_span(&mut self, span: TextSpan) -> TextSpan {
+        span
+    }
+
+    /// Each expression, by default folding its nodes
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        Expression { repr: self.fold_nodes(expr.repr) }
+    }
+
+    /// Each piece of text
+    fn fold_text(&mut self, text: String) -> String {
+        text
+    }
+
+    /// The name of each `$$variable`
+    fn fold_variable(&mut self, name: String) -> String {
+        name
+    }
+
+    /// The key of each `::key value` pair
+    fn fold_key(&mut self, key: String) -> String {
+        key
+    }
+}
+
+/// Folds each node in a list
+pub fn fold_nodes<F: Fold + ?Sized>(f: &mut F, nodes: Vec<Node>) -> Vec<Node> {
+    nodes.into_iter().map(|node| f.fold_node(node)).collect()
+}
+
+/// Folds the parts of a node
+pub fn fold_node<F: Fold + ?Sized>(f: &mut F, node: Node) -> Node {
+    match node {
+        Node::Expression(span, e) => Node::Expression(f.fold_span(span), f.fold_expression(e)),
+        Node::Divider(span) => Node::Divider(f.fold_span(span)),
+        Node::EmptyLines(span) => Node::EmptyLines(f.fold_span(span)),
+        Node::Variable(span, name) => Node::Variable(f.fold_span(span), f.fold_variable(name)),
+        Node::Text(span, text) => Node::Text(f.fold_span(span), f.fold_text(text)),
+        Node::Property(span, key, value) => {
+            let span = f.fold_span(span);
+            let key = f.fold_key(key);
+            Node::Property(span, key, Box::new(f.fold_node(*value)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ast::{ Ast, Node };
+    use lexer::{ Lexer, TextSpan };
+    use parser;
+
+    fn parse(src: &str) -> Ast {
+        parser::parse(Lexer::new(src)).unwrap()
+    }
+
+    struct Variables(Vec<String>);
+
+    impl Visitor for Variables {
+        fn visit_variable(&mut self, _span: TextSpan, name: &str) {
+            self.0.push(name.to_owned());
+        }
+    }
+
+    #[test]
+    fn visits_nested_nodes() {
+        let ast = parse("[{ a ::p [{ $$x }] || $$y }] $$z");
+        let mut vars = Variables(Vec::new());
+        vars.visit_ast(&ast);
+        assert_eq!(vec!["x", "y", "z"], vars.0);
+    }
+
+    struct Shift(usize);
+
+    impl VisitorMut for Shift {
+        fn visit_span_mut(&mut self, span: &mut TextSpan) {
+            span.low += self.0;
+            span.high += self.0;
+        }
+    }
+
+    #[test]
+    fn visits_every_span() {
+        let mut ast = parse("[{ a ::p b }]");
+        Shift(3).visit_ast_mut(&mut ast);
+        assert_eq!(parse("   [{ a ::p b }]"), ast);
+    }
+
+    /// Drops every divider
+    struct NoDividers;
+
+    impl Fold for NoDividers {
+        fn fold_nodes(&mut self, nodes: Vec<Node>) -> Vec<Node> {
+            let nodes = nodes.into_iter().filter(|n| match *n { Node::Divider(_) => false, _ => true }).collect();
+            fold_nodes(self, nodes)
+        }
+    }
+
+    #[test]
+    fn folds_can_remove_nodes() {
+        let ast = NoDividers.fold_ast(parse("[{ a || [{ b || c }] }]"));
+        let expected = parse("[{ a    [{ b    c }] }]");
+        assert_eq!(expected, ast);
+    }
+}
@@ -0,0 +1,255 @@
+//! Visiting the expression tree
+
+use expression::{ Expression, Node, Section };
+
+/// Looks at an expression tree
+pub trait Visitor {
+    /// Each expression, by default visiting its sections
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr)
+    }
+
+    /// Each section of an expression, by default visiting its nodes
+    fn visit_section(&mut self, section: &Section) {
+        walk_section(self, section)
+    }
+
+    /// Each node, by default handing it to the method for its kind
+    fn visit_node(&mut self, node: &Node) {
+        walk_node(self, node)
+    }
+
+    /// Each `::key value` pair, by default visiting the value
+    fn visit_prop(&mut self, _key: &str, value: &Node) {
+        self.visit_node(value)
+    }
+
+    /// Each piece of text
+    fn visit_atom(&mut self, _text: &str) {}
+
+    /// Each `$$variable`
+    fn visit_var(&mut self, _name: &str) {}
+
+    /// Each run of blank lines
+    fn visit_blank(&mut self) {}
+}
+
+/// Visits each section of an expression
+pub fn walk_expression<V: Visitor + ?Sized>(v: &mut V, expr: &Expression) {
+    for section in &expr.sections {
+        v.visit_section(section);
+    }
+}
+
+/// Visits each node of a section
+pub fn walk_section<V: Visitor + ?Sized>(v: &mut V, section: &Section) {
+    for node in &section.content {
+        v.visit_node(node);
+    }
+}
+
+/// Visits a node with the method for its kind
+pub fn walk_node<V: Visitor + ?Sized>(v: &mut V, node: &Node) {
+    match *node {
+        Node::Expr(ref e) => v.visit_expression(e),
+        Node::Prop(ref key, ref value) => v.visit_prop(key, value),
+        Node::Atom(ref text) => v.visit_atom(text),
+        Node::Var(ref name) => v.visit_var(name),
+        Node::Blank => v.visit_blank(),
+    }
+}
+
+/// Edits an expression tree in place
+pub trait VisitorMut {
+    /// Each expression, by default visiting its sections
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr)
+    }
+
+    /// Each section of an expression, by default visiting its nodes
+    fn visit_section_mut(&mut self, section: &mut Section) {
+        walk_section_mut(self, section)
+    }
+
+    /// Each node, by default handing it to the method for its kind
+    fn visit_node_mut(&mut self, node: &mut Node) {
+        walk_node_mut(self, node)
+    }
+
+    /// Each `::key value` pair, by default visiting the value
+    fn visit_prop_mut(&mut self, _key: &mut String, value: &mut Node) {
+        self.visit_node_mut(value)
+    }
+
+    /// Each piece of text
+    fn visit_atom_mut(&mut self, _text: &mut String) {}
+
+    /// Each `$$variable`
+    fn visit_var_mut(&mut self, _name: &mut String) {}
+
+    /// Each run of blank lines
+    fn visit_blank_mut(&mut self) {}
+}
+
+/// Visits each section of an expression
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(v: &mut V, expr: &mut Expression) {
+    for section in &mut expr.sections {
+        v.visit_section_mut(section);
+    }
+}
+
+/// Visits each node of a section
+pub fn walk_section_mut<V: VisitorMut + ?Sized>(v: &mut V, section: &mut Section) {
+    for node in &mut section.content {
+        v.visit_node_mut(node);
+    }
+}
+
+/// Visits a node with the method for its kind
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(v: &mut V, node: &mut Node) {
+    match *node {
+        Node::Expr(ref mut e) => v.visit_expression_mut(e),
+        Node::Prop(ref mut key, ref mut value) => v.visit_prop_mut(key, value),
+        Node::Atom(ref mut text) => v.visit_atom_mut(text),
+        Node::Var(ref mut name) => v.visit_var_mut(name),
+        Node::Blank => v.visit_blank_mut(),
+    }
+}
+
+/// Rebuilds an expression tree, by value.
+///
+/// `fold_nodes` sees the contents of each section, so a fold can drop nodes or splice in new ones.
+pub trait Fold {
+    /// Each expression, by default folding its sections
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        fold_expression(self, expr)
+    }
+
+    /// Each section of an expression, by default folding its nodes
+    fn fold_section(&mut self, section: Section) -> Section {
+        Section { content: self.fold_nodes(section.content) }
+    }
+
+    /// The contents of each section, by default folding each node
+    fn fold_nodes(&mut self, nodes: Vec<Node>) -> Vec<Node> {
+        fold_nodes(self, nodes)
+    }
+
+    /// Each node, by default folding its parts
+    fn fold_node(&mut self, node: Node) -> Node {
+        fold_node(self, node)
+    }
+
+    /// Each piece of text
+    fn fold_atom(&mut self, text: String) -> String {
+        text
+    }
+
+    /// The name of each `$$variable`
+    fn fold_var(&mut self, name: String) -> String {
+        name
+    }
+
+    /// The key of each `::key value` pair
+    fn fold_key(&mut self, key: String) -> String {
+        key
+    }
+}
+
+/// Folds each section of an expression, keeping its title
+pub fn fold_expression<F: Fold + ?Sized>(f: &mut F, expr: Expression) -> Expression {
+    Expression {
+        title: expr.title,
+        sections: expr.sections.into_iter().map(|s| f.fold_section(s)).collect(),
+    }
+}
+
+/// Folds each node in a list
+pub fn fold_nodes<F: Fold + ?Sized>(f: &mut F, nodes: Vec<Node>) -> Vec<Node> {
+    nodes.into_iter().map(|node| f.fold_node(node)).collect()
+}
+
+/// Folds the parts of a node
+pub fn fold_node<F: Fold + ?Sized>(f: &mut F, node: Node) -> Node {
+    match node {
+        Node::Expr(e) => Node::Expr(f.fold_expression(e)),
+        Node::Prop(key, value) => {
+            let key = f.fold_key(key);
+            Node::Prop(key, Box::new(f.fold_node(*value)))
+        }
+        Node::Atom(text) => Node::Atom(f.fold_atom(text)),
+        Node::Var(name) => Node::Var(f.fold_var(name)),
+        Node::Blank => Node::Blank,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use expression::{ Expression, Node };
+    use lexer::Lexer;
+    use parser;
+
+    fn lower(src: &str) -> Expression {
+        Expression::from_ast(parser::parse(Lexer::new(src)).unwrap()).unwrap()
+    }
+
+    struct Variables(Vec<String>);
+
+    impl Visitor for Variables {
+        fn visit_var(&mut self, name: &str) {
+            self.0.push(name.to_owned());
+        }
+    }
+
+    #[test]
+    fn visits_nested_nodes() {
+        let expr = lower("[{ a ::p [{ $$x }] || $$y [{ b || $$z }] }]");
+        let mut vars = Variables(Vec::new());
+        vars.visit_expression(&expr);
+        assert_eq!(vec!["x", "y", "z"], vars.0);
+    }
+
+    /// Upper cases every atom, and counts blank lines
+    struct Shout(usize);
+
+    impl VisitorMut for Shout {
+        fn visit_atom_mut(&mut self, text: &mut String) {
+            *text = text.to_uppercase();
+        }
+
+        fn visit_blank_mut(&mut self) {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn visits_every_node_mut() {
+        let mut expr = lower("[{ a ::p [{ x b }] || c\n\nd }]");
+        let mut shout = Shout(0);
+        shout.visit_expression_mut(&mut expr);
+        assert_eq!(1, shout.0);
+        assert_eq!(format!("{:?}", lower("[{ a ::p [{ x B }] || C\n\nD }]")), format!("{:?}", expr));
+    }
+
+    /// Drops every blank line, and renames `::old` to `::new`
+    struct Tidy;
+
+    impl Fold for Tidy {
+        fn fold_nodes(&mut self, nodes: Vec<Node>) -> Vec<Node> {
+            let nodes = nodes.into_iter().filter(|n| match *n { Node::Blank => false, _ => true }).collect();
+            fold_nodes(self, nodes)
+        }
+
+        fn fold_key(&mut self, key: String) -> String {
+            if key == "old" { "new".to_owned() } else { key }
+        }
+    }
+
+    #[test]
+    fn folds_can_remove_nodes_and_rename_keys() {
+        let expr = Tidy.fold_expression(lower("[{ a || b\n\n[{ c ::old d }] }]"));
+        let expected = lower("[{ a || b [{ c ::new d }] }]");
+        assert_eq!(format!("{:?}", expected), format!("{:?}", expr));
+    }
+}
@@ -0,0 +1,8 @@
+//! Visitors and folds over the syntax tree and the expression tree.
+//!
+//! Each tree gets three traits: `Visitor` looks, `VisitorMut` edits in place, and `Fold` rebuilds
+//! the tree by value. Every method defaults to walking into its children, so a pass only has to
+//! override the nodes it cares about, calling the matching `walk_*` function to keep descending.
+
+pub mod ast;
+pub mod expression;
@@ -0,0 +1,285 @@
+//! Watch mode: rebuild a document whenever it or anything it uses changes, and serve the
+//! result as HTML on localhost. Pages reload themselves after each rebuild.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::io;
+use std::io::{ BufRead, BufReader, Write };
+use std::net::{ SocketAddr, TcpListener, TcpStream };
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, Mutex };
+use std::thread;
+use std::time::{ Duration, SystemTime };
+
+use document::{ Document, Strategy };
+use error::Result;
+use include;
+use include::Resolver;
+use lexer::Lexer;
+use parser;
+use render::html;
+
+/// How often watched files are checked for changes
+const POLL_INTERVAL_MS: u64 = 250;
+
+/// Polled by the page to find out when to reload
+const VERSION_PATH: &'static str = "/__litx/version";
+
+/// Served pages ask for the current version every so often, and reload when it changes.
+const RELOAD_SCRIPT: &'static str = "\
+<script>
+(function() {
+    var version = '{version}';
+    setInterval(function() {
+        var req = new XMLHttpRequest();
+        req.onload = function() { if (req.responseText != version) location.reload(); };
+        req.open('GET', '/__litx/version');
+        req.send();
+    }, 500);
+})();
+</script>
+";
+
+/// A document being watched, and the socket its preview is served on
+#[derive(Debug)]
+pub struct Server {
+    path: PathBuf,
+    listener: TcpListener,
+    preview: Arc<Mutex<Preview>>,
+}
+
+impl Server {
+    /// Build a document and listen on `127.0.0.1:port`, or on any free port if it's 0.
+    pub fn bind(path: &Path, port: u16) -> Result<Server> {
+        let listener = try!(TcpListener::bind(("127.0.0.1", port)));
+        let preview = Preview { build: build(path), version: 0 };
+        Ok(Server { path: path.to_path_buf(), listener: listener, preview: Arc::new(Mutex::new(preview)) })
+    }
+
+    /// Where the preview is served
+    pub fn addr(&self) -> Result<SocketAddr> {
+        Ok(try!(self.listener.local_addr()))
+    }
+
+    /// Rebuild and serve until killed, passing `rebuilt` each new version number
+    pub fn serve<F>(self, rebuilt: F) -> Result<()>
+        where F: FnMut(u64) + Send + 'static
+    {
+        let path = self.path;
+        let watched = self.preview.clone();
+        thread::spawn(move || poll(&path, &watched, rebuilt));
+
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let preview = self.preview.clone();
+            thread::spawn(move || {
+                // A browser hanging up on us isn't worth reporting
+                let _ = respond(stream, &preview);
+            });
+        }
+        Ok(())
+    }
+}
+
+/// What's currently being served
+#[derive(Debug)]
+struct Preview {
+    build: Build,
+    /// Bumped after every rebuild
+    version: u64,
+}
+
+/// The result of building a document
+#[derive(Debug)]
+struct Build {
+    /// The rendered document, or a page describing what went wrong
+    html: String,
+    /// Every file the build read, or tried to
+    files: Vec<PathBuf>,
+}
+
+/// Rebuild whenever one of the last build's files changes
+fn poll<F: FnMut(u64)>(path: &Path, preview: &Mutex<Preview>, mut rebuilt: F) {
+    let mut stamps = HashMap::new();
+    loop {
+        let files = preview.lock().unwrap().build.files.clone();
+        let mut changed = false;
+        for file in files {
+            let stamp = modified(&file);
+            if stamps.get(&file) != Some(&stamp) {
+                // The first look at a file isn't a change
+                changed |= stamps.contains_key(&file);
+                stamps.insert(file, stamp);
+            }
+        }
+        if changed {
+            let build = build(path);
+            let version = {
+                let mut preview = preview.lock().unwrap();
+                preview.build = build;
+                preview.version += 1;
+                preview.version
+            };
+            rebuilt(version);
+        }
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|m| m.modified()).ok()
+}
+
+/// Build a document, turning any failure into an error page. A panic is a failure too, so that
+/// it doesn't end the watching along with it.
+fn build(path: &Path) -> Build {
+    let mut files = vec![path.to_path_buf()];
+    files.extend(include::dependencies(path));
+    let html = match panic::catch_unwind(AssertUnwindSafe(|| render(path, &mut files))) {
+        Ok(Ok(html)) => html,
+        Ok(Err(e)) => error_page(path, &e.to_string()),
+        Err(payload) => error_page(path, &panic_message(&*payload)),
+    };
+    Build { html: html, files: files }
+}
+
+/// What a panic said, when it said it with a string
+fn panic_message(payload: &(Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => format!("litx panicked: {}", message),
+        (_, Some(message)) => format!("litx panicked: {}", message),
+        _ => "litx panicked".to_owned(),
+    }
+}
+
+fn render(path: &Path, files: &mut Vec<PathBuf>) -> Result<String> {
+    let source = try!(include::read(path));
+    let ast = try!(parser::parse(Lexer::new(&source)));
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let ast = try!(include::expand(ast, &Resolver::new(dir)));
+    let strategy = match include::strategy_name(&source) {
+        Some(ref name) if name != "none" => {
+            let strategy_path = try!(Resolver::new(dir).strategy(name));
+            files.push(strategy_path.clone());
+            files.extend(include::dependencies(&strategy_path));
+            try!(Strategy::open(&strategy_path))
+        }
+        _ => Strategy::none(),
+    };
+    let doc = try!(Document::from_ast_in(ast, strategy, dir));
+    // Figures' images are rebuilt from too
+    files.extend(doc.paragraphs().iter().filter_map(|p| p.figure()).map(|figure| figure.path.clone()));
+    html::render(&doc)
+}
+
+fn error_page(path: &Path, message: &str) -> String {
+    format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Build failed</title>\n</head>\n\
+             <body>\n<h1>Could not build {}</h1>\n<pre>{}</pre>\n</body>\n</html>\n",
+            html::escape(&path.display().to_string()), html::escape(message))
+}
+
+/// Answer a single HTTP request
+fn respond(stream: TcpStream, preview: &Mutex<Preview>) -> io::Result<()> {
+    let mut reader = BufReader::new(try!(stream.try_clone()));
+    let mut request = String::new();
+    try!(reader.read_line(&mut request));
+    // Skip the headers, we don't need any of them
+    loop {
+        let mut header = String::new();
+        if try!(reader.read_line(&mut header)) == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let target = request.split_whitespace().nth(1).unwrap_or("/");
+    let (status, content_type, body) = {
+        let preview = preview.lock().unwrap();
+        let version = preview.version.to_string();
+        match target {
+            "/" | "/index.html" => {
+                let script = RELOAD_SCRIPT.replace("{version}", &version);
+                ("200 OK", "text/html; charset=utf-8", inject(&preview.build.html, &script))
+            }
+            VERSION_PATH => ("200 OK", "text/plain", version),
+            _ => ("404 Not Found", "text/plain", "Not found".to_owned()),
+        }
+    };
+
+    let mut stream = stream;
+    try!(write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+                         Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+                status, content_type, body.len()));
+    try!(stream.write_all(body.as_bytes()));
+    stream.flush()
+}
+
+/// Put a script at the end of a page's body
+fn inject(page: &str, script: &str) -> String {
+    match page.rfind("</body>") {
+        Some(i) => format!("{}{}{}", &page[.. i], script, &page[i ..]),
+        None => format!("{}{}", page, script),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+    use std::io::{ Read, Write };
+    use scratch::Scratch;
+
+    #[test]
+    fn parse_errors_become_pages() {
+        let dir = Scratch::new("watch-parse-error");
+        let path = dir.join("doc.litx");
+        File::create(&path).unwrap().write_all(b"[{document || oops }] }]").unwrap();
+        let build = build(&path);
+        assert!(build.html.contains("<h1>Could not build"));
+        assert!(build.html.contains("Parsing Failure"));
+        assert_eq!(vec![path], build.files);
+    }
+
+    #[test]
+    fn panics_become_messages() {
+        let payload = panic::catch_unwind(|| panic!("at {}", 3)).unwrap_err();
+        assert_eq!("litx panicked: at 3", panic_message(&*payload));
+        let payload = panic::catch_unwind(|| panic!("plain")).unwrap_err();
+        assert_eq!("litx panicked: plain", panic_message(&*payload));
+    }
+
+    #[test]
+    fn serves_on_a_free_port() {
+        let dir = Scratch::new("watch-serve");
+        let path = dir.join("doc.litx");
+        File::create(&path).unwrap().write_all(b"[{document || hi }]").unwrap();
+        let server = Server::bind(&path, 0).unwrap();
+        let addr = server.addr().unwrap();
+        assert!(addr.port() != 0);
+        thread::spawn(move || server.serve(|_| ()));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /__litx/version HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("\r\n\r\n0"));
+    }
+
+    #[test]
+    fn watches_strategy_and_includes() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/mla.litx");
+        let build = build(&path);
+        let names: Vec<_> = build.files.iter()
+            .map(|f| f.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(vec!["mla.litx", "mla.litx", "default-text-settings.litxpart"], names);
+        assert!(build.html.contains("<title>LitX Example</title>"));
+        // Text settings come from the strategy's include
+        assert!(build.html.contains("text-indent: 36pt; font-family: &#39;Times New Roman&#39;, &#39;Times&#39;, serif"));
+    }
+}